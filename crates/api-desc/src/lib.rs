@@ -429,12 +429,14 @@ impl Mod {
         let mut iter = Vec::new();
         let mut id = Vec::new();
         let mut erase = Vec::new();
+        let mut matches = Vec::new();
         for item in items {
             match item {
                 Item::Enum(_) => (),
                 Item::Struct(_) => (),
                 Item::Fn(Fn { name, .. }) => {
                     let doc = format!("Selector for [`{name}`]({name}::Sig).");
+                    let name_str = name.as_str();
                     let name_camel = camel(name);
                     let name = format_ident!("{}", name);
                     let pat = quote!(Api::#name_camel);
@@ -447,9 +449,11 @@ impl Mod {
                     iter.push(quote!(output.push(wrap(Api::#name_camel(#name::Sig)));));
                     id.push(quote!(#pat(_) => Api::#name_camel(#name::Sig),));
                     erase.push(quote!(#pat(x) => <T as crate::Dispatch>::erase(x),));
+                    matches.push(quote!(#pat(_) => path == #name_str,));
                 }
                 Item::Mod(Mod { name, .. }) => {
                     let doc = format!("Selector for [`{name}`]({name}).");
+                    let name_str = name.as_str();
                     let name_camel = camel(name);
                     let name = format_ident!("{}", name);
                     let pat = quote!(Api::#name_camel(x) =>);
@@ -463,6 +467,13 @@ impl Mod {
                     });
                     id.push(quote!(#pat Api::#name_camel(x.id()),));
                     erase.push(quote!(#pat x.erase(),));
+                    matches.push(quote! {
+                        #pat match path.strip_prefix(#name_str) {
+                            Some("") => true,
+                            Some(rest) => rest.strip_prefix("::").map_or(false, |rest| x.matches(rest)),
+                            None => false,
+                        },
+                    });
                 }
             }
         }
@@ -489,6 +500,13 @@ impl Mod {
                 pub fn descriptor(&self) -> crate::Descriptor {
                     match self { #(#descriptor)* }
                 }
+                /// Returns whether the function is designated by a path.
+                ///
+                /// The path is relative to this module and designates either a module (e.g.
+                /// `crypto::ec`) or a function (e.g. `crypto::ec::is_supported`).
+                pub fn matches(&self, path: &str) -> bool {
+                    match self { #(#matches)* }
+                }
                 // TODO: Find a solution to have this computed at compile time.
                 pub fn iter<T>(output: &mut alloc::vec::Vec<T>, wrap: impl Fn(Self) -> T) {
                     #(#iter)*
@@ -31,7 +31,8 @@ pub(crate) fn new() -> Item {
             fn insert "si" {
                 /// Key of the entry.
                 ///
-                /// This must be smaller than 4096.
+                /// This must be smaller than 3072 divided by the number of applets the platform
+                /// can run.
                 key: usize,
 
                 /// Value of the entry.
@@ -89,6 +90,80 @@ pub(crate) fn new() -> Item {
             /// Clears the store, removing all entries.
             fn clear "sc" {} -> ()
         },
+        #[cfg(feature = "api-store")]
+        item! {
+            /// Update of a transaction.
+            struct Update {
+                /// Key of the entry.
+                ///
+                /// This has the same constraints as for `insert()`.
+                key: u32,
+
+                /// Value of the entry, or null to remove the entry.
+                ptr: u32,
+
+                /// Length of the value (ignored when removing the entry).
+                len: u32,
+            }
+        },
+        #[cfg(feature = "api-store")]
+        item! {
+            /// Applies a sequence of updates atomically.
+            ///
+            /// Either all updates are applied or none. The keys of the updates must be distinct
+            /// and there must be at most 31 updates.
+            fn transaction "st" {
+                /// Array of [`super::Update`] structs.
+                ptr: *const u8,
+
+                /// Number of updates.
+                len: usize,
+            } -> ()
+        },
+        #[cfg(feature = "api-store")]
+        item! {
+            /// Progression ratio in words for store metrics.
+            struct Ratio {
+                /// How much of the metric is used.
+                used: u32,
+
+                /// How much of the metric can be used at most.
+                total: u32,
+            }
+        },
+        #[cfg(feature = "api-store")]
+        item! {
+            /// Returns the capacity used by the applet.
+            ///
+            /// The total is the quota of the applet, an even share of the store capacity between
+            /// the applets the platform can run.
+            fn capacity "sy" {
+                /// Pointer to the output [`super::Ratio`] struct.
+                ptr: *mut u8,
+            } -> ()
+        },
+        #[cfg(feature = "api-store")]
+        item! {
+            /// Returns the lifetime used by the store.
+            ///
+            /// The lifetime is shared by all applets and the platform. It is used when writing
+            /// entries and when compacting the store.
+            fn lifetime "sl" {
+                /// Pointer to the output [`super::Ratio`] struct.
+                ptr: *mut u8,
+            } -> ()
+        },
+        #[cfg(feature = "api-store")]
+        item! {
+            /// Compacts the store once if needed to insert a value without compaction.
+            ///
+            /// This permits to compact the store at a convenient time, since compaction may take
+            /// a while. This is a no-op if the value can already be inserted without compaction.
+            fn prepare "sp" {
+                /// Length of the value.
+                len: usize,
+            } -> ()
+        },
         #[cfg(feature = "api-store-fragment")]
         fragment::new(),
     ];
@@ -13,14 +13,18 @@
 // limitations under the License.
 
 use std::fmt::Display;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use cargo_metadata::{Metadata, MetadataCommand};
 use clap::{ValueEnum, ValueHint};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
 use rusb::UsbContext;
-use wasefire_protocol::{self as service, applet, Api};
+use wasefire_interpreter::{custom_sections, push_custom_section, Module};
+use wasefire_protocol::{self as service, applet, platform, Api, Service};
 use wasefire_protocol_usb::Connection;
 
 use crate::{cmd, fs};
@@ -53,10 +57,168 @@ impl Rpc {
     }
 }
 
+/// Lists the applets installed on a platform.
+#[derive(clap::Args)]
+pub struct AppletList {}
+
+impl AppletList {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let AppletList {} = self;
+        let applets = connection.call::<service::AppletList>(())?;
+        let applets = applets.get();
+        println!("There are {} installed applets:", applets.len());
+        for applet::Metadata { name, size, running } in applets {
+            let status = if *running { "running" } else { "stopped" };
+            println!("- {name} ({size} bytes, {status})");
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Args)]
+pub struct AppletInstall {
+    /// Path to the applet module.
+    #[arg(value_hint = ValueHint::FilePath)]
+    applet: PathBuf,
+
+    /// Name of the applet in the platform (defaults to the file name without extension).
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Size of the chunks sent to the platform.
+    #[arg(long, default_value = "1024")]
+    chunk_size: usize,
+}
+
+impl AppletInstall {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let name = self.name()?;
+        let AppletInstall { applet, name: _, chunk_size } = self;
+        ensure!(0 < chunk_size, "chunk size must be positive");
+        let module = fs::read(&applet)?;
+        let size = module.len().try_into()?;
+        connection.call::<service::AppletInstall>(applet::Install::Start { name: &name, size })?;
+        for chunk in module.chunks(chunk_size) {
+            connection.call::<service::AppletInstall>(applet::Install::Write { chunk })?;
+        }
+        if let Err(error) = connection.call::<service::AppletInstall>(applet::Install::Finish) {
+            let version = *connection.call::<service::ApiVersion>(())?.get();
+            if service::AppletRejection::VERSIONS.contains(version)? {
+                if let Some(reason) = *connection.call::<service::AppletRejection>(&name)?.get() {
+                    let reason = match reason {
+                        applet::Rejection::Invalid => "invalid module",
+                        applet::Rejection::Unsigned => "not signed",
+                        applet::Rejection::Malformed => "malformed signature",
+                        applet::Rejection::Untrusted => "not signed by a trusted key",
+                    };
+                    bail!("the platform rejected {name}: {reason}");
+                }
+            }
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> Result<String> {
+        if let Some(name) = &self.name {
+            return Ok(name.clone());
+        }
+        match self.applet.file_stem().and_then(|x| x.to_str()) {
+            Some(x) => Ok(x.to_string()),
+            None => bail!("cannot infer applet name from {}", self.applet.display()),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct AppletUpdate {
+    #[clap(flatten)]
+    install: AppletInstall,
+}
+
+impl AppletUpdate {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let AppletUpdate { install } = self;
+        let name = install.name()?;
+        let applets = connection.call::<service::AppletList>(())?;
+        ensure!(applets.get().iter().any(|x| x.name == name), "applet {name} is not installed");
+        install.run(connection)
+    }
+}
+
+/// Signs an applet.
+///
+/// The signature is appended to the module as a custom section, replacing any previous signature.
+#[derive(clap::Args)]
+pub struct AppletSign {
+    /// Path to the applet module.
+    #[arg(value_hint = ValueHint::FilePath)]
+    applet: PathBuf,
+
+    /// Path to the private key (32 bytes) signing the applet.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    key: PathBuf,
+
+    /// Where to write the signed applet (defaults to signing in place).
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+impl AppletSign {
+    pub fn run(self) -> Result<()> {
+        let AppletSign { applet, key, output } = self;
+        let key = read_signing_key(&key)?;
+        let module = sign_applet(fs::read(&applet)?, &key)?;
+        fs::write(output.as_ref().unwrap_or(&applet), module)?;
+        let point = key.verifying_key().to_encoded_point(false);
+        let (x, y) = (point.x().unwrap(), point.y().unwrap());
+        println!("Signed with public key {x:x}{y:x}");
+        Ok(())
+    }
+}
+
+/// Prepares an applet for fast loading.
+///
+/// The module is validated and its side table for control flow is appended as a custom section,
+/// replacing any previous one. Platforms skip validation of prepared applets if they are signed by
+/// a trusted key, so the applet must be signed again afterwards.
+#[derive(clap::Args)]
+pub struct AppletPrepare {
+    /// Path to the applet module.
+    #[arg(value_hint = ValueHint::FilePath)]
+    applet: PathBuf,
+
+    /// Where to write the prepared applet (defaults to preparing in place).
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+impl AppletPrepare {
+    pub fn run(self) -> Result<()> {
+        let AppletPrepare { applet, output } = self;
+        let module = prepare_applet(fs::read(&applet)?)?;
+        fs::write(output.as_ref().unwrap_or(&applet), module)
+    }
+}
+
+#[derive(clap::Args)]
+pub struct AppletUninstall {
+    /// Name of the applet in the platform.
+    name: String,
+}
+
+impl AppletUninstall {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let AppletUninstall { name } = self;
+        connection.call::<service::AppletUninstall>(&name)?;
+        Ok(())
+    }
+}
+
 /// Calls an RPC to an applet on a platform.
 #[derive(clap::Args)]
 pub struct AppletRpc {
-    /// Applet identifier in the platform.
+    /// Applet name in the platform (defaults to `applet`).
     applet: Option<String>,
 
     #[clap(flatten)]
@@ -70,14 +232,31 @@ pub struct AppletRpc {
 impl AppletRpc {
     pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
         let AppletRpc { applet, rpc, retries } = self;
-        let applet_id = match applet {
-            Some(_) => bail!("applet identifiers are not supported yet"),
-            None => applet::AppletId,
+        let request = &rpc.read()?;
+        let version = *connection.call::<service::ApiVersion>(())?.get();
+        let applet_id = if service::AppletRequest::VERSIONS.contains(version)? {
+            let name = applet.as_deref().unwrap_or("applet");
+            connection.call::<service::AppletRequest>(applet::Request {
+                applet_id: applet::AppletId { name },
+                request,
+            })?;
+            Some(applet::AppletId { name })
+        } else {
+            ensure!(applet.is_none(), "applet identifiers are not supported by the platform");
+            let applet_id = applet::legacy::AppletId;
+            connection.call::<service::LegacyAppletRequest>(applet::legacy::Request {
+                applet_id,
+                request,
+            })?;
+            None
         };
-        let request = applet::Request { applet_id, request: &rpc.read()? };
-        connection.call::<service::AppletRequest>(request)?.get();
         for _ in 0 .. retries {
-            let response = connection.call::<service::AppletResponse>(applet_id)?;
+            let response = match applet_id {
+                Some(applet_id) => connection.call::<service::AppletResponse>(applet_id)?,
+                None => {
+                    connection.call::<service::LegacyAppletResponse>(applet::legacy::AppletId)?
+                }
+            };
             if let Some(response) = response.get().response {
                 return rpc.write(response);
             }
@@ -96,10 +275,101 @@ impl PlatformReboot {
         connection.send(&Api::PlatformReboot(()))?;
         match connection.receive::<service::PlatformReboot>() {
             Ok(x) => *x.get(),
-            Err(e) => match e.downcast_ref::<rusb::Error>() {
-                Some(rusb::Error::Timeout | rusb::Error::NoDevice) => Ok(()),
-                _ => Err(e),
-            },
+            Err(e) if is_timeout(&e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Prints the health of the platform store.
+#[derive(clap::Args)]
+pub struct PlatformStoreHealth {}
+
+impl PlatformStoreHealth {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let PlatformStoreHealth {} = self;
+        let health = connection.call::<service::PlatformStoreHealth>(())?;
+        let health = health.get();
+        let ratio = |x: &service::platform::Ratio| format!("{}/{} words", x.used, x.total);
+        println!("Capacity: {}", ratio(&health.capacity));
+        println!("Lifetime: {}", ratio(&health.lifetime));
+        println!("Remaining page erases: {}", health.remaining_erases);
+        println!("Pages per number of erases:");
+        for &(bucket, count) in &health.wear {
+            match bucket {
+                0 | 1 => println!("- {bucket}: {count}"),
+                _ => println!("- {bucket} to {}: {count}", 2 * bucket - 1),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the metadata for platform update.
+#[derive(clap::Args)]
+pub struct PlatformUpdateMetadata {
+    /// Writes the metadata to this file instead of standard output.
+    #[arg(long, value_hint = ValueHint::AnyPath)]
+    output: Option<PathBuf>,
+}
+
+impl PlatformUpdateMetadata {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let PlatformUpdateMetadata { output } = self;
+        let metadata = connection.call::<service::PlatformUpdateMetadata>(())?;
+        match output {
+            Some(path) => fs::write(path, metadata.get()),
+            None => fs::write_stdout(metadata.get()),
+        }
+    }
+}
+
+/// Updates a platform.
+#[derive(clap::Args)]
+pub struct PlatformUpdate {
+    /// Path to the platform update.
+    #[arg(value_hint = ValueHint::FilePath)]
+    platform: PathBuf,
+
+    /// Only checks the update without applying it.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Size of the chunks sent to the platform.
+    #[arg(long, default_value = "1024")]
+    chunk_size: usize,
+
+    /// Number of retries when sending a chunk times out.
+    #[arg(long, default_value = "3")]
+    retries: usize,
+}
+
+impl PlatformUpdate {
+    pub fn run<T: UsbContext>(self, connection: &Connection<T>) -> Result<()> {
+        let PlatformUpdate { platform: path, dry_run, chunk_size, retries } = self;
+        ensure!(0 < chunk_size, "chunk size must be positive");
+        let update = fs::read(&path)?;
+        connection.call::<service::PlatformUpdate>(platform::Update::Initialize { dry_run })?;
+        let mut offset = 0;
+        for chunk in update.chunks(chunk_size) {
+            let request = || platform::Update::Process { offset: offset as u32, chunk };
+            let mut retry = 0;
+            // The platform acknowledges a chunk it already processed, so we can resend it.
+            while let Err(error) = connection.call::<service::PlatformUpdate>(request()) {
+                ensure!(is_timeout(&error) && retry < retries, error);
+                retry += 1;
+                eprintln!("\nTimed out at offset {offset}, resuming ({retry}/{retries}).");
+            }
+            offset += chunk.len();
+            eprint!("\rUpdating platform: {}%", 100 * offset / update.len());
+        }
+        eprintln!();
+        connection.send(&Api::PlatformUpdate(platform::Update::Finalize))?;
+        match connection.receive::<service::PlatformUpdate>() {
+            Ok(x) => Ok(*x.get()),
+            // The platform reboots when the update is successful.
+            Err(e) if !dry_run && is_timeout(&e) => Ok(()),
+            Err(e) => Err(e),
         }
     }
 }
@@ -181,6 +451,14 @@ pub struct RustAppletBuild {
     #[clap(long, default_value = "16384")]
     pub stack_size: usize,
 
+    /// Prepares the applet for fast loading (ignored for native applets).
+    #[arg(long)]
+    pub prepare: bool,
+
+    /// Signs the applet with this private key (ignored for native applets).
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub signing_key: Option<PathBuf>,
+
     /// Extra arguments to cargo, e.g. --features=foo.
     #[clap(last = true)]
     pub cargo: Vec<String>,
@@ -243,6 +521,13 @@ impl RustAppletBuild {
         if fs::copy_if_changed(target_dir.join(src), &applet)? && dst.ends_with(".wasm") {
             optimize_wasm(&applet, self.opt_level)?;
         }
+        if self.native.is_none() && self.prepare {
+            fs::write(&applet, prepare_applet(fs::read(&applet)?)?)?;
+        }
+        if let (None, Some(key)) = (&self.native, &self.signing_key) {
+            let key = read_signing_key(key)?;
+            fs::write(&applet, sign_applet(fs::read(&applet)?, &key)?)?;
+        }
         Ok(())
     }
 }
@@ -302,8 +587,11 @@ impl Display for OptLevel {
     }
 }
 
-/// Strips and optimizes a WASM applet.
+/// Strips and optimizes a WASM applet (preserving its capabilities).
 pub fn optimize_wasm(applet: impl AsRef<Path>, opt_level: Option<OptLevel>) -> Result<()> {
+    // The capability section is removed by wasm-strip, so we add it back at the end.
+    let module = fs::read(applet.as_ref())?;
+    let capabilities = custom_section(&module, CAPABILITY_SECTION)?.map(|x| module[x].to_vec());
     let mut strip = Command::new("wasm-strip");
     strip.arg(applet.as_ref());
     cmd::execute(&mut strip)?;
@@ -317,9 +605,97 @@ pub fn optimize_wasm(applet: impl AsRef<Path>, opt_level: Option<OptLevel>) -> R
     opt.arg("-o");
     opt.arg(applet.as_ref());
     cmd::execute(&mut opt)?;
+    if let Some(capabilities) = capabilities {
+        let mut module = fs::read(applet.as_ref())?;
+        module.extend_from_slice(&capabilities);
+        fs::write(applet.as_ref(), module)?;
+    }
     Ok(())
 }
 
+/// Name of the custom section holding the applet capabilities.
+// Keep in sync with the capabilities macro of the prelude.
+const CAPABILITY_SECTION: &str = "wasefire-capabilities";
+
+/// Name of the custom section holding the applet signature.
+// Keep in sync with the applet-signature feature of the scheduler.
+const SIGNATURE_SECTION: &str = "wasefire-signature";
+
+/// Name of the custom section holding the side table of a prepared applet.
+// Keep in sync with the prepared modules of the interpreter.
+const PREPARED_SECTION: &str = "wasefire-side-table";
+
+/// Prepares an applet, replacing its previous preparation if any.
+///
+/// The signature is removed since it would not cover the prepared module.
+fn prepare_applet(mut module: Vec<u8>) -> Result<Vec<u8>> {
+    module.truncate(unsigned_length(&module)?);
+    if let Some(section) = custom_section(&module, PREPARED_SECTION)? {
+        ensure!(section.end == module.len(), "side table section is not the last section");
+        module.truncate(section.start);
+    }
+    let module = Module::new(&module).map_err(|e| anyhow!("invalid applet: {e:?}"))?;
+    module.prepare().map_err(|e| anyhow!("failed to prepare applet: {e:?}"))
+}
+
+fn read_signing_key(path: impl AsRef<Path>) -> Result<SigningKey> {
+    let key = fs::read(path.as_ref())?;
+    ensure!(key.len() == 32, "{} must contain exactly 32 bytes", path.as_ref().display());
+    Ok(SigningKey::from_slice(&key)?)
+}
+
+/// Signs an applet, replacing its previous signature if any.
+///
+/// The signature section content is the P-256 ECDSA signature of the SHA-256 digest of the module
+/// without the signature section. The applet must be valid and, if prepared, its side table must
+/// match (see [`check_applet()`]).
+pub fn sign_applet(mut module: Vec<u8>, key: &SigningKey) -> Result<Vec<u8>> {
+    module.truncate(unsigned_length(&module)?);
+    check_applet(&module)?;
+    let signature: Signature = key.sign(&module);
+    push_custom_section(&mut module, SIGNATURE_SECTION, &signature.to_bytes())
+        .map_err(|e| anyhow!("failed to sign applet: {e:?}"))?;
+    Ok(module)
+}
+
+/// Checks that an unsigned applet is valid.
+///
+/// Signed prepared applets are loaded without validation, so their side table section is checked
+/// to be exactly the one computed by validating the module.
+fn check_applet(module: &[u8]) -> Result<()> {
+    if custom_section(module, PREPARED_SECTION)?.is_none() {
+        Module::new(module).map_err(|e| anyhow!("invalid applet: {e:?}"))?;
+        return Ok(());
+    }
+    ensure!(prepare_applet(module.to_vec())? == module, "side table does not match the applet");
+    Ok(())
+}
+
+/// Returns the length of a module without its signature section (which must be last).
+fn unsigned_length(module: &[u8]) -> Result<usize> {
+    match custom_section(module, SIGNATURE_SECTION)? {
+        Some(section) => {
+            ensure!(section.end == module.len(), "signature section is not the last section");
+            Ok(section.start)
+        }
+        None => Ok(module.len()),
+    }
+}
+
+/// Returns the byte range of the first custom section with a given name, if any.
+fn custom_section(module: &[u8], name: &str) -> Result<Option<Range<usize>>> {
+    let sections = custom_sections(module).map_err(|_| anyhow!("invalid WASM module"))?;
+    Ok(sections.into_iter().find(|x| x.name == name).map(|x| x.range))
+}
+
+/// Returns whether an error is a timeout or a disconnection (e.g. because the platform rebooted).
+fn is_timeout(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<rusb::Error>(),
+        Some(rusb::Error::Timeout | rusb::Error::NoDevice)
+    )
+}
+
 fn metadata(dir: impl Into<PathBuf>) -> Result<Metadata> {
     let metadata = MetadataCommand::new().current_dir(dir).no_deps().exec()?;
     ensure!(metadata.packages.len() == 1, "not exactly one package");
@@ -340,3 +716,49 @@ fn wasefire_feature(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Module with an empty function: `(module (func))`.
+    const MODULE: &[u8] =
+        b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\x03\x02\x01\0\x0a\x04\x01\x02\0\x0b";
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[1; 32]).unwrap()
+    }
+
+    #[test]
+    fn sign_applet_replaces_signature() {
+        let signed = sign_applet(MODULE.to_vec(), &signing_key()).unwrap();
+        assert_eq!(unsigned_length(&signed).unwrap(), MODULE.len());
+        assert_eq!(sign_applet(signed.clone(), &signing_key()).unwrap(), signed);
+    }
+
+    #[test]
+    fn sign_applet_checks_module() {
+        let key = signing_key();
+        let invalid = &MODULE[.. MODULE.len() - 1];
+        assert!(sign_applet(invalid.to_vec(), &key).is_err());
+        // The function drops a value from an empty stack.
+        let mut invalid = MODULE[.. MODULE.len() - 6].to_vec();
+        invalid.extend_from_slice(b"\x0a\x05\x01\x03\0\x1a\x0b");
+        assert!(sign_applet(invalid, &key).is_err());
+    }
+
+    #[test]
+    fn sign_applet_checks_side_table() {
+        let key = signing_key();
+        let prepared = prepare_applet(MODULE.to_vec()).unwrap();
+        let signed = sign_applet(prepared.clone(), &key).unwrap();
+        assert_eq!(unsigned_length(&signed).unwrap(), prepared.len());
+        let mut tampered = prepared.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(sign_applet(tampered, &key).is_err());
+        // The side table must be the last section.
+        let mut misplaced = prepared;
+        push_custom_section(&mut misplaced, "other", &[]).unwrap();
+        assert!(sign_applet(misplaced, &key).is_err());
+    }
+}
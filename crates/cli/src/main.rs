@@ -53,24 +53,34 @@ struct Options {
 #[derive(clap::Subcommand)]
 enum Action {
     /// Lists the applets installed on a platform.
-    AppletList,
+    AppletList(action::AppletList),
 
     /// Installs an applet on a platform.
-    AppletInstall,
+    AppletInstall(action::AppletInstall),
 
     /// Updates an applet on a platform.
-    AppletUpdate,
+    AppletUpdate(action::AppletUpdate),
 
     /// Uninstalls an applet from a platform.
-    AppletUninstall,
+    AppletUninstall(action::AppletUninstall),
 
     AppletRpc(action::AppletRpc),
 
+    AppletPrepare(action::AppletPrepare),
+
+    AppletSign(action::AppletSign),
+
     /// Lists the connected platforms.
     PlatformList,
 
+    /// Prints the health of the platform store.
+    PlatformStoreHealth(action::PlatformStoreHealth),
+
+    /// Returns the metadata for platform update.
+    PlatformUpdateMetadata(action::PlatformUpdateMetadata),
+
     /// Updates a connected platform.
-    PlatformUpdate,
+    PlatformUpdate(action::PlatformUpdate),
 
     PlatformReboot(action::PlatformReboot),
     PlatformRpc(action::PlatformRpc),
@@ -116,13 +126,17 @@ fn main() -> Result<()> {
     CONNECTION.lock().unwrap().set(flags.options.timeout, flags.options.serial);
     let dir = std::env::current_dir()?;
     match flags.action {
-        Action::AppletList => bail!("not implemented yet"),
-        Action::AppletInstall => bail!("not implemented yet"),
-        Action::AppletUpdate => bail!("not implemented yet"),
-        Action::AppletUninstall => bail!("not implemented yet"),
+        Action::AppletList(x) => x.run(CONNECTION.lock().unwrap().get()?),
+        Action::AppletInstall(x) => x.run(CONNECTION.lock().unwrap().get()?),
+        Action::AppletUpdate(x) => x.run(CONNECTION.lock().unwrap().get()?),
+        Action::AppletUninstall(x) => x.run(CONNECTION.lock().unwrap().get()?),
         Action::AppletRpc(x) => x.run(CONNECTION.lock().unwrap().get()?),
+        Action::AppletPrepare(x) => x.run(),
+        Action::AppletSign(x) => x.run(),
         Action::PlatformList => platform_list(flags.options.timeout),
-        Action::PlatformUpdate => bail!("not implemented yet"),
+        Action::PlatformStoreHealth(x) => x.run(CONNECTION.lock().unwrap().get()?),
+        Action::PlatformUpdateMetadata(x) => x.run(CONNECTION.lock().unwrap().get()?),
+        Action::PlatformUpdate(x) => x.run(CONNECTION.lock().unwrap().get()?),
         Action::PlatformReboot(x) => x.run(CONNECTION.lock().unwrap().get()?),
         Action::PlatformRpc(x) => x.run(CONNECTION.lock().unwrap().get()?),
         Action::RustAppletNew(x) => x.run(),
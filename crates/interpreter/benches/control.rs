@@ -0,0 +1,69 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmarks control flow (branches over large blocks).
+
+#![feature(test)]
+#![allow(unused_crate_dependencies)]
+
+extern crate test;
+
+use test::Bencher;
+use wasefire_interpreter::*;
+
+/// Returns a module exporting a `run` function looping over branches of a given size.
+fn module(size: usize) -> Vec<u8> {
+    let filler = "(drop (i32.const 0))".repeat(size);
+    let wat = format!(
+        r#"(module
+  (func (export "run") (param $n i32) (result i32)
+    (local $i i32) (local $acc i32)
+    (block $exit
+      (loop $loop
+        (br_if $exit (i32.ge_u (local.get $i) (local.get $n)))
+        (if (i32.and (local.get $i) (i32.const 1))
+          (then {filler} (local.set $acc (i32.add (local.get $acc) (local.get $i))))
+          (else
+            (block $skip
+              (br_if $skip (i32.const 1))
+              {filler})
+            (local.set $acc (i32.xor (local.get $acc) (local.get $i)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $loop)))
+    (local.get $acc)))"#
+    );
+    let buffer = wast::parser::ParseBuffer::new(&wat).unwrap();
+    let mut wat: wast::Wat = wast::parser::parse(&buffer).unwrap();
+    wat.encode().unwrap()
+}
+
+fn run(b: &mut Bencher, size: usize) {
+    let wasm = module(size);
+    let mut store = Store::default();
+    let inst = store.instantiate(Module::new(&wasm).unwrap(), &mut []).unwrap();
+    b.iter(|| {
+        let result = store.invoke(inst, "run", vec![Val::I32(1000)]).unwrap();
+        assert!(matches!(result, RunResult::Done(_)));
+    });
+}
+
+#[bench]
+fn small_blocks(b: &mut Bencher) {
+    run(b, 1);
+}
+
+#[bench]
+fn large_blocks(b: &mut Bencher) {
+    run(b, 100);
+}
@@ -59,6 +59,10 @@ fn main() {
                 assert!(results.is_empty());
                 break;
             }
+            // We didn't set any fuel, so execution is never preempted.
+            RunResult::Preempted => unreachable!(),
+            // The module doesn't throw exceptions.
+            RunResult::Exception(_) => unreachable!(),
         };
 
         // We only linked one function, which has thus index zero.
@@ -25,8 +25,75 @@ pub enum Error {
     Unsupported(Unsupported),
 
     /// Execution trapped.
-    // TODO: In debug mode, trap could describe the reason (like resource exhaustion, etc).
+    ///
+    /// The reason and backtrace are described by [`Store::core_dump()`](crate::Store::core_dump).
     Trap,
+
+    /// A limit of the store is exceeded.
+    ///
+    /// The limits are described by [`StoreLimits`](crate::StoreLimits).
+    LimitExceeded(StoreLimit),
+}
+
+/// Limits of a store.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StoreLimit {
+    /// The value stack of a thread is exhausted.
+    StackBytes,
+
+    /// The call stack of a thread is exhausted.
+    Frames,
+
+    /// A called function has too many locals.
+    Locals,
+
+    /// A table is too large.
+    TableElems,
+
+    /// A memory is too large.
+    MemPages,
+}
+
+/// Reasons for execution to trap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TrapReason {
+    /// The `unreachable` instruction was executed.
+    Unreachable,
+
+    /// A memory access is out of bounds.
+    MemoryOutOfBounds,
+
+    /// A memory write targets a read-only memory.
+    MemoryReadOnly,
+
+    /// An atomic memory access is not naturally aligned.
+    UnalignedAtomic,
+
+    /// A table access is out of bounds.
+    TableOutOfBounds,
+
+    /// An indirect call targets a null table element.
+    UninitializedElement,
+
+    /// An indirect call targets a function of the wrong type.
+    IndirectCallMismatch,
+
+    /// An integer is divided by zero.
+    DivisionByZero,
+
+    /// An integer operation or conversion overflows.
+    IntegerOverflow,
+
+    /// A NaN is converted to an integer.
+    InvalidConversion,
+
+    /// A null exception reference is thrown.
+    NullReference,
+
+    /// The start function throws an exception.
+    UncaughtException,
 }
 
 #[cfg(not(feature = "debug"))]
@@ -38,7 +105,10 @@ pub type Unsupported = ();
 pub enum Unsupported {
     Opcode(u8),
     OpcodeFc(u32),
+    OpcodeFd(u32),
     MaxLocals,
+    SideTable,
+    Tag,
 }
 
 #[cfg(feature = "debug")]
@@ -73,6 +143,12 @@ pub fn trap() -> Error {
     Error::Trap
 }
 
+pub fn limit_exceeded(limit: StoreLimit) -> Error {
+    #[cfg(feature = "debug")]
+    print_backtrace();
+    Error::LimitExceeded(limit)
+}
+
 pub fn check(cond: bool) -> Result<(), Error> {
     if cond {
         Ok(())
@@ -13,15 +13,24 @@
 // limitations under the License.
 
 // TODO: Some toctou could be used instead of panic.
+use alloc::borrow::Cow;
 use alloc::vec;
 use alloc::vec::Vec;
 
 use crate::error::*;
 use crate::module::*;
+use crate::side_table::*;
 use crate::syntax::*;
 use crate::toctou::*;
 use crate::*;
 
+mod snapshot;
+#[cfg(feature = "trace")]
+mod trace;
+
+#[cfg(feature = "trace")]
+pub use trace::{Profiler, Tracer};
+
 pub const MEMORY_ALIGN: usize = 16;
 
 /// Runtime values.
@@ -39,6 +48,8 @@ pub enum Val {
     Null(RefType),
     Ref(Ptr),
     RefExtern(usize),
+    #[cfg(feature = "exception-handling")]
+    RefExn(usize),
 }
 
 static STORE_ID: id::UniqueId = id::UniqueId::new();
@@ -49,23 +60,123 @@ static STORE_ID: id::UniqueId = id::UniqueId::new();
 pub struct Store<'m> {
     id: usize,
     insts: Vec<Instance<'m>>,
-    // TODO: This should be an Linker struct that can be shared between stores. The FuncType can be
-    // reconstructed on demand (only counts can be stored).
-    funcs: Vec<(HostName<'m>, FuncType<'m>)>,
-    // When present, contains a module name and a length. In that case, any unresolved imported
-    // function for that module name is appended to funcs (one per type, so the function name is
-    // the name of the first unresolved function of that type). The length of resolvable host
-    // functions in `funcs` is stored to limit normal linking to that part.
-    func_default: Option<(&'m str, usize)>,
+    // Host functions, possibly shared with other stores.
+    linker: Cow<'m, Linker<'m>>,
+    // When present, contains a module name and function types. In that case, any unresolved
+    // imported function for that module name is appended to the function types (one per type).
+    // Those functions are indexed after the functions of the linker.
+    func_default: Option<(&'m str, Vec<FuncType<'m>>)>,
+    // Memories provided by the host.
+    mems: Vec<(HostName<'m>, Memory<'m>)>,
     threads: Vec<Continuation<'m>>,
+    // Exceptions caught by reference (indexed by exception references).
+    #[cfg(feature = "exception-handling")]
+    exns: Vec<Exn>,
+    // Remaining number of instructions before preemption (unlimited if none).
+    fuel: Option<usize>,
+    limits: StoreLimits,
+    core_dump: Option<CoreDump<'m>>,
+    #[cfg(feature = "trace")]
+    tracer: Option<trace::Hooks>,
+}
+
+/// Resource limits of a store.
+///
+/// Exceeding a limit returns [`Error::LimitExceeded`], except for growing tables and memories
+/// which fails as if their maximum was the limit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StoreLimits {
+    /// Maximum number of bytes of the value stack of a thread (including locals).
+    ///
+    /// Each function call accounts for its locals and the maximum height of its value stack (as
+    /// computed during validation) times the size of a value.
+    pub max_stack_bytes: usize,
+
+    /// Maximum number of frames of the call stack of a thread.
+    pub max_frames: usize,
+
+    /// Maximum number of locals (including parameters) of a called function.
+    ///
+    /// Validation already rejects functions with more than 100 locals.
+    pub max_locals: usize,
+
+    /// Maximum number of elements of a table defined by an instance.
+    pub max_table_elems: u32,
+
+    /// Maximum number of pages of a memory defined by an instance.
+    pub max_mem_pages: u32,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+impl Default for StoreLimits {
+    fn default() -> Self {
+        StoreLimits {
+            max_stack_bytes: usize::MAX,
+            max_frames: 1000,
+            max_locals: crate::parser::MAX_LOCALS,
+            max_table_elems: TABLE_MAX,
+            max_mem_pages: MEM_MAX,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct HostName<'m> {
     module: &'m str,
     name: &'m str,
 }
 
+/// Host functions that can be shared between stores.
+///
+/// Functions are sorted by module and name, such that stores resolve them by binary search.
+#[derive(Debug, Default, Clone)]
+pub struct Linker<'m> {
+    funcs: Vec<(HostName<'m>, FuncType<'m>)>,
+}
+
+impl<'m> Linker<'m> {
+    /// Links a host function provided its signature.
+    ///
+    /// This is a convenient wrapper around [`Self::link_func_custom()`] for functions which
+    /// parameter and return types are only `i32`. The `params` and `results` parameters describe
+    /// how many `i32` are taken as parameters and returned as results respectively.
+    pub fn link_func(
+        &mut self, module: &'m str, name: &'m str, params: usize, results: usize,
+    ) -> Result<(), Error> {
+        static TYPES: &[ValType] = &[ValType::I32; 8];
+        check(params <= TYPES.len() && results <= TYPES.len())?;
+        let type_ = FuncType { params: TYPES[.. params].into(), results: TYPES[.. results].into() };
+        self.link_func_custom(module, name, type_)
+    }
+
+    /// Links a host function provided its signature.
+    ///
+    /// Functions must be linked in increasing order of module and name. This order defines their
+    /// index. The first linked function has index 0, the second has index 1, etc. This index is
+    /// used when a module calls in the host to identify the function (see [`Call::index()`]).
+    pub fn link_func_custom(
+        &mut self, module: &'m str, name: &'m str, type_: FuncType<'m>,
+    ) -> Result<(), Error> {
+        let name = HostName { module, name };
+        check(self.funcs.last().map_or(true, |x| x.0 < name))?;
+        self.funcs.push((name, type_));
+        Ok(())
+    }
+
+    /// Returns the number of linked host functions.
+    pub fn len(&self) -> usize {
+        self.funcs.len()
+    }
+
+    /// Returns whether no host functions are linked.
+    pub fn is_empty(&self) -> bool {
+        self.funcs.is_empty()
+    }
+
+    fn find(&self, name: &HostName<'m>) -> Option<usize> {
+        self.funcs.binary_search_by(|x| x.0.cmp(name)).ok()
+    }
+}
+
 /// Identifies a store.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StoreId(usize);
@@ -96,14 +207,29 @@ impl<'m> Default for Store<'m> {
         Self {
             id: STORE_ID.next(),
             insts: vec![],
-            funcs: vec![],
+            linker: Cow::Owned(Linker::default()),
             func_default: None,
+            mems: vec![],
             threads: vec![],
+            #[cfg(feature = "exception-handling")]
+            exns: vec![],
+            fuel: None,
+            limits: StoreLimits::default(),
+            core_dump: None,
+            #[cfg(feature = "trace")]
+            tracer: None,
         }
     }
 }
 
 impl<'m> Store<'m> {
+    /// Creates a store using the host functions of a linker.
+    ///
+    /// The linker is shared with other stores instead of linking host functions in each store.
+    pub fn new(linker: &'m Linker<'m>) -> Self {
+        Store { linker: Cow::Borrowed(linker), ..Store::default() }
+    }
+
     /// Returns the identifier of this store.
     pub fn id(&self) -> StoreId {
         StoreId(self.id)
@@ -114,9 +240,54 @@ impl<'m> Store<'m> {
     /// The memory is not dynamically allocated and must thus be provided. It is not necessary for
     /// the memory length to be a multiple of 64kB. Execution will trap if the module tries to
     /// access part of the memory that does not exist.
+    ///
+    /// The memory is used for the first memory defined by the module (if any). See
+    /// [`Self::instantiate_with_memories()`] for modules defining multiple memories.
     pub fn instantiate(
         &mut self, module: Module<'m>, memory: &'m mut [u8],
     ) -> Result<InstId, Error> {
+        self.instantiate_with_memories(module, [memory])
+    }
+
+    /// Instantiates a valid module in this store with one memory per defined memory.
+    ///
+    /// Memories are taken in order for each memory defined by the module (imported memories are
+    /// not part of them). Missing memories are empty, such that execution traps on access.
+    pub fn instantiate_with_memories(
+        &mut self, module: Module<'m>, memories: impl IntoIterator<Item = &'m mut [u8]>,
+    ) -> Result<InstId, Error> {
+        let inst_id = self.instantiate_without_start(module, memories)?;
+        if let Some(mut parser) = self.insts[inst_id].module.section(SectionId::Start) {
+            let x = parser.parse_funcidx().into_ok();
+            let ptr = self.func_ptr(inst_id, x);
+            let inst_id = ptr.instance().unwrap_wasm();
+            let module = &self.insts[inst_id].module;
+            let mut parser = module.func(ptr.index());
+            let stp = module.side_table_start(ptr.index());
+            let mut locals = Vec::new();
+            append_locals(&mut parser, &mut locals);
+            let mut frame = Frame::new(inst_id, ptr.index(), 0, &[], locals, stp);
+            frame.check_limits(self, None)?;
+            #[cfg(feature = "trace")]
+            self.trace_enter(inst_id, ptr.index(), parser.save());
+            let thread = Thread::new(parser, vec![frame]);
+            // The start function is not preempted.
+            let fuel = self.fuel.take();
+            let result = thread.run(self).map(|x| x.forget());
+            self.fuel = fuel;
+            if matches!(result, Ok(RunAnswer::Exception(_))) {
+                return Err(self.trap(TrapReason::UncaughtException, Vec::new()));
+            }
+            assert!(matches!(result?, RunAnswer::Done(x) if x.is_empty()));
+        }
+        Ok(InstId { store_id: self.id, inst_id })
+    }
+
+    /// Instantiates a valid module without running its start function.
+    fn instantiate_without_start(
+        &mut self, module: Module<'m>, memories: impl IntoIterator<Item = &'m mut [u8]>,
+    ) -> Result<usize, Error> {
+        let mut memories = memories.into_iter();
         let inst_id = self.insts.len();
         self.insts.push(Instance::default());
         self.last_inst().module = module;
@@ -128,21 +299,23 @@ impl<'m> Store<'m> {
                 ImportDesc::Table(_) => self.last_inst().tables.ext.push(id),
                 ImportDesc::Mem(_) => self.last_inst().mems.ext.push(id),
                 ImportDesc::Global(_) => self.last_inst().globals.ext.push(id),
+                ImportDesc::Tag(_) => self.last_inst().tags.ext.push(id),
             }
         }
         if let Some(mut parser) = self.last_inst().module.section(SectionId::Table) {
             for _ in 0 .. parser.parse_vec().into_ok() {
-                (self.last_inst().tables.int).push(Table::new(parser.parse_tabletype().into_ok()));
+                let type_ = parser.parse_tabletype().into_ok();
+                let table = Table::new(type_, self.limits.max_table_elems)?;
+                self.last_inst().tables.int.push(table);
             }
         }
         if let Some(mut parser) = self.last_inst().module.section(SectionId::Memory) {
-            match parser.parse_vec().into_ok() {
-                0 => (),
-                1 => {
-                    let limits = parser.parse_memtype().into_ok();
-                    self.last_inst().mems.int.init(memory, limits)?;
-                }
-                _ => unimplemented!(),
+            for _ in 0 .. parser.parse_vec().into_ok() {
+                let limits = parser.parse_memtype().into_ok();
+                let max_pages = self.limits.max_mem_pages;
+                let mut memory = Memory::default();
+                memory.init(memories.next().unwrap_or_default(), limits, max_pages)?;
+                self.last_inst().mems.int.push(memory);
             }
         }
         if let Some(mut parser) = self.last_inst().module.section(SectionId::Global) {
@@ -163,7 +336,7 @@ impl<'m> Store<'m> {
                     ElemMode::Active { table, offset } => {
                         let n = init.len();
                         let table = self.table(inst_id, table);
-                        table_init(offset, 0, n, table, &init)?;
+                        table_init(offset, 0, n, table, &init).map_err(|x| self.trap(x, vec![]))?;
                         true
                     }
                     ElemMode::Declarative => true,
@@ -181,25 +354,15 @@ impl<'m> Store<'m> {
                     DataMode::Active { memory, offset } => {
                         let n = init.len();
                         let memory = self.mem(inst_id, memory);
-                        memory_init(offset, 0, n, memory, init)?;
+                        memory_init(offset, 0, n, memory, init)
+                            .map_err(|x| self.trap(x, vec![]))?;
                         true
                     }
                 };
                 self.last_inst().datas.push(drop);
             }
         }
-        if let Some(mut parser) = self.last_inst().module.section(SectionId::Start) {
-            let x = parser.parse_funcidx().into_ok();
-            let ptr = self.func_ptr(inst_id, x);
-            let inst_id = ptr.instance().unwrap_wasm();
-            let mut parser = self.insts[inst_id].module.func(ptr.index());
-            let mut locals = Vec::new();
-            append_locals(&mut parser, &mut locals);
-            let thread = Thread::new(parser, vec![Frame::new(inst_id, 0, &[], locals)]);
-            let result = thread.run(self)?;
-            assert!(matches!(result, RunResult::Done(x) if x.is_empty()));
-        }
-        Ok(InstId { store_id: self.id, inst_id })
+        Ok(inst_id)
     }
 
     /// Invokes a function in an instance provided its name.
@@ -221,10 +384,14 @@ impl<'m> Store<'m> {
         let x = ptr.index();
         let t = inst.module.func_type(x);
         let mut parser = inst.module.func(x);
+        let stp = inst.module.side_table_start(x);
         check_types(&t.params, &args)?;
         let mut locals = args;
         append_locals(&mut parser, &mut locals);
-        let frame = Frame::new(inst_id, t.results.len(), &[], locals);
+        let mut frame = Frame::new(inst_id, x, t.results.len(), &[], locals, stp);
+        frame.check_limits(self, None)?;
+        #[cfg(feature = "trace")]
+        self.trace_enter(inst_id, x, parser.save());
         Thread::new(parser, vec![frame]).run(self)
     }
 
@@ -256,10 +423,8 @@ impl<'m> Store<'m> {
     pub fn link_func(
         &mut self, module: &'m str, name: &'m str, params: usize, results: usize,
     ) -> Result<(), Error> {
-        static TYPES: &[ValType] = &[ValType::I32; 8];
-        check(params <= TYPES.len() && results <= TYPES.len())?;
-        let type_ = FuncType { params: TYPES[.. params].into(), results: TYPES[.. results].into() };
-        self.link_func_custom(module, name, type_)
+        self.check_linkable()?;
+        self.linker.to_mut().link_func(module, name, params, results)
     }
 
     /// Enables linking unresolved imported function for the given module.
@@ -272,48 +437,103 @@ impl<'m> Store<'m> {
     /// This function can be called at most once, and once called `link_func` cannot be called.
     pub fn link_func_default(&mut self, module: &'m str) -> Result<(), Error> {
         check(self.func_default.is_none())?;
-        self.func_default = Some((module, self.funcs.len()));
+        self.func_default = Some((module, Vec::new()));
         Ok(())
     }
 
     /// Links a host function provided its signature.
     ///
-    /// Note that the order in which functions are linked defines their index. The first linked
-    /// function has index 0, the second has index 1, etc. This index is used when a module calls in
-    /// the host to identify the function.
+    /// See [`Linker::link_func_custom()`] for the order and index of functions. If the store was
+    /// created from a shared linker, the linker is copied before linking.
     pub fn link_func_custom(
         &mut self, module: &'m str, name: &'m str, type_: FuncType<'m>,
+    ) -> Result<(), Error> {
+        self.check_linkable()?;
+        self.linker.to_mut().link_func_custom(module, name, type_)
+    }
+
+    /// Links a host memory.
+    ///
+    /// Modules importing this memory share it with the host without copying. The memory cannot
+    /// grow and its size is the length of the slice rounded up to pages (accesses past the slice
+    /// trap). If `read_only` is set, writes from modules trap (including active data segments).
+    /// The slice must be aligned to [`MEMORY_ALIGN`].
+    pub fn link_mem(
+        &mut self, module: &'m str, name: &'m str, memory: &'m mut [u8], read_only: bool,
     ) -> Result<(), Error> {
         let name = HostName { module, name };
-        check(self.func_default.is_none())?;
-        check(self.insts.is_empty())?;
-        check(self.funcs.last().map_or(true, |x| x.0 < name))?;
-        self.funcs.push((name, type_));
+        check(self.mems.iter().all(|x| x.0 != name))?;
+        self.mems.push((name, Memory::host(memory, read_only)?));
         Ok(())
     }
 
     /// Returns the call in the host, if any.
     ///
-    /// This function returns `None` if nothing is running.
+    /// This function returns `None` if nothing is running or if execution was preempted.
     // NOTE: This is like poll. Could be called next.
     pub fn last_call(&mut self) -> Option<Call<'_, 'm>> {
-        if self.threads.is_empty() {
-            None
-        } else {
-            Some(Call { store: self })
+        match self.threads.last() {
+            Some(Continuation { call: Some(_), .. }) => Some(Call { store: self }),
+            _ => None,
         }
     }
+
+    /// Sets the fuel of the store.
+    ///
+    /// Each executed instruction consumes one unit of fuel. When there is no more fuel, execution
+    /// is preempted (see [`RunResult::Preempted`]). Fuel metering is disabled with `None`, which
+    /// is the default.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    /// Returns the remaining fuel of the store (`None` if fuel metering is disabled).
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    /// Sets the resource limits of the store.
+    ///
+    /// Table and memory limits apply to instances created afterwards. Other limits apply to calls
+    /// made afterwards.
+    pub fn set_limits(&mut self, limits: StoreLimits) {
+        self.limits = limits;
+    }
+
+    /// Returns the resource limits of the store.
+    pub fn limits(&self) -> StoreLimits {
+        self.limits
+    }
+
+    /// Returns whether execution was preempted.
+    pub fn is_preempted(&self) -> bool {
+        matches!(self.threads.last(), Some(Continuation { call: None, .. }))
+    }
+
+    /// Returns information about the last trap, if any.
+    pub fn core_dump(&self) -> Option<&CoreDump<'m>> {
+        self.core_dump.as_ref()
+    }
+
+    /// Resumes execution after preemption.
+    ///
+    /// The fuel should be refilled (see [`Self::set_fuel()`]) before resuming.
+    pub fn resume(&mut self) -> Result<RunResult<'_, 'm>, Error> {
+        check(self.is_preempted())?;
+        let Continuation { thread, .. } = self.threads.pop().unwrap();
+        thread.run(self)
+    }
 }
 
 impl<'a, 'm> Call<'a, 'm> {
     /// Returns the index of the host function being called.
     pub fn index(&self) -> usize {
-        self.cont().index
+        self.host().index
     }
 
     /// Returns the arguments to the host function being called.
     pub fn args(&self) -> &[Val] {
-        &self.cont().args
+        &self.host().args
     }
 
     /// Returns the identifier of the instance calling the host.
@@ -333,15 +553,35 @@ impl<'a, 'm> Call<'a, 'm> {
 
     /// Resumes execution with the results from the host.
     pub fn resume(self, results: &[Val]) -> Result<RunResult<'a, 'm>, Error> {
-        let Continuation { mut thread, arity, .. } = self.store.threads.pop().unwrap();
-        check(results.len() == arity)?;
+        let Continuation { mut thread, call } = self.store.threads.pop().unwrap();
+        let call = call.unwrap();
+        if results.len() != call.arity {
+            #[cfg(feature = "trace")]
+            self.store.trace_exit(thread.frames.len());
+            return Err(invalid());
+        }
         thread.push_values(results);
+        if call.tail {
+            #[cfg(feature = "trace")]
+            self.store.trace_exit(1);
+            thread = match thread.exit_frame() {
+                ThreadResult::Continue(x) => x,
+                ThreadResult::Done(x) => return Ok(RunResult::Done(x)),
+                ThreadResult::Host => unreachable!(),
+                #[cfg(feature = "exception-handling")]
+                ThreadResult::Exception(_) => unreachable!(),
+            };
+        }
         thread.run(self.store)
     }
 
-    fn cont(&self) -> &Continuation {
+    fn cont(&self) -> &Continuation<'m> {
         self.store.threads.last().unwrap()
     }
+
+    fn host(&self) -> &HostCall {
+        self.cont().call.as_ref().unwrap()
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -417,10 +657,12 @@ impl core::fmt::Debug for Val {
             #[cfg(feature = "float-types")]
             Val::F64(x) => write!(f, "{}", f64::from_bits(x)),
             #[cfg(feature = "vector-types")]
-            Val::V128(_) => todo!(),
+            Val::V128(x) => write!(f, "{x:#034x}"),
             Val::Null(_) => write!(f, "null"),
             Val::Ref(p) => write!(f, "ref@{:?}:{}", p.instance(), p.index()),
             Val::RefExtern(p) => write!(f, "ext@{p}"),
+            #[cfg(feature = "exception-handling")]
+            Val::RefExn(p) => write!(f, "exn@{p}"),
         }
     }
 }
@@ -439,6 +681,8 @@ impl Val {
             Val::Null(t) => t.into(),
             Val::Ref(_) => ValType::FuncRef,
             Val::RefExtern(_) => ValType::ExternRef,
+            #[cfg(feature = "exception-handling")]
+            Val::RefExn(_) => ValType::ExnRef,
         }
     }
 }
@@ -450,8 +694,9 @@ struct Instance<'m> {
     module: Module<'m>,
     funcs: Component<()>,
     tables: Component<Vec<Table>>,
-    mems: Component<Memory<'m>>,
+    mems: Component<Vec<Memory<'m>>>,
     globals: Component<Vec<Global>>,
+    tags: Component<()>,
     elems: Vec<bool>, // whether the elem segment is dropped
     datas: Vec<bool>, // whether the data segment is dropped
 }
@@ -470,6 +715,16 @@ pub enum RunResult<'a, 'm> {
 
     /// Execution is calling into the host.
     Host(Call<'a, 'm>),
+
+    /// Execution ran out of fuel.
+    ///
+    /// Execution can be resumed with [`Store::resume()`].
+    Preempted,
+
+    /// Execution threw an exception that was not caught.
+    ///
+    /// This only happens with the `exception-handling` feature.
+    Exception(Exception),
 }
 
 /// Runtime result without host call information.
@@ -477,6 +732,8 @@ pub enum RunResult<'a, 'm> {
 pub enum RunAnswer {
     Done(Vec<Val>),
     Host,
+    Preempted,
+    Exception(Exception),
 }
 
 impl<'a, 'm> RunResult<'a, 'm> {
@@ -484,16 +741,75 @@ impl<'a, 'm> RunResult<'a, 'm> {
         match self {
             RunResult::Done(result) => RunAnswer::Done(result),
             RunResult::Host(_) => RunAnswer::Host,
+            RunResult::Preempted => RunAnswer::Preempted,
+            RunResult::Exception(x) => RunAnswer::Exception(x),
         }
     }
 }
 
+/// Exception that was not caught.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exception {
+    /// Instance defining the tag of the exception.
+    pub inst: InstId,
+
+    /// Index of the tag in its module (imported tags included).
+    pub tag: u32,
+
+    /// Payload of the exception.
+    pub values: Vec<Val>,
+}
+
+/// Information about a trap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreDump<'m> {
+    /// Why execution trapped.
+    pub reason: TrapReason,
+
+    /// Call stack when execution trapped (innermost frame first).
+    pub frames: Vec<FrameInfo<'m>>,
+}
+
+/// Frame of a call stack.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameInfo<'m> {
+    /// Instance of the function.
+    pub inst: InstId,
+
+    /// Index of the function in its module (imported functions included).
+    pub func: FuncIdx,
+
+    /// Offset in the module of the current instruction.
+    ///
+    /// For frames other than the innermost one, this is the instruction after the call.
+    pub offset: usize,
+
+    /// Name of the function from the name section of its module, if any.
+    pub name: Option<&'m str>,
+}
+
 #[derive(Debug)]
 struct Continuation<'m> {
     thread: Thread<'m>,
+    /// Host call of the thread (none if the thread was preempted).
+    call: Option<HostCall>,
+}
+
+/// Exception caught by reference.
+#[cfg(feature = "exception-handling")]
+#[derive(Debug)]
+struct Exn {
+    tag: Ptr,
+    values: Vec<Val>,
+}
+
+#[derive(Debug)]
+struct HostCall {
     index: usize,
     args: Vec<Val>,
     arity: usize,
+    /// Whether the calling frame returns with the results.
+    tail: bool,
 }
 
 impl<'m> Store<'m> {
@@ -506,13 +822,34 @@ impl<'m> Store<'m> {
         Ok(inst.inst_id)
     }
 
+    fn trap(&mut self, reason: TrapReason, frames: Vec<FrameInfo<'m>>) -> Error {
+        self.core_dump = Some(CoreDump { reason, frames });
+        trap()
+    }
+
+    /// Returns information about a frame at a given position.
+    fn frame_info(&self, inst_id: usize, func: FuncIdx, pos: &[u8]) -> FrameInfo<'m> {
+        let module = &self.insts[inst_id].module;
+        let func = self.insts[inst_id].funcs.ext.len() as FuncIdx + func;
+        let inst = InstId { store_id: self.id, inst_id };
+        FrameInfo { inst, func, offset: module.offset(pos), name: module.func_name(func) }
+    }
+
+    fn check_linkable(&self) -> Result<(), Error> {
+        check(self.func_default.is_none())?;
+        check(self.insts.is_empty())
+    }
+
     fn resolve_inst(&self, name: &str) -> Result<usize, Error> {
         self.insts.iter().position(|x| x.name == name).ok_or_else(not_found)
     }
 
     fn func_type(&self, ptr: Ptr) -> FuncType<'m> {
         match ptr.instance() {
-            Side::Host => self.funcs[ptr.index() as usize].1,
+            Side::Host => match ptr.index() as usize {
+                x if x < self.linker.len() => self.linker.funcs[x].1,
+                x => self.func_default.as_ref().unwrap().1[x - self.linker.len()],
+            },
             Side::Wasm(x) => self.insts[x].module.func_type(ptr.index()),
         }
     }
@@ -533,6 +870,14 @@ impl<'m> Store<'m> {
         self.insts[inst_id].globals.ptr(inst_id, x)
     }
 
+    fn tag_ptr(&self, inst_id: usize, x: TagIdx) -> Ptr {
+        self.insts[inst_id].tags.ptr(inst_id, x)
+    }
+
+    fn tag_type(&self, ptr: Ptr) -> FuncType<'m> {
+        self.insts[ptr.instance().unwrap_wasm()].module.tag_type(ptr.index())
+    }
+
     fn table(&mut self, inst_id: usize, x: TableIdx) -> &mut Table {
         let ptr = self.table_ptr(inst_id, x);
         let x = ptr.instance().unwrap_wasm();
@@ -540,10 +885,57 @@ impl<'m> Store<'m> {
     }
 
     fn mem(&mut self, inst_id: usize, x: MemIdx) -> &mut Memory<'m> {
-        let ptr = self.mem_ptr(inst_id, x);
-        let x = ptr.instance().unwrap_wasm();
-        assert_eq!(ptr.index(), 0);
-        &mut self.insts[x].mems.int
+        self.mem_at(self.mem_ptr(inst_id, x))
+    }
+
+    fn mem_at(&mut self, ptr: Ptr) -> &mut Memory<'m> {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => &mut self.mems[x].1,
+            Side::Wasm(i) => &mut self.insts[i].mems.int[x],
+        }
+    }
+
+    fn mem_type(&self, ptr: Ptr) -> MemType {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => {
+                let mem = &self.mems[x].1;
+                Limits { min: mem.size(), max: mem.max() }
+            }
+            Side::Wasm(i) => {
+                let mut t = self.insts[i].module.mem_type(ptr.index());
+                t.min = self.insts[i].mems.int[x].size();
+                t
+            }
+        }
+    }
+
+    fn memory_copy(
+        &mut self, inst_id: usize, x: MemIdx, y: MemIdx, d: usize, s: usize, n: usize,
+    ) -> Result<(), TrapReason> {
+        let dst = self.mem_ptr(inst_id, x);
+        let src = self.mem_ptr(inst_id, y);
+        let src_len = self.mem_at(src).len() as usize;
+        let mem = self.mem_at(dst);
+        mem.check_writable()?;
+        if s.checked_add(n).map_or(true, |x| x > src_len)
+            || d.checked_add(n).map_or(true, |x| x > mem.len() as usize)
+        {
+            return Err(TrapReason::MemoryOutOfBounds);
+        }
+        if dst == src {
+            mem.data.copy_within(s .. s + n, d);
+            return Ok(());
+        }
+        // The memories are distinct but may not be borrowed together.
+        let mut buffer = [0; 64];
+        for i in (0 .. n).step_by(buffer.len()) {
+            let k = core::cmp::min(buffer.len(), n - i);
+            buffer[.. k].copy_from_slice(&self.mem_at(src).data[s + i ..][.. k]);
+            self.mem_at(dst).data[d + i ..][.. k].copy_from_slice(&buffer[.. k]);
+        }
+        Ok(())
     }
 
     fn global(&mut self, inst_id: usize, x: GlobalIdx) -> &mut Global {
@@ -555,27 +947,28 @@ impl<'m> Store<'m> {
     fn resolve(&mut self, import: &Import<'m>, imp_type_: ExternType<'m>) -> Result<Ptr, Error> {
         let host_name = HostName { module: import.module, name: import.name };
         let mut found = None;
-        let funcs_len = match self.func_default {
-            Some((_, x)) => x,
-            None => self.funcs.len(),
-        };
-        if let Ok(x) = self.funcs[.. funcs_len].binary_search_by(|x| x.0.cmp(&host_name)) {
-            found = Some((Ptr::new(Side::Host, x as u32), ExternType::Func(self.funcs[x].1)));
+        if let Some(x) = self.linker.find(&host_name) {
+            let type_ = self.linker.funcs[x].1;
+            found = Some((Ptr::new(Side::Host, x as u32), ExternType::Func(type_)));
+        } else if let Some(x) = self.mems.iter().position(|x| x.0 == host_name) {
+            let ptr = Ptr::new(Side::Host, x as u32);
+            found = Some((ptr, ExternType::Mem(self.mem_type(ptr))));
         } else if matches!(imp_type_, ExternType::Func(x) if *x.results == [ValType::I32])
-            && matches!(self.func_default, Some((x, _)) if x == host_name.module)
+            && matches!(&self.func_default, Some((x, _)) if *x == host_name.module)
         {
             let type_ = match imp_type_ {
                 ExternType::Func(x) => x,
                 _ => unreachable!(),
             };
-            let idx = match self.funcs[funcs_len ..].iter().position(|x| x.1 == type_) {
-                Some(x) => funcs_len + x,
+            let types = &mut self.func_default.as_mut().unwrap().1;
+            let idx = match types.iter().position(|x| *x == type_) {
+                Some(x) => x,
                 None => {
-                    let idx = self.funcs.len();
-                    self.funcs.push((host_name, type_));
-                    idx
+                    types.push(type_);
+                    types.len() - 1
                 }
             };
+            let idx = self.linker.len() + idx;
             found = Some((Ptr::new(Side::Host, idx as u32), ExternType::Func(type_)));
         } else {
             let inst_id = self.resolve_inst(import.module)?;
@@ -601,17 +994,17 @@ impl<'m> Store<'m> {
                         }
                         ExportDesc::Mem(x) => {
                             let ptr = self.mem_ptr(inst_id, x);
-                            let inst = &self.insts[ptr.instance().unwrap_wasm()];
-                            let mut t = inst.module.mem_type(ptr.index());
-                            assert_eq!(ptr.index(), 0);
-                            t.min = inst.mems.int.size();
-                            (ptr, ExternType::Mem(t))
+                            (ptr, ExternType::Mem(self.mem_type(ptr)))
                         }
                         ExportDesc::Global(x) => {
                             let ptr = self.global_ptr(inst_id, x);
                             let inst = &self.insts[ptr.instance().unwrap_wasm()];
                             (ptr, ExternType::Global(inst.module.global_type(ptr.index())))
                         }
+                        ExportDesc::Tag(x) => {
+                            let ptr = self.tag_ptr(inst_id, x);
+                            (ptr, ExternType::Tag(self.tag_type(ptr)))
+                        }
                     });
                     break;
                 }
@@ -724,6 +1117,8 @@ enum ThreadResult<'m> {
     Continue(Thread<'m>),
     Done(Vec<Val>),
     Host,
+    #[cfg(feature = "exception-handling")]
+    Exception(Exception),
 }
 
 impl<'m> Thread<'m> {
@@ -732,7 +1127,8 @@ impl<'m> Thread<'m> {
     }
 
     fn const_expr(store: &mut Store<'m>, inst_id: usize, mut_parser: &mut Parser<'m>) -> Val {
-        let frames = vec![Frame::new(inst_id, 1, &[], Vec::new())];
+        // Constant expressions are not part of a function and don't trap.
+        let frames = vec![Frame::new(inst_id, FuncIdx::MAX, 1, &[], Vec::new(), 0)];
         let parser = mut_parser.clone();
         let mut thread = Thread::new(parser, frames);
         let (parser, results) = loop {
@@ -741,6 +1137,8 @@ impl<'m> Thread<'m> {
                 ThreadResult::Continue(x) => thread = x,
                 ThreadResult::Done(x) => break (p, x),
                 ThreadResult::Host => unreachable!(),
+                #[cfg(feature = "exception-handling")]
+                ThreadResult::Exception(_) => unreachable!(),
             }
         };
         unsafe { mut_parser.restore(parser) };
@@ -752,12 +1150,33 @@ impl<'m> Thread<'m> {
 
     fn run<'a>(mut self, store: &'a mut Store<'m>) -> Result<RunResult<'a, 'm>, Error> {
         loop {
-            // TODO: When trapping, we could return some CoreDump<'m> that contains the Thread<'m>.
-            // This permits to dump the frames.
-            match self.step(store)? {
+            if let Some(fuel) = &mut store.fuel {
+                if *fuel == 0 {
+                    store.threads.push(Continuation { thread: self, call: None });
+                    return Ok(RunResult::Preempted);
+                }
+                *fuel -= 1;
+            }
+            #[cfg(feature = "trace")]
+            let depth = self.frames.len();
+            #[cfg(feature = "trace")]
+            if let Some(hooks) = store.tracer.as_mut().filter(|x| x.instrs) {
+                let offset = store.insts[self.inst_id()].module.offset(self.parser.save());
+                hooks.tracer.instr(offset);
+            }
+            let result = self.step(store);
+            #[cfg(feature = "trace")]
+            store.trace_exit(match &result {
+                Ok(ThreadResult::Continue(x)) => depth.saturating_sub(x.frames.len()),
+                Ok(ThreadResult::Host) => 0,
+                _ => depth,
+            });
+            match result? {
                 ThreadResult::Continue(x) => self = x,
                 ThreadResult::Done(x) => return Ok(RunResult::Done(x)),
                 ThreadResult::Host => return Ok(RunResult::Host(Call { store })),
+                #[cfg(feature = "exception-handling")]
+                ThreadResult::Exception(x) => return Ok(RunResult::Exception(x)),
             }
         }
     }
@@ -767,45 +1186,75 @@ impl<'m> Thread<'m> {
         let saved = self.parser.save();
         let inst_id = self.frame().inst_id;
         let inst = &mut store.insts[inst_id];
+        macro_rules! trap {
+            ($result:expr) => {
+                match $result {
+                    Ok(x) => x,
+                    Err(reason) => return Err(self.trap(store, saved, reason)),
+                }
+            };
+        }
         match self.parser.parse_instr().into_ok() {
-            Unreachable => return Err(trap()),
+            Unreachable => return Err(self.trap(store, saved, TrapReason::Unreachable)),
             Nop => (),
-            Block(b) => self.push_label(self.blocktype(inst, &b), LabelKind::Block),
-            Loop(b) => self.push_label(self.blocktype(inst, &b), LabelKind::Loop(saved)),
-            If(b) => match self.pop_value().unwrap_i32() {
-                0 => {
-                    self.skip_to_else(inst);
-                    self.push_label(self.blocktype(inst, &b), LabelKind::Block);
+            Block(b) => self.push_label(self.blocktype(inst, &b)),
+            Loop(b) => self.push_label(self.blocktype(inst, &b)),
+            If(b) => {
+                let c = self.pop_value().unwrap_i32();
+                self.push_label(self.blocktype(inst, &b));
+                match c {
+                    0 => _ = self.jump(inst, saved),
+                    _ => self.frame().stp += 1,
                 }
-                _ => self.push_label(self.blocktype(inst, &b), LabelKind::If),
-            },
+            }
             Else => {
-                self.skip_to_end(inst, 0);
+                self.jump(inst, saved);
                 return Ok(self.exit_label());
             }
             End => return Ok(self.exit_label()),
-            Br(l) => return Ok(self.pop_label(inst, l)),
-            BrIf(l) => {
-                if self.pop_value().unwrap_i32() != 0 {
-                    return Ok(self.pop_label(inst, l));
-                }
-            }
+            Br(l) => return Ok(self.pop_label(inst, saved, 0, l)),
+            BrIf(l) => match self.pop_value().unwrap_i32() {
+                0 => self.frame().stp += 1,
+                _ => return Ok(self.pop_label(inst, saved, 0, l)),
+            },
             BrTable(ls, ln) => {
-                let i = self.pop_value().unwrap_i32() as usize;
-                return Ok(self.pop_label(inst, ls.get(i).cloned().unwrap_or(ln)));
+                let i = core::cmp::min(self.pop_value().unwrap_i32() as usize, ls.len());
+                return Ok(self.pop_label(inst, saved, i, ls.get(i).cloned().unwrap_or(ln)));
             }
             Return => return Ok(self.exit_frame()),
-            Call(x) => return self.invoke(store, store.func_ptr(inst_id, x)),
+            Call(x) => return self.invoke(store, store.func_ptr(inst_id, x), false),
             CallIndirect(x, y) => {
-                let i = self.pop_value().unwrap_i32();
-                let x = match store.table(inst_id, x).elems.get(i as usize) {
-                    None | Some(Val::Null(_)) => return Err(trap()),
-                    Some(x) => x.unwrap_ref(),
-                };
-                if store.func_type(x) != store.insts[inst_id].module.types()[y as usize] {
-                    return Err(trap());
+                let x = trap!(self.indirect(store, inst_id, x, y));
+                return self.invoke(store, x, false);
+            }
+            #[cfg(feature = "tail-call")]
+            ReturnCall(x) => return self.invoke(store, store.func_ptr(inst_id, x), true),
+            #[cfg(feature = "tail-call")]
+            ReturnCallIndirect(x, y) => {
+                let x = trap!(self.indirect(store, inst_id, x, y));
+                return self.invoke(store, x, true);
+            }
+            #[cfg(feature = "exception-handling")]
+            Throw(x) => {
+                let tag = store.tag_ptr(inst_id, x);
+                let values = self.pop_values(store.tag_type(tag).params.len());
+                return Ok(self.throw(store, tag, values, None));
+            }
+            #[cfg(feature = "exception-handling")]
+            ThrowRef => match self.pop_value() {
+                Val::RefExn(i) => {
+                    let Exn { tag, values } = &store.exns[i];
+                    return Ok(self.throw(store, *tag, values.clone(), Some(i)));
                 }
-                return self.invoke(store, x);
+                _ => return Err(self.trap(store, saved, TrapReason::NullReference)),
+            },
+            #[cfg(feature = "exception-handling")]
+            TryTable(b, catches) => {
+                // The side table has one entry per catch before the entries of the block.
+                let stp = self.frame().stp;
+                self.push_label(self.blocktype(inst, &b));
+                self.label().handler = Some((saved.len(), stp));
+                self.frame().stp += catches.len();
             }
             Drop => drop(self.pop_value()),
             Select(_) => {
@@ -833,31 +1282,82 @@ impl<'m> Thread<'m> {
             GlobalSet(x) => store.global(inst_id, x).value = self.pop_value(),
             TableGet(x) => {
                 let i = self.pop_value().unwrap_i32();
-                let v = *store.table(inst_id, x).elems.get(i as usize).ok_or_else(trap)?;
+                let v = match store.table(inst_id, x).elems.get(i as usize) {
+                    Some(&x) => x,
+                    None => return Err(self.trap(store, saved, TrapReason::TableOutOfBounds)),
+                };
                 self.push_value(v);
             }
             TableSet(x) => {
                 let val = self.pop_value();
                 let i = self.pop_value().unwrap_i32();
-                let v = store.table(inst_id, x).elems.get_mut(i as usize).ok_or_else(trap)?;
-                *v = val;
+                match store.table(inst_id, x).elems.get_mut(i as usize) {
+                    Some(x) => *x = val,
+                    None => return Err(self.trap(store, saved, TrapReason::TableOutOfBounds)),
+                }
+            }
+            ILoad(n, m) => {
+                trap!(self.load(store.mem(inst_id, m.memory), NumType::i(n), n.into(), Sx::U, m))
             }
-            ILoad(n, m) => self.load(store.mem(inst_id, 0), NumType::i(n), n.into(), Sx::U, m)?,
             #[cfg(feature = "float-types")]
-            FLoad(n, m) => self.load(store.mem(inst_id, 0), NumType::f(n), n.into(), Sx::U, m)?,
+            FLoad(n, m) => {
+                trap!(self.load(store.mem(inst_id, m.memory), NumType::f(n), n.into(), Sx::U, m))
+            }
             ILoad_(b, s, m) => {
-                self.load(store.mem(inst_id, 0), NumType::i(b.into()), b.into(), s, m)?
+                trap!(self.load(store.mem(inst_id, m.memory), NumType::i(b.into()), b.into(), s, m))
+            }
+            IStore(n, m) => {
+                trap!(self.store(store.mem(inst_id, m.memory), NumType::i(n), n.into(), m))
             }
-            IStore(n, m) => self.store(store.mem(inst_id, 0), NumType::i(n), n.into(), m)?,
             #[cfg(feature = "float-types")]
-            FStore(n, m) => self.store(store.mem(inst_id, 0), NumType::f(n), n.into(), m)?,
+            FStore(n, m) => {
+                trap!(self.store(store.mem(inst_id, m.memory), NumType::f(n), n.into(), m))
+            }
             IStore_(b, m) => {
-                self.store(store.mem(inst_id, 0), NumType::i(b.into()), b.into(), m)?
+                trap!(self.store(store.mem(inst_id, m.memory), NumType::i(b.into()), b.into(), m))
             }
-            MemorySize => self.push_value(Val::I32(store.mem(inst_id, 0).size())),
-            MemoryGrow => {
+            MemorySize(x) => self.push_value(Val::I32(store.mem(inst_id, x).size())),
+            MemoryGrow(x) => {
                 let n = self.pop_value().unwrap_i32();
-                self.push_value(Val::I32(grow(store.mem(inst_id, 0), n, ())));
+                self.push_value(Val::I32(grow(store.mem(inst_id, x), n, ())));
+            }
+            #[cfg(feature = "threads")]
+            MemoryAtomicNotify(m) => trap!(self.atomic_notify(store.mem(inst_id, m.memory), m)),
+            #[cfg(feature = "threads")]
+            MemoryAtomicWait(n, m) => trap!(self.atomic_wait(store.mem(inst_id, m.memory), n, m)),
+            #[cfg(feature = "threads")]
+            AtomicFence => (),
+            #[cfg(feature = "threads")]
+            IAtomicLoad(n, m) => {
+                trap!(self.atomic_load(store.mem(inst_id, m.memory), n, n.into(), m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicLoad_(b, m) => {
+                trap!(self.atomic_load(store.mem(inst_id, m.memory), b.into(), b.into(), m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicStore(n, m) => {
+                trap!(self.atomic_store(store.mem(inst_id, m.memory), n, n.into(), m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicStore_(b, m) => {
+                trap!(self.atomic_store(store.mem(inst_id, m.memory), b.into(), b.into(), m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicRmw(n, op, m) => {
+                trap!(self.atomic_rmw(store.mem(inst_id, m.memory), n, n.into(), op, m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicRmw_(b, op, m) => {
+                trap!(self.atomic_rmw(store.mem(inst_id, m.memory), b.into(), b.into(), op, m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicCmpxchg(n, m) => {
+                trap!(self.atomic_cmpxchg(store.mem(inst_id, m.memory), n, n.into(), m))
+            }
+            #[cfg(feature = "threads")]
+            IAtomicCmpxchg_(b, m) => {
+                trap!(self.atomic_cmpxchg(store.mem(inst_id, m.memory), b.into(), b.into(), m))
             }
             I32Const(c) => self.push_value(Val::I32(c)),
             I64Const(c) => self.push_value(Val::I64(c)),
@@ -869,13 +1369,13 @@ impl<'m> Thread<'m> {
             IRelOp(n, op) => self.irelop(n, op),
             #[cfg(feature = "float-types")]
             FRelOp(n, op) => self.frelop(n, op),
-            IUnOp(n, op) => self.iunop(n, op)?,
+            IUnOp(n, op) => trap!(self.iunop(n, op)),
             #[cfg(feature = "float-types")]
             FUnOp(n, op) => self.funop(n, op),
-            IBinOp(n, op) => self.ibinop(n, op)?,
+            IBinOp(n, op) => trap!(self.ibinop(n, op)),
             #[cfg(feature = "float-types")]
             FBinOp(n, op) => self.fbinop(n, op),
-            CvtOp(op) => self.cvtop(op)?,
+            CvtOp(op) => trap!(self.cvtop(op)),
             IExtend(b) => self.extend(b),
             RefNull(t) => self.push_value(Val::Null(t)),
             RefIsNull => {
@@ -883,7 +1383,7 @@ impl<'m> Thread<'m> {
                 self.push_value(Val::I32(c));
             }
             RefFunc(x) => self.push_value(Val::Ref(store.func_ptr(inst_id, x))),
-            MemoryInit(x) => {
+            MemoryInit(x, y) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let s = self.pop_value().unwrap_i32() as usize;
                 let d = self.pop_value().unwrap_i32() as usize;
@@ -895,28 +1395,25 @@ impl<'m> Thread<'m> {
                     parser.parse_data(&mut data).into_ok();
                     data.init
                 };
-                let mem = store.mem(inst_id, 0);
-                memory_init(d, s, n, mem, data)?;
+                let mem = store.mem(inst_id, y);
+                trap!(memory_init(d, s, n, mem, data));
             }
             DataDrop(x) => inst.datas[x as usize] = true,
-            MemoryCopy => {
+            MemoryCopy(x, y) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let s = self.pop_value().unwrap_i32() as usize;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let mem = store.mem(inst_id, 0);
-                if core::cmp::max(s, d).checked_add(n).map_or(true, |x| x > mem.len() as usize) {
-                    return Err(trap());
-                }
-                mem.data.copy_within(s .. s + n, d);
+                trap!(store.memory_copy(inst_id, x, y, d, s, n));
             }
-            MemoryFill => {
+            MemoryFill(x) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let val = self.pop_value().unwrap_i32() as u8;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let mem = store.mem(inst_id, 0);
+                let mem = store.mem(inst_id, x);
+                trap!(mem.check_writable());
                 if d.checked_add(n).map_or(true, |x| x > mem.len() as usize) {
                     memory_too_small(d, n, mem);
-                    return Err(trap());
+                    return Err(self.trap(store, saved, TrapReason::MemoryOutOfBounds));
                 }
                 mem.data[d ..][.. n].fill(val);
             }
@@ -933,18 +1430,19 @@ impl<'m> Thread<'m> {
                     elems.init
                 };
                 let table = store.table(inst_id, x);
-                table_init(d, s, n, table, &elems)?;
+                trap!(table_init(d, s, n, table, &elems));
             }
             ElemDrop(x) => inst.elems[x as usize] = true,
             TableCopy(x, y) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let s = self.pop_value().unwrap_i32() as usize;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let sn = s.checked_add(n).ok_or_else(trap)?;
-                let dn = d.checked_add(n).ok_or_else(trap)?;
+                let oob = TrapReason::TableOutOfBounds;
+                let sn = trap!(s.checked_add(n).ok_or(oob));
+                let dn = trap!(d.checked_add(n).ok_or(oob));
                 // TODO: This is not efficient.
-                let ys = store.table(inst_id, y).elems.get(s .. sn).ok_or_else(trap)?.to_vec();
-                let xs = store.table(inst_id, x).elems.get_mut(d .. dn).ok_or_else(trap)?;
+                let ys = trap!(store.table(inst_id, y).elems.get(s .. sn).ok_or(oob)).to_vec();
+                let xs = trap!(store.table(inst_id, x).elems.get_mut(d .. dn).ok_or(oob));
                 xs.copy_from_slice(&ys);
             }
             TableGrow(x) => {
@@ -960,10 +1458,134 @@ impl<'m> Thread<'m> {
                 let i = self.pop_value().unwrap_i32() as usize;
                 let table = store.table(inst_id, x);
                 if i.checked_add(n).map_or(true, |x| x > table.elems.len()) {
-                    return Err(trap());
+                    return Err(self.trap(store, saved, TrapReason::TableOutOfBounds));
                 }
                 table.elems[i ..][.. n].fill(val);
             }
+            #[cfg(feature = "vector-types")]
+            V128Load(m) => {
+                let x = trap!(self.vload(store.mem(inst_id, m.memory), m, 16));
+                self.push_value(Val::V128(x));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadExtend(l, s, m) => {
+                let x = trap!(self.vload(store.mem(inst_id, m.memory), m, 8));
+                self.push_value(Val::V128(l.double().extend(Half::Low, s, x)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadSplat(l, m) => {
+                let x = trap!(self.vload(store.mem(inst_id, m.memory), m, l.bits() as usize / 8));
+                self.push_value(Val::V128(l.splat(x as i128)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadZero(l, m) => {
+                let x = trap!(self.vload(store.mem(inst_id, m.memory), m, l.bits() as usize / 8));
+                self.push_value(Val::V128(x));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadLane(l, m, i) => {
+                let v = self.pop_value().unwrap_v128();
+                let x = trap!(self.vload(store.mem(inst_id, m.memory), m, l.bits() as usize / 8));
+                self.push_value(Val::V128(l.set(v, i, x as i128)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128Store(m) => {
+                let x = self.pop_value().unwrap_v128();
+                trap!(self.vstore(store.mem(inst_id, m.memory), m, 16, x));
+            }
+            #[cfg(feature = "vector-types")]
+            V128StoreLane(l, m, i) => {
+                let x = l.get(self.pop_value().unwrap_v128(), i, Sx::U) as u128;
+                trap!(self.vstore(store.mem(inst_id, m.memory), m, l.bits() as usize / 8, x));
+            }
+            #[cfg(feature = "vector-types")]
+            V128Const(c) => self.push_value(Val::V128(c)),
+            #[cfg(feature = "vector-types")]
+            I8x16Shuffle(xs) => self.vbinop(|x, y| {
+                Lx::L8.build(|i| match xs[i as usize] {
+                    j @ 0 .. 16 => Lx::L8.get(x, j, Sx::U),
+                    j => Lx::L8.get(y, j - 16, Sx::U),
+                })
+            }),
+            #[cfg(feature = "vector-types")]
+            I8x16Swizzle => self.vbinop(|x, y| {
+                Lx::L8.build(|i| match Lx::L8.get(y, i, Sx::U) {
+                    j @ 0 .. 16 => Lx::L8.get(x, j as u8, Sx::U),
+                    _ => 0,
+                })
+            }),
+            #[cfg(feature = "vector-types")]
+            VSplat(s) => {
+                let x = self.pop_value().unwrap_lane();
+                self.push_value(Val::V128(s.lx().splat(x)));
+            }
+            #[cfg(feature = "vector-types")]
+            VExtractLane(s, sx, i) => {
+                let x = self.pop_value().unwrap_v128();
+                self.push_value(Val::lane(s, s.lx().get(x, i, sx)));
+            }
+            #[cfg(feature = "vector-types")]
+            VReplaceLane(s, i) => {
+                let y = self.pop_value().unwrap_lane();
+                let x = self.pop_value().unwrap_v128();
+                self.push_value(Val::V128(s.lx().set(x, i, y)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128Not => self.vunop(|x| !x),
+            #[cfg(feature = "vector-types")]
+            VvBinOp(op) => self.vbinop(|x, y| op.v128(x, y)),
+            #[cfg(feature = "vector-types")]
+            V128Bitselect => {
+                let c = self.pop_value().unwrap_v128();
+                self.vbinop(|x, y| (x & c) | (y & !c));
+            }
+            #[cfg(feature = "vector-types")]
+            V128AnyTrue => {
+                let x = self.pop_value().unwrap_v128();
+                self.push_value(Val::I32((x != 0) as u32));
+            }
+            #[cfg(feature = "vector-types")]
+            VIRelOp(l, op) => self.vbinop(|x, y| op.v128(l, x, y)),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFRelOp(n, op) => self.vbinop(|x, y| op.v128(n, x, y)),
+            #[cfg(feature = "vector-types")]
+            VIUnOp(l, op) => self.vunop(|x| op.v128(l, x)),
+            #[cfg(feature = "vector-types")]
+            VIBinOp(l, op) => self.vbinop(|x, y| op.v128(l, x, y)),
+            #[cfg(feature = "vector-types")]
+            VIShiftOp(l, op) => {
+                let y = self.pop_value().unwrap_i32();
+                self.vunop(|x| op.v128(l, x, y));
+            }
+            #[cfg(feature = "vector-types")]
+            VIAllTrue(l) => {
+                let x = self.pop_value().unwrap_v128();
+                self.push_value(Val::I32(l.all_true(x) as u32));
+            }
+            #[cfg(feature = "vector-types")]
+            VIBitmask(l) => {
+                let x = self.pop_value().unwrap_v128();
+                self.push_value(Val::I32(l.bitmask(x)));
+            }
+            #[cfg(feature = "vector-types")]
+            VINarrow(l, s) => self.vbinop(|x, y| l.narrow(s, x, y)),
+            #[cfg(feature = "vector-types")]
+            VIExtend(l, h, s) => self.vunop(|x| l.extend(h, s, x)),
+            #[cfg(feature = "vector-types")]
+            VIExtMul(l, h, s) => self.vbinop(|x, y| l.ext_mul(h, s, x, y)),
+            #[cfg(feature = "vector-types")]
+            VIExtAddPairwise(l, s) => self.vunop(|x| l.ext_add_pairwise(s, x)),
+            #[cfg(feature = "vector-types")]
+            VIDot => self.vbinop(|x, y| {
+                let mul = |i| Lx::L16.get(x, i, Sx::S) * Lx::L16.get(y, i, Sx::S);
+                Lx::L32.build(|i| mul(2 * i) + mul(2 * i + 1))
+            }),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFUnOp(n, op) => self.vunop(|x| op.v128(n, x)),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFBinOp(n, op) => self.vbinop(|x, y| op.v128(n, x, y)),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VCvtOp(op) => self.vunop(|x| op.v128(x)),
         }
         Ok(ThreadResult::Continue(self))
     }
@@ -972,6 +1594,16 @@ impl<'m> Thread<'m> {
         self.frames.last().unwrap().inst_id
     }
 
+    /// Records the core dump of a trap at a given position.
+    fn trap(&self, store: &mut Store<'m>, mut pos: &'m [u8], reason: TrapReason) -> Error {
+        let mut frames = Vec::new();
+        for frame in self.frames.iter().rev() {
+            frames.push(store.frame_info(frame.inst_id, frame.func, pos));
+            pos = frame.ret;
+        }
+        store.trap(reason, frames)
+    }
+
     fn inst(&self, store: &Store<'m>) -> InstId {
         InstId { store_id: store.id, inst_id: self.inst_id() }
     }
@@ -980,11 +1612,11 @@ impl<'m> Thread<'m> {
         self.frames.last_mut().unwrap()
     }
 
-    fn labels(&mut self) -> &mut Vec<Label<'m>> {
+    fn labels(&mut self) -> &mut Vec<Label> {
         &mut self.frame().labels
     }
 
-    fn label(&mut self) -> &mut Label<'m> {
+    fn label(&mut self) -> &mut Label {
         self.labels().last_mut().unwrap()
     }
 
@@ -1000,12 +1632,14 @@ impl<'m> Thread<'m> {
         self.values().push(value);
     }
 
-    fn push_value_or_trap(&mut self, value: Option<Val>) -> Result<(), Error> {
+    fn push_value_or_trap(
+        &mut self, value: Option<Val>, reason: impl FnOnce() -> TrapReason,
+    ) -> Result<(), TrapReason> {
         if let Some(x) = value {
             self.push_value(x);
             Ok(())
         } else {
-            Err(trap())
+            Err(reason())
         }
     }
 
@@ -1026,32 +1660,44 @@ impl<'m> Thread<'m> {
         values
     }
 
-    fn push_label(&mut self, type_: FuncType<'m>, kind: LabelKind<'m>) {
+    fn push_label(&mut self, type_: FuncType<'m>) {
         let values = self.pop_values(type_.params.len());
-        let arity = match kind {
-            LabelKind::Block | LabelKind::If => type_.results.len(),
-            LabelKind::Loop(_) => type_.params.len(),
-        };
-        let label = Label { arity, kind, values };
-        self.labels().push(label);
+        self.labels().push(Label::new(values));
     }
 
-    fn pop_label(mut self, inst: &mut Instance<'m>, l: LabelIdx) -> ThreadResult<'m> {
+    /// Branches to a label using the side table entry at offset `k` from the current one.
+    fn pop_label(
+        mut self, inst: &Instance<'m>, saved: &'m [u8], k: usize, l: LabelIdx,
+    ) -> ThreadResult<'m> {
         let i = self.labels().len() - l as usize - 1;
         if i == 0 {
             return self.exit_frame();
         }
+        self.frame().stp += k;
+        let arity = self.jump(inst, saved).val_cnt as usize;
         let values = core::mem::take(self.values());
-        let frame = self.frame();
-        let Label { arity, kind, .. } = frame.labels.drain(i ..).next().unwrap();
+        self.frame().labels.truncate(i);
         self.values().extend_from_slice(&values[values.len() - arity ..]);
-        match kind {
-            LabelKind::Loop(pos) => unsafe { self.parser.restore(pos) },
-            LabelKind::Block | LabelKind::If => self.skip_to_end(inst, l),
-        }
         ThreadResult::Continue(self)
     }
 
+    /// Jumps to the target of the current side table entry.
+    ///
+    /// The instruction at `saved` must be the branch site of the entry.
+    fn jump(&mut self, inst: &Instance<'m>, saved: &'m [u8]) -> SideTableEntry {
+        let stp = self.frame().stp;
+        let entry = inst.module.side_table_entry(stp);
+        let delta_ip = entry.delta_ip as isize;
+        // SAFETY: The side table only points within the function, which ends with the parser.
+        unsafe {
+            let ptr = saved.as_ptr().offset(delta_ip);
+            let len = (saved.len() as isize - delta_ip) as usize;
+            self.parser.restore(core::slice::from_raw_parts(ptr, len));
+        }
+        self.frame().stp = (stp as isize + entry.delta_stp as isize) as usize;
+        entry
+    }
+
     fn exit_label(mut self) -> ThreadResult<'m> {
         let frame = self.frame();
         let label = frame.labels.pop().unwrap();
@@ -1080,19 +1726,62 @@ impl<'m> Thread<'m> {
         ThreadResult::Continue(self)
     }
 
-    fn skip_to_else(&mut self, inst: &mut Instance<'m>) {
-        inst.module.skip_to_else(&mut self.parser);
-    }
-
-    fn skip_to_end(&mut self, inst: &mut Instance<'m>, l: LabelIdx) {
-        inst.module.skip_to_end(&mut self.parser, l);
+    /// Unwinds the stack to the innermost handler catching an exception.
+    ///
+    /// Rethrown exceptions keep their exception reference.
+    #[cfg(feature = "exception-handling")]
+    fn throw(
+        mut self, store: &mut Store<'m>, tag: Ptr, values: Vec<Val>, exn: Option<usize>,
+    ) -> ThreadResult<'m> {
+        // Position in the function of the innermost frame not yet unwound.
+        let mut pos = self.parser.save();
+        while let Some(frame) = self.frames.last() {
+            let inst_id = frame.inst_id;
+            for i in (1 .. frame.labels.len()).rev() {
+                let Some((len, stp)) = self.frames.last().unwrap().labels[i].handler else {
+                    continue;
+                };
+                // SAFETY: Handlers are before the current position in the same function.
+                let saved = unsafe {
+                    core::slice::from_raw_parts(pos.as_ptr().add(pos.len()).sub(len), len)
+                };
+                let catches = match unsafe { Parser::new(saved) }.parse_instr().into_ok() {
+                    Instr::TryTable(_, catches) => catches,
+                    _ => unreachable!(),
+                };
+                for (k, catch) in catches.into_iter().enumerate() {
+                    if catch.tag.is_some_and(|x| store.tag_ptr(inst_id, x) != tag) {
+                        continue;
+                    }
+                    let frame = self.frame();
+                    frame.labels.truncate(i);
+                    frame.stp = stp + k;
+                    if catch.tag.is_some() {
+                        self.push_values(&values);
+                    }
+                    if catch.is_ref {
+                        let exn = exn.unwrap_or_else(|| {
+                            store.exns.push(Exn { tag, values });
+                            store.exns.len() - 1
+                        });
+                        self.push_value(Val::RefExn(exn));
+                    }
+                    return self.pop_label(&store.insts[inst_id], saved, 0, catch.label);
+                }
+            }
+            pos = self.frames.pop().unwrap().ret;
+        }
+        let inst_id = tag.instance().unwrap_wasm();
+        let inst = InstId { store_id: store.id, inst_id };
+        let tag = store.insts[inst_id].tags.ext.len() as u32 + tag.index();
+        ThreadResult::Exception(Exception { inst, tag, values })
     }
 
     fn blocktype(&self, inst: &Instance<'m>, b: &BlockType) -> FuncType<'m> {
         match *b {
             BlockType::None => FuncType { params: ().into(), results: ().into() },
             BlockType::Type(t) => FuncType { params: ().into(), results: t.into() },
-            BlockType::Index(x) => inst.module.types()[x as usize],
+            BlockType::Index(x) => inst.module.type_(x),
         }
     }
 
@@ -1109,10 +1798,10 @@ impl<'m> Thread<'m> {
 
     fn load(
         &mut self, mem: &mut Memory<'m>, t: NumType, n: usize, s: Sx, m: MemArg,
-    ) -> Result<(), Error> {
+    ) -> Result<(), TrapReason> {
         let i = self.pop_value().unwrap_i32();
         let mem = match self.mem_slice(mem, m, i, n / 8) {
-            None => return Err(trap()),
+            None => return Err(TrapReason::MemoryOutOfBounds),
             Some(x) => x,
         };
         macro_rules! convert {
@@ -1145,11 +1834,12 @@ impl<'m> Thread<'m> {
 
     fn store(
         &mut self, mem: &mut Memory<'m>, t: NumType, n: usize, m: MemArg,
-    ) -> Result<(), Error> {
+    ) -> Result<(), TrapReason> {
         let c = self.pop_value();
         let i = self.pop_value().unwrap_i32();
+        mem.check_writable()?;
         let mem = match self.mem_slice(mem, m, i, n / 8) {
-            None => return Err(trap()),
+            None => return Err(TrapReason::MemoryOutOfBounds),
             Some(x) => x,
         };
         macro_rules! convert {
@@ -1176,6 +1866,39 @@ impl<'m> Thread<'m> {
         Ok(())
     }
 
+    #[cfg(feature = "vector-types")]
+    fn vload(&mut self, mem: &mut Memory<'m>, m: MemArg, n: usize) -> Result<u128, TrapReason> {
+        let i = self.pop_value().unwrap_i32();
+        let mem = self.mem_slice(mem, m, i, n).ok_or(TrapReason::MemoryOutOfBounds)?;
+        let mut x = [0; 16];
+        x[.. n].copy_from_slice(mem);
+        Ok(u128::from_le_bytes(x))
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vstore(
+        &mut self, mem: &mut Memory<'m>, m: MemArg, n: usize, x: u128,
+    ) -> Result<(), TrapReason> {
+        let i = self.pop_value().unwrap_i32();
+        mem.check_writable()?;
+        let mem = self.mem_slice(mem, m, i, n).ok_or(TrapReason::MemoryOutOfBounds)?;
+        mem.copy_from_slice(&x.to_le_bytes()[.. n]);
+        Ok(())
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vunop(&mut self, op: impl FnOnce(u128) -> u128) {
+        let x = self.pop_value().unwrap_v128();
+        self.push_value(Val::V128(op(x)));
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vbinop(&mut self, op: impl FnOnce(u128, u128) -> u128) {
+        let y = self.pop_value().unwrap_v128();
+        let x = self.pop_value().unwrap_v128();
+        self.push_value(Val::V128(op(x, y)));
+    }
+
     fn itestop(&mut self, n: Nx, op: ITestOp) {
         let x = self.pop_value();
         let z = match n {
@@ -1195,7 +1918,7 @@ impl<'m> Thread<'m> {
         self.push_value(Val::I32(z as u32))
     }
 
-    fn iunop(&mut self, n: Nx, op: IUnOp) -> Result<(), Error> {
+    fn iunop(&mut self, n: Nx, op: IUnOp) -> Result<(), TrapReason> {
         let x = self.pop_value();
         let z = try {
             match n {
@@ -1203,10 +1926,10 @@ impl<'m> Thread<'m> {
                 Nx::N64 => Val::I64(op.n64(x.unwrap_i64())?),
             }
         };
-        self.push_value_or_trap(z)
+        self.push_value_or_trap(z, || TrapReason::IntegerOverflow)
     }
 
-    fn ibinop(&mut self, n: Nx, op: IBinOp) -> Result<(), Error> {
+    fn ibinop(&mut self, n: Nx, op: IBinOp) -> Result<(), TrapReason> {
         let y = self.pop_value();
         let x = self.pop_value();
         let z = try {
@@ -1215,7 +1938,10 @@ impl<'m> Thread<'m> {
                 Nx::N64 => Val::I64(op.n64(x.unwrap_i64(), y.unwrap_i64())?),
             }
         };
-        self.push_value_or_trap(z)
+        self.push_value_or_trap(z, || match y {
+            Val::I32(0) | Val::I64(0) => TrapReason::DivisionByZero,
+            _ => TrapReason::IntegerOverflow,
+        })
     }
 
     #[cfg(feature = "float-types")]
@@ -1250,7 +1976,7 @@ impl<'m> Thread<'m> {
         self.push_value(z);
     }
 
-    fn cvtop(&mut self, op: CvtOp) -> Result<(), Error> {
+    fn cvtop(&mut self, op: CvtOp) -> Result<(), TrapReason> {
         #[cfg(feature = "float-types")]
         macro_rules! trunc {
             ($x:expr, $n:tt, $m:tt, $s:tt) => {{
@@ -1328,7 +2054,117 @@ impl<'m> Thread<'m> {
                 },
             }
         };
-        self.push_value_or_trap(z)
+        self.push_value_or_trap(z, || match x {
+            #[cfg(feature = "float-types")]
+            Val::F32(x) if f32::from_bits(x).is_nan() => TrapReason::InvalidConversion,
+            #[cfg(feature = "float-types")]
+            Val::F64(x) if f64::from_bits(x).is_nan() => TrapReason::InvalidConversion,
+            _ => TrapReason::IntegerOverflow,
+        })
+    }
+
+    /// Accesses memory atomically and returns the value read (zero-extended).
+    ///
+    /// Pops the address and writes the value returned by the update function, if any. Execution is
+    /// single-threaded, so atomicity is trivial and only alignment is checked.
+    #[cfg(feature = "threads")]
+    fn atomic(
+        &mut self, mem: &mut Memory<'m>, b: usize, m: MemArg,
+        update: impl FnOnce(u64) -> Option<u64>,
+    ) -> Result<u64, TrapReason> {
+        let i = self.pop_value().unwrap_i32();
+        let writable = mem.check_writable();
+        let mem = match self.mem_slice(mem, m, i, b / 8) {
+            None => return Err(TrapReason::MemoryOutOfBounds),
+            Some(x) => x,
+        };
+        if i.wrapping_add(m.offset) as usize % (b / 8) != 0 {
+            return Err(TrapReason::UnalignedAtomic);
+        }
+        let mut bytes = [0; 8];
+        bytes[.. b / 8].copy_from_slice(mem);
+        let x = u64::from_le_bytes(bytes);
+        if let Some(y) = update(x) {
+            writable?;
+            mem.copy_from_slice(&y.to_le_bytes()[.. b / 8]);
+        }
+        Ok(x)
+    }
+
+    /// Pops an atomic operand truncated to the width of the access.
+    #[cfg(feature = "threads")]
+    fn pop_atomic(&mut self, n: Nx, b: usize) -> u64 {
+        let x = match n {
+            Nx::N32 => self.pop_value().unwrap_i32() as u64,
+            Nx::N64 => self.pop_value().unwrap_i64(),
+        };
+        x & (u64::MAX >> (64 - b))
+    }
+
+    #[cfg(feature = "threads")]
+    fn push_atomic(&mut self, n: Nx, x: u64) {
+        self.push_value(match n {
+            Nx::N32 => Val::I32(x as u32),
+            Nx::N64 => Val::I64(x),
+        })
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_load(
+        &mut self, mem: &mut Memory<'m>, n: Nx, b: usize, m: MemArg,
+    ) -> Result<(), TrapReason> {
+        let x = self.atomic(mem, b, m, |_| None)?;
+        self.push_atomic(n, x);
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_store(
+        &mut self, mem: &mut Memory<'m>, n: Nx, b: usize, m: MemArg,
+    ) -> Result<(), TrapReason> {
+        let c = self.pop_atomic(n, b);
+        self.atomic(mem, b, m, |_| Some(c))?;
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_rmw(
+        &mut self, mem: &mut Memory<'m>, n: Nx, b: usize, op: AtomicRmwOp, m: MemArg,
+    ) -> Result<(), TrapReason> {
+        let c = self.pop_atomic(n, b);
+        let x = self.atomic(mem, b, m, |x| Some(op.apply(x, c)))?;
+        self.push_atomic(n, x);
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_cmpxchg(
+        &mut self, mem: &mut Memory<'m>, n: Nx, b: usize, m: MemArg,
+    ) -> Result<(), TrapReason> {
+        let r = self.pop_atomic(n, b);
+        let e = self.pop_atomic(n, b);
+        let x = self.atomic(mem, b, m, |x| (x == e).then_some(r))?;
+        self.push_atomic(n, x);
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_wait(&mut self, mem: &mut Memory<'m>, n: Nx, m: MemArg) -> Result<(), TrapReason> {
+        let _timeout = self.pop_value();
+        let e = self.pop_atomic(n, n.into());
+        let x = self.atomic(mem, n.into(), m, |_| None)?;
+        // Returns "not-equal" or "timed-out" since no other thread could notify.
+        self.push_value(Val::I32(if x == e { 2 } else { 1 }));
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_notify(&mut self, mem: &mut Memory<'m>, m: MemArg) -> Result<(), TrapReason> {
+        let _count = self.pop_value();
+        self.atomic(mem, 32, m, |_| None)?;
+        // Returns the number of woken threads, which is none since no other thread could wait.
+        self.push_value(Val::I32(0));
+        Ok(())
     }
 
     fn extend(&mut self, n: Bx) {
@@ -1343,51 +2179,87 @@ impl<'m> Thread<'m> {
         self.push_value(z)
     }
 
-    fn invoke(mut self, store: &mut Store<'m>, ptr: Ptr) -> Result<ThreadResult<'m>, Error> {
-        // TODO: This should be based on actual size in RAM.
-        const MAX_FRAMES: usize = 1000;
-        if self.frames.len() >= MAX_FRAMES {
-            return Err(trap());
+    /// Returns the function of a table element for an indirect call.
+    fn indirect(
+        &mut self, store: &mut Store<'m>, inst_id: usize, x: TableIdx, y: TypeIdx,
+    ) -> Result<Ptr, TrapReason> {
+        let i = self.pop_value().unwrap_i32();
+        let x = match store.table(inst_id, x).elems.get(i as usize) {
+            None => return Err(TrapReason::TableOutOfBounds),
+            Some(Val::Null(_)) => return Err(TrapReason::UninitializedElement),
+            Some(x) => x.unwrap_ref(),
+        };
+        if store.func_type(x) != store.insts[inst_id].module.type_(y) {
+            return Err(TrapReason::IndirectCallMismatch);
+        }
+        Ok(x)
+    }
+
+    /// Calls a function.
+    ///
+    /// Tail calls replace the current frame, such that tail recursion runs in constant stack.
+    fn invoke(
+        mut self, store: &mut Store<'m>, ptr: Ptr, tail: bool,
+    ) -> Result<ThreadResult<'m>, Error> {
+        if !tail && self.frames.len() >= store.limits.max_frames {
+            return Err(limit_exceeded(StoreLimit::Frames));
         }
         let t = store.func_type(ptr);
         let inst_id = match ptr.instance() {
             Side::Host => {
                 let index = ptr.index() as usize;
-                let t = store.funcs[index].1;
                 let arity = t.results.len();
                 let args = self.pop_values(t.params.len());
-                store.threads.push(Continuation { thread: self, arity, index, args });
+                // Host tail calls keep the frame and return from it when resumed.
+                let call = Some(HostCall { index, args, arity, tail });
+                store.threads.push(Continuation { thread: self, call });
                 return Ok(ThreadResult::Host);
             }
             Side::Wasm(x) => x,
         };
-        let mut parser = store.insts[inst_id].module.func(ptr.index());
+        let module = &store.insts[inst_id].module;
+        let mut parser = module.func(ptr.index());
+        let stp = module.side_table_start(ptr.index());
         let mut locals = self.pop_values(t.params.len());
         append_locals(&mut parser, &mut locals);
-        let ret = self.parser.save();
+        let ret = match tail {
+            false => self.parser.save(),
+            true => self.frames.pop().unwrap().ret,
+        };
+        let mut frame = Frame::new(inst_id, ptr.index(), t.results.len(), ret, locals, stp);
+        frame.check_limits(store, self.frames.last())?;
+        #[cfg(feature = "trace")]
+        store.trace_exit(tail as usize);
+        #[cfg(feature = "trace")]
+        store.trace_enter(inst_id, ptr.index(), parser.save());
         self.parser = parser;
-        self.frames.push(Frame::new(inst_id, t.results.len(), ret, locals));
+        self.frames.push(frame);
         Ok(ThreadResult::Continue(self))
     }
 }
 
-fn table_init(d: usize, s: usize, n: usize, table: &mut Table, elems: &[Val]) -> Result<(), Error> {
+fn table_init(
+    d: usize, s: usize, n: usize, table: &mut Table, elems: &[Val],
+) -> Result<(), TrapReason> {
     if s.checked_add(n).map_or(true, |x| x > elems.len())
         || d.checked_add(n).map_or(true, |x| x > table.elems.len())
     {
-        Err(trap())
+        Err(TrapReason::TableOutOfBounds)
     } else {
         table.elems[d ..][.. n].copy_from_slice(&elems[s ..][.. n]);
         Ok(())
     }
 }
 
-fn memory_init(d: usize, s: usize, n: usize, mem: &mut Memory, data: &[u8]) -> Result<(), Error> {
+fn memory_init(
+    d: usize, s: usize, n: usize, mem: &mut Memory, data: &[u8],
+) -> Result<(), TrapReason> {
+    mem.check_writable()?;
     if s.checked_add(n).map_or(true, |x| x > data.len())
         || d.checked_add(n).map_or(true, |x| x > mem.len() as usize)
     {
         memory_too_small(d, n, mem);
-        Err(trap())
+        Err(TrapReason::MemoryOutOfBounds)
     } else {
         mem.data[d ..][.. n].copy_from_slice(&data[s ..][.. n]);
         Ok(())
@@ -1397,43 +2269,68 @@ fn memory_init(d: usize, s: usize, n: usize, mem: &mut Memory, data: &[u8]) -> R
 #[derive(Debug)]
 struct Frame<'m> {
     inst_id: usize,
+    func: FuncIdx, // index without imported functions
     arity: usize,
     ret: &'m [u8],
     locals: Vec<Val>,
-    labels: Vec<Label<'m>>,
+    labels: Vec<Label>,
+    /// Index of the next side table entry.
+    stp: usize,
+    /// Stack bytes accounted for this frame and the frames below.
+    stack_bytes: usize,
 }
 
 impl<'m> Frame<'m> {
-    fn new(inst_id: usize, arity: usize, ret: &'m [u8], locals: Vec<Val>) -> Self {
-        let label = Label { arity, kind: LabelKind::Block, values: vec![] };
-        Frame { inst_id, arity, ret, locals, labels: vec![label] }
+    fn new(
+        inst_id: usize, func: FuncIdx, arity: usize, ret: &'m [u8], locals: Vec<Val>, stp: usize,
+    ) -> Self {
+        let label = Label::new(vec![]);
+        Frame { inst_id, func, arity, ret, locals, labels: vec![label], stp, stack_bytes: 0 }
+    }
+
+    /// Accounts for the stack usage of this frame on top of the given one.
+    fn check_limits(&mut self, store: &Store<'m>, below: Option<&Frame<'m>>) -> Result<(), Error> {
+        if store.limits.max_locals < self.locals.len() {
+            return Err(limit_exceeded(StoreLimit::Locals));
+        }
+        let max_stack = store.insts[self.inst_id].module.max_stack(self.func);
+        let bytes = (self.locals.len() + max_stack) * core::mem::size_of::<Val>();
+        self.stack_bytes = below.map_or(0, |x| x.stack_bytes).saturating_add(bytes);
+        if store.limits.max_stack_bytes < self.stack_bytes {
+            return Err(limit_exceeded(StoreLimit::StackBytes));
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
-struct Label<'m> {
-    arity: usize,
-    kind: LabelKind<'m>,
+struct Label {
     values: Vec<Val>,
+    /// Position (as the number of bytes until the end of the function) and side table pointer of
+    /// the `try_table` instruction of this label, if any.
+    #[cfg(feature = "exception-handling")]
+    handler: Option<(usize, usize)>,
 }
 
-#[derive(Debug)]
-enum LabelKind<'m> {
-    // TODO: If and Block can be merged and then we just have Option<NonNull<u8>> which is
-    // optimized.
-    Block,
-    // TODO: Could be just NonNull<u8> since we can reuse the end of current parser since it
-    // never changes.
-    Loop(&'m [u8]),
-    If,
+impl Label {
+    fn new(values: Vec<Val>) -> Self {
+        Label {
+            values,
+            #[cfg(feature = "exception-handling")]
+            handler: None,
+        }
+    }
 }
 
 impl Table {
-    fn new(type_: TableType) -> Self {
-        Table {
-            max: type_.limits.max,
-            elems: vec![Val::Null(type_.item); type_.limits.min as usize],
+    fn new(type_: TableType, max_elems: u32) -> Result<Self, Error> {
+        if max_elems < type_.limits.min {
+            return Err(limit_exceeded(StoreLimit::TableElems));
         }
+        Ok(Table {
+            max: core::cmp::min(type_.limits.max, max_elems),
+            elems: vec![Val::Null(type_.item); type_.limits.min as usize],
+        })
     }
 }
 
@@ -1444,13 +2341,21 @@ struct Memory<'m> {
     // The size currently available to the module. May be larger than the actual data.
     size: u32,
     max: u32,
+    // Whether writes from the module trap (only for host memories).
+    read_only: bool,
 }
 
 impl<'m> Memory<'m> {
-    fn init(&mut self, mut data: &'m mut [u8], limits: Limits) -> Result<(), Error> {
-        if !data.as_ptr().is_aligned_to(MEMORY_ALIGN) {
+    fn init(
+        &mut self, mut data: &'m mut [u8], limits: Limits, max_pages: u32,
+    ) -> Result<(), Error> {
+        if !data.is_empty() && !data.as_ptr().is_aligned_to(MEMORY_ALIGN) {
             return Err(invalid());
         }
+        if max_pages < limits.min {
+            return Err(limit_exceeded(StoreLimit::MemPages));
+        }
+        let limits = Limits { max: core::cmp::min(limits.max, max_pages), ..limits };
         if limits.max < 0x10000 {
             let max = core::cmp::min(limits.max as usize * 0x10000, data.len());
             data = &mut data[.. max];
@@ -1464,9 +2369,23 @@ impl<'m> Memory<'m> {
         Ok(())
     }
 
+    /// Creates a host memory, which cannot grow.
+    fn host(data: &'m mut [u8], read_only: bool) -> Result<Self, Error> {
+        check(data.as_ptr().is_aligned_to(MEMORY_ALIGN))?;
+        let size = u32::try_from(data.len().div_ceil(0x10000)).map_err(|_| invalid())?;
+        Ok(Memory { data, size, max: size, read_only })
+    }
+
     fn len(&self) -> u32 {
         core::cmp::min(self.data.len() as u32, self.size * 0x10000)
     }
+
+    fn check_writable(&self) -> Result<(), TrapReason> {
+        match self.read_only {
+            false => Ok(()),
+            true => Err(TrapReason::MemoryReadOnly),
+        }
+    }
 }
 
 impl Global {
@@ -1553,6 +2472,36 @@ impl_val_unwrap!(unwrap_f32, F32, u32);
 #[cfg(feature = "float-types")]
 impl_val_unwrap!(unwrap_f64, F64, u64);
 impl_val_unwrap!(unwrap_ref, Ref, Ptr);
+#[cfg(feature = "vector-types")]
+impl_val_unwrap!(unwrap_v128, V128, u128);
+
+#[cfg(feature = "vector-types")]
+impl Val {
+    fn unwrap_lane(self) -> i128 {
+        match self {
+            Val::I32(x) => x as i128,
+            Val::I64(x) => x as i128,
+            #[cfg(feature = "float-types")]
+            Val::F32(x) => x as i128,
+            #[cfg(feature = "float-types")]
+            Val::F64(x) => x as i128,
+            _ => unreachable!(),
+        }
+    }
+
+    fn lane(s: Shape, x: i128) -> Val {
+        match s.unpacked() {
+            NumType::I32 => Val::I32(x as u32),
+            NumType::I64 => Val::I64(x as u64),
+            #[cfg(feature = "float-types")]
+            NumType::F32 => Val::F32(x as u32),
+            #[cfg(feature = "float-types")]
+            NumType::F64 => Val::F64(x as u64),
+            #[cfg(not(feature = "float-types"))]
+            _ => unreachable!(),
+        }
+    }
+}
 
 impl ValType {
     fn default(self) -> Val {
@@ -1564,6 +2513,7 @@ impl ValType {
             ValType::V128 => support_if!("vector-types"[], Val::V128(0), unreachable!()),
             ValType::FuncRef => Val::Null(RefType::FuncRef),
             ValType::ExternRef => Val::Null(RefType::ExternRef),
+            ValType::ExnRef => Val::Null(RefType::ExnRef),
         }
     }
 
@@ -1572,7 +2522,10 @@ impl ValType {
             (ValType::I32, Val::I32(_))
             | (ValType::I64, Val::I64(_))
             | (ValType::FuncRef, Val::Null(RefType::FuncRef) | Val::Ref(_))
-            | (ValType::ExternRef, Val::Null(RefType::ExternRef) | Val::RefExtern(_)) => true,
+            | (ValType::ExternRef, Val::Null(RefType::ExternRef) | Val::RefExtern(_))
+            | (ValType::ExnRef, Val::Null(RefType::ExnRef)) => true,
+            #[cfg(feature = "exception-handling")]
+            (ValType::ExnRef, Val::RefExn(_)) => true,
             #[cfg(feature = "float-types")]
             (ValType::F32, Val::F32(_)) | (ValType::F64, Val::F64(_)) => true,
             #[cfg(feature = "vector-types")]
@@ -1601,3 +2554,49 @@ fn memory_too_small(x: usize, n: usize, mem: &Memory) {
     #[cfg(feature = "debug")]
     eprintln!("Memory too small: {x} + {n} > {}", mem.len());
 }
+
+#[cfg(test)]
+mod tests {
+    use wast::parser::{self, ParseBuffer};
+    use wast::Wat;
+
+    use super::*;
+
+    fn encode(wat: &str) -> Vec<u8> {
+        let buffer = ParseBuffer::new(wat).unwrap();
+        parser::parse::<Wat>(&buffer).unwrap().encode().unwrap()
+    }
+
+    /// Returns the sum of the integers from 1 to its argument.
+    const WAT: &str = r#"(module
+      (func (export "sum") (param $n i32) (result i32)
+        (local $sum i32)
+        (loop $l
+          (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+          (local.tee $n (i32.sub (local.get $n) (i32.const 1)))
+          (br_if $l))
+        (local.get $sum)))"#;
+
+    #[test]
+    fn preempt_and_resume() {
+        let binary = encode(WAT);
+        let mut store = Store::default();
+        let inst = store.instantiate(Module::new(&binary).unwrap(), &mut []).unwrap();
+        store.set_fuel(Some(50));
+        let mut result = store.invoke(inst, "sum", vec![Val::I32(100)]).unwrap().forget();
+        let mut preempted = 0;
+        while let RunAnswer::Preempted = result {
+            preempted += 1;
+            assert!(store.is_preempted());
+            assert_eq!(store.fuel(), Some(0));
+            store.set_fuel(Some(50));
+            result = store.resume().unwrap().forget();
+        }
+        // The loop runs about 10 instructions per iteration.
+        assert!(10 <= preempted, "preempted {preempted} times");
+        assert!(!store.is_preempted());
+        assert_eq!(store.resume().err(), Some(Error::Invalid));
+        let RunAnswer::Done(results) = result else { panic!("{result:?}") };
+        assert_eq!(results, [Val::I32(5050)]);
+    }
+}
@@ -0,0 +1,426 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshot and restore of an instance.
+//!
+//! A snapshot starts with a version byte and the hash of the module. Integers are encoded in
+//! unsigned LEB128 (like in the binary format) and memories are truncated after their last non-zero
+//! byte. Positions in the module are encoded as a length and an offset (omitted if the length is
+//! zero).
+
+use super::*;
+use crate::toctou::Check;
+
+const VERSION: u8 = 1;
+
+type Reader<'a> = crate::parser::Parser<'a, Check>;
+
+impl<'m> Store<'m> {
+    /// Serializes the state of an instance.
+    ///
+    /// The snapshot contains the globals, memories, tables, and dropped segments defined by the
+    /// instance, as well as the threads of the store (waiting for the host or preempted). Imported
+    /// globals, memories, and tables are not part of the snapshot. The instance must not reference
+    /// functions of other instances nor exceptions, and the threads must only run in this instance.
+    pub fn snapshot(&self, inst: InstId) -> Result<Vec<u8>, Error> {
+        let inst_id = self.inst_id(inst)?;
+        let inst = &self.insts[inst_id];
+        let module = &inst.module;
+        let mut w = Writer { inst_id, data: vec![VERSION] };
+        w.data.extend_from_slice(&module.hash().to_le_bytes());
+        w.usize(inst.globals.int.len());
+        for global in &inst.globals.int {
+            w.val(global.value)?;
+        }
+        w.usize(inst.mems.int.len());
+        for mem in &inst.mems.int {
+            w.u64(mem.size as u64);
+            let len = mem.data.iter().rposition(|&x| x != 0).map_or(0, |x| x + 1);
+            w.usize(len);
+            w.data.extend_from_slice(&mem.data[.. len]);
+        }
+        w.usize(inst.tables.int.len());
+        for table in &inst.tables.int {
+            w.vals(&table.elems)?;
+        }
+        w.bools(&inst.elems);
+        w.bools(&inst.datas);
+        w.usize(self.threads.len());
+        for Continuation { thread, call } in &self.threads {
+            w.pos(module, thread.parser.save());
+            w.usize(thread.frames.len());
+            for frame in &thread.frames {
+                check(frame.inst_id == inst_id)?;
+                w.u64(frame.func as u64);
+                w.usize(frame.arity);
+                w.pos(module, frame.ret);
+                w.vals(&frame.locals)?;
+                w.usize(frame.labels.len());
+                for label in &frame.labels {
+                    w.vals(&label.values)?;
+                    #[cfg(feature = "exception-handling")]
+                    if let Some((len, stp)) = label.handler {
+                        w.data.push(1);
+                        w.usize(len);
+                        w.usize(stp);
+                        continue;
+                    }
+                    w.data.push(0);
+                }
+                w.usize(frame.stp);
+            }
+            match call {
+                None => w.data.push(0),
+                Some(HostCall { index, args, arity, tail }) => {
+                    w.data.push(1);
+                    w.usize(*index);
+                    w.vals(args)?;
+                    w.usize(*arity);
+                    w.data.push(*tail as u8);
+                }
+            }
+        }
+        Ok(w.data)
+    }
+
+    /// Instantiates a valid module in this store from a snapshot.
+    ///
+    /// This is like [`Self::instantiate()`] except that the start function is not executed. The
+    /// state of the instance and the threads are restored from the snapshot instead. Execution
+    /// continues with [`Self::last_call()`] or [`Self::resume()`] depending on the threads. The
+    /// store must not have threads.
+    ///
+    /// Snapshots of a different module (according to its hash) are rejected.
+    ///
+    /// # Safety
+    ///
+    /// The snapshot must have been returned by [`Self::snapshot()`] for the same module and a store
+    /// with the same host functions.
+    pub unsafe fn restore(
+        &mut self, module: Module<'m>, memory: &'m mut [u8], snapshot: &[u8],
+    ) -> Result<InstId, Error> {
+        check(self.threads.is_empty())?;
+        let mut r = Reader::new(snapshot);
+        check(r.parse_byte()? == VERSION)?;
+        let hash = r.parse_bytes(8)?.try_into().unwrap();
+        check(u64::from_le_bytes(hash) == module.hash())?;
+        let inst_id = self.instantiate_without_start(module, [memory])?;
+        let inst = &mut self.insts[inst_id];
+        check(r.parse_u32()? as usize == inst.globals.int.len())?;
+        for global in &mut inst.globals.int {
+            global.value = read_val(&mut r, inst_id)?;
+        }
+        check(r.parse_u32()? as usize == inst.mems.int.len())?;
+        for mem in &mut inst.mems.int {
+            let size = r.parse_u32()?;
+            check(size <= mem.max)?;
+            let len = r.parse_u32()? as usize;
+            let data = r.parse_bytes(len)?;
+            check(data.len() <= mem.data.len())?;
+            mem.data[.. data.len()].copy_from_slice(data);
+            mem.data[data.len() ..].fill(0);
+            mem.size = size;
+        }
+        check(r.parse_u32()? as usize == inst.tables.int.len())?;
+        for table in &mut inst.tables.int {
+            let elems = read_vals(&mut r, inst_id)?;
+            check(elems.len() as u64 <= table.max as u64)?;
+            table.elems = elems;
+        }
+        read_bools(&mut r, &mut inst.elems)?;
+        read_bools(&mut r, &mut inst.datas)?;
+        for _ in 0 .. r.parse_u32()? {
+            let module = &self.insts[inst_id].module;
+            let parser = unsafe { Parser::new(read_pos(&mut r, module)?) };
+            let num_frames = r.parse_u32()? as usize;
+            if self.limits.max_frames < num_frames {
+                return Err(limit_exceeded(StoreLimit::Frames));
+            }
+            let mut frames: Vec<Frame<'m>> = Vec::with_capacity(num_frames);
+            for _ in 0 .. num_frames {
+                let module = &self.insts[inst_id].module;
+                let func = r.parse_u32()?;
+                let arity = r.parse_u32()? as usize;
+                let ret = read_pos(&mut r, module)?;
+                let locals = read_vals(&mut r, inst_id)?;
+                let mut labels = Vec::new();
+                for _ in 0 .. r.parse_u32()? {
+                    let values = read_vals(&mut r, inst_id)?;
+                    labels.push(match r.parse_byte()? {
+                        0 => Label::new(values),
+                        #[cfg(feature = "exception-handling")]
+                        1 => {
+                            let handler = Some((r.parse_u32()? as usize, r.parse_u32()? as usize));
+                            Label { values, handler }
+                        }
+                        _ => return Err(invalid()),
+                    });
+                }
+                let stp = r.parse_u32()? as usize;
+                let mut frame = Frame::new(inst_id, func, arity, ret, locals, stp);
+                frame.labels = labels;
+                frame.check_limits(self, frames.last())?;
+                frames.push(frame);
+            }
+            let call = match r.parse_byte()? {
+                0 => None,
+                1 => Some(HostCall {
+                    index: r.parse_u32()? as usize,
+                    args: read_vals(&mut r, inst_id)?,
+                    arity: r.parse_u32()? as usize,
+                    tail: r.parse_byte()? != 0,
+                }),
+                _ => return Err(invalid()),
+            };
+            let thread = Thread::new(parser, frames);
+            #[cfg(feature = "trace")]
+            {
+                let mut pos = thread.parser.save();
+                let mut infos = Vec::new();
+                for frame in thread.frames.iter().rev() {
+                    infos.push((frame.func, pos));
+                    pos = frame.ret;
+                }
+                for (func, pos) in infos.into_iter().rev() {
+                    self.trace_enter(inst_id, func, pos);
+                }
+            }
+            self.threads.push(Continuation { thread, call });
+        }
+        check(r.is_empty())?;
+        Ok(InstId { store_id: self.id, inst_id })
+    }
+}
+
+struct Writer {
+    inst_id: usize,
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u64(&mut self, mut x: u64) {
+        loop {
+            let byte = x as u8 & 0x7f;
+            x >>= 7;
+            if x == 0 {
+                break self.data.push(byte);
+            }
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    fn usize(&mut self, x: usize) {
+        self.u64(x as u64);
+    }
+
+    fn bools(&mut self, xs: &[bool]) {
+        self.usize(xs.len());
+        self.data.extend(xs.iter().map(|&x| x as u8));
+    }
+
+    fn pos(&mut self, module: &Module, pos: &[u8]) {
+        self.usize(pos.len());
+        if !pos.is_empty() {
+            self.usize(module.offset(pos));
+        }
+    }
+
+    fn vals(&mut self, xs: &[Val]) -> Result<(), Error> {
+        self.usize(xs.len());
+        xs.iter().try_for_each(|&x| self.val(x))
+    }
+
+    fn val(&mut self, x: Val) -> Result<(), Error> {
+        match x {
+            Val::I32(x) => {
+                self.data.push(0);
+                self.u64(x as u64);
+            }
+            Val::I64(x) => {
+                self.data.push(1);
+                self.u64(x);
+            }
+            #[cfg(feature = "float-types")]
+            Val::F32(x) => {
+                self.data.push(2);
+                self.u64(x as u64);
+            }
+            #[cfg(feature = "float-types")]
+            Val::F64(x) => {
+                self.data.push(3);
+                self.u64(x);
+            }
+            #[cfg(feature = "vector-types")]
+            Val::V128(x) => {
+                self.data.push(4);
+                self.data.extend_from_slice(&x.to_le_bytes());
+            }
+            Val::Null(t) => self.data.extend_from_slice(&[5, t as u8]),
+            Val::Ref(ptr) => {
+                let side = match ptr.instance() {
+                    Side::Host => 0,
+                    Side::Wasm(x) => {
+                        check(x == self.inst_id)?;
+                        1
+                    }
+                };
+                self.data.extend_from_slice(&[6, side]);
+                self.u64(ptr.index() as u64);
+            }
+            Val::RefExtern(x) => {
+                self.data.push(7);
+                self.usize(x);
+            }
+            #[cfg(feature = "exception-handling")]
+            Val::RefExn(_) => return Err(invalid()),
+        }
+        Ok(())
+    }
+}
+
+fn read_bools(r: &mut Reader, xs: &mut [bool]) -> Result<(), Error> {
+    check(r.parse_u32()? as usize == xs.len())?;
+    for x in xs {
+        *x = match r.parse_byte()? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid()),
+        };
+    }
+    Ok(())
+}
+
+fn read_pos<'m>(r: &mut Reader, module: &Module<'m>) -> Result<&'m [u8], Error> {
+    match r.parse_u32()? as usize {
+        0 => Ok(&[]),
+        len => module.slice(r.parse_u32()? as usize, len).ok_or_else(invalid),
+    }
+}
+
+fn read_vals(r: &mut Reader, inst_id: usize) -> Result<Vec<Val>, Error> {
+    (0 .. r.parse_u32()?).map(|_| read_val(r, inst_id)).collect()
+}
+
+fn read_val(r: &mut Reader, inst_id: usize) -> Result<Val, Error> {
+    Ok(match r.parse_byte()? {
+        0 => Val::I32(r.parse_u32()?),
+        1 => Val::I64(r.parse_leb128(false, 64)?),
+        #[cfg(feature = "float-types")]
+        2 => Val::F32(r.parse_u32()?),
+        #[cfg(feature = "float-types")]
+        3 => Val::F64(r.parse_leb128(false, 64)?),
+        #[cfg(feature = "vector-types")]
+        4 => Val::V128(u128::from_le_bytes(r.parse_bytes(16)?.try_into().unwrap())),
+        5 => Val::Null(r.parse_reftype()?),
+        6 => {
+            let side = match r.parse_byte()? {
+                0 => Side::Host,
+                1 => Side::Wasm(inst_id),
+                _ => return Err(invalid()),
+            };
+            let index = r.parse_u32()?;
+            check(index <= Ptr::INDEX_MASK)?;
+            Val::Ref(Ptr::new(side, index))
+        }
+        7 => Val::RefExtern(r.parse_leb128(false, 64)? as usize),
+        _ => return Err(invalid()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use wast::parser::{self, ParseBuffer};
+    use wast::Wat;
+
+    use super::*;
+
+    #[repr(align(16))]
+    struct Memory([u8; 0x10000]);
+
+    fn encode(wat: &str) -> &'static [u8] {
+        let buffer = ParseBuffer::new(wat).unwrap();
+        Vec::leak(parser::parse::<Wat>(&buffer).unwrap().encode().unwrap())
+    }
+
+    fn memory() -> &'static mut [u8] {
+        &mut alloc::boxed::Box::leak(alloc::boxed::Box::new(Memory([0; 0x10000]))).0
+    }
+
+    fn new_store() -> Store<'static> {
+        let mut store = Store::default();
+        store.link_func("env", "wait", 1, 1).unwrap();
+        store
+    }
+
+    const WAT: &str = r#"(module
+      (import "env" "wait" (func $wait (param i32) (result i32)))
+      (memory 1)
+      (global $count (mut i32) (i32.const 0))
+      (func (export "main") (param $x i32) (result i32)
+        (loop $l
+          (global.set $count (i32.add (global.get $count) (i32.const 1)))
+          (i32.store (i32.const 16) (global.get $count))
+          (local.set $x (i32.add (local.get $x) (call $wait (global.get $count))))
+          (br_if $l (i32.lt_u (global.get $count) (i32.const 3))))
+        (i32.add (local.get $x) (i32.load (i32.const 16)))))"#;
+
+    fn run(mut result: RunResult) -> Vec<Val> {
+        loop {
+            match result {
+                RunResult::Done(x) => return x,
+                RunResult::Host(call) => {
+                    let Val::I32(x) = call.args()[0] else { unreachable!() };
+                    result = call.resume(&[Val::I32(10 * x)]).unwrap();
+                }
+                RunResult::Preempted | RunResult::Exception(_) => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let binary = encode(WAT);
+        let mut store = new_store();
+        let inst = store.instantiate(Module::new(binary).unwrap(), memory()).unwrap();
+        let call = match store.invoke(inst, "main", vec![Val::I32(1000)]).unwrap() {
+            RunResult::Host(x) => x,
+            _ => unreachable!(),
+        };
+        let call = match call.resume(&[Val::I32(5)]).unwrap() {
+            RunResult::Host(x) => x,
+            _ => unreachable!(),
+        };
+        assert_eq!(call.args(), [Val::I32(2)]);
+        let snapshot = store.snapshot(inst).unwrap();
+        let mut restored = new_store();
+        let module = Module::new(binary).unwrap();
+        let inst = unsafe { restored.restore(module, memory(), &snapshot) }.unwrap();
+        assert_eq!(restored.snapshot(inst).unwrap(), snapshot);
+        let call = restored.last_call().unwrap();
+        assert_eq!(call.args(), [Val::I32(2)]);
+        // 1000 + 5 + 20 + 30 + 3
+        assert_eq!(run(call.resume(&[Val::I32(20)]).unwrap()), [Val::I32(1058)]);
+    }
+
+    #[test]
+    fn restore_other_module() {
+        let mut store = new_store();
+        let module = Module::new(encode(WAT)).unwrap();
+        let inst = store.instantiate(module, memory()).unwrap();
+        let snapshot = store.snapshot(inst).unwrap();
+        let module = Module::new(encode(&WAT.replace("(i32.const 3)", "(i32.const 4)"))).unwrap();
+        let result = unsafe { store.restore(module, memory(), &snapshot) };
+        assert_eq!(result.err(), Some(Error::Invalid));
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use super::*;
+
+/// Execution hooks of a store.
+///
+/// Hooks are called in execution order for all threads of the store. A function invoked while
+/// another is calling the host is entered as if called by the function calling the host.
+pub trait Tracer {
+    /// Returns whether [`Self::instr()`] should be called.
+    ///
+    /// This is only queried when the tracer is set.
+    fn trace_instrs(&self) -> bool {
+        false
+    }
+
+    /// Called when entering a function.
+    ///
+    /// The offset of the frame is the first instruction of the function, except for frames of
+    /// restored threads (see [`Store::restore()`]).
+    fn enter(&mut self, _frame: FrameInfo) {}
+
+    /// Called when exiting the most recently entered function (not yet exited).
+    ///
+    /// This happens when the function returns, tail-calls another function, is unwound by an
+    /// exception, or traps.
+    fn exit(&mut self) {}
+
+    /// Called before executing an instruction of the most recently entered function.
+    ///
+    /// The offset is the position of the instruction in the module.
+    fn instr(&mut self, _offset: usize) {}
+}
+
+pub(super) struct Hooks {
+    pub(super) tracer: Box<dyn Tracer>,
+    pub(super) instrs: bool,
+}
+
+impl core::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Hooks").field("instrs", &self.instrs).finish_non_exhaustive()
+    }
+}
+
+impl<'m> Store<'m> {
+    /// Sets the execution hooks of the store.
+    ///
+    /// The tracer should be set when the store has no threads, otherwise it would see functions
+    /// exiting without entering them.
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn Tracer>>) {
+        self.tracer = tracer.map(|tracer| Hooks { instrs: tracer.trace_instrs(), tracer });
+    }
+
+    pub(super) fn trace_enter(&mut self, inst_id: usize, func: FuncIdx, pos: &'m [u8]) {
+        if self.tracer.is_some() {
+            let frame = self.frame_info(inst_id, func, pos);
+            self.tracer.as_mut().unwrap().tracer.enter(frame);
+        }
+    }
+
+    pub(super) fn trace_exit(&mut self, count: usize) {
+        if let Some(hooks) = &mut self.tracer {
+            (0 .. count).for_each(|_| hooks.tracer.exit());
+        }
+    }
+}
+
+/// Counting profiler.
+///
+/// Counts the instructions executed by each call stack. Functions are named from the name section
+/// of their module, or by their index otherwise.
+#[derive(Debug)]
+pub struct Profiler {
+    // The first node is the root and has no function.
+    nodes: Vec<Node>,
+    current: usize,
+}
+
+#[derive(Debug)]
+struct Node {
+    parent: usize,
+    func: (InstId, FuncIdx),
+    name: String,
+    children: Vec<usize>,
+    count: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        let func = (InstId { store_id: 0, inst_id: 0 }, FuncIdx::MAX);
+        let root = Node { parent: 0, func, name: String::new(), children: Vec::new(), count: 0 };
+        Profiler { nodes: vec![root], current: 0 }
+    }
+}
+
+impl Profiler {
+    /// Writes the profile in the folded stack format.
+    ///
+    /// Each line is a call stack (outermost function first) separated by semicolons, followed by a
+    /// space and the number of instructions executed by the innermost function. This is the input
+    /// format of flame graph tools.
+    pub fn write_folded(&self, output: &mut impl Write) -> core::fmt::Result {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.count == 0 {
+                continue;
+            }
+            let mut stack = Vec::new();
+            let mut index = index;
+            while index != 0 {
+                stack.push(self.nodes[index].name.as_str());
+                index = self.nodes[index].parent;
+            }
+            stack.reverse();
+            writeln!(output, "{} {}", stack.join(";"), node.count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Tracer for Profiler {
+    fn trace_instrs(&self) -> bool {
+        true
+    }
+
+    fn enter(&mut self, frame: FrameInfo) {
+        let func = (frame.inst, frame.func);
+        let children = &self.nodes[self.current].children;
+        let child = children.iter().find(|&&x| self.nodes[x].func == func);
+        self.current = match child {
+            Some(&child) => child,
+            None => {
+                let child = self.nodes.len();
+                let parent = self.current;
+                let name = match frame.name {
+                    Some(name) => name.into(),
+                    None => alloc::format!("func[{}]", frame.func),
+                };
+                let children = Vec::new();
+                self.nodes.push(Node { parent, func, name, children, count: 0 });
+                self.nodes[parent].children.push(child);
+                child
+            }
+        };
+    }
+
+    fn exit(&mut self) {
+        self.current = self.nodes[self.current].parent;
+    }
+
+    fn instr(&mut self, _offset: usize) {
+        self.nodes[self.current].count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use wast::parser::{self, ParseBuffer};
+    use wast::Wat;
+
+    use super::*;
+
+    fn encode(wat: &str) -> &'static [u8] {
+        let buffer = ParseBuffer::new(wat).unwrap();
+        Vec::leak(parser::parse::<Wat>(&buffer).unwrap().encode().unwrap())
+    }
+
+    struct Shared(Rc<RefCell<Profiler>>);
+
+    impl Tracer for Shared {
+        fn trace_instrs(&self) -> bool {
+            self.0.borrow().trace_instrs()
+        }
+
+        fn enter(&mut self, frame: FrameInfo) {
+            self.0.borrow_mut().enter(frame)
+        }
+
+        fn exit(&mut self) {
+            self.0.borrow_mut().exit()
+        }
+
+        fn instr(&mut self, offset: usize) {
+            self.0.borrow_mut().instr(offset)
+        }
+    }
+
+    #[test]
+    fn profile() {
+        let module = Module::new(encode(
+            r#"(module
+              (func $leaf (result i32) (i32.const 1))
+              (func $main (export "main") (result i32)
+                (drop (call $leaf))
+                (call $leaf))
+              (func $fail (export "fail") (call $main) (unreachable)))"#,
+        ))
+        .unwrap();
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        let mut store = Store::default();
+        store.set_tracer(Some(Box::new(Shared(profiler.clone()))));
+        let inst = store.instantiate(module, &mut []).unwrap();
+        assert!(matches!(store.invoke(inst, "fail", vec![]), Err(Error::Trap)));
+        assert!(matches!(store.invoke(inst, "main", vec![]), Ok(RunResult::Done(_))));
+        let mut output = String::new();
+        profiler.borrow().write_folded(&mut output).unwrap();
+        let expected = "fail 2\nfail;main 4\nfail;main;leaf 4\nmain 4\nmain;leaf 4\n";
+        assert_eq!(output, expected);
+    }
+}
@@ -52,6 +52,17 @@
 //! # }
 //! ```
 //!
+//! Host functions can also be linked once in a [`Linker`] shared between stores:
+//!
+//! ```
+//! # use wasefire_interpreter::*;
+//! # fn doc(linker: &'static mut Linker<'static>) -> Result<(), Error> {
+//! linker.link_func("env", "add", 2, 1)?;
+//! let store = Store::new(linker);
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! Instantiating a valid module in a store is done with [`Store::instantiate()`]:
 //!
 //! ```
@@ -83,6 +94,10 @@
 //!     let mut call = match result {
 //!         RunResult::Done(results) => return Ok(results),
 //!         RunResult::Host(call) => call,
+//!         // Only happens when using fuel (see below).
+//!         RunResult::Preempted => unreachable!(),
+//!         // Only happens with the exception-handling feature.
+//!         RunResult::Exception(_) => unreachable!(),
 //!     };
 //!     let results = process(&mut call)?;
 //!     result = call.resume(&results)?;
@@ -90,6 +105,22 @@
 //! # }
 //! ```
 //!
+//! Execution can be bounded with [`Store::set_fuel()`]. When the fuel runs out, execution is
+//! preempted and can later be resumed with [`Store::resume()`]:
+//!
+//! ```
+//! # use wasefire_interpreter::*;
+//! # fn doc<'a>(store: &mut Store<'a>, inst: InstId) -> Result<(), Error> {
+//! store.set_fuel(Some(1000));
+//! let mut result = store.invoke(inst, "loop", vec![])?.forget();
+//! while let RunAnswer::Preempted = result {
+//!     store.set_fuel(Some(1000));
+//!     result = store.resume()?.forget();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Atomic support
 //!
 //! This crate uses atomic operations and relies on the `portable-atomic` crate to support
@@ -139,20 +170,25 @@ macro_rules! support_if {
     }};
 }
 
-mod cache;
 mod error;
 mod exec;
 mod id;
 mod module;
 mod parser;
+mod side_table;
 mod syntax;
 mod toctou;
 mod valid;
 
-pub use error::{Error, Unsupported};
-pub use exec::{Call, InstId, RunAnswer, RunResult, Store, StoreId, Val, MEMORY_ALIGN};
-pub use module::Module;
+pub use error::{Error, StoreLimit, TrapReason, Unsupported};
+pub use exec::{
+    Call, CoreDump, Exception, FrameInfo, InstId, Linker, RunAnswer, RunResult, Store, StoreId,
+    StoreLimits, Val, MEMORY_ALIGN,
+};
+#[cfg(feature = "trace")]
+pub use exec::{Profiler, Tracer};
+pub use module::{custom_sections, push_custom_section, CustomSection, Module};
 pub use syntax::{
-    FuncType, GlobalType, ImportDesc, Limits, Mut, RefType, ResultType, TableType, ValType,
+    FuncType, GlobalType, Import, ImportDesc, Limits, Mut, RefType, ResultType, TableType, ValType,
 };
 pub use valid::validate;
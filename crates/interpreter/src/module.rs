@@ -14,35 +14,21 @@
 
 use alloc::vec::Vec;
 use core::cmp::Ordering;
+use core::ops::Range;
 
-use crate::cache::Cache;
+use crate::error::*;
 use crate::parser::{SkipData, SkipElem};
+use crate::side_table::*;
 use crate::syntax::*;
 use crate::toctou::*;
-use crate::valid::validate;
+use crate::valid::prepare;
 use crate::*;
 
 /// Valid module.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Module<'m> {
     binary: &'m [u8],
-    types: Vec<FuncType<'m>>,
-    cache: Cache<CacheKey, CacheValue>,
-}
-
-impl<'m> Default for Module<'m> {
-    fn default() -> Self {
-        Self { binary: &[], types: Vec::new(), cache: Cache::unbounded() }
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Hash)]
-enum CacheKey {
-    Skip { ptr: *const u8, depth: LabelIdx },
-}
-
-union CacheValue {
-    skip: usize, // delta
+    side_table: SideTable<'m>,
 }
 
 impl<'m> Import<'m> {
@@ -54,10 +40,11 @@ impl<'m> Import<'m> {
 impl ImportDesc {
     pub fn type_<'m>(&self, module: &Module<'m>) -> ExternType<'m> {
         match *self {
-            ImportDesc::Func(x) => ExternType::Func(module.types[x as usize]),
+            ImportDesc::Func(x) => ExternType::Func(module.type_(x)),
             ImportDesc::Table(t) => ExternType::Table(t),
             ImportDesc::Mem(t) => ExternType::Mem(t),
             ImportDesc::Global(t) => ExternType::Global(t),
+            ImportDesc::Tag(x) => ExternType::Tag(module.type_(x)),
         }
     }
 }
@@ -65,31 +52,98 @@ impl ImportDesc {
 impl<'m> Module<'m> {
     /// Validates a WASM module in binary format.
     pub fn new(binary: &'m [u8]) -> Result<Self, Error> {
-        validate(binary)?;
-        Ok(unsafe { Self::new_unchecked(binary) })
+        let side_table = prepare(binary)?;
+        Ok(unsafe { Self::from_parts(binary, side_table) })
     }
 
-    /// Creates a valid module from binary format.
+    /// Creates a valid module from binary format and a persisted side table.
     ///
     /// # Safety
     ///
-    /// The module must be valid.
-    pub unsafe fn new_unchecked(binary: &'m [u8]) -> Self {
-        // Only keep the sections (i.e. skip the header).
-        let mut module = Module { binary: &binary[8 ..], ..Self::default() };
-        if let Some(mut parser) = module.section(SectionId::Type) {
-            for _ in 0 .. parser.parse_vec().into_ok() {
-                module.types.push(parser.parse_functype().into_ok());
+    /// The module must be valid and the side table must be the one returned by
+    /// [`Self::side_table()`] for this module.
+    pub unsafe fn new_with_side_table(binary: &'m [u8], side_table: &'m [u8]) -> Self {
+        unsafe { Self::from_parts(binary, SideTable::borrowed(side_table)) }
+    }
+
+    /// Creates a valid module from a prepared binary.
+    ///
+    /// A prepared binary is returned by [`Self::prepare()`] and carries the side table for control
+    /// flow in a custom section, such that neither validation nor the side table computation is
+    /// needed. Other custom sections may follow (e.g. a signature).
+    ///
+    /// Returns [`Error::NotFound`] if the binary is not prepared and [`Error::Invalid`] if it was
+    /// prepared for a different binary or with a different version of this crate.
+    ///
+    /// # Safety
+    ///
+    /// The binary must be a valid module returned by [`Self::prepare()`]. The digest of the custom
+    /// section only detects accidental mismatches, so the binary should be authenticated (e.g.
+    /// signed) if it comes from an untrusted source.
+    pub unsafe fn new_prepared(binary: &'m [u8]) -> Result<Self, Error> {
+        let sections = binary.get(8 ..).ok_or_else(invalid)?;
+        let mut parser = unsafe { Parser::new(sections) };
+        while !parser.is_empty() {
+            let start = sections.len() - parser.save().len();
+            let id = parser.parse_section_id().into_ok();
+            let mut section = parser.split_section().into_ok();
+            if id != SectionId::Custom || section.parse_name().into_ok() != PREPARED_SECTION {
+                continue;
+            }
+            let content = section.save();
+            if content.len() < 9
+                || content[0] != PREPARED_VERSION
+                || content[1 .. 9] != fnv1a(&sections[.. start]).to_le_bytes()
+            {
+                return Err(invalid());
             }
+            let side_table = SideTable::borrowed(&content[9 ..]);
+            return Ok(unsafe { Self::from_parts(binary, side_table) });
         }
-        module
+        Err(not_found())
     }
 
-    pub(crate) fn types(&self) -> &[FuncType<'m>] {
-        &self.types
+    /// Returns the binary of the module with its side table in a custom section.
+    ///
+    /// The result can be loaded with [`Self::new_prepared()`]. The custom section is named
+    /// `wasefire-side-table` and contains a version byte, the FNV-1a digest of the sections before
+    /// it (8 bytes in little-endian), and the side table (see [`Self::side_table()`]).
+    ///
+    /// Returns [`Error::Invalid`] if the module is already prepared.
+    pub fn prepare(&self) -> Result<Vec<u8>, Error> {
+        if self.custom_section(PREPARED_SECTION).is_some() {
+            return Err(invalid());
+        }
+        let mut content = Vec::new();
+        content.push(PREPARED_VERSION);
+        content.extend_from_slice(&self.hash().to_le_bytes());
+        content.extend_from_slice(self.side_table());
+        let mut binary = Vec::with_capacity(8 + self.binary.len() + 32 + content.len());
+        binary.extend_from_slice(b"\0asm\x01\0\0\0");
+        binary.extend_from_slice(self.binary);
+        push_custom_section(&mut binary, PREPARED_SECTION, &content)?;
+        Ok(binary)
+    }
+
+    unsafe fn from_parts(binary: &'m [u8], side_table: SideTable<'m>) -> Self {
+        // Only keep the sections (i.e. skip the header).
+        Module { binary: &binary[8 ..], side_table }
+    }
+
+    /// Returns the side table for control flow.
+    ///
+    /// It can be persisted alongside the module to skip validation with
+    /// [`Self::new_with_side_table()`].
+    pub fn side_table(&self) -> &[u8] {
+        self.side_table.as_bytes()
     }
 
-    pub(crate) fn imports(&self) -> impl Iterator<Item = Import<'m>> {
+    pub(crate) fn type_(&self, x: TypeIdx) -> FuncType<'m> {
+        self.parser(self.side_table.type_offset(x)).parse_functype().into_ok()
+    }
+
+    /// Returns the imports of the module.
+    pub fn imports(&self) -> impl Iterator<Item = Import<'m>> {
         let (n, mut parser) = match self.section(SectionId::Import) {
             None => (0, Parser::default()),
             Some(mut parser) => (parser.parse_vec().into_ok(), parser),
@@ -101,10 +155,102 @@ impl<'m> Module<'m> {
             Import { module, name, desc }
         })
     }
+
+    /// Returns the content of the first custom section with a given name, if any.
+    pub fn custom_section(&self, name: &str) -> Option<&'m [u8]> {
+        let mut parser = unsafe { Parser::new(self.binary) };
+        while !parser.is_empty() {
+            let id = parser.parse_section_id().into_ok();
+            let mut section = parser.split_section().into_ok();
+            if id == SectionId::Custom && section.parse_name().into_ok() == name {
+                return Some(section.save());
+            }
+        }
+        None
+    }
 }
 
 pub type Parser<'m> = parser::Parser<'m, Use>;
 
+/// Custom section of a module in binary format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomSection<'m> {
+    /// Name of the section.
+    pub name: &'m str,
+
+    /// Content of the section (after its name).
+    pub content: &'m [u8],
+
+    /// Position of the section in the binary (including its id and size).
+    pub range: Range<usize>,
+}
+
+/// Returns the custom sections of a module in binary format.
+///
+/// The module is not validated: only its header and the framing of its sections are checked.
+/// Returns [`Error::Invalid`] if they are malformed.
+pub fn custom_sections(binary: &[u8]) -> Result<Vec<CustomSection<'_>>, Error> {
+    let mut parser = parser::Parser::<Check>::new(binary);
+    if parser.parse_bytes(8)? != b"\0asm\x01\0\0\0" {
+        return Err(invalid());
+    }
+    let mut sections = Vec::new();
+    while !parser.is_empty() {
+        let start = binary.len() - parser.save().len();
+        let id = parser.parse_section_id()?;
+        let mut section = parser.split_section()?;
+        if id != SectionId::Custom {
+            continue;
+        }
+        let name = section.parse_name()?;
+        let range = start .. binary.len() - parser.save().len();
+        sections.push(CustomSection { name, content: section.save(), range });
+    }
+    Ok(sections)
+}
+
+/// Appends a custom section to a module in binary format.
+///
+/// Returns [`Error::Invalid`] if the section is too large.
+pub fn push_custom_section(binary: &mut Vec<u8>, name: &str, content: &[u8]) -> Result<(), Error> {
+    let mut section = Vec::with_capacity(5 + name.len() + content.len());
+    write_u32(&mut section, name.len().try_into().map_err(|_| invalid())?);
+    section.extend_from_slice(name.as_bytes());
+    section.extend_from_slice(content);
+    binary.push(SectionId::Custom as u8);
+    write_u32(binary, section.len().try_into().map_err(|_| invalid())?);
+    binary.extend_from_slice(&section);
+    Ok(())
+}
+
+/// Name of the custom section holding the side table of a prepared module.
+const PREPARED_SECTION: &str = "wasefire-side-table";
+
+/// Version of the side table format in prepared modules.
+///
+/// It must be incremented when the side table format changes.
+const PREPARED_VERSION: u8 = 2;
+
+/// Returns the FNV-1a hash of some bytes.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for &byte in bytes {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn write_u32(data: &mut Vec<u8>, mut x: u32) {
+    loop {
+        let byte = x as u8 & 0x7f;
+        x >>= 7;
+        if x == 0 {
+            break data.push(byte);
+        }
+        data.push(byte | 0x80);
+    }
+}
+
 impl<'m> Module<'m> {
     pub(crate) fn section(&self, expected_id: SectionId) -> Option<Parser<'m>> {
         let mut parser = unsafe { Parser::new(self.binary) };
@@ -126,14 +272,7 @@ impl<'m> Module<'m> {
     }
 
     pub(crate) fn func_type(&self, x: FuncIdx) -> FuncType<'m> {
-        let mut parser = self.section(SectionId::Function).unwrap();
-        for i in 0 .. parser.parse_vec().into_ok() {
-            let y = parser.parse_typeidx().into_ok();
-            if i == x as usize {
-                return self.types[y as usize];
-            }
-        }
-        unreachable!()
+        self.type_(self.side_table.func_type(x))
     }
 
     pub(crate) fn table_type(&self, x: TableIdx) -> TableType {
@@ -170,6 +309,17 @@ impl<'m> Module<'m> {
         unreachable!()
     }
 
+    pub(crate) fn tag_type(&self, x: TagIdx) -> FuncType<'m> {
+        let mut parser = self.section(SectionId::Tag).unwrap();
+        for i in 0 .. parser.parse_vec().into_ok() {
+            let y = parser.parse_tagtype().into_ok();
+            if i == x as usize {
+                return self.type_(y);
+            }
+        }
+        unreachable!()
+    }
+
     pub(crate) fn export(&self, expected_name: &str) -> Option<ExportDesc> {
         let mut parser = self.section(SectionId::Export).unwrap();
         for _ in 0 .. parser.parse_vec().into_ok() {
@@ -194,15 +344,9 @@ impl<'m> Module<'m> {
     }
 
     pub(crate) fn func(&self, x: FuncIdx) -> Parser<'m> {
-        let mut parser = self.section(SectionId::Code).unwrap();
-        for i in 0 .. parser.parse_vec().into_ok() {
-            let size = parser.parse_u32().into_ok() as usize;
-            let parser = parser.split_at(size).into_ok();
-            if i == x as usize {
-                return parser;
-            }
-        }
-        unreachable!()
+        let mut parser = self.parser(self.side_table.func_offset(x));
+        let size = parser.parse_u32().into_ok() as usize;
+        parser.split_at(size).into_ok()
     }
 
     pub(crate) fn data(&self, x: DataIdx) -> Parser<'m> {
@@ -216,27 +360,169 @@ impl<'m> Module<'m> {
         unreachable!()
     }
 
-    pub(crate) fn skip(
-        &mut self, parser: &mut Parser<'m>, depth: LabelIdx,
-        compute: impl Fn(&mut Parser<'m>, LabelIdx),
-    ) {
-        let saved = parser.save();
-        let key = CacheKey::Skip { ptr: saved.as_ptr(), depth };
-        match self.cache.get(&key) {
-            Some(delta) => unsafe { parser.restore(&saved[delta.skip ..]) },
-            None => {
-                compute(parser, depth);
-                let delta = saved.len() - parser.save().len();
-                self.cache.put(key, CacheValue { skip: delta });
+    /// Returns the offset of a position from the start of the module.
+    pub(crate) fn offset(&self, pos: &[u8]) -> usize {
+        // The header is not part of the binary.
+        8 + (pos.as_ptr() as usize - self.binary.as_ptr() as usize)
+    }
+
+    /// Returns a parser at an offset (as returned by [`Self::offset()`]).
+    fn parser(&self, offset: usize) -> Parser<'m> {
+        unsafe { Parser::new(&self.binary[offset - 8 ..]) }
+    }
+
+    /// Returns the slice of the binary at an offset (as returned by [`Self::offset()`]).
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> Option<&'m [u8]> {
+        self.binary.get(offset.checked_sub(8)? ..)?.get(.. len)
+    }
+
+    /// Returns the FNV-1a hash of the binary.
+    ///
+    /// This is only meant to detect accidental use of a different module.
+    pub(crate) fn hash(&self) -> u64 {
+        fnv1a(self.binary)
+    }
+
+    /// Returns the name of a function from the name section, if any.
+    pub(crate) fn func_name(&self, x: FuncIdx) -> Option<&'m str> {
+        // Custom sections are not validated.
+        let mut parser = parser::Parser::<Check>::new(self.custom_section("name")?);
+        while !parser.is_empty() {
+            let id = parser.parse_byte().ok()?;
+            let size = parser.parse_u32().ok()? as usize;
+            let mut subsection = parser.split_at(size).ok()?;
+            if id != 1 {
+                continue;
+            }
+            for _ in 0 .. subsection.parse_vec().ok()? {
+                let y = subsection.parse_funcidx().ok()?;
+                let name = subsection.parse_name().ok()?;
+                if x == y {
+                    return Some(name);
+                }
             }
+            break;
         }
+        None
+    }
+
+    pub(crate) fn side_table_entry(&self, stp: usize) -> SideTableEntry {
+        self.side_table.entry(stp)
+    }
+
+    /// Returns the index of the first side table entry of a function.
+    pub(crate) fn side_table_start(&self, x: FuncIdx) -> usize {
+        self.side_table.func(x)
+    }
+
+    /// Returns the maximum stack height of a function.
+    pub(crate) fn max_stack(&self, x: FuncIdx) -> usize {
+        self.side_table.max_stack(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wast::parser::{self, ParseBuffer};
+    use wast::Wat;
+
+    use super::*;
+
+    fn encode(wat: &str) -> Vec<u8> {
+        let buffer = ParseBuffer::new(wat).unwrap();
+        parser::parse::<Wat>(&buffer).unwrap().encode().unwrap()
+    }
+
+    const WAT: &str = r#"(module
+      (func (export "f") (param i32) (result i32)
+        (block (br_if 0 (local.get 0)) (return (i32.const 1)))
+        (i32.const 2)))"#;
+
+    #[test]
+    fn prepare_roundtrip() {
+        let binary = encode(WAT);
+        let module = Module::new(&binary).unwrap();
+        let prepared = module.prepare().unwrap();
+        let again = unsafe { Module::new_prepared(&prepared) }.unwrap();
+        assert_eq!(again.prepare().err(), Some(Error::Invalid));
+        let mut signed = prepared.clone();
+        signed.extend_from_slice(b"\0\x05\x04sign");
+        let actual = unsafe { Module::new_prepared(&signed) }.unwrap();
+        assert_eq!(actual.side_table(), module.side_table());
+        assert_eq!(actual.func_type(0), module.func_type(0));
+    }
+
+    #[test]
+    fn prepared_tables() {
+        let binary = encode(
+            r#"(module
+              (type (func))
+              (type (func (param i32) (result i32)))
+              (type (func (param i64)))
+              (import "env" "g" (func (type 2)))
+              (func (type 1) (local.get 0))
+              (func (type 0))
+              (func (type 1) (i32.const 3)))"#,
+        );
+        let prepared = Module::new(&binary).unwrap().prepare().unwrap();
+        let module = unsafe { Module::new_prepared(&prepared) }.unwrap();
+        let i32_i32 =
+            FuncType { params: [ValType::I32][..].into(), results: [ValType::I32][..].into() };
+        assert_eq!(module.type_(0), FuncType { params: [][..].into(), results: [][..].into() });
+        assert_eq!(module.type_(1), i32_i32);
+        assert_eq!(
+            module.imports().next().unwrap().type_(&module),
+            ExternType::Func(module.type_(2))
+        );
+        // Function indices exclude imported functions.
+        assert_eq!(module.func_type(0), i32_i32);
+        assert_eq!(module.func_type(2), i32_i32);
+        // The function body starts with its locals.
+        assert_eq!(module.func(0).save(), b"\0\x20\0\x0b");
+        assert_eq!(module.func(1).save(), b"\0\x0b");
+        assert_eq!(module.func(2).save(), b"\0\x41\x03\x0b");
+    }
+
+    #[test]
+    fn custom_sections_roundtrip() {
+        let mut binary = encode(WAT);
+        let len = binary.len();
+        push_custom_section(&mut binary, "foo", b"bar").unwrap();
+        push_custom_section(&mut binary, "foo", &[0; 200]).unwrap();
+        let sections = custom_sections(&binary).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(
+            sections[0],
+            CustomSection { name: "foo", content: b"bar", range: len .. len + 9 }
+        );
+        assert_eq!(sections[1].content, &[0; 200]);
+        assert_eq!(sections[1].range, len + 9 .. binary.len());
     }
 
-    pub(crate) fn skip_to_else(&mut self, parser: &mut Parser<'m>) {
-        self.skip(parser, 0, |p, _| p.skip_to_else().into_ok());
+    #[test]
+    fn custom_sections_errors() {
+        let binary = encode(WAT);
+        assert_eq!(custom_sections(&binary[.. 7]), Err(Error::Invalid));
+        assert_eq!(custom_sections(&binary[.. binary.len() - 1]), Err(Error::Invalid));
+        let mut invalid = binary.clone();
+        invalid[4] = 2;
+        assert_eq!(custom_sections(&invalid), Err(Error::Invalid));
+        // The section size is not a valid LEB128 (overlong).
+        let mut invalid = binary.clone();
+        invalid.extend_from_slice(b"\0\x80\x80\x80\x80\x80\x00");
+        assert_eq!(custom_sections(&invalid), Err(Error::Invalid));
+        // The section name is not valid UTF-8.
+        let mut invalid = binary;
+        invalid.extend_from_slice(b"\0\x02\x01\xff");
+        assert_eq!(custom_sections(&invalid), Err(Error::Invalid));
     }
 
-    pub(crate) fn skip_to_end(&mut self, parser: &mut Parser<'m>, l: LabelIdx) {
-        self.skip(parser, l, |p, l| p.skip_to_end(l).into_ok());
+    #[test]
+    fn prepare_errors() {
+        let binary = encode(WAT);
+        assert_eq!(unsafe { Module::new_prepared(&binary) }.err(), Some(Error::NotFound));
+        let mut prepared = Module::new(&binary).unwrap().prepare().unwrap();
+        prepared[binary.len() - 1] ^= 1;
+        assert_eq!(unsafe { Module::new_prepared(&prepared) }.err(), Some(Error::Invalid));
     }
 }
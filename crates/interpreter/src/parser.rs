@@ -154,6 +154,10 @@ impl<'m, M: Mode> Parser<'m, M> {
         self.parse_u32()
     }
 
+    pub fn parse_tagidx(&mut self) -> MResult<TypeIdx, M> {
+        self.parse_u32()
+    }
+
     pub fn parse_section_id(&mut self) -> MResult<SectionId, M> {
         byte_enum::<M, _>(self.parse_byte()?)
     }
@@ -201,8 +205,12 @@ impl<'m, M: Mode> Parser<'m, M> {
         byte_enum::<M, _>(self.parse_byte()?)
     }
 
-    pub fn parse_limits(&mut self, mut max: u32) -> MResult<Limits, M> {
+    pub fn parse_limits(&mut self, max: u32) -> MResult<Limits, M> {
         let has_max = byte_enum::<M, bool>(self.parse_byte()?)?;
+        self.parse_limits_(has_max, max)
+    }
+
+    fn parse_limits_(&mut self, has_max: bool, mut max: u32) -> MResult<Limits, M> {
         let min = self.parse_u32()?;
         if has_max {
             max = self.parse_u32()?;
@@ -217,7 +225,14 @@ impl<'m, M: Mode> Parser<'m, M> {
     }
 
     pub fn parse_memtype(&mut self) -> MResult<MemType, M> {
-        self.parse_limits(MEM_MAX)
+        let has_max = match self.parse_byte()? {
+            // Shared memories must have a maximum. They are otherwise treated as unshared memories
+            // since execution is single-threaded.
+            #[cfg(feature = "threads")]
+            3 => true,
+            x => byte_enum::<M, bool>(x)?,
+        };
+        self.parse_limits_(has_max, MEM_MAX)
     }
 
     pub fn parse_mut(&mut self) -> MResult<Mut, M> {
@@ -230,12 +245,25 @@ impl<'m, M: Mode> Parser<'m, M> {
         Ok(GlobalType { mutable, value })
     }
 
+    /// Parses a tag type and returns the index of its function type.
+    pub fn parse_tagtype(&mut self) -> MResult<TypeIdx, M> {
+        // The only attribute is exception.
+        let attribute = self.parse_byte()?;
+        M::check(|| attribute == 0)?;
+        self.parse_typeidx()
+    }
+
     pub fn parse_importdesc(&mut self) -> MResult<ImportDesc, M> {
         Ok(match self.parse_byte()? {
             0 => ImportDesc::Func(self.parse_typeidx()?),
             1 => ImportDesc::Table(self.parse_tabletype()?),
             2 => ImportDesc::Mem(self.parse_memtype()?),
             3 => ImportDesc::Global(self.parse_globaltype()?),
+            4 => support_if!(
+                "exception-handling"[],
+                ImportDesc::Tag(self.parse_tagtype()?),
+                M::unsupported(if_debug!(Unsupported::Tag))?
+            ),
             _ => M::invalid()?,
         })
     }
@@ -246,14 +274,21 @@ impl<'m, M: Mode> Parser<'m, M> {
             1 => ExportDesc::Table(self.parse_tableidx()?),
             2 => ExportDesc::Mem(self.parse_memidx()?),
             3 => ExportDesc::Global(self.parse_globalidx()?),
+            4 => ExportDesc::Tag(self.parse_tagidx()?),
             _ => M::invalid()?,
         })
     }
 
     pub fn parse_memarg(&mut self) -> MResult<MemArg, M> {
-        let align = self.parse_u32()?;
+        let mut align = self.parse_u32()?;
+        // The memory index is only present if bit 6 of the alignment is set.
+        let mut memory = 0;
+        if align & 0x40 != 0 {
+            align &= !0x40;
+            memory = self.parse_memidx()?;
+        }
         let offset = self.parse_u32()?;
-        Ok(MemArg { align, offset })
+        Ok(MemArg { align, offset, memory })
     }
 
     pub fn parse_blocktype(&mut self) -> MResult<BlockType, M> {
@@ -272,6 +307,25 @@ impl<'m, M: Mode> Parser<'m, M> {
         Ok(Instr::BrTable(labels, self.parse_labelidx()?))
     }
 
+    #[cfg(feature = "exception-handling")]
+    pub fn parse_catch(&mut self) -> MResult<Catch, M> {
+        let kind = self.parse_byte()?;
+        M::check(|| kind < 4)?;
+        let tag = match kind & 2 {
+            0 => Some(self.parse_tagidx()?),
+            _ => None,
+        };
+        Ok(Catch { tag, is_ref: kind & 1 != 0, label: self.parse_labelidx()? })
+    }
+
+    #[cfg(feature = "exception-handling")]
+    pub fn parse_try_table(&mut self) -> MResult<Instr<'m>, M> {
+        let b = self.parse_blocktype()?;
+        let catches =
+            (0 .. self.parse_u32()?).map(|_| self.parse_catch()).collect::<Result<_, _>>()?;
+        Ok(Instr::TryTable(b, catches))
+    }
+
     pub fn parse_instr_fc(&mut self) -> MResult<Instr<'m>, M> {
         Ok(match self.parse_u32()? {
             x @ 0 ..= 7 => support_if!(
@@ -285,18 +339,14 @@ impl<'m, M: Mode> Parser<'m, M> {
             ),
             8 => {
                 let x = self.parse_dataidx()?;
-                check_eq::<M, _>(self.parse_byte()?, 0)?;
-                Instr::MemoryInit(x)
+                Instr::MemoryInit(x, self.parse_memidx()?)
             }
             9 => Instr::DataDrop(self.parse_dataidx()?),
             10 => {
-                check_eq::<M, _>(self.parse_bytes(2)?, &[0; 2])?;
-                Instr::MemoryCopy
-            }
-            11 => {
-                check_eq::<M, _>(self.parse_byte()?, 0)?;
-                Instr::MemoryFill
+                let x = self.parse_memidx()?;
+                Instr::MemoryCopy(x, self.parse_memidx()?)
             }
+            11 => Instr::MemoryFill(self.parse_memidx()?),
             12 => {
                 // For some reason, parsing order differs from field order here.
                 let y = self.parse_elemidx()?;
@@ -312,6 +362,188 @@ impl<'m, M: Mode> Parser<'m, M> {
         })
     }
 
+    #[cfg(feature = "vector-types")]
+    pub fn parse_instr_fd(&mut self) -> MResult<Instr<'m>, M> {
+        use Lx::*;
+        let x = self.parse_u32()?;
+        macro_rules! float {
+            ([$($var:ident)*] $then:expr) => {
+                support_if!(
+                    "float-types"[$($var)*],
+                    $then,
+                    M::unsupported(if_debug!(Unsupported::OpcodeFd(x)))?
+                )
+            };
+        }
+        let sx = |y: u32| Sx::from(y as u8);
+        Ok(match x {
+            0x00 => Instr::V128Load(self.parse_memarg()?),
+            y @ 0x01 ..= 0x06 => {
+                let l = Lx::from(((y - 0x01) / 2) as u8);
+                Instr::V128LoadExtend(l, sx((y - 0x01) % 2), self.parse_memarg()?)
+            }
+            y @ 0x07 ..= 0x0a => {
+                Instr::V128LoadSplat(((y - 0x07) as u8).into(), self.parse_memarg()?)
+            }
+            0x0b => Instr::V128Store(self.parse_memarg()?),
+            0x0c => {
+                Instr::V128Const(u128::from_le_bytes(self.parse_bytes(16)?.try_into().unwrap()))
+            }
+            0x0d => Instr::I8x16Shuffle(self.parse_bytes(16)?.try_into().unwrap()),
+            0x0e => Instr::I8x16Swizzle,
+            y @ 0x0f ..= 0x12 => Instr::VSplat(Shape::I(((y - 0x0f) as u8).into())),
+            y @ 0x13 ..= 0x14 => float!([y] Instr::VSplat(Shape::F(((y - 0x13) as u8).into()))),
+            y @ 0x15 ..= 0x1a => {
+                let l = Lx::from(((y - 0x15) / 3) as u8);
+                match (y - 0x15) % 3 {
+                    2 => Instr::VReplaceLane(Shape::I(l), self.parse_byte()?),
+                    z => Instr::VExtractLane(Shape::I(l), sx(z), self.parse_byte()?),
+                }
+            }
+            y @ 0x1b ..= 0x1e => {
+                let l = Lx::from(((y - 0x1b) / 2 + 2) as u8);
+                match (y - 0x1b) % 2 {
+                    0 => Instr::VExtractLane(Shape::I(l), Sx::U, self.parse_byte()?),
+                    _ => Instr::VReplaceLane(Shape::I(l), self.parse_byte()?),
+                }
+            }
+            y @ 0x1f ..= 0x22 => float!([y] {
+                let s = Shape::F((((y - 0x1f) / 2) as u8).into());
+                match (y - 0x1f) % 2 {
+                    0 => Instr::VExtractLane(s, Sx::U, self.parse_byte()?),
+                    _ => Instr::VReplaceLane(s, self.parse_byte()?),
+                }
+            }),
+            y @ 0x23 ..= 0x40 => {
+                Instr::VIRelOp((((y - 0x23) / 10) as u8).into(), (((y - 0x23) % 10) as u8).into())
+            }
+            y @ 0x41 ..= 0x4c => {
+                float!([y] Instr::VFRelOp((((y - 0x41) / 6) as u8).into(), (((y - 0x41) % 6) as u8).into()))
+            }
+            0x4d => Instr::V128Not,
+            y @ 0x4e ..= 0x51 => Instr::VvBinOp(((y - 0x4e) as u8).into()),
+            0x52 => Instr::V128Bitselect,
+            0x53 => Instr::V128AnyTrue,
+            y @ 0x54 ..= 0x57 => {
+                let m = self.parse_memarg()?;
+                Instr::V128LoadLane(((y - 0x54) as u8).into(), m, self.parse_byte()?)
+            }
+            y @ 0x58 ..= 0x5b => {
+                let m = self.parse_memarg()?;
+                Instr::V128StoreLane(((y - 0x58) as u8).into(), m, self.parse_byte()?)
+            }
+            0x5c => Instr::V128LoadZero(L32, self.parse_memarg()?),
+            0x5d => Instr::V128LoadZero(L64, self.parse_memarg()?),
+            0x5e => float!([] Instr::VCvtOp(VCvtOp::DemoteZero)),
+            0x5f => float!([] Instr::VCvtOp(VCvtOp::PromoteLow)),
+            0x62 => Instr::VIUnOp(L8, VIUnOp::PopCnt),
+            0x82 => Instr::VIBinOp(L16, VIBinOp::Q15MulrSatS),
+            y @ (0x65 ..= 0x66 | 0x85 ..= 0x86) => {
+                Instr::VINarrow((((y - 0x65) / 0x20) as u8).into(), sx((y - 0x65) % 2))
+            }
+            y @ 0x67 ..= 0x6a => float!([y] Instr::VFUnOp(Nx::N32, ((y - 0x67 + 2) as u8).into())),
+            0x74 => float!([] Instr::VFUnOp(Nx::N64, FUnOp::Ceil)),
+            0x75 => float!([] Instr::VFUnOp(Nx::N64, FUnOp::Floor)),
+            0x7a => float!([] Instr::VFUnOp(Nx::N64, FUnOp::Trunc)),
+            0x94 => float!([] Instr::VFUnOp(Nx::N64, FUnOp::Nearest)),
+            y @ 0x7c ..= 0x7f => {
+                let l = Lx::from(((y - 0x7c) / 2 + 1) as u8);
+                Instr::VIExtAddPairwise(l, sx((y - 0x7c) % 2))
+            }
+            0xba => Instr::VIDot,
+            y @ 0xd6 ..= 0xdb => Instr::VIRelOp(
+                L64,
+                match y {
+                    0xd6 => IRelOp::Eq,
+                    0xd7 => IRelOp::Ne,
+                    0xd8 => IRelOp::Lt(Sx::S),
+                    0xd9 => IRelOp::Gt(Sx::S),
+                    0xda => IRelOp::Le(Sx::S),
+                    _ => IRelOp::Ge(Sx::S),
+                },
+            ),
+            // Integer operations share a layout for each lane width (except some gaps).
+            y @ 0x60 ..= 0xdf => {
+                let l = Lx::from(((y - 0x60) / 0x20) as u8);
+                let z = (y - 0x60) % 0x20;
+                match z {
+                    0x00 => Instr::VIUnOp(l, VIUnOp::Abs),
+                    0x01 => Instr::VIUnOp(l, VIUnOp::Neg),
+                    0x03 => Instr::VIAllTrue(l),
+                    0x04 => Instr::VIBitmask(l),
+                    0x07 ..= 0x0a if l != L8 => {
+                        Instr::VIExtend(l, Half::from(((z - 0x07) % 2) as u8), sx((z - 0x07) / 2))
+                    }
+                    0x0b => Instr::VIShiftOp(l, VIShiftOp::Shl),
+                    0x0c ..= 0x0d => Instr::VIShiftOp(l, VIShiftOp::Shr(sx(z - 0x0c))),
+                    0x0e => Instr::VIBinOp(l, VIBinOp::Add),
+                    0x0f ..= 0x10 if l < L32 => Instr::VIBinOp(l, VIBinOp::AddSat(sx(z - 0x0f))),
+                    0x11 => Instr::VIBinOp(l, VIBinOp::Sub),
+                    0x12 ..= 0x13 if l < L32 => Instr::VIBinOp(l, VIBinOp::SubSat(sx(z - 0x12))),
+                    0x15 if l != L8 => Instr::VIBinOp(l, VIBinOp::Mul),
+                    0x16 ..= 0x17 if l != L64 => Instr::VIBinOp(l, VIBinOp::Min(sx(z - 0x16))),
+                    0x18 ..= 0x19 if l != L64 => Instr::VIBinOp(l, VIBinOp::Max(sx(z - 0x18))),
+                    0x1b if l < L32 => Instr::VIBinOp(l, VIBinOp::AvgrU),
+                    0x1c ..= 0x1f if l != L8 => {
+                        Instr::VIExtMul(l, Half::from(((z - 0x1c) % 2) as u8), sx((z - 0x1c) / 2))
+                    }
+                    _ => M::invalid()?,
+                }
+            }
+            y @ 0xe0 ..= 0xf7 => float!([y] {
+                let n = Nx::from(((y - 0xe0) / 12) as u8);
+                match (y - 0xe0) % 12 {
+                    0 => Instr::VFUnOp(n, FUnOp::Abs),
+                    1 => Instr::VFUnOp(n, FUnOp::Neg),
+                    3 => Instr::VFUnOp(n, FUnOp::Sqrt),
+                    z @ 4 .. => Instr::VFBinOp(n, ((z - 4) as u8).into()),
+                    _ => M::invalid()?,
+                }
+            }),
+            y @ 0xf8 ..= 0xff => float!([y] {
+                let s = sx((y - 0xf8) % 2);
+                Instr::VCvtOp(match (y - 0xf8) / 2 {
+                    0 => VCvtOp::TruncSat(s),
+                    1 => VCvtOp::Convert(s),
+                    2 => VCvtOp::TruncSatZero(s),
+                    _ => VCvtOp::ConvertLow(s),
+                })
+            }),
+            _ => M::invalid()?,
+        })
+    }
+
+    #[cfg(feature = "threads")]
+    pub fn parse_instr_fe(&mut self) -> MResult<Instr<'m>, M> {
+        Ok(match self.parse_u32()? {
+            0x00 => Instr::MemoryAtomicNotify(self.parse_memarg()?),
+            x @ 0x01 ..= 0x02 => {
+                Instr::MemoryAtomicWait(((x - 0x01) as u8).into(), self.parse_memarg()?)
+            }
+            0x03 => {
+                let x = self.parse_byte()?;
+                M::check(|| x == 0)?;
+                Instr::AtomicFence
+            }
+            // Each operation comes in 7 widths: i32, i64, then the narrow widths in Bx order.
+            x @ 0x10 ..= 0x4e => {
+                let m = self.parse_memarg()?;
+                let (op, w) = ((x - 0x10) / 7, ((x - 0x10) % 7) as u8);
+                match (op, w) {
+                    (0, 0 ..= 1) => Instr::IAtomicLoad(w.into(), m),
+                    (0, _) => Instr::IAtomicLoad_((w - 2).into(), m),
+                    (1, 0 ..= 1) => Instr::IAtomicStore(w.into(), m),
+                    (1, _) => Instr::IAtomicStore_((w - 2).into(), m),
+                    (8, 0 ..= 1) => Instr::IAtomicCmpxchg(w.into(), m),
+                    (8, _) => Instr::IAtomicCmpxchg_((w - 2).into(), m),
+                    (_, 0 ..= 1) => Instr::IAtomicRmw(w.into(), ((op - 2) as u8).into(), m),
+                    (_, _) => Instr::IAtomicRmw_((w - 2).into(), ((op - 2) as u8).into(), m),
+                }
+            }
+            _ => M::invalid()?,
+        })
+    }
+
     pub fn parse_instr(&mut self) -> MResult<Instr<'m>, M> {
         Ok(match self.parse_byte()? {
             0x00 => Instr::Unreachable,
@@ -320,6 +552,16 @@ impl<'m, M: Mode> Parser<'m, M> {
             0x03 => Instr::Loop(self.parse_blocktype()?),
             0x04 => Instr::If(self.parse_blocktype()?),
             0x05 => Instr::Else,
+            0x08 => support_if!(
+                "exception-handling"[],
+                Instr::Throw(self.parse_tagidx()?),
+                M::unsupported(if_debug!(Unsupported::Opcode(0x08)))?
+            ),
+            0x0a => support_if!(
+                "exception-handling"[],
+                Instr::ThrowRef,
+                M::unsupported(if_debug!(Unsupported::Opcode(0x0a)))?
+            ),
             0x0b => Instr::End,
             0x0c => Instr::Br(self.parse_labelidx()?),
             0x0d => Instr::BrIf(self.parse_labelidx()?),
@@ -332,9 +574,28 @@ impl<'m, M: Mode> Parser<'m, M> {
                 let x = self.parse_tableidx()?;
                 Instr::CallIndirect(x, y)
             }
+            0x12 => support_if!(
+                "tail-call"[],
+                Instr::ReturnCall(self.parse_funcidx()?),
+                M::unsupported(if_debug!(Unsupported::Opcode(0x12)))?
+            ),
+            0x13 => support_if!(
+                "tail-call"[],
+                {
+                    let y = self.parse_typeidx()?;
+                    let x = self.parse_tableidx()?;
+                    Instr::ReturnCallIndirect(x, y)
+                },
+                M::unsupported(if_debug!(Unsupported::Opcode(0x13)))?
+            ),
             0x1a => Instr::Drop,
             0x1b => Instr::Select(None),
             0x1c => Instr::Select(Some(self.parse_resulttype()?)),
+            0x1f => support_if!(
+                "exception-handling"[],
+                self.parse_try_table()?,
+                M::unsupported(if_debug!(Unsupported::Opcode(0x1f)))?
+            ),
             0x20 => Instr::LocalGet(self.parse_localidx()?),
             0x21 => Instr::LocalSet(self.parse_localidx()?),
             0x22 => Instr::LocalTee(self.parse_localidx()?),
@@ -360,14 +621,8 @@ impl<'m, M: Mode> Parser<'m, M> {
                 M::unsupported(if_debug!(Unsupported::Opcode(x)))?
             ),
             x @ 0x3a ..= 0x3e => Instr::IStore_((x - 0x3a).into(), self.parse_memarg()?),
-            0x3f => {
-                check_eq::<M, _>(self.parse_byte()?, 0)?;
-                Instr::MemorySize
-            }
-            0x40 => {
-                check_eq::<M, _>(self.parse_byte()?, 0)?;
-                Instr::MemoryGrow
-            }
+            0x3f => Instr::MemorySize(self.parse_memidx()?),
+            0x40 => Instr::MemoryGrow(self.parse_memidx()?),
             0x41 => Instr::I32Const(self.parse_i32()?),
             0x42 => Instr::I64Const(self.parse_i64()?),
             0x43 => support_if!(
@@ -461,9 +716,14 @@ impl<'m, M: Mode> Parser<'m, M> {
             0xfc => self.parse_instr_fc()?,
             0xfd => support_if!(
                 "vector-types"[],
-                unimplemented!(),
+                self.parse_instr_fd()?,
                 M::unsupported(if_debug!(Unsupported::Opcode(0xfd)))?
             ),
+            0xfe => support_if!(
+                "threads"[],
+                self.parse_instr_fe()?,
+                M::unsupported(if_debug!(Unsupported::Opcode(0xfe)))?
+            ),
             _ => M::invalid()?,
         })
     }
@@ -550,25 +810,6 @@ impl<'m, M: Mode> Parser<'m, M> {
         user.init(self.parse_bytes(len)?)
     }
 
-    pub fn skip_to_else(&mut self) -> MResult<(), M> {
-        let mut depth = 0;
-        loop {
-            let saved = self.save();
-            match self.parse_instr()? {
-                Instr::Block(_) => depth += 1,
-                Instr::Loop(_) => depth += 1,
-                Instr::If(_) => depth += 1,
-                Instr::End if depth == 0 => {
-                    unsafe { self.restore(saved) };
-                    return Ok(());
-                }
-                Instr::End => depth -= 1,
-                Instr::Else if depth == 0 => return Ok(()),
-                _ => (),
-            }
-        }
-    }
-
     pub fn skip_to_end(&mut self, l: LabelIdx) -> MResult<(), M> {
         let mut depth = l as usize + 1;
         while depth > 0 {
@@ -576,6 +817,8 @@ impl<'m, M: Mode> Parser<'m, M> {
                 Instr::Block(_) => depth += 1,
                 Instr::Loop(_) => depth += 1,
                 Instr::If(_) => depth += 1,
+                #[cfg(feature = "exception-handling")]
+                Instr::TryTable(..) => depth += 1,
                 Instr::End => depth -= 1,
                 _ => (),
             }
@@ -650,11 +893,7 @@ impl<'m, M: Mode> Parser<'m, M> {
 
 /// Maximum number of locals (must be less than 2^32).
 // NOTE: This should be configurable.
-const MAX_LOCALS: usize = 100;
-
-fn check_eq<M: Mode, T: Eq>(x: T, y: T) -> MResult<(), M> {
-    M::check(|| x == y)
-}
+pub const MAX_LOCALS: usize = 100;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,181 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Side table for control flow.
+//!
+//! Validation records one entry for each branch site (`if`, `else`, `br`, `br_if`, and each label
+//! of `br_table`) in program order. Execution keeps a side table pointer next to the instruction
+//! pointer and uses the entry of a branch site to jump to its target without parsing.
+//!
+//! Validation also records the maximum height of the value stack of each function, such that
+//! execution can account for the stack usage of a call when entering the function.
+//!
+//! Finally, validation records the offset of each type and the type and offset of each function,
+//! such that execution can look them up without walking the type, function, and code sections.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use crate::error::*;
+use crate::syntax::{FuncIdx, TypeIdx};
+
+/// Side table entry of a branch site.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SideTableEntry {
+    /// Offset (in bytes) from the branch instruction to the target instruction.
+    pub delta_ip: i32,
+    /// Offset (in entries) from this entry to the first entry after the target.
+    pub delta_stp: i16,
+    /// Number of values kept when branching.
+    pub val_cnt: u16,
+}
+
+impl SideTableEntry {
+    const SIZE: usize = 8;
+
+    /// Creates an entry, if the deltas and count fit.
+    pub fn new(delta_ip: isize, delta_stp: isize, val_cnt: usize) -> Result<Self, Error> {
+        let unsupported = |_| unsupported(if_debug!(Unsupported::SideTable));
+        Ok(SideTableEntry {
+            delta_ip: delta_ip.try_into().map_err(unsupported)?,
+            delta_stp: delta_stp.try_into().map_err(unsupported)?,
+            val_cnt: val_cnt.try_into().map_err(unsupported)?,
+        })
+    }
+
+    fn encode(self) -> [u8; Self::SIZE] {
+        let x = self.delta_ip as u32 as u64
+            | (self.delta_stp as u16 as u64) << 32
+            | (self.val_cnt as u64) << 48;
+        x.to_le_bytes()
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let x = u64::from_le_bytes(bytes[.. Self::SIZE].try_into().unwrap());
+        SideTableEntry {
+            delta_ip: x as i32,
+            delta_stp: (x >> 32) as i16,
+            val_cnt: (x >> 48) as u16,
+        }
+    }
+}
+
+/// Side table of a function.
+#[derive(Debug, Default)]
+pub struct FuncSideTable {
+    pub entries: Vec<SideTableEntry>,
+    /// Maximum number of values on the stack (excluding locals).
+    pub max_stack: usize,
+    /// Type of the function.
+    pub type_: TypeIdx,
+    /// Offset (in bytes) of the function (starting with its size) from the start of the module.
+    pub offset: usize,
+}
+
+/// Side table of a module.
+///
+/// The serialized form is the number of functions, then the index of the first entry and the
+/// maximum stack height of each function, then the entries. Integers are in little-endian.
+#[derive(Debug, Default)]
+pub struct SideTable<'m> {
+    bytes: Cow<'m, [u8]>,
+}
+
+impl<'m> SideTable<'m> {
+    /// Creates a side table from the offset of each type and the side table of each function.
+    ///
+    /// Offsets are in bytes from the start of the module.
+    pub fn new(types: &[usize], funcs: &[FuncSideTable]) -> Result<Self, Error> {
+        let num_entries: usize = funcs.iter().map(|x| x.entries.len()).sum();
+        let mut bytes = Vec::with_capacity(
+            8 + 4 * types.len() + 16 * funcs.len() + SideTableEntry::SIZE * num_entries,
+        );
+        let mut push = |x: usize| {
+            let x = u32::try_from(x).map_err(|_| unsupported(if_debug!(Unsupported::SideTable)))?;
+            bytes.extend_from_slice(&x.to_le_bytes());
+            Ok::<_, Error>(())
+        };
+        push(types.len())?;
+        push(funcs.len())?;
+        for &offset in types {
+            push(offset)?;
+        }
+        let mut start = 0usize;
+        for func in funcs {
+            push(func.type_ as usize)?;
+            push(func.offset)?;
+            push(start)?;
+            push(func.max_stack)?;
+            start += func.entries.len();
+        }
+        for entry in funcs.iter().flat_map(|x| &x.entries) {
+            bytes.extend_from_slice(&entry.encode());
+        }
+        Ok(SideTable { bytes: Cow::Owned(bytes) })
+    }
+
+    /// Wraps a serialized side table.
+    pub fn borrowed(bytes: &'m [u8]) -> Self {
+        SideTable { bytes: Cow::Borrowed(bytes) }
+    }
+
+    /// Returns the serialized side table.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the offset of a type (from the start of the module).
+    pub fn type_offset(&self, x: TypeIdx) -> usize {
+        self.u32(2 + x as usize) as usize
+    }
+
+    /// Returns the type of a function (without imported functions).
+    pub fn func_type(&self, x: FuncIdx) -> TypeIdx {
+        self.func_u32(x, 0)
+    }
+
+    /// Returns the offset of a function (without imported functions) from the start of the module.
+    pub fn func_offset(&self, x: FuncIdx) -> usize {
+        self.func_u32(x, 1) as usize
+    }
+
+    /// Returns the index of the first entry of a function (without imported functions).
+    pub fn func(&self, x: FuncIdx) -> usize {
+        self.func_u32(x, 2) as usize
+    }
+
+    /// Returns the maximum stack height of a function (without imported functions).
+    pub fn max_stack(&self, x: FuncIdx) -> usize {
+        self.func_u32(x, 3) as usize
+    }
+
+    /// Returns the entry at a given index.
+    pub fn entry(&self, stp: usize) -> SideTableEntry {
+        let offset = 4 * self.entries_start() + SideTableEntry::SIZE * stp;
+        SideTableEntry::decode(&self.bytes[offset ..])
+    }
+
+    /// Returns the index (in words) of the first entry.
+    fn entries_start(&self) -> usize {
+        2 + self.u32(0) as usize + 4 * self.u32(1) as usize
+    }
+
+    fn func_u32(&self, x: FuncIdx, i: usize) -> u32 {
+        self.u32(2 + self.u32(0) as usize + 4 * x as usize + i)
+    }
+
+    fn u32(&self, i: usize) -> u32 {
+        u32::from_le_bytes(self.bytes[4 * i ..][.. 4].try_into().unwrap())
+    }
+}
@@ -37,6 +37,7 @@ pub enum VecType {
 pub enum RefType {
     FuncRef = 0x70,
     ExternRef = 0x6f,
+    ExnRef = 0x69,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive, UnsafeFromPrimitive)]
@@ -49,6 +50,7 @@ pub enum ValType {
     V128 = 0x7b,
     FuncRef = 0x70,
     ExternRef = 0x6f,
+    ExnRef = 0x69,
 }
 
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
@@ -121,6 +123,17 @@ pub enum Bx {
     N64B32,
 }
 
+#[cfg(feature = "threads")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AtomicRmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ITestOp {
     Eqz,
@@ -214,6 +227,100 @@ pub enum CvtOp {
     FReinterpret(Nx),
 }
 
+/// Lane width of integer vector shapes.
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Lx {
+    L8,
+    L16,
+    L32,
+    L64,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shape {
+    I(Lx),
+    #[cfg(feature = "float-types")]
+    F(Nx),
+}
+
+/// Which half of the lanes of a vector are used.
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Half {
+    Low,
+    High,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VvBinOp {
+    And,
+    AndNot,
+    Or,
+    Xor,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VIUnOp {
+    Abs,
+    Neg,
+    PopCnt,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VIBinOp {
+    Add,
+    Sub,
+    Mul,
+    AddSat(Sx),
+    SubSat(Sx),
+    Min(Sx),
+    Max(Sx),
+    AvgrU,
+    Q15MulrSatS,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VIShiftOp {
+    Shl,
+    Shr(Sx),
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VFBinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    PMin,
+    PMax,
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VCvtOp {
+    /// i32x4.trunc_sat_f32x4
+    TruncSat(Sx),
+    /// i32x4.trunc_sat_f64x2_zero
+    TruncSatZero(Sx),
+    /// f32x4.convert_i32x4
+    Convert(Sx),
+    /// f64x2.convert_low_i32x4
+    ConvertLow(Sx),
+    /// f32x4.demote_f64x2_zero
+    DemoteZero,
+    /// f64x2.promote_low_f32x4
+    PromoteLow,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instr<'m> {
     Unreachable,
@@ -229,6 +336,16 @@ pub enum Instr<'m> {
     Return,
     Call(FuncIdx),
     CallIndirect(TableIdx, TypeIdx), // parsing order differs
+    #[cfg(feature = "tail-call")]
+    ReturnCall(FuncIdx),
+    #[cfg(feature = "tail-call")]
+    ReturnCallIndirect(TableIdx, TypeIdx), // parsing order differs
+    #[cfg(feature = "exception-handling")]
+    Throw(TagIdx),
+    #[cfg(feature = "exception-handling")]
+    ThrowRef,
+    #[cfg(feature = "exception-handling")]
+    TryTable(BlockType, Vec<Catch>),
     Drop,
     Select(Option<ResultType<'m>>),
     LocalGet(LocalIdx),
@@ -246,8 +363,31 @@ pub enum Instr<'m> {
     #[cfg(feature = "float-types")]
     FStore(Nx, MemArg),
     IStore_(Bx, MemArg),
-    MemorySize,
-    MemoryGrow,
+    MemorySize(MemIdx),
+    MemoryGrow(MemIdx),
+    #[cfg(feature = "threads")]
+    MemoryAtomicNotify(MemArg),
+    #[cfg(feature = "threads")]
+    MemoryAtomicWait(Nx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicFence,
+    #[cfg(feature = "threads")]
+    IAtomicLoad(Nx, MemArg),
+    /// Narrow atomic loads are always unsigned.
+    #[cfg(feature = "threads")]
+    IAtomicLoad_(Bx, MemArg),
+    #[cfg(feature = "threads")]
+    IAtomicStore(Nx, MemArg),
+    #[cfg(feature = "threads")]
+    IAtomicStore_(Bx, MemArg),
+    #[cfg(feature = "threads")]
+    IAtomicRmw(Nx, AtomicRmwOp, MemArg),
+    #[cfg(feature = "threads")]
+    IAtomicRmw_(Bx, AtomicRmwOp, MemArg),
+    #[cfg(feature = "threads")]
+    IAtomicCmpxchg(Nx, MemArg),
+    #[cfg(feature = "threads")]
+    IAtomicCmpxchg_(Bx, MemArg),
     I32Const(u32),
     I64Const(u64),
     #[cfg(feature = "float-types")]
@@ -269,16 +409,87 @@ pub enum Instr<'m> {
     RefNull(RefType),
     RefIsNull,
     RefFunc(FuncIdx),
-    MemoryInit(DataIdx),
+    MemoryInit(DataIdx, MemIdx),
     DataDrop(DataIdx),
-    MemoryCopy,
-    MemoryFill,
+    MemoryCopy(MemIdx, MemIdx), // destination then source
+    MemoryFill(MemIdx),
     TableInit(TableIdx, ElemIdx), // parsing order differs
     ElemDrop(ElemIdx),
     TableCopy(TableIdx, TableIdx),
     TableGrow(TableIdx),
     TableSize(TableIdx),
     TableFill(TableIdx),
+    #[cfg(feature = "vector-types")]
+    V128Load(MemArg),
+    /// Loads 64 bits as lanes of the given width and extends them.
+    #[cfg(feature = "vector-types")]
+    V128LoadExtend(Lx, Sx, MemArg),
+    #[cfg(feature = "vector-types")]
+    V128LoadSplat(Lx, MemArg),
+    #[cfg(feature = "vector-types")]
+    V128LoadZero(Lx, MemArg),
+    #[cfg(feature = "vector-types")]
+    V128LoadLane(Lx, MemArg, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    V128Store(MemArg),
+    #[cfg(feature = "vector-types")]
+    V128StoreLane(Lx, MemArg, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    V128Const(u128),
+    #[cfg(feature = "vector-types")]
+    I8x16Shuffle(&'m [u8; 16]),
+    #[cfg(feature = "vector-types")]
+    I8x16Swizzle,
+    #[cfg(feature = "vector-types")]
+    VSplat(Shape),
+    /// The sign extension is only relevant for i8x16 and i16x8.
+    #[cfg(feature = "vector-types")]
+    VExtractLane(Shape, Sx, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    VReplaceLane(Shape, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    V128Not,
+    #[cfg(feature = "vector-types")]
+    VvBinOp(VvBinOp),
+    #[cfg(feature = "vector-types")]
+    V128Bitselect,
+    #[cfg(feature = "vector-types")]
+    V128AnyTrue,
+    #[cfg(feature = "vector-types")]
+    VIRelOp(Lx, IRelOp),
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VFRelOp(Nx, FRelOp),
+    #[cfg(feature = "vector-types")]
+    VIUnOp(Lx, VIUnOp),
+    #[cfg(feature = "vector-types")]
+    VIBinOp(Lx, VIBinOp),
+    #[cfg(feature = "vector-types")]
+    VIShiftOp(Lx, VIShiftOp),
+    #[cfg(feature = "vector-types")]
+    VIAllTrue(Lx),
+    #[cfg(feature = "vector-types")]
+    VIBitmask(Lx),
+    /// Narrows to the given lane width.
+    #[cfg(feature = "vector-types")]
+    VINarrow(Lx, Sx),
+    /// Extends to the given lane width.
+    #[cfg(feature = "vector-types")]
+    VIExtend(Lx, Half, Sx),
+    /// Extends to the given lane width before multiplying.
+    #[cfg(feature = "vector-types")]
+    VIExtMul(Lx, Half, Sx),
+    /// Extends to the given lane width before adding pairwise.
+    #[cfg(feature = "vector-types")]
+    VIExtAddPairwise(Lx, Sx),
+    /// i32x4.dot_i16x8_s
+    #[cfg(feature = "vector-types")]
+    VIDot,
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VFUnOp(Nx, FUnOp),
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VFBinOp(Nx, VFBinOp),
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VCvtOp(VCvtOp),
 }
 
 pub type TypeIdx = u32;
@@ -288,8 +499,11 @@ pub type MemIdx = u32;
 pub type GlobalIdx = u32;
 pub type ElemIdx = u32;
 pub type DataIdx = u32;
+pub type TagIdx = u32;
 pub type LocalIdx = u32;
 pub type LabelIdx = u32;
+#[cfg(feature = "vector-types")]
+pub type LaneIdx = u8;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExportDesc {
@@ -297,6 +511,7 @@ pub enum ExportDesc {
     Table(TableIdx),
     Mem(MemIdx),
     Global(GlobalIdx),
+    Tag(TagIdx),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -305,6 +520,7 @@ pub enum ImportDesc {
     Table(TableType),
     Mem(MemType),
     Global(GlobalType),
+    Tag(TypeIdx),
 }
 
 #[derive(Debug, Clone)]
@@ -320,6 +536,7 @@ pub enum ExternType<'m> {
     Table(TableType),
     Mem(MemType),
     Global(GlobalType),
+    Tag(FuncType<'m>),
 }
 
 impl<'m> ExternType<'m> {
@@ -332,6 +549,7 @@ impl<'m> ExternType<'m> {
             ) => t.matches(*s) && x == y,
             (ExternType::Mem(t), ExternType::Mem(s)) => t.matches(*s),
             (ExternType::Global(x), ExternType::Global(y)) => x == y,
+            (ExternType::Tag(x), ExternType::Tag(y)) => x == y,
             _ => false,
         }
     }
@@ -344,10 +562,22 @@ pub enum BlockType {
     Index(TypeIdx),
 }
 
+/// Handler of a `try_table` instruction.
+#[cfg(feature = "exception-handling")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Catch {
+    /// Tag of the caught exceptions (all exceptions if none).
+    pub tag: Option<TagIdx>,
+    /// Whether the exception reference is pushed after the payload.
+    pub is_ref: bool,
+    pub label: LabelIdx,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MemArg {
     pub align: u32,
     pub offset: u32,
+    pub memory: MemIdx,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive, UnsafeFromPrimitive)]
@@ -366,6 +596,7 @@ pub enum SectionId {
     Code = 10,
     Data = 11,
     DataCount = 12,
+    Tag = 13,
 }
 
 impl<'m> Deref for ResultType<'m> {
@@ -397,7 +628,7 @@ impl<'m> From<ValType> for ResultType<'m> {
                       [$i + 1][$($input)*])
             };
         }
-        make!(I32, I64, F32, F64, V128, FuncRef, ExternRef)
+        make!(I32, I64, F32, F64, V128, FuncRef, ExternRef, ExnRef)
     }
 }
 
@@ -452,6 +683,23 @@ impl From<Bx> for usize {
     }
 }
 
+#[cfg(feature = "threads")]
+impl AtomicRmwOp {
+    /// Returns the value to write given the value read and the operand.
+    ///
+    /// The result must be truncated to the width of the access.
+    pub fn apply(&self, x: u64, y: u64) -> u64 {
+        match self {
+            AtomicRmwOp::Add => x.wrapping_add(y),
+            AtomicRmwOp::Sub => x.wrapping_sub(y),
+            AtomicRmwOp::And => x & y,
+            AtomicRmwOp::Or => x | y,
+            AtomicRmwOp::Xor => x ^ y,
+            AtomicRmwOp::Xchg => y,
+        }
+    }
+}
+
 impl CvtOp {
     pub fn dst(&self) -> NumType {
         match *self {
@@ -648,6 +896,348 @@ mod float {
     }
 }
 
+#[cfg(feature = "vector-types")]
+impl Lx {
+    pub fn bits(self) -> u32 {
+        match self {
+            Lx::L8 => 8,
+            Lx::L16 => 16,
+            Lx::L32 => 32,
+            Lx::L64 => 64,
+        }
+    }
+
+    pub fn lanes(self) -> u8 {
+        (128 / self.bits()) as u8
+    }
+
+    pub fn half(self) -> Lx {
+        match self {
+            Lx::L8 => unreachable!(),
+            Lx::L16 => Lx::L8,
+            Lx::L32 => Lx::L16,
+            Lx::L64 => Lx::L32,
+        }
+    }
+
+    pub fn double(self) -> Lx {
+        match self {
+            Lx::L8 => Lx::L16,
+            Lx::L16 => Lx::L32,
+            Lx::L32 => Lx::L64,
+            Lx::L64 => unreachable!(),
+        }
+    }
+
+    /// Returns a lane of a vector, extended to 128 bits.
+    pub fn get(self, x: u128, i: u8, s: Sx) -> i128 {
+        let k = 128 - self.bits();
+        let x = x >> (i as u32 * self.bits()) << k;
+        match s {
+            Sx::U => (x >> k) as i128,
+            Sx::S => (x as i128) >> k,
+        }
+    }
+
+    /// Returns a vector with a lane replaced, truncated to the lane width.
+    pub fn set(self, x: u128, i: u8, y: i128) -> u128 {
+        let m = u128::MAX >> (128 - self.bits());
+        let k = i as u32 * self.bits();
+        (x & !(m << k)) | ((y as u128 & m) << k)
+    }
+
+    /// Returns a vector from a function computing its lanes.
+    pub fn build(self, mut f: impl FnMut(u8) -> i128) -> u128 {
+        (0 .. self.lanes()).fold(0, |x, i| self.set(x, i, f(i)))
+    }
+
+    pub fn splat(self, y: i128) -> u128 {
+        self.build(|_| y)
+    }
+
+    /// Saturates a value to the lane width.
+    pub fn sat(self, s: Sx, x: i128) -> i128 {
+        let b = self.bits();
+        match s {
+            Sx::U => x.clamp(0, (1 << b) - 1),
+            Sx::S => x.clamp(-(1 << (b - 1)), (1 << (b - 1)) - 1),
+        }
+    }
+
+    pub fn all_true(self, x: u128) -> bool {
+        (0 .. self.lanes()).all(|i| self.get(x, i, Sx::U) != 0)
+    }
+
+    pub fn bitmask(self, x: u128) -> u32 {
+        (0 .. self.lanes()).fold(0, |r, i| r | ((self.get(x, i, Sx::S) < 0) as u32) << i)
+    }
+
+    /// Narrows the lanes of 2 vectors to this lane width.
+    pub fn narrow(self, s: Sx, x: u128, y: u128) -> u128 {
+        let w = self.double();
+        let n = w.lanes();
+        self.build(|i| match i < n {
+            true => self.sat(s, w.get(x, i, Sx::S)),
+            false => self.sat(s, w.get(y, i - n, Sx::S)),
+        })
+    }
+
+    /// Extends half of the lanes of a vector to this lane width.
+    pub fn extend(self, h: Half, s: Sx, x: u128) -> u128 {
+        let o = h.offset(self);
+        self.build(|i| self.half().get(x, o + i, s))
+    }
+
+    pub fn ext_mul(self, h: Half, s: Sx, x: u128, y: u128) -> u128 {
+        let (w, o) = (self.half(), h.offset(self));
+        self.build(|i| w.get(x, o + i, s).wrapping_mul(w.get(y, o + i, s)))
+    }
+
+    pub fn ext_add_pairwise(self, s: Sx, x: u128) -> u128 {
+        let w = self.half();
+        self.build(|i| w.get(x, 2 * i, s) + w.get(x, 2 * i + 1, s))
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl Half {
+    /// Returns the index of the first lane for vectors with twice the lane width.
+    fn offset(self, l: Lx) -> u8 {
+        match self {
+            Half::Low => 0,
+            Half::High => l.lanes(),
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl From<Nx> for Lx {
+    fn from(x: Nx) -> Self {
+        match x {
+            Nx::N32 => Lx::L32,
+            Nx::N64 => Lx::L64,
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl Shape {
+    pub fn lx(self) -> Lx {
+        match self {
+            Shape::I(l) => l,
+            #[cfg(feature = "float-types")]
+            Shape::F(n) => n.into(),
+        }
+    }
+
+    /// Returns the type of the lanes when extracted.
+    pub fn unpacked(self) -> NumType {
+        match self {
+            Shape::I(Lx::L64) => NumType::I64,
+            Shape::I(_) => NumType::I32,
+            #[cfg(feature = "float-types")]
+            Shape::F(n) => NumType::f(n),
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+fn mask(x: bool) -> i128 {
+    -(x as i128)
+}
+
+#[cfg(feature = "vector-types")]
+impl IRelOp {
+    pub fn v128(&self, l: Lx, x: u128, y: u128) -> u128 {
+        let s = match *self {
+            IRelOp::Eq | IRelOp::Ne => Sx::U,
+            IRelOp::Lt(s) | IRelOp::Gt(s) | IRelOp::Le(s) | IRelOp::Ge(s) => s,
+        };
+        l.build(|i| {
+            let (x, y) = (l.get(x, i, s), l.get(y, i, s));
+            mask(match self {
+                IRelOp::Eq => x == y,
+                IRelOp::Ne => x != y,
+                IRelOp::Lt(_) => x < y,
+                IRelOp::Gt(_) => x > y,
+                IRelOp::Le(_) => x <= y,
+                IRelOp::Ge(_) => x >= y,
+            })
+        })
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl VvBinOp {
+    pub fn v128(&self, x: u128, y: u128) -> u128 {
+        match self {
+            VvBinOp::And => x & y,
+            VvBinOp::AndNot => x & !y,
+            VvBinOp::Or => x | y,
+            VvBinOp::Xor => x ^ y,
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl VIUnOp {
+    pub fn v128(&self, l: Lx, x: u128) -> u128 {
+        l.build(|i| match self {
+            VIUnOp::Abs => l.get(x, i, Sx::S).abs(),
+            VIUnOp::Neg => -l.get(x, i, Sx::S),
+            VIUnOp::PopCnt => l.get(x, i, Sx::U).count_ones() as i128,
+        })
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl VIBinOp {
+    pub fn v128(&self, l: Lx, x: u128, y: u128) -> u128 {
+        let get = |i, s| (l.get(x, i, s), l.get(y, i, s));
+        l.build(|i| match *self {
+            VIBinOp::Add => get(i, Sx::U).0 + get(i, Sx::U).1,
+            VIBinOp::Sub => get(i, Sx::U).0 - get(i, Sx::U).1,
+            VIBinOp::Mul => {
+                let (x, y) = get(i, Sx::U);
+                x.wrapping_mul(y)
+            }
+            VIBinOp::AddSat(s) => {
+                let (x, y) = get(i, s);
+                l.sat(s, x + y)
+            }
+            VIBinOp::SubSat(s) => {
+                let (x, y) = get(i, s);
+                l.sat(s, x - y)
+            }
+            VIBinOp::Min(s) => {
+                let (x, y) = get(i, s);
+                x.min(y)
+            }
+            VIBinOp::Max(s) => {
+                let (x, y) = get(i, s);
+                x.max(y)
+            }
+            VIBinOp::AvgrU => {
+                let (x, y) = get(i, Sx::U);
+                (x + y + 1) >> 1
+            }
+            VIBinOp::Q15MulrSatS => {
+                let (x, y) = get(i, Sx::S);
+                let b = l.bits();
+                l.sat(Sx::S, (x * y + (1 << (b - 2))) >> (b - 1))
+            }
+        })
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl VIShiftOp {
+    pub fn v128(&self, l: Lx, x: u128, y: u32) -> u128 {
+        let y = y % l.bits();
+        l.build(|i| match *self {
+            VIShiftOp::Shl => l.get(x, i, Sx::U) << y,
+            VIShiftOp::Shr(s) => l.get(x, i, s) >> y,
+        })
+    }
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+macro_rules! impl_vfop {
+    ($n:ident, $u:ident, $f:ident) => {
+        impl VFBinOp {
+            fn $n(&self, x: $u, y: $u) -> $u {
+                match self {
+                    VFBinOp::Add => FBinOp::Add.$n(x, y),
+                    VFBinOp::Sub => FBinOp::Sub.$n(x, y),
+                    VFBinOp::Mul => FBinOp::Mul.$n(x, y),
+                    VFBinOp::Div => FBinOp::Div.$n(x, y),
+                    VFBinOp::Min => FBinOp::Min.$n(x, y),
+                    VFBinOp::Max => FBinOp::Max.$n(x, y),
+                    VFBinOp::PMin if $f::from_bits(y) < $f::from_bits(x) => y,
+                    VFBinOp::PMax if $f::from_bits(x) < $f::from_bits(y) => y,
+                    VFBinOp::PMin | VFBinOp::PMax => x,
+                }
+            }
+        }
+    };
+}
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl_vfop!(n32, u32, f32);
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl_vfop!(n64, u64, f64);
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl FRelOp {
+    pub fn v128(&self, n: Nx, x: u128, y: u128) -> u128 {
+        let l = Lx::from(n);
+        l.build(|i| {
+            let (x, y) = (l.get(x, i, Sx::U), l.get(y, i, Sx::U));
+            mask(match n {
+                Nx::N32 => self.n32(x as u32, y as u32),
+                Nx::N64 => self.n64(x as u64, y as u64),
+            })
+        })
+    }
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl FUnOp {
+    pub fn v128(&self, n: Nx, x: u128) -> u128 {
+        let l = Lx::from(n);
+        l.build(|i| {
+            let x = l.get(x, i, Sx::U);
+            match n {
+                Nx::N32 => self.n32(x as u32) as i128,
+                Nx::N64 => self.n64(x as u64) as i128,
+            }
+        })
+    }
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl VFBinOp {
+    pub fn v128(&self, n: Nx, x: u128, y: u128) -> u128 {
+        let l = Lx::from(n);
+        l.build(|i| {
+            let (x, y) = (l.get(x, i, Sx::U), l.get(y, i, Sx::U));
+            match n {
+                Nx::N32 => self.n32(x as u32, y as u32) as i128,
+                Nx::N64 => self.n64(x as u64, y as u64) as i128,
+            }
+        })
+    }
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl VCvtOp {
+    pub fn v128(&self, x: u128) -> u128 {
+        let f32 = |i| f32::from_bits(Lx::L32.get(x, i, Sx::U) as u32);
+        let f64 = |i| f64::from_bits(Lx::L64.get(x, i, Sx::U) as u64);
+        let trunc = |s, x: f64| match s {
+            Sx::U => x as u32 as i128,
+            Sx::S => x as i32 as i128,
+        };
+        match *self {
+            VCvtOp::TruncSat(s) => Lx::L32.build(|i| trunc(s, f32(i) as f64)),
+            VCvtOp::TruncSatZero(s) => Lx::L32.build(|i| match i < 2 {
+                true => trunc(s, f64(i)),
+                false => 0,
+            }),
+            VCvtOp::Convert(s) => {
+                Lx::L32.build(|i| (Lx::L32.get(x, i, s) as f32).to_bits() as i128)
+            }
+            VCvtOp::ConvertLow(s) => {
+                Lx::L64.build(|i| (Lx::L32.get(x, i, s) as f64).to_bits() as i128)
+            }
+            VCvtOp::DemoteZero => Lx::L32.build(|i| match i < 2 {
+                true => (f64(i) as f32).to_bits() as i128,
+                false => 0,
+            }),
+            VCvtOp::PromoteLow => Lx::L64.build(|i| (f32(i) as f64).to_bits() as i128),
+        }
+    }
+}
+
 impl From<u8> for Sx {
     fn from(x: u8) -> Self {
         match x {
@@ -681,6 +1271,21 @@ impl From<u8> for Bx {
     }
 }
 
+#[cfg(feature = "threads")]
+impl From<u8> for AtomicRmwOp {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => AtomicRmwOp::Add,
+            1 => AtomicRmwOp::Sub,
+            2 => AtomicRmwOp::And,
+            3 => AtomicRmwOp::Or,
+            4 => AtomicRmwOp::Xor,
+            5 => AtomicRmwOp::Xchg,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl From<u8> for IRelOp {
     fn from(x: u8) -> Self {
         match x {
@@ -773,13 +1378,70 @@ impl From<u8> for FBinOp {
     }
 }
 
+#[cfg(feature = "vector-types")]
+impl From<u8> for Lx {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => Lx::L8,
+            1 => Lx::L16,
+            2 => Lx::L32,
+            3 => Lx::L64,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl From<u8> for Half {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => Half::Low,
+            1 => Half::High,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl From<u8> for VvBinOp {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => VvBinOp::And,
+            1 => VvBinOp::AndNot,
+            2 => VvBinOp::Or,
+            3 => VvBinOp::Xor,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl From<u8> for VFBinOp {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => VFBinOp::Add,
+            1 => VFBinOp::Sub,
+            2 => VFBinOp::Mul,
+            3 => VFBinOp::Div,
+            4 => VFBinOp::Min,
+            5 => VFBinOp::Max,
+            6 => VFBinOp::PMin,
+            7 => VFBinOp::PMax,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl SectionId {
     pub fn order(self) -> u8 {
+        // Tag is actually between Memory and Global.
         // DataCount is actually between Element and Code.
         match self as u8 {
-            x @ 0 ..= 9 => x,
-            12 => 10,
-            x @ 10 ..= 11 => x + 1,
+            x @ 0 ..= 5 => x,
+            13 => 6,
+            x @ 6 ..= 9 => x + 1,
+            12 => 11,
+            x @ 10 ..= 11 => x + 2,
             _ => unreachable!(),
         }
     }
@@ -18,13 +18,21 @@ use alloc::vec::Vec;
 use core::cmp::Ordering;
 
 use crate::error::*;
+use crate::side_table::*;
 use crate::syntax::*;
 use crate::toctou::*;
 use crate::*;
 
 /// Checks whether a WASM module in binary format is valid.
 pub fn validate(binary: &[u8]) -> Result<(), Error> {
-    Context::default().check_module(&mut Parser::new(binary))
+    prepare(binary).map(|_| ())
+}
+
+/// Checks whether a WASM module in binary format is valid and returns its side table.
+pub(crate) fn prepare(binary: &[u8]) -> Result<SideTable<'static>, Error> {
+    let mut context = Context::default();
+    context.check_module(&mut Parser::new(binary))?;
+    SideTable::new(&context.type_offsets, &context.side_table)
 }
 
 type Parser<'m> = parser::Parser<'m, Check>;
@@ -33,21 +41,29 @@ type CheckResult = MResult<(), Check>;
 #[derive(Default)]
 struct Context<'m> {
     types: Vec<FuncType<'m>>,
+    /// Offset of each type from the start of the module.
+    type_offsets: Vec<usize>,
     funcs: Vec<TypeIdx>,
     tables: Vec<TableType>,
     mems: Vec<MemType>,
     globals: Vec<GlobalType>,
+    tags: Vec<TypeIdx>,
     elems: Vec<RefType>,
     datas: Option<usize>,
+    side_table: Vec<FuncSideTable>,
 }
 
 impl<'m> Context<'m> {
     fn check_module(&mut self, parser: &mut Parser<'m>) -> CheckResult {
+        let module = parser.save().as_ptr() as usize;
+        let offset = |parser: &Parser<'m>| parser.save().as_ptr() as usize - module;
         check(parser.parse_bytes(8)? == b"\0asm\x01\0\0\0")?;
         if let Some(mut parser) = self.check_section(parser, SectionId::Type)? {
             let n = parser.parse_vec()?;
             self.types.reserve(n);
+            self.type_offsets.reserve(n);
             for _ in 0 .. n {
+                self.type_offsets.push(offset(&parser));
                 self.types.push(parser.parse_functype()?);
             }
             check(parser.is_empty())?;
@@ -78,7 +94,19 @@ impl<'m> Context<'m> {
             }
             check(parser.is_empty())?;
         }
-        check(self.mems.len() <= 1)?;
+        if let Some(parser) = self.check_section(parser, SectionId::Tag)? {
+            support_if!(
+                "exception-handling"[parser],
+                {
+                    let mut parser = parser;
+                    for _ in 0 .. parser.parse_vec()? {
+                        self.add_tagtype(parser.parse_tagtype()?)?;
+                    }
+                    check(parser.is_empty())?;
+                },
+                return Err(unsupported(if_debug!(Unsupported::Tag)))
+            );
+        }
         let globals_len = self.globals.len();
         if let Some(mut parser) = self.check_section(parser, SectionId::Global)? {
             for _ in 0 .. parser.parse_vec()? {
@@ -125,12 +153,16 @@ impl<'m> Context<'m> {
         if let Some(mut parser) = self.check_section(parser, SectionId::Code)? {
             check(self.funcs.len() == imported_funcs + parser.parse_vec()?)?;
             for x in imported_funcs .. self.funcs.len() {
+                let func_offset = offset(&parser);
                 let size = parser.parse_u32()? as usize;
                 let mut parser = parser.split_at(size)?;
                 let t = self.functype(x as FuncIdx).unwrap();
                 let mut locals = t.params.to_vec();
                 parser.parse_locals(&mut locals)?;
-                Expr::check_body(self, &mut parser, &refs, locals, t.results)?;
+                let mut side_table = Expr::check_body(self, &mut parser, &refs, locals, t.results)?;
+                side_table.type_ = self.funcs[x];
+                side_table.offset = func_offset;
+                self.side_table.push(side_table);
                 check(parser.is_empty())?;
             }
             check(parser.is_empty())?;
@@ -184,6 +216,7 @@ impl<'m> Context<'m> {
             ImportDesc::Table(t) => self.add_tabletype(t),
             ImportDesc::Mem(m) => self.add_memtype(m),
             ImportDesc::Global(g) => self.add_globaltype(g),
+            ImportDesc::Tag(x) => self.add_tagtype(x),
         }
     }
 
@@ -210,12 +243,19 @@ impl<'m> Context<'m> {
         Ok(())
     }
 
+    fn add_tagtype(&mut self, x: TypeIdx) -> CheckResult {
+        check(self.type_(x)?.results.is_empty())?;
+        self.tags.push(x);
+        Ok(())
+    }
+
     fn check_exportdesc(&self, desc: &ExportDesc) -> CheckResult {
         let (&x, n) = match desc {
             ExportDesc::Func(x) => (x, self.funcs.len()),
             ExportDesc::Table(x) => (x, self.tables.len()),
             ExportDesc::Mem(x) => (x, self.mems.len()),
             ExportDesc::Global(x) => (x, self.globals.len()),
+            ExportDesc::Tag(x) => (x, self.tags.len()),
         };
         check((x as usize) < n)
     }
@@ -240,6 +280,11 @@ impl<'m> Context<'m> {
         self.globals.get(x as usize).ok_or_else(invalid)
     }
 
+    #[cfg(feature = "exception-handling")]
+    fn tag(&self, x: TagIdx) -> Result<FuncType<'m>, Error> {
+        self.type_(*self.tags.get(x as usize).ok_or_else(invalid)?)
+    }
+
     fn elem(&self, x: ElemIdx) -> Result<RefType, Error> {
         self.elems.get(x as usize).cloned().ok_or_else(invalid)
     }
@@ -347,6 +392,7 @@ enum OpdType {
     V128 = 0x7b,
     FuncRef = 0x70,
     ExternRef = 0x6f,
+    ExnRef = 0x69,
 }
 
 macro_rules! impl_type_conv {
@@ -361,10 +407,10 @@ macro_rules! impl_type_conv {
     };
 }
 impl_type_conv!(NumType => ValType [I32 I64 F32 F64]);
-impl_type_conv!(RefType => ValType [FuncRef ExternRef]);
+impl_type_conv!(RefType => ValType [FuncRef ExternRef ExnRef]);
 impl_type_conv!(NumType => OpdType [I32 I64 F32 F64]);
-impl_type_conv!(RefType => OpdType [FuncRef ExternRef]);
-impl_type_conv!(ValType => OpdType [I32 I64 F32 F64 V128 FuncRef ExternRef]);
+impl_type_conv!(RefType => OpdType [FuncRef ExternRef ExnRef]);
+impl_type_conv!(ValType => OpdType [I32 I64 F32 F64 V128 FuncRef ExternRef ExnRef]);
 
 impl OpdType {
     fn matches(self, other: ValType) -> bool {
@@ -388,7 +434,7 @@ impl OpdType {
     }
 
     fn is_ref(self) -> bool {
-        matches!(self, OpdType::Bottom | OpdType::FuncRef | OpdType::ExternRef)
+        matches!(self, OpdType::Bottom | OpdType::FuncRef | OpdType::ExternRef | OpdType::ExnRef)
     }
 }
 
@@ -401,6 +447,10 @@ struct Expr<'a, 'm> {
     is_body: bool,
     locals: Vec<ValType>,
     labels: Vec<Label<'m>>,
+    /// Branch sites in program order (one side table entry each).
+    branches: Vec<Branch>,
+    /// Maximum number of values on the stack (over all labels).
+    max_stack: usize,
 }
 
 #[derive(Debug, Default)]
@@ -411,14 +461,34 @@ struct Label<'m> {
     /// Whether the bottom of the stack is polymorphic.
     polymorphic: bool,
     stack: Vec<OpdType>,
+    /// Branches targeting this label (patched at `end`).
+    branches: Vec<usize>,
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 enum LabelKind {
     #[default]
     Block,
-    Loop,
-    If,
+    /// Branches target the loop instruction.
+    Loop(Target),
+    /// The `if` branch (patched at `else` or `end`).
+    If(usize),
+}
+
+#[derive(Debug)]
+struct Branch {
+    /// Position of the branch instruction.
+    source: usize,
+    target: Option<Target>,
+    val_cnt: usize,
+}
+
+/// Positions are the number of bytes until the end of the function.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Target {
+    ip: usize,
+    /// Number of branches before the target.
+    stp: usize,
 }
 
 impl<'a, 'm> Expr<'a, 'm> {
@@ -434,6 +504,8 @@ impl<'a, 'm> Expr<'a, 'm> {
             is_body: false,
             locals: vec![],
             labels: vec![Label::default()],
+            branches: vec![],
+            max_stack: 0,
         }
     }
 
@@ -450,26 +522,41 @@ impl<'a, 'm> Expr<'a, 'm> {
     fn check_body(
         context: &'a Context<'m>, parser: &'a mut Parser<'m>, refs: &'a [bool],
         locals: Vec<ValType>, results: ResultType<'m>,
-    ) -> CheckResult {
+    ) -> Result<FuncSideTable, Error> {
         let mut expr = Expr::new(context, parser, Err(refs));
         expr.is_body = true;
         expr.locals = locals;
         expr.label().type_.results = results;
-        expr.check()
+        expr.check()?;
+        expr.side_table()
     }
 
-    fn check(mut self) -> CheckResult {
+    fn check(&mut self) -> CheckResult {
         while !self.labels.is_empty() {
             self.instr()?;
+            let height = self.labels.iter().map(|x| x.stack.len()).sum();
+            self.max_stack = core::cmp::max(self.max_stack, height);
         }
         Ok(())
     }
 
+    fn side_table(&self) -> Result<FuncSideTable, Error> {
+        let mut entries = Vec::with_capacity(self.branches.len());
+        for (stp, branch) in self.branches.iter().enumerate() {
+            let target = branch.target.unwrap();
+            let delta_ip = branch.source as isize - target.ip as isize;
+            let delta_stp = target.stp as isize - stp as isize;
+            entries.push(SideTableEntry::new(delta_ip, delta_stp, branch.val_cnt)?);
+        }
+        Ok(FuncSideTable { entries, max_stack: self.max_stack, ..Default::default() })
+    }
+
     fn instr(&mut self) -> CheckResult {
         use Instr::*;
+        let source = self.parser.save().len();
         let instr = self.parser.parse_instr()?;
         if matches!(instr, End) {
-            return self.end_label();
+            return self.end_label(source);
         }
         if self.is_const.is_ok() {
             match instr {
@@ -486,6 +573,8 @@ impl<'a, 'm> Expr<'a, 'm> {
                 F64Const(_) => (),
                 RefNull(_) => (),
                 RefFunc(_) => (),
+                #[cfg(feature = "vector-types")]
+                V128Const(_) => (),
                 _ => return Err(invalid()),
             }
         }
@@ -493,38 +582,53 @@ impl<'a, 'm> Expr<'a, 'm> {
             Unreachable => self.stack_polymorphic(),
             Nop => (),
             Block(b) => self.push_label(self.blocktype(&b)?, LabelKind::Block)?,
-            Loop(b) => self.push_label(self.blocktype(&b)?, LabelKind::Loop)?,
+            Loop(b) => {
+                let target = Target { ip: source, stp: self.branches.len() };
+                self.push_label(self.blocktype(&b)?, LabelKind::Loop(target))?;
+            }
             If(b) => {
                 self.pop_check(ValType::I32)?;
-                self.push_label(self.blocktype(&b)?, LabelKind::If)?;
+                let i = self.push_branch(source, None, 0);
+                self.push_label(self.blocktype(&b)?, LabelKind::If(i))?;
             }
             Else => {
                 let label = self.label();
-                check(core::mem::replace(&mut label.kind, LabelKind::Block) == LabelKind::If)?;
+                let i = match core::mem::replace(&mut label.kind, LabelKind::Block) {
+                    LabelKind::If(i) => i,
+                    _ => return Err(invalid()),
+                };
                 let FuncType { params, results } = label.type_;
                 self.pops(results)?;
                 check(self.stack().is_empty())?;
                 self.label().polymorphic = false;
                 self.pushs(params);
+                let j = self.push_branch(source, None, 0);
+                self.label().branches.push(j);
+                self.branches[i].target = Some(self.target());
             }
             End => unreachable!(),
             Br(l) => {
                 self.pops(self.br_label(l)?)?;
+                self.br_branch(source, l);
                 self.stack_polymorphic();
             }
             BrIf(l) => {
                 self.pop_check(ValType::I32)?;
                 self.swaps(self.br_label(l)?)?;
+                self.br_branch(source, l);
             }
             BrTable(ls, ln) => {
                 self.pop_check(ValType::I32)?;
                 let tn = self.br_label(ln)?;
                 self.peeks(tn)?;
-                for l in ls {
+                for &l in &ls {
                     let t = self.br_label(l)?;
                     check(tn.len() == t.len())?;
                     self.peeks(t)?;
                 }
+                for l in ls.into_iter().chain(core::iter::once(ln)) {
+                    self.br_branch(source, l);
+                }
                 self.stack_polymorphic();
             }
             Return => {
@@ -538,6 +642,42 @@ impl<'a, 'm> Expr<'a, 'm> {
                 self.pop_check(ValType::I32)?;
                 self.call(self.context.type_(y)?)?;
             }
+            #[cfg(feature = "tail-call")]
+            ReturnCall(x) => self.return_call(self.context.functype(x)?)?,
+            #[cfg(feature = "tail-call")]
+            ReturnCallIndirect(x, y) => {
+                check(self.context.table(x)?.item == RefType::FuncRef)?;
+                self.pop_check(ValType::I32)?;
+                self.return_call(self.context.type_(y)?)?;
+            }
+            #[cfg(feature = "exception-handling")]
+            Throw(x) => {
+                self.pops(self.context.tag(x)?.params)?;
+                self.stack_polymorphic();
+            }
+            #[cfg(feature = "exception-handling")]
+            ThrowRef => {
+                self.pop_check(ValType::ExnRef)?;
+                self.stack_polymorphic();
+            }
+            #[cfg(feature = "exception-handling")]
+            TryTable(b, catches) => {
+                // Catch labels are relative to the outside of the block.
+                for catch in &catches {
+                    let mut t = match catch.tag {
+                        Some(x) => self.context.tag(x)?.params.to_vec(),
+                        None => Vec::new(),
+                    };
+                    if catch.is_ref {
+                        t.push(ValType::ExnRef);
+                    }
+                    check(self.br_label(catch.label)?[..] == t[..])?;
+                }
+                for catch in catches {
+                    self.br_branch(source, catch.label);
+                }
+                self.push_label(self.blocktype(&b)?, LabelKind::Block)?;
+            }
             Drop => drop(self.pop()?),
             Select(None) => {
                 self.pop_check(ValType::I32)?;
@@ -566,22 +706,51 @@ impl<'a, 'm> Expr<'a, 'm> {
             TableSet(x) => {
                 self.pops([ValType::I32, self.context.table(x)?.item.into()][..].into())?;
             }
-            ILoad(n, m) => self.load(false, NumType::i(n), n.into(), m)?,
+            ILoad(n, m) => self.load(false, NumType::i(n).into(), n.into(), m)?,
             #[cfg(feature = "float-types")]
-            FLoad(n, m) => self.load(false, NumType::f(n), n.into(), m)?,
-            ILoad_(b, _, m) => self.load(false, NumType::i(b.into()), b.into(), m)?,
-            IStore(n, m) => self.store(false, NumType::i(n), n.into(), m)?,
+            FLoad(n, m) => self.load(false, NumType::f(n).into(), n.into(), m)?,
+            ILoad_(b, _, m) => self.load(false, NumType::i(b.into()).into(), b.into(), m)?,
+            IStore(n, m) => self.store(false, NumType::i(n).into(), n.into(), m)?,
             #[cfg(feature = "float-types")]
-            FStore(n, m) => self.store(false, NumType::f(n), n.into(), m)?,
-            IStore_(b, m) => self.store(false, NumType::i(b.into()), b.into(), m)?,
-            MemorySize => {
-                check(!self.context.mems.is_empty())?;
+            FStore(n, m) => self.store(false, NumType::f(n).into(), n.into(), m)?,
+            IStore_(b, m) => self.store(false, NumType::i(b.into()).into(), b.into(), m)?,
+            MemorySize(x) => {
+                self.context.mem(x)?;
                 self.push(OpdType::I32);
             }
-            MemoryGrow => {
-                check(!self.context.mems.is_empty())?;
+            MemoryGrow(x) => {
+                self.context.mem(x)?;
                 self.swap(ValType::I32)?;
             }
+            #[cfg(feature = "threads")]
+            MemoryAtomicNotify(m) => {
+                self.check_mem(true, 32, m)?;
+                self.relop(NumType::I32)?;
+            }
+            #[cfg(feature = "threads")]
+            MemoryAtomicWait(n, m) => {
+                self.check_mem(true, n.into(), m)?;
+                self.pops([ValType::I32, NumType::i(n).into(), ValType::I64][..].into())?;
+                self.push(OpdType::I32);
+            }
+            #[cfg(feature = "threads")]
+            AtomicFence => (),
+            #[cfg(feature = "threads")]
+            IAtomicLoad(n, m) => self.load(true, NumType::i(n).into(), n.into(), m)?,
+            #[cfg(feature = "threads")]
+            IAtomicLoad_(b, m) => self.load(true, NumType::i(b.into()).into(), b.into(), m)?,
+            #[cfg(feature = "threads")]
+            IAtomicStore(n, m) => self.store(true, NumType::i(n).into(), n.into(), m)?,
+            #[cfg(feature = "threads")]
+            IAtomicStore_(b, m) => self.store(true, NumType::i(b.into()).into(), b.into(), m)?,
+            #[cfg(feature = "threads")]
+            IAtomicRmw(n, _, m) => self.atomic_rmw(NumType::i(n), n.into(), 1, m)?,
+            #[cfg(feature = "threads")]
+            IAtomicRmw_(b, _, m) => self.atomic_rmw(NumType::i(b.into()), b.into(), 1, m)?,
+            #[cfg(feature = "threads")]
+            IAtomicCmpxchg(n, m) => self.atomic_rmw(NumType::i(n), n.into(), 2, m)?,
+            #[cfg(feature = "threads")]
+            IAtomicCmpxchg_(b, m) => self.atomic_rmw(NumType::i(b.into()), b.into(), 2, m)?,
             I32Const(_) => self.push(OpdType::I32),
             I64Const(_) => self.push(OpdType::I64),
             #[cfg(feature = "float-types")]
@@ -613,14 +782,19 @@ impl<'a, 'm> Expr<'a, 'm> {
                 }
                 self.push(OpdType::FuncRef);
             }
-            MemoryInit(x) => {
-                check(!self.context.mems.is_empty())?;
+            MemoryInit(x, y) => {
+                self.context.mem(y)?;
                 self.context.data(x)?;
                 self.pops([ValType::I32; 3][..].into())?;
             }
             DataDrop(x) => self.context.data(x)?,
-            MemoryCopy | MemoryFill => {
-                check(!self.context.mems.is_empty())?;
+            MemoryCopy(x, y) => {
+                self.context.mem(x)?;
+                self.context.mem(y)?;
+                self.pops([ValType::I32; 3][..].into())?;
+            }
+            MemoryFill(x) => {
+                self.context.mem(x)?;
                 self.pops([ValType::I32; 3][..].into())?;
             }
             TableInit(x, y) => {
@@ -645,6 +819,77 @@ impl<'a, 'm> Expr<'a, 'm> {
                 let t = self.context.table(x)?.item;
                 self.pops([ValType::I32, t.into(), ValType::I32][..].into())?;
             }
+            #[cfg(feature = "vector-types")]
+            V128Load(m) => self.load(false, ValType::V128, 128, m)?,
+            #[cfg(feature = "vector-types")]
+            V128LoadExtend(_, _, m) => self.load(false, ValType::V128, 64, m)?,
+            #[cfg(feature = "vector-types")]
+            V128LoadSplat(l, m) | V128LoadZero(l, m) => {
+                self.load(false, ValType::V128, l.bits() as usize, m)?
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadLane(l, m, i) => {
+                check(i < l.lanes())?;
+                self.pop_check(ValType::V128)?;
+                self.load(false, ValType::V128, l.bits() as usize, m)?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128Store(m) => self.store(false, ValType::V128, 128, m)?,
+            #[cfg(feature = "vector-types")]
+            V128StoreLane(l, m, i) => {
+                check(i < l.lanes())?;
+                self.store(false, ValType::V128, l.bits() as usize, m)?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128Const(_) => self.push(OpdType::V128),
+            #[cfg(feature = "vector-types")]
+            I8x16Shuffle(xs) => {
+                check(xs.iter().all(|&x| x < 32))?;
+                self.vbinop()?;
+            }
+            #[cfg(feature = "vector-types")]
+            VSplat(s) => {
+                self.pop_check(s.unpacked().into())?;
+                self.push(OpdType::V128);
+            }
+            #[cfg(feature = "vector-types")]
+            VExtractLane(s, _, i) => {
+                check(i < s.lx().lanes())?;
+                self.pop_check(ValType::V128)?;
+                self.push(s.unpacked().into());
+            }
+            #[cfg(feature = "vector-types")]
+            VReplaceLane(s, i) => {
+                check(i < s.lx().lanes())?;
+                self.pop_check(s.unpacked().into())?;
+                self.swap(ValType::V128)?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128Not | VIUnOp(..) | VIExtend(..) | VIExtAddPairwise(..) => {
+                self.swap(ValType::V128)?
+            }
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFUnOp(..) | VCvtOp(_) => self.swap(ValType::V128)?,
+            #[cfg(feature = "vector-types")]
+            I8x16Swizzle | VvBinOp(_) | VIRelOp(..) | VIBinOp(..) | VINarrow(..) | VIExtMul(..)
+            | VIDot => self.vbinop()?,
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFRelOp(..) | VFBinOp(..) => self.vbinop()?,
+            #[cfg(feature = "vector-types")]
+            V128Bitselect => {
+                self.pops([ValType::V128; 2][..].into())?;
+                self.swap(ValType::V128)?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128AnyTrue | VIAllTrue(_) | VIBitmask(_) => {
+                self.pop_check(ValType::V128)?;
+                self.push(OpdType::I32);
+            }
+            #[cfg(feature = "vector-types")]
+            VIShiftOp(..) => {
+                self.pop_check(ValType::I32)?;
+                self.swap(ValType::V128)?;
+            }
         }
         Ok(())
     }
@@ -734,19 +979,26 @@ impl<'a, 'm> Expr<'a, 'm> {
     fn push_label(&mut self, type_: FuncType<'m>, kind: LabelKind) -> CheckResult {
         self.pops(type_.params)?;
         let stack = type_.params.iter().cloned().map(OpdType::from).collect();
-        let label = Label { type_, kind, polymorphic: false, stack };
+        let label = Label { type_, kind, polymorphic: false, stack, branches: vec![] };
         self.labels.push(label);
         Ok(())
     }
 
-    fn end_label(&mut self) -> CheckResult {
+    fn end_label(&mut self, source: usize) -> CheckResult {
         let label = self.label();
-        if label.kind == LabelKind::If {
+        if let LabelKind::If(i) = label.kind {
             check(label.type_.params == label.type_.results)?;
+            // Without else, the false branch jumps to the end instruction.
+            self.branches[i].target = Some(Target { ip: source, stp: self.branches.len() });
         }
-        let results = label.type_.results;
+        let results = self.label().type_.results;
         self.pops(results)?;
-        check(self.labels.pop().unwrap().stack.is_empty())?;
+        let label = self.labels.pop().unwrap();
+        check(label.stack.is_empty())?;
+        let target = self.target();
+        for i in label.branches {
+            self.branches[i].target = Some(target);
+        }
         if !self.labels.is_empty() {
             self.pushs(results);
         }
@@ -759,37 +1011,84 @@ impl<'a, 'm> Expr<'a, 'm> {
         check(l < n)?;
         let label = &self.labels[n - l - 1];
         Ok(match label.kind {
-            LabelKind::Block | LabelKind::If => label.type_.results,
-            LabelKind::Loop => label.type_.params,
+            LabelKind::Block | LabelKind::If(_) => label.type_.results,
+            LabelKind::Loop(_) => label.type_.params,
         })
     }
 
+    /// Returns the current position as a branch target.
+    fn target(&self) -> Target {
+        Target { ip: self.parser.save().len(), stp: self.branches.len() }
+    }
+
+    fn push_branch(&mut self, source: usize, target: Option<Target>, val_cnt: usize) -> usize {
+        self.branches.push(Branch { source, target, val_cnt });
+        self.branches.len() - 1
+    }
+
+    /// Records a branch to a label (which must be valid).
+    fn br_branch(&mut self, source: usize, l: LabelIdx) {
+        let val_cnt = self.br_label(l).unwrap().len();
+        let i = self.branches.len();
+        let n = self.labels.len();
+        let label = &mut self.labels[n - l as usize - 1];
+        let target = match label.kind {
+            LabelKind::Loop(target) => Some(target),
+            LabelKind::Block | LabelKind::If(_) => {
+                label.branches.push(i);
+                None
+            }
+        };
+        self.push_branch(source, target, val_cnt);
+    }
+
     fn call(&mut self, t: FuncType) -> CheckResult {
         self.pops(t.params)?;
         self.pushs(t.results);
         Ok(())
     }
 
+    #[cfg(feature = "tail-call")]
+    fn return_call(&mut self, t: FuncType) -> CheckResult {
+        check(self.is_body)?;
+        check(t.results == self.labels[0].type_.results)?;
+        self.pops(t.params)?;
+        self.stack_polymorphic();
+        Ok(())
+    }
+
     fn stack_polymorphic(&mut self) {
         let label = self.label();
         label.stack.clear();
         label.polymorphic = true;
     }
 
-    fn load(&mut self, aligned: bool, t: NumType, n: usize, m: MemArg) -> CheckResult {
+    fn load(&mut self, aligned: bool, t: ValType, n: usize, m: MemArg) -> CheckResult {
         self.check_mem(aligned, n, m)?;
         self.pop_check(ValType::I32)?;
         self.push(t.into());
         Ok(())
     }
 
-    fn store(&mut self, aligned: bool, t: NumType, n: usize, m: MemArg) -> CheckResult {
+    fn store(&mut self, aligned: bool, t: ValType, n: usize, m: MemArg) -> CheckResult {
         self.check_mem(aligned, n, m)?;
-        self.pop_check(t.into())?;
+        self.pop_check(t)?;
         self.pop_check(ValType::I32)?;
         Ok(())
     }
 
+    /// Checks an atomic read-modify-write taking an address and the given number of operands.
+    #[cfg(feature = "threads")]
+    fn atomic_rmw(&mut self, t: NumType, n: usize, operands: usize, m: MemArg) -> CheckResult {
+        self.check_mem(true, n, m)?;
+        for _ in 0 .. operands {
+            self.pop_check(t.into())?;
+        }
+        self.pop_check(ValType::I32)?;
+        self.push(t.into());
+        Ok(())
+    }
+
     fn testop(&mut self, t: NumType) -> CheckResult {
         self.pop_check(t.into())?;
         self.push(OpdType::I32);
@@ -811,6 +1110,12 @@ impl<'a, 'm> Expr<'a, 'm> {
         self.swap(ValType::from(t))
     }
 
+    #[cfg(feature = "vector-types")]
+    fn vbinop(&mut self) -> CheckResult {
+        self.pop_check(ValType::V128)?;
+        self.swap(ValType::V128)
+    }
+
     fn cvtop(&mut self, dst: NumType, src: NumType) -> CheckResult {
         self.pop_check(src.into())?;
         self.push(dst.into());
@@ -818,7 +1123,7 @@ impl<'a, 'm> Expr<'a, 'm> {
     }
 
     fn check_mem(&self, aligned: bool, n: usize, m: MemArg) -> CheckResult {
-        check(!self.context.mems.is_empty())?;
+        self.context.mem(m.memory)?;
         match (aligned, 1usize.checked_shl(m.align), n / 8) {
             (false, Some(m), n) if m <= n => Ok(()),
             (true, Some(m), n) if m == n => Ok(()),
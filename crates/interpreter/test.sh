@@ -21,11 +21,11 @@ ensure_submodule third_party/WebAssembly/spec
 
 test_helper
 
-cargo test --lib --features=toctou
+cargo test --lib --features=toctou,trace
 cargo check --lib --target=thumbv7em-none-eabi
 cargo check --lib --target=riscv32imc-unknown-none-elf \
   --features=portable-atomic/critical-section
 RUSTFLAGS=--cfg=portable_atomic_unsafe_assume_single_core \
   cargo check --lib --target=riscv32imc-unknown-none-elf
-cargo test --test=spec --features=debug,toctou,float-types,vector-types
+cargo test --test=spec --features=debug,toctou,float-types,vector-types,exception-handling,tail-call,threads
 cargo check --example=hello
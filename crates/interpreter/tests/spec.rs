@@ -52,11 +52,12 @@ fn test(repo: &str, name: &str) {
             WastDirective::AssertReturn { exec, results, .. } => {
                 assert_return(&mut env, exec, results)
             }
-            WastDirective::AssertTrap { exec, .. } => assert_trap(&mut env, exec),
+            WastDirective::AssertTrap { exec, message, .. } => assert_trap(&mut env, exec, message),
             WastDirective::Invoke(invoke) => assert_invoke(&mut env, invoke),
             WastDirective::AssertExhaustion { call, .. } => assert_exhaustion(&mut env, call),
             WastDirective::Register { name, module, .. } => env.register_name(name, module),
             WastDirective::AssertUnlinkable { module, .. } => assert_unlinkable(&mut env, module),
+            WastDirective::AssertException { exec, .. } => assert_exception(&mut env, exec),
             _ => unimplemented!("{:?}", directive),
         }
     }
@@ -75,6 +76,10 @@ fn pool_size(name: &str) -> usize {
         "memory_grow" => 0x400000,
         "memory_init" => 0x400000,
         "memory_trap" => 0x200000,
+        "simd/simd_align" => 0x1000000,
+        "simd/simd_const" => 0x800000,
+        x if x.starts_with("multi-memory/") => 0x2000000,
+        x if x.starts_with("simd/") && mem_size(x) == 0x10000 => 0x400000,
         _ => 0x100000,
     }
 }
@@ -91,11 +96,27 @@ fn mem_size(name: &str) -> usize {
         "memory_grow" => 0x80000,
         "memory_init" => 0x20000,
         "memory_trap" => 0x10000,
+        "multi-memory/memory_grow" => 0x80000,
+        x if x.starts_with("multi-memory/") => 0x20000,
+        "simd/simd_address" => 0x10000,
+        "simd/simd_align" => 0x10000,
+        "simd/simd_lane" => 0x10000,
+        "simd/simd_splat" => 0x10000,
+        x if x.starts_with("simd/simd_load") || x.starts_with("simd/simd_store") => 0x10000,
         "spectest" => 0x10000,
         _ => 0x1000,
     }
 }
 
+fn alloc<'m>(pool: &mut &'m mut [u8], size: usize) -> &'m mut [u8] {
+    if pool.len() < size {
+        panic!("pool is too small");
+    }
+    let (result, rest) = std::mem::take(pool).split_at_mut(size.next_multiple_of(MEMORY_ALIGN));
+    *pool = rest;
+    &mut result[.. size]
+}
+
 struct Env<'m> {
     pool: &'m mut [u8],
     store: Store<'m>,
@@ -109,14 +130,7 @@ impl<'m> Env<'m> {
     }
 
     fn alloc(&mut self, size: usize) -> &'m mut [u8] {
-        if self.pool.len() < size {
-            panic!("pool is too small");
-        }
-        let mut self_pool: &mut [u8] = &mut [];
-        std::mem::swap(&mut self.pool, &mut self_pool);
-        let (result, pool) = self_pool.split_at_mut(size.next_multiple_of(MEMORY_ALIGN));
-        self.pool = pool;
-        &mut result[.. size]
+        alloc(&mut self.pool, size)
     }
 
     fn set_inst(&mut self, inst: Result<InstId, Error>) {
@@ -134,8 +148,9 @@ impl<'m> Env<'m> {
             Ok(x) => x,
             Err(e) => return Err(e),
         };
-        let memory = self.alloc(mem_size(name));
-        self.store.instantiate(module, memory)
+        let pool = &mut self.pool;
+        let memories = std::iter::repeat_with(|| alloc(pool, mem_size(name)));
+        self.store.instantiate_with_memories(module, memories)
     }
 
     fn instantiate(&mut self, name: &str, wasm: &[u8]) {
@@ -146,7 +161,9 @@ impl<'m> Env<'m> {
     fn invoke(&mut self, inst_id: InstId, name: &str, args: Vec<Val>) -> Result<Vec<Val>, Error> {
         Ok(match self.store.invoke(inst_id, name, args)? {
             RunResult::Done(x) => x,
-            RunResult::Host { .. } => unreachable!(),
+            RunResult::Host { .. } | RunResult::Preempted | RunResult::Exception(_) => {
+                unreachable!()
+            }
         })
     }
 
@@ -277,9 +294,10 @@ fn assert_return(env: &mut Env, exec: WastExecute, expected: Vec<WastRet>) {
             #[cfg(feature = "float-types")]
             (F64(x), C(W::F64(_))) => assert!(f64::from_bits(x).is_nan()),
             #[cfg(feature = "vector-types")]
-            (V128(_), _) => unimplemented!(),
+            (V128(x), C(W::V128(y))) => assert_v128(x, y),
             (Null(RefType::ExternRef), C(W::RefNull(None | Some(HeapType::Extern)))) => (),
             (Null(RefType::FuncRef), C(W::RefNull(None | Some(HeapType::Func)))) => (),
+            (Null(RefType::ExnRef), C(W::RefNull(None | Some(HeapType::Exn)))) => (),
             (Ref(_), _) => unimplemented!(),
             (RefExtern(x), C(W::RefExtern(Some(y)))) => assert_eq!(x, y as usize),
             (x, y) => panic!("{x:?} !~ {y:?}"),
@@ -287,8 +305,61 @@ fn assert_return(env: &mut Env, exec: WastExecute, expected: Vec<WastRet>) {
     }
 }
 
-fn assert_trap(env: &mut Env, exec: WastExecute) {
+#[cfg(feature = "vector-types")]
+fn assert_v128(x: u128, y: wast::core::V128Pattern) {
+    use wast::core::{NanPattern as NP, V128Pattern as P};
+    let x: Vec<u64> =
+        x.to_le_bytes().chunks(8).map(|x| u64::from_le_bytes(x.try_into().unwrap())).collect();
+    let lanes = |n: u32| {
+        x.iter()
+            .flat_map(move |&x| (0 .. 64 / n).map(move |i| x >> (i * n) & (u64::MAX >> (64 - n))))
+            .collect::<Vec<_>>()
+    };
+    match y {
+        P::I8x16(y) => assert_eq!(lanes(8), y.map(|y| y as u8 as u64)),
+        P::I16x8(y) => assert_eq!(lanes(16), y.map(|y| y as u16 as u64)),
+        P::I32x4(y) => assert_eq!(lanes(32), y.map(|y| y as u32 as u64)),
+        P::I64x2(y) => assert_eq!(lanes(64), y.map(|y| y as u64)),
+        P::F32x4(y) => {
+            for (x, y) in lanes(32).into_iter().zip(y) {
+                match y {
+                    NP::Value(y) => assert_eq!(x as u32, y.bits),
+                    _ => assert!(f32::from_bits(x as u32).is_nan()),
+                }
+            }
+        }
+        P::F64x2(y) => {
+            for (x, y) in lanes(64).into_iter().zip(y) {
+                match y {
+                    NP::Value(y) => assert_eq!(x, y.bits),
+                    _ => assert!(f64::from_bits(x).is_nan()),
+                }
+            }
+        }
+    }
+}
+
+fn assert_trap(env: &mut Env, exec: WastExecute, message: &str) {
     assert_eq!(wast_execute(env, exec), Err(Error::Trap));
+    if let Some(reason) = trap_reason(message) {
+        assert_eq!(env.store.core_dump().unwrap().reason, reason, "{message}");
+    }
+}
+
+fn trap_reason(message: &str) -> Option<TrapReason> {
+    Some(match message {
+        "unreachable" => TrapReason::Unreachable,
+        "out of bounds memory access" => TrapReason::MemoryOutOfBounds,
+        "unaligned atomic" => TrapReason::UnalignedAtomic,
+        "out of bounds table access" | "undefined element" => TrapReason::TableOutOfBounds,
+        x if x.starts_with("uninitialized element") => TrapReason::UninitializedElement,
+        "indirect call type mismatch" => TrapReason::IndirectCallMismatch,
+        "integer divide by zero" => TrapReason::DivisionByZero,
+        "integer overflow" => TrapReason::IntegerOverflow,
+        "invalid conversion to integer" => TrapReason::InvalidConversion,
+        "null exception reference" => TrapReason::NullReference,
+        _ => return None,
+    })
 }
 
 fn assert_invoke(env: &mut Env, invoke: WastInvoke) {
@@ -315,7 +386,7 @@ fn assert_invalid(mut wat: QuoteWat) {
 fn assert_exhaustion(env: &mut Env, call: WastInvoke) {
     let result = wast_invoke(env, call);
     if !matches!(result, Err(Error::Unsupported(_))) {
-        assert_eq!(result, Err(Error::Trap));
+        assert_eq!(result, Err(Error::LimitExceeded(StoreLimit::Frames)));
     }
 }
 
@@ -326,6 +397,30 @@ fn assert_unlinkable(env: &mut Env, mut wat: Wat) {
     }
 }
 
+fn assert_exception(env: &mut Env, exec: WastExecute) {
+    let result = match exec {
+        WastExecute::Invoke(invoke) => match env.inst_id(invoke.module) {
+            Ok(inst_id) => env.store.invoke(inst_id, invoke.name, wast_args(invoke.args)),
+            Err(e) => Err(e),
+        },
+        WastExecute::Wat(mut wat) => {
+            let result = env.maybe_instantiate("", &wat.encode().unwrap());
+            if result == Err(Error::Trap) {
+                let reason = env.store.core_dump().unwrap().reason;
+                assert_eq!(reason, TrapReason::UncaughtException);
+                return;
+            }
+            result.map(|_| RunResult::Done(Vec::new()))
+        }
+        WastExecute::Get { .. } => unreachable!(),
+    };
+    match result {
+        Ok(RunResult::Exception(_)) | Err(Error::Unsupported(_)) => (),
+        Ok(x) => panic!("{:?}", x.forget()),
+        Err(e) => panic!("{e:?}"),
+    }
+}
+
 fn wast_execute(env: &mut Env, exec: WastExecute) -> Result<Vec<Val>, Error> {
     match exec {
         WastExecute::Invoke(invoke) => wast_invoke(env, invoke),
@@ -365,8 +460,11 @@ fn wast_arg_core(core: WastArgCore) -> Val {
         WastArgCore::F32(x) => Val::F32(x.bits),
         #[cfg(feature = "float-types")]
         WastArgCore::F64(x) => Val::F64(x.bits),
+        #[cfg(feature = "vector-types")]
+        WastArgCore::V128(x) => Val::V128(u128::from_le_bytes(x.to_le_bytes())),
         WastArgCore::RefNull(HeapType::Func) => Val::Null(RefType::FuncRef),
         WastArgCore::RefNull(HeapType::Extern) => Val::Null(RefType::ExternRef),
+        WastArgCore::RefNull(HeapType::Exn) => Val::Null(RefType::ExnRef),
         WastArgCore::RefExtern(x) => Val::RefExtern(x as usize),
         _ => unimplemented!("{:?}", core),
     }
@@ -487,3 +585,128 @@ test!(utf8_custom_section_id, "utf8-custom-section-id");
 test!(utf8_import_field, "utf8-import-field");
 test!(utf8_import_module, "utf8-import-module");
 test!(utf8_invalid_encoding, "utf8-invalid-encoding");
+
+mod multi_memory {
+    use super::*;
+
+    test!(address0, "multi-memory/address0");
+    test!(address1, "multi-memory/address1");
+    test!(align0, "multi-memory/align0");
+    test!(binary0, "multi-memory/binary0");
+    test!(data0, "multi-memory/data0");
+    test!(data1, "multi-memory/data1");
+    test!(data_drop0, "multi-memory/data_drop0");
+    test!(exports0, "multi-memory/exports0");
+    test!(float_exprs0, "multi-memory/float_exprs0");
+    test!(float_exprs1, "multi-memory/float_exprs1");
+    test!(float_memory0, "multi-memory/float_memory0");
+    test!(imports0, "multi-memory/imports0");
+    test!(imports1, "multi-memory/imports1");
+    test!(imports2, "multi-memory/imports2");
+    test!(imports3, "multi-memory/imports3");
+    test!(imports4, "multi-memory/imports4");
+    test!(linking0, "multi-memory/linking0");
+    test!(linking1, "multi-memory/linking1");
+    test!(linking2, "multi-memory/linking2");
+    test!(linking3, "multi-memory/linking3");
+    test!(load0, "multi-memory/load0");
+    test!(load1, "multi-memory/load1");
+    test!(load2, "multi-memory/load2");
+    test!(memory_copy0, "multi-memory/memory_copy0");
+    test!(memory_copy1, "multi-memory/memory_copy1");
+    test!(memory_fill0, "multi-memory/memory_fill0");
+    test!(memory_grow, "multi-memory/memory_grow");
+    test!(memory_init0, "multi-memory/memory_init0");
+    test!(memory_size0, "multi-memory/memory_size0");
+    test!(memory_size1, "multi-memory/memory_size1");
+    test!(memory_size2, "multi-memory/memory_size2");
+    test!(memory_size3, "multi-memory/memory_size3");
+    test!(memory_trap0, "multi-memory/memory_trap0");
+    test!(memory_trap1, "multi-memory/memory_trap1");
+    test!(memory_multi, "multi-memory/memory-multi");
+    test!(start0, "multi-memory/start0");
+    test!(store0, "multi-memory/store0");
+    test!(store1, "multi-memory/store1");
+    test!(traps0, "multi-memory/traps0");
+}
+
+#[cfg(feature = "vector-types")]
+mod simd {
+    use super::*;
+
+    test!(simd_address, "simd/simd_address");
+    test!(simd_align, "simd/simd_align");
+    test!(simd_bit_shift, "simd/simd_bit_shift");
+    test!(simd_bitwise, "simd/simd_bitwise");
+    test!(simd_boolean, "simd/simd_boolean");
+    test!(simd_const, "simd/simd_const");
+    test!(simd_conversions, "simd/simd_conversions");
+    test!(simd_f32x4, "simd/simd_f32x4");
+    test!(simd_f32x4_arith, "simd/simd_f32x4_arith");
+    test!(simd_f32x4_cmp, "simd/simd_f32x4_cmp");
+    test!(simd_f32x4_pmin_pmax, "simd/simd_f32x4_pmin_pmax");
+    test!(simd_f32x4_rounding, "simd/simd_f32x4_rounding");
+    test!(simd_f64x2, "simd/simd_f64x2");
+    test!(simd_f64x2_arith, "simd/simd_f64x2_arith");
+    test!(simd_f64x2_cmp, "simd/simd_f64x2_cmp");
+    test!(simd_f64x2_pmin_pmax, "simd/simd_f64x2_pmin_pmax");
+    test!(simd_f64x2_rounding, "simd/simd_f64x2_rounding");
+    test!(simd_i16x8_arith, "simd/simd_i16x8_arith");
+    test!(simd_i16x8_arith2, "simd/simd_i16x8_arith2");
+    test!(simd_i16x8_cmp, "simd/simd_i16x8_cmp");
+    test!(simd_i16x8_extadd_pairwise_i8x16, "simd/simd_i16x8_extadd_pairwise_i8x16");
+    test!(simd_i16x8_extmul_i8x16, "simd/simd_i16x8_extmul_i8x16");
+    test!(simd_i16x8_q15mulr_sat_s, "simd/simd_i16x8_q15mulr_sat_s");
+    test!(simd_i16x8_sat_arith, "simd/simd_i16x8_sat_arith");
+    test!(simd_i32x4_arith, "simd/simd_i32x4_arith");
+    test!(simd_i32x4_arith2, "simd/simd_i32x4_arith2");
+    test!(simd_i32x4_cmp, "simd/simd_i32x4_cmp");
+    test!(simd_i32x4_dot_i16x8, "simd/simd_i32x4_dot_i16x8");
+    test!(simd_i32x4_extadd_pairwise_i16x8, "simd/simd_i32x4_extadd_pairwise_i16x8");
+    test!(simd_i32x4_extmul_i16x8, "simd/simd_i32x4_extmul_i16x8");
+    test!(simd_i32x4_trunc_sat_f32x4, "simd/simd_i32x4_trunc_sat_f32x4");
+    test!(simd_i32x4_trunc_sat_f64x2, "simd/simd_i32x4_trunc_sat_f64x2");
+    test!(simd_i64x2_arith, "simd/simd_i64x2_arith");
+    test!(simd_i64x2_arith2, "simd/simd_i64x2_arith2");
+    test!(simd_i64x2_cmp, "simd/simd_i64x2_cmp");
+    test!(simd_i64x2_extmul_i32x4, "simd/simd_i64x2_extmul_i32x4");
+    test!(simd_i8x16_arith, "simd/simd_i8x16_arith");
+    test!(simd_i8x16_arith2, "simd/simd_i8x16_arith2");
+    test!(simd_i8x16_cmp, "simd/simd_i8x16_cmp");
+    test!(simd_i8x16_sat_arith, "simd/simd_i8x16_sat_arith");
+    test!(simd_int_to_int_extend, "simd/simd_int_to_int_extend");
+    test!(simd_lane, "simd/simd_lane");
+    test!(simd_linking, "simd/simd_linking");
+    test!(simd_load, "simd/simd_load");
+    test!(simd_load16_lane, "simd/simd_load16_lane");
+    test!(simd_load32_lane, "simd/simd_load32_lane");
+    test!(simd_load64_lane, "simd/simd_load64_lane");
+    test!(simd_load8_lane, "simd/simd_load8_lane");
+    test!(simd_load_extend, "simd/simd_load_extend");
+    test!(simd_load_splat, "simd/simd_load_splat");
+    test!(simd_load_zero, "simd/simd_load_zero");
+    test!(simd_splat, "simd/simd_splat");
+    test!(simd_store, "simd/simd_store");
+    test!(simd_store16_lane, "simd/simd_store16_lane");
+    test!(simd_store32_lane, "simd/simd_store32_lane");
+    test!(simd_store64_lane, "simd/simd_store64_lane");
+    test!(simd_store8_lane, "simd/simd_store8_lane");
+}
+
+#[cfg(feature = "exception-handling")]
+mod exception_handling {
+    use super::*;
+
+    test!(tag);
+    test!(throw);
+    test!(throw_ref);
+    test!(try_table);
+}
+
+#[cfg(feature = "tail-call")]
+mod tail_call {
+    use super::*;
+
+    test!(return_call);
+    test!(return_call_indirect);
+}
@@ -34,3 +34,15 @@ impl Termination for Result<(), crate::Error> {
         self.unwrap();
     }
 }
+
+/// Converts a string to an array of bytes at compile time.
+pub const fn to_array<const N: usize>(x: &str) -> [u8; N] {
+    let x = x.as_bytes();
+    let mut result = [0; N];
+    let mut i = 0;
+    while i < N {
+        result[i] = x[i];
+        i += 1;
+    }
+    result
+}
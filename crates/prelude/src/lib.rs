@@ -148,6 +148,38 @@ macro_rules! applet {
     };
 }
 
+/// Declares the capabilities of an applet.
+///
+/// The platform only lets the applet use the API modules (or functions) designated by those paths,
+/// e.g. `"store"`, `"crypto::ec"`, or `"usb::serial"`. The `debug` and `scheduling` modules are
+/// always granted. Applets without capabilities are granted the whole API.
+///
+/// # Examples
+///
+/// ```ignore
+/// #![no_std]
+/// wasefire::applet!();
+/// wasefire::capabilities!("store", "crypto::ec");
+/// ```
+#[cfg(not(any(feature = "native", feature = "test")))]
+#[macro_export]
+macro_rules! capabilities {
+    ($($path:literal),* $(,)?) => {
+        const _: () = {
+            const CAPABILITIES: &str = concat!($($path, ","),*);
+            #[used]
+            #[link_section = "wasefire-capabilities"]
+            static SECTION: [u8; CAPABILITIES.len()] = $crate::internal::to_array(CAPABILITIES);
+        };
+    };
+}
+#[allow(missing_docs)] // see above
+#[cfg(any(feature = "native", feature = "test"))]
+#[macro_export]
+macro_rules! capabilities {
+    ($($path:literal),* $(,)?) => {};
+}
+
 #[cfg(not(feature = "test"))]
 #[panic_handler]
 fn handle_panic(info: &core::panic::PanicInfo) -> ! {
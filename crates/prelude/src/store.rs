@@ -19,8 +19,14 @@ use alloc::boxed::Box;
 #[cfg(feature = "api-store")]
 use alloc::vec::Vec;
 
+#[cfg(feature = "api-store")]
+use bytemuck::Zeroable;
 #[cfg(feature = "api-store")]
 use wasefire_applet_api::store as api;
+#[cfg(feature = "api-store")]
+pub use wasefire_applet_api::store::Ratio;
+#[cfg(feature = "api-store")]
+use wasefire_error::Code;
 
 #[cfg(feature = "api-store")]
 use crate::{convert, convert_bool, convert_unit, Error};
@@ -30,9 +36,9 @@ pub mod fragment;
 
 /// Inserts an entry in the store.
 ///
-/// The `key` argument must be a small integer (currently less than 4096). The `value` argument is
-/// the slice to associate with this key. If there was already a value, it is overwritten.
-/// Overwritten values are zeroized from flash.
+/// The `key` argument must be a small integer (currently less than 3072 divided by the number of
+/// applets the platform can run). The `value` argument is the slice to associate with this key. If
+/// there was already a value, it is overwritten. Overwritten values are zeroized from flash.
 #[cfg(feature = "api-store")]
 pub fn insert(key: usize, value: &[u8]) -> Result<(), Error> {
     let params = api::insert::Params { key, ptr: value.as_ptr(), len: value.len() };
@@ -81,3 +87,83 @@ pub fn keys() -> Result<Vec<u16>, Error> {
 pub fn clear() -> Result<(), Error> {
     convert_unit(unsafe { api::clear() })
 }
+
+/// Sequence of updates applied atomically.
+///
+/// Either all updates are applied or none. The keys of the updates must be distinct and there must
+/// be at most 31 updates.
+#[cfg(feature = "api-store")]
+#[derive(Debug, Default)]
+pub struct Transaction<'a> {
+    updates: Vec<(usize, Option<&'a [u8]>)>,
+}
+
+#[cfg(feature = "api-store")]
+impl<'a> Transaction<'a> {
+    /// Creates an empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts an entry in the store when the transaction is committed.
+    ///
+    /// The `key` argument has the same constraints as for [`insert()`].
+    pub fn insert(&mut self, key: usize, value: &'a [u8]) -> &mut Self {
+        self.updates.push((key, Some(value)));
+        self
+    }
+
+    /// Removes an entry from the store when the transaction is committed.
+    pub fn remove(&mut self, key: usize) -> &mut Self {
+        self.updates.push((key, None));
+        self
+    }
+
+    /// Applies the updates of the transaction atomically.
+    pub fn commit(&self) -> Result<(), Error> {
+        let mut updates = Vec::with_capacity(self.updates.len());
+        for &(key, value) in &self.updates {
+            let key = u32::try_from(key).map_err(|_| Error::user(Code::InvalidArgument))?;
+            updates.push(match value {
+                Some(value) => {
+                    api::Update { key, ptr: value.as_ptr() as u32, len: value.len() as u32 }
+                }
+                None => api::Update { key, ptr: 0, len: 0 },
+            });
+        }
+        let params =
+            api::transaction::Params { ptr: updates.as_ptr() as *const u8, len: updates.len() };
+        convert_unit(unsafe { api::transaction(params) })
+    }
+}
+
+/// Returns the capacity in words used by the applet and its quota.
+///
+/// The quota is an even share of the store capacity between the applets the platform can run.
+#[cfg(feature = "api-store")]
+pub fn capacity() -> Result<Ratio, Error> {
+    let mut result = Ratio::zeroed();
+    let params = api::capacity::Params { ptr: &mut result as *mut Ratio as *mut u8 };
+    convert_unit(unsafe { api::capacity(params) })?;
+    Ok(result)
+}
+
+/// Returns the lifetime in words used by the store and its total.
+///
+/// The lifetime is shared by all applets and the platform.
+#[cfg(feature = "api-store")]
+pub fn lifetime() -> Result<Ratio, Error> {
+    let mut result = Ratio::zeroed();
+    let params = api::lifetime::Params { ptr: &mut result as *mut Ratio as *mut u8 };
+    convert_unit(unsafe { api::lifetime(params) })?;
+    Ok(result)
+}
+
+/// Compacts the store once if needed to insert a value of a given length without compaction.
+///
+/// Compaction may take a while, so this permits to do it at a convenient time.
+#[cfg(feature = "api-store")]
+pub fn prepare(len: usize) -> Result<(), Error> {
+    let params = api::prepare::Params { len };
+    convert_unit(unsafe { api::prepare(params) })
+}
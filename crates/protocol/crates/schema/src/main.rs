@@ -19,7 +19,6 @@ use std::fmt::Write;
 use std::process::Command;
 
 use anyhow::{bail, ensure, Context, Result};
-use wasefire_cli_tools::{cmd, fs};
 use wasefire_error::Error;
 use wasefire_protocol::{Api, Descriptor, Request, Response, DESCRIPTORS, VERSION};
 use wasefire_wire::schema::{View, ViewEnum, ViewFields};
@@ -125,7 +124,10 @@ impl Schema<'static> {
         let base = Self::base()?;
         let mut git = Command::new("git");
         git.args(["show", &format!("{base}:./{SIDE}.bin")]);
-        let data = cmd::output(&mut git)?.stdout.into_boxed_slice();
+        // We can't use wasefire-cli-tools because it enables the host feature of the protocol.
+        let output = git.output()?;
+        ensure!(output.status.success(), "git failed with status {}", output.status);
+        let data = output.stdout.into_boxed_slice();
         Ok(wasefire_wire::decode_yoke(data)?)
     }
 
@@ -146,7 +148,7 @@ impl Schema<'static> {
     }
 
     fn write(&self) -> Result<()> {
-        fs::write(format!("{SIDE}.bin"), wasefire_wire::encode(self)?)
+        Ok(std::fs::write(format!("{SIDE}.bin"), wasefire_wire::encode(self)?)?)
     }
 
     fn print(&self) -> Result<()> {
@@ -170,7 +172,7 @@ impl Schema<'static> {
         }
         assert!(response.next().is_none());
         assert!(descriptor.next().is_none());
-        fs::write(format!("{SIDE}.txt"), &output)
+        Ok(std::fs::write(format!("{SIDE}.txt"), &output)?)
     }
 }
 
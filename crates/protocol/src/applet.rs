@@ -16,7 +16,7 @@ use wasefire_wire::Wire;
 
 #[derive(Debug, Wire)]
 pub struct Request<'a> {
-    pub applet_id: AppletId,
+    pub applet_id: AppletId<'a>,
     pub request: &'a [u8],
 }
 
@@ -25,11 +25,76 @@ pub struct Response<'a> {
     pub response: Option<&'a [u8]>,
 }
 
+/// Applet identifier.
 #[derive(Debug, Copy, Clone, Wire)]
-pub struct AppletId;
+pub struct AppletId<'a> {
+    /// Name of the applet in the platform.
+    pub name: &'a str,
+}
 
 #[derive(Debug, Wire)]
 pub struct Tunnel<'a> {
-    pub applet_id: AppletId,
+    pub applet_id: AppletId<'a>,
     pub delimiter: &'a [u8],
 }
+
+/// Types of the services addressing the default applet (before applet identifiers).
+pub mod legacy {
+    use wasefire_wire::Wire;
+
+    #[derive(Debug, Wire)]
+    pub struct Request<'a> {
+        pub applet_id: AppletId,
+        pub request: &'a [u8],
+    }
+
+    /// Identifies the default applet of the platform.
+    #[derive(Debug, Copy, Clone, Wire)]
+    pub struct AppletId;
+
+    #[derive(Debug, Wire)]
+    pub struct Tunnel<'a> {
+        pub applet_id: AppletId,
+        pub delimiter: &'a [u8],
+    }
+}
+
+#[derive(Debug, Wire)]
+pub enum Install<'a> {
+    /// Starts installing an applet.
+    ///
+    /// Any unfinished installation is discarded. If an applet with the same name is already
+    /// installed, it is replaced when the installation finishes.
+    Start { name: &'a str, size: u32 },
+
+    /// Writes the next chunk of the applet module.
+    Write { chunk: &'a [u8] },
+
+    /// Finishes the installation and starts the applet.
+    ///
+    /// The module must have been entirely written.
+    Finish,
+}
+
+#[derive(Debug, Wire)]
+pub struct Metadata<'a> {
+    pub name: &'a str,
+    pub size: u32,
+    pub running: bool,
+}
+
+/// Reason why an applet was rejected by the platform.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Wire)]
+pub enum Rejection {
+    /// The module is not valid.
+    Invalid,
+
+    /// The module is not signed.
+    Unsigned,
+
+    /// The signature of the module is malformed.
+    Malformed,
+
+    /// The module is not signed by a trusted key.
+    Untrusted,
+}
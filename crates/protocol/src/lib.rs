@@ -34,6 +34,7 @@
 extern crate alloc;
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 use wasefire_error::Error;
 use wasefire_wire::Wire;
@@ -204,27 +205,57 @@ api! {
     //!
     //! Variants gated by the `full` feature are deprecated. They won't be used by new devices.
     //! However, to support older devices, the host must be able to use them.
-    version = 2;
-    next = 7;
+    version = 7;
+    next = 17;
 
     /// Returns the device API version.
     0 [0 -] ApiVersion: () => u32,
 
-    /// Sends a request to an applet.
-    1 [0 -] AppletRequest: applet::Request<'a> => (),
+    /// Sends a request to the default applet (prefer `AppletRequest`).
+    1 [0 -] LegacyAppletRequest: applet::legacy::Request<'a> => (),
 
-    /// Reads a response from an applet.
-    2 [0 -] AppletResponse: applet::AppletId => applet::Response<'a>,
+    /// Reads a response from the default applet (prefer `AppletResponse`).
+    2 [0 -] LegacyAppletResponse: applet::legacy::AppletId => applet::Response<'a>,
 
     /// Reboots the platform.
     3 [0 -] PlatformReboot: () => !,
 
-    /// Starts a direct tunnel with an applet.
-    4 [0 -] AppletTunnel: applet::Tunnel<'a> => (),
+    /// Starts a direct tunnel with the default applet (prefer `AppletTunnel`).
+    4 [0 -] LegacyAppletTunnel: applet::legacy::Tunnel<'a> => (),
 
     /// Returns platform information (e.g. serial and version).
     5 [1 -] PlatformInfo: () => platform::Info<'a>,
 
     /// Calls a vendor-specific platform command.
     6 [2 -] PlatformVendor: &'a [u8] => &'a [u8],
+
+    /// Installs an applet (streamed in chunks).
+    7 [3 -] AppletInstall: applet::Install<'a> => (),
+
+    /// Lists the installed applets.
+    8 [3 -] AppletList: () => Vec<applet::Metadata<'a>>,
+
+    /// Uninstalls an applet given its name.
+    9 [3 -] AppletUninstall: &'a str => (),
+
+    /// Sends a request to an applet.
+    10 [4 -] AppletRequest: applet::Request<'a> => (),
+
+    /// Reads a response from an applet.
+    11 [4 -] AppletResponse: applet::AppletId<'a> => applet::Response<'a>,
+
+    /// Starts a direct tunnel with an applet.
+    12 [4 -] AppletTunnel: applet::Tunnel<'a> => (),
+
+    /// Returns the metadata for platform update.
+    13 [5 -] PlatformUpdateMetadata: () => &'a [u8],
+
+    /// Updates the platform (streamed in chunks).
+    14 [5 -] PlatformUpdate: platform::Update<'a> => (),
+
+    /// Returns why an applet was last rejected, if it was.
+    15 [6 -] AppletRejection: &'a str => Option<applet::Rejection>,
+
+    /// Returns the health of the platform store (e.g. wear of the flash storage).
+    16 [7 -] PlatformStoreHealth: () => platform::StoreHealth,
 }
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::vec::Vec;
+
 use wasefire_wire::Wire;
 
 #[derive(Debug, Wire)]
@@ -19,3 +21,50 @@ pub struct Info<'a> {
     pub serial: &'a [u8],
     pub version: &'a [u8],
 }
+
+#[derive(Debug, Wire)]
+pub enum Update<'a> {
+    /// Starts a platform update.
+    ///
+    /// Any unfinished update is discarded. During a dry-run, the platform only performs checks.
+    Initialize { dry_run: bool },
+
+    /// Processes the next chunk of the platform update.
+    ///
+    /// The offset must be the number of bytes already processed. A chunk that was just processed
+    /// is acknowledged again (without being processed), such that the host can resend a chunk
+    /// when the response is lost.
+    Process { offset: u32, chunk: &'a [u8] },
+
+    /// Finalizes the platform update.
+    ///
+    /// The platform reboots when the update is successful (and not a dry-run). In that case, there
+    /// is no response.
+    Finalize,
+}
+
+/// Health of the platform store.
+#[derive(Debug, Wire)]
+pub struct StoreHealth {
+    /// Capacity in words (size of what is stored).
+    pub capacity: Ratio,
+
+    /// Lifetime in words (age of the storage).
+    pub lifetime: Ratio,
+
+    /// Projected number of remaining page erases.
+    pub remaining_erases: u32,
+
+    /// Number of pages per bucket of erase counts.
+    ///
+    /// Buckets are numbers sharing the same highest bit and are identified by their lower-bound,
+    /// i.e. 0, 1, 2, 4, 8, etc. Only non-empty buckets are listed, in increasing order.
+    pub wear: Vec<(u32, u32)>,
+}
+
+/// Used and total amount of a metric.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Wire)]
+pub struct Ratio {
+    pub used: u32,
+    pub total: u32,
+}
@@ -16,7 +16,7 @@ pub mod button;
 mod crypto;
 mod debug;
 mod led;
-mod platform;
+pub mod platform;
 mod rng;
 mod storage;
 pub mod timer;
@@ -40,6 +40,7 @@ pub struct State {
     #[cfg(feature = "usb")]
     pub usb: usb::Usb,
     pub storage: Option<FileStorage>,
+    pub update: platform::update::Update,
     #[cfg(feature = "web")]
     pub web: web_server::Client,
 }
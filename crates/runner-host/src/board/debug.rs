@@ -37,6 +37,8 @@ impl board::debug::Api for Impl {
     }
 
     fn exit(success: bool) -> ! {
+        #[cfg(feature = "profile")]
+        crate::profile::write();
         std::process::exit(if success { 0 } else { 1 })
     }
 }
@@ -19,12 +19,16 @@ use wasefire_board_api::platform::Api;
 use wasefire_board_api::Error;
 use wasefire_error::Code;
 
+pub mod update;
+
 pub enum Impl {}
 
 impl Api for Impl {
     #[cfg(feature = "usb")]
     type Protocol = crate::board::usb::ProtocolImpl;
 
+    type Update = update::Impl;
+
     fn serial() -> Cow<'static, [u8]> {
         from_hex(option_env!("WASEFIRE_HOST_SERIAL"))
     }
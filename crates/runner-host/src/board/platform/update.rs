@@ -0,0 +1,168 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! File-backed A/B platform update.
+//!
+//! The platform has 2 sides (A and B), each backed by an image file in a directory. The directory
+//! also contains a file with the current side. An update writes the image of the other side, which
+//! becomes the current side when the update is finalized. The platform then exits (instead of
+//! rebooting) and is expected to be restarted.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use wasefire_board_api::platform::update::Api;
+use wasefire_board_api::Supported;
+use wasefire_error::{Code, Error};
+use wasefire_logger as log;
+
+use crate::with_state;
+
+pub enum Impl {}
+
+impl Supported for Impl {}
+
+impl Api for Impl {
+    fn metadata() -> Result<Box<[u8]>, Error> {
+        with_state(|state| {
+            let update = &state.update;
+            let side = update.side()?;
+            let mut metadata = Vec::new();
+            update.push_side(&mut metadata, side)?;
+            update.push_side(&mut metadata, !side)?;
+            Ok(metadata.into_boxed_slice())
+        })
+    }
+
+    fn initialize(dry_run: bool) -> Result<(), Error> {
+        with_state(|state| {
+            let update = &mut state.update;
+            update.session = None;
+            let file = match dry_run {
+                true => None,
+                false => {
+                    std::fs::create_dir_all(&update.dir).map_err(world)?;
+                    Some(File::create(update.image(!update.side()?)).map_err(world)?)
+                }
+            };
+            update.session = Some(Session { file });
+            Ok(())
+        })
+    }
+
+    fn process(chunk: &[u8]) -> Result<(), Error> {
+        with_state(|state| {
+            let session = state.update.session.as_mut().ok_or(Error::user(Code::InvalidState))?;
+            if let Some(file) = &mut session.file {
+                file.write_all(chunk).map_err(world)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn finalize() -> Result<(), Error> {
+        with_state(|state| {
+            let update = &mut state.update;
+            let session = update.session.take().ok_or(Error::user(Code::InvalidState))?;
+            let Some(file) = session.file else { return Ok(()) };
+            file.sync_all().map_err(world)?;
+            let side = !update.side()?;
+            std::fs::write(update.dir.join("side"), side.name()).map_err(world)?;
+            log::info!("Updated side {}. Exiting to restart.", side.name());
+            #[cfg(feature = "profile")]
+            crate::profile::write();
+            std::process::exit(0)
+        })
+    }
+}
+
+pub struct Update {
+    /// Directory containing the side images and the current side.
+    dir: PathBuf,
+
+    /// Update in progress, if any.
+    session: Option<Session>,
+}
+
+struct Session {
+    /// Image of the other side, unless during a dry-run.
+    file: Option<File>,
+}
+
+impl Update {
+    pub fn new(dir: &Path) -> Self {
+        Update { dir: dir.to_path_buf(), session: None }
+    }
+
+    fn side(&self) -> Result<Side, Error> {
+        match std::fs::read(self.dir.join("side")) {
+            Ok(x) if x == b"A" => Ok(Side::A),
+            Ok(x) if x == b"B" => Ok(Side::B),
+            Ok(_) => Err(Error::world(Code::InvalidState)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Side::A),
+            Err(e) => Err(world(e)),
+        }
+    }
+
+    fn image(&self, side: Side) -> PathBuf {
+        self.dir.join(format!("{}.bin", side.name()))
+    }
+
+    /// Pushes the side (0xa or 0xb) and the length of its image as big-endian u32.
+    fn push_side(&self, metadata: &mut Vec<u8>, side: Side) -> Result<(), Error> {
+        let length = match std::fs::metadata(self.image(side)) {
+            Ok(x) => u32::try_from(x.len()).map_err(|_| Error::world(Code::OutOfBounds))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(world(e)),
+        };
+        metadata.push(match side {
+            Side::A => 0xa,
+            Side::B => 0xb,
+        });
+        metadata.extend_from_slice(&length.to_be_bytes());
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    fn name(self) -> &'static str {
+        match self {
+            Side::A => "A",
+            Side::B => "B",
+        }
+    }
+}
+
+impl std::ops::Not for Side {
+    type Output = Side;
+
+    fn not(self) -> Side {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+fn world(error: std::io::Error) -> Error {
+    log::error!("Platform update failed: {}", error);
+    Error::world(0)
+}
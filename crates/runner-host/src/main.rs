@@ -12,24 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#![cfg_attr(test, allow(unused_crate_dependencies))]
 #![feature(never_type)]
 #![feature(try_blocks)]
 
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::Path;
+#[cfg(feature = "wasm")]
+use std::path::PathBuf;
 use std::sync::Mutex;
 
+#[cfg(feature = "wasm")]
+use anyhow::anyhow;
 use anyhow::Result;
 use board::Board;
 use clap::Parser;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::{channel, Receiver};
 use wasefire_board_api::Event;
-#[cfg(feature = "wasm")]
+#[cfg(all(feature = "wasm", not(feature = "profile")))]
 use wasefire_interpreter as _;
 use wasefire_scheduler::Scheduler;
-use wasefire_store::{FileOptions, FileStorage};
+use wasefire_store::{FileOptions, FileStorage, MeteredStorage};
 
 mod board;
+#[cfg(feature = "profile")]
+mod profile;
 
 static STATE: Mutex<Option<board::State>> = Mutex::new(None);
 static RECEIVER: Mutex<Option<Receiver<Event<Board>>>> = Mutex::new(None);
@@ -40,6 +49,22 @@ fn with_state<R>(f: impl FnOnce(&mut board::State) -> R) -> R {
 
 #[derive(Parser)]
 struct Flags {
+    /// Applets to run in addition to the installed applets.
+    ///
+    /// Applets are named after their file stem in the platform protocol. The scheduler must be
+    /// built with enough applet slots (see `--applet-count` in xtask).
+    #[cfg(feature = "wasm")]
+    #[clap(long = "applet", value_name = "PATH")]
+    applets: Vec<PathBuf>,
+
+    /// Profiles the applets and writes their profiles in this directory when exiting.
+    ///
+    /// Profiles count the executed instructions of each call stack in the folded stack format (as
+    /// used by flame graph tools). Exit with Ctrl-C (or from the platform) to write them.
+    #[cfg(feature = "profile")]
+    #[clap(long, value_name = "DIR")]
+    profile: Option<PathBuf>,
+
     #[cfg(feature = "web")]
     #[clap(flatten)]
     web_options: WebOptions,
@@ -59,12 +84,19 @@ struct WebOptions {
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
-    #[cfg_attr(not(feature = "web"), allow(unused_variables))]
+    #[cfg_attr(not(any(feature = "wasm", feature = "web")), allow(unused_variables))]
     let flags = Flags::parse();
     // TODO: Should be a flag controlled by xtask (value is duplicated there).
     const STORAGE: &str = "../../target/wasefire/storage.bin";
-    let options = FileOptions { word_size: 4, page_size: 4096, num_pages: 16 };
+    let mut options = FileOptions { word_size: 4, page_size: 4096, num_pages: 16 };
+    // The store health persists its statistics in additional pages.
+    if cfg!(feature = "store-health") {
+        options.num_pages += MeteredStorage::<FileStorage>::JOURNAL_PAGES;
+    }
+    resize_storage(Path::new(STORAGE), &options)?;
     let storage = Some(FileStorage::new(Path::new(STORAGE), options).unwrap());
+    const PLATFORM: &str = "../../target/wasefire/platform";
+    let update = board::platform::update::Update::new(Path::new(PLATFORM));
     let (sender, receiver) = channel(10);
     *RECEIVER.lock().unwrap() = Some(receiver);
     #[cfg(feature = "web")]
@@ -91,6 +123,7 @@ async fn main() -> Result<()> {
         #[cfg(feature = "usb")]
         usb: board::usb::Usb::default(),
         storage,
+        update,
         #[cfg(feature = "web")]
         web,
     });
@@ -117,10 +150,58 @@ async fn main() -> Result<()> {
     });
     println!("Board initialized. Starting scheduler.");
     #[cfg(feature = "wasm")]
-    const WASM: &[u8] = include_bytes!("../../../target/wasefire/applet.wasm");
+    let mut applets = Vec::new();
     #[cfg(feature = "wasm")]
-    Handle::current().spawn_blocking(|| Scheduler::<board::Board>::run(WASM)).await?;
+    for path in &flags.applets {
+        let name = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| anyhow!("invalid applet path"))?;
+        let name: &'static str = Box::leak(name.into());
+        let applet: &'static [u8] = Box::leak(std::fs::read(path)?.into_boxed_slice());
+        applets.push((name, applet));
+    }
+    #[cfg(feature = "profile")]
+    let profiling = flags.profile.is_some();
+    #[cfg(feature = "profile")]
+    if let Some(dir) = flags.profile {
+        profile::init(dir);
+        tokio::spawn(async {
+            tokio::signal::ctrl_c().await.unwrap();
+            profile::write();
+            // This is the exit code of a process interrupted by Ctrl-C.
+            std::process::exit(130);
+        });
+    }
+    #[cfg(feature = "profile")]
+    Handle::current()
+        .spawn_blocking(move || match profiling {
+            false => Scheduler::<board::Board>::run_applets(&applets),
+            true => Scheduler::<board::Board>::run_applets_traced(&applets, profile::new_tracer),
+        })
+        .await?;
+    #[cfg(all(feature = "wasm", not(feature = "profile")))]
+    Handle::current()
+        .spawn_blocking(move || Scheduler::<board::Board>::run_applets(&applets))
+        .await?;
     #[cfg(feature = "native")]
     Handle::current().spawn_blocking(|| Scheduler::<board::Board>::run()).await?;
     Ok(())
 }
+
+/// Resizes the storage file when the store health is enabled or disabled.
+///
+/// The journal pages of the store health are appended (erased) or removed, such that the store
+/// keeps its data.
+fn resize_storage(path: &Path, options: &FileOptions) -> Result<()> {
+    let Ok(metadata) = std::fs::metadata(path) else { return Ok(()) };
+    let journal = MeteredStorage::<FileStorage>::JOURNAL_PAGES * options.page_size;
+    let len = options.num_pages * options.page_size;
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    match metadata.len() as usize {
+        x if x + journal == len => file.write_all(&vec![0xff; journal])?,
+        x if x == len + journal => file.set_len(len as u64)?,
+        _ => (),
+    }
+    Ok(())
+}
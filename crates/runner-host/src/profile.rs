@@ -0,0 +1,80 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Profiling of WASM applets.
+//!
+//! Each applet has a counting profiler. The profiles are written in the folded stack format when
+//! the platform exits, in a file named after the applet (e.g. `<dir>/<name>.folded`).
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use wasefire_interpreter::{FrameInfo, Profiler, Tracer};
+
+struct State {
+    dir: PathBuf,
+    profilers: Vec<(String, Arc<Mutex<Profiler>>)>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+/// Enables profiling with profiles written in a directory.
+pub fn init(dir: PathBuf) {
+    *STATE.lock().unwrap() = Some(State { dir, profilers: Vec::new() });
+}
+
+/// Creates the tracer of an applet (replacing its previous profile if any).
+pub fn new_tracer(name: &str) -> Box<dyn Tracer> {
+    let profiler = Arc::new(Mutex::new(Profiler::default()));
+    let mut state = STATE.lock().unwrap();
+    let profilers = &mut state.as_mut().unwrap().profilers;
+    profilers.retain(|(x, _)| x != name);
+    profilers.push((name.to_string(), profiler.clone()));
+    Box::new(Shared(profiler))
+}
+
+/// Writes the profiles if profiling is enabled.
+pub fn write() {
+    let state = STATE.lock().unwrap();
+    let Some(state) = state.as_ref() else { return };
+    for (name, profiler) in &state.profilers {
+        let mut output = String::new();
+        profiler.lock().unwrap().write_folded(&mut output).unwrap();
+        let path = state.dir.join(format!("{name}.folded"));
+        match std::fs::create_dir_all(&state.dir).and_then(|()| std::fs::write(&path, output)) {
+            Ok(()) => println!("Wrote profile of {name} to {}.", path.display()),
+            Err(error) => eprintln!("Failed to write profile of {name}: {error}"),
+        }
+    }
+}
+
+struct Shared(Arc<Mutex<Profiler>>);
+
+impl Tracer for Shared {
+    fn trace_instrs(&self) -> bool {
+        self.0.lock().unwrap().trace_instrs()
+    }
+
+    fn enter(&mut self, frame: FrameInfo) {
+        self.0.lock().unwrap().enter(frame)
+    }
+
+    fn exit(&mut self) {
+        self.0.lock().unwrap().exit()
+    }
+
+    fn instr(&mut self, offset: usize) {
+        self.0.lock().unwrap().instr(offset)
+    }
+}
@@ -22,7 +22,10 @@ ensure_applet
 test_helper
 
 cargo test --bin=runner-host --features=wasm,debug
+WASEFIRE_APPLET_COUNT=2 cargo test --test=applets --no-default-features --features=wasm,debug
 cargo check --bin=runner-host --features=wasm,debug,web
+cargo check --bin=runner-host --features=wasm,debug,profile
+cargo check --bin=runner-host --features=wasm,debug,store-health
 cargo check --bin=runner-host --features=wasm,release
 cargo check --bin=runner-host --target=i686-unknown-linux-gnu --features=native,release
 cargo check --bin=runner-host --no-default-features --features=wasm,debug
@@ -0,0 +1,143 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs applets side by side on the host runner.
+//!
+//! The runner must be built with 2 applet slots (`WASEFIRE_APPLET_COUNT=2`) and without the `usb`
+//! feature (see `test.sh`).
+
+#![allow(unused_crate_dependencies)]
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use wast::parser::{self, ParseBuffer};
+use wast::Wat;
+
+/// Prints a tick on each period of its timer and exits successfully after 10 ticks.
+const TICKER: &str = r#"(module
+  (import "env" "dp" (func $dp (param i32 i32) (result i32)))
+  (import "env" "ta" (func $ta (param i32 i32) (result i32)))
+  (import "env" "tb" (func $tb (param i32 i32 i32) (result i32)))
+  (import "env" "de" (func $de (param i32) (result i32)))
+  (memory 1)
+  (global $ticks (mut i32) (i32.const 0))
+  (data (i32.const 0) "tick")
+  (func (export "alloc") (param i32 i32) (result i32) (i32.const 0))
+  (func (export "init"))
+  (func (export "main")
+    (drop (call $tb (call $ta (i32.const 0) (i32.const 0)) (i32.const 1) (i32.const 10))))
+  (func (export "cb0") (param i32 i32)
+    (global.set $ticks (i32.add (global.get $ticks) (i32.const 1)))
+    (drop (call $dp (i32.const 0) (i32.const 4)))
+    (if (i32.eq (global.get $ticks) (i32.const 10))
+      (then (drop (call $de (i32.const 0)))))))"#;
+
+/// Traps when its timer fires, while the ticker is still ticking.
+const TRAPPER: &str = r#"(module
+  (import "env" "ta" (func $ta (param i32 i32) (result i32)))
+  (import "env" "tb" (func $tb (param i32 i32 i32) (result i32)))
+  (memory 1)
+  (func (export "alloc") (param i32 i32) (result i32) (i32.const 0))
+  (func (export "init"))
+  (func (export "main")
+    (drop (call $tb (call $ta (i32.const 0) (i32.const 0)) (i32.const 0) (i32.const 30))))
+  (func (export "cb0") (param i32 i32) (unreachable)))"#;
+
+fn encode(wat: &str, path: &Path) {
+    let buffer = ParseBuffer::new(wat).unwrap();
+    std::fs::write(path, parser::parse::<Wat>(&buffer).unwrap().encode().unwrap()).unwrap();
+}
+
+/// Spawns the runner with the given applets in a fresh root named after the test.
+///
+/// Returns the root (to be removed by the caller) and the runner process.
+fn spawn(test: &str, applets: &[(&str, &str)]) -> (PathBuf, Child) {
+    let root = std::env::temp_dir().join(format!("runner-host-{test}-{}", std::process::id()));
+    // The runner stores its state in ../../target/wasefire relative to its working directory.
+    let dir = root.join("crates/runner-host");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::create_dir_all(root.join("target/wasefire")).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_runner-host"));
+    for (name, wat) in applets {
+        encode(wat, &dir.join(format!("{name}.wasm")));
+        command.arg(format!("--applet={name}.wasm"));
+    }
+    let child = command
+        .current_dir(&dir)
+        .env("RUST_LOG", "warn")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    (root, child)
+}
+
+#[test]
+fn trap_one_of_two_applets() {
+    let (root, mut child) = spawn("trap-one", &[("ticker", TICKER), ("trapper", TRAPPER)]);
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("the runner did not exit");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+    assert!(status.success(), "stdout:\n{stdout}\nstderr:\n{stderr}");
+    assert!(stderr.contains("trapped"), "stderr:\n{stderr}");
+    assert_eq!(stdout.matches("tick").count(), 10, "stdout:\n{stdout}");
+}
+
+#[test]
+fn trap_all_applets() {
+    let (root, mut child) = spawn("trap-all", &[("first", TRAPPER), ("second", TRAPPER)]);
+    let stderr = child.stderr.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let reader = std::thread::spawn(move || {
+        let mut lines = Vec::new();
+        for line in BufReader::new(stderr).lines() {
+            let line = line.unwrap();
+            if line.contains("All applets stopped.") {
+                sender.send(()).unwrap();
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    });
+    let stopped = receiver.recv_timeout(Duration::from_secs(10)).is_ok();
+    // The platform (and thus the platform protocol) keeps running without applets.
+    std::thread::sleep(Duration::from_millis(500));
+    let status = child.try_wait().unwrap();
+    if status.is_none() {
+        child.kill().unwrap();
+    }
+    child.wait().unwrap();
+    let stderr = reader.join().unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+    assert!(stopped, "stderr:\n{stderr}");
+    assert_eq!(status, None, "stderr:\n{stderr}");
+    assert_eq!(stderr.matches("trapped").count(), 2, "stderr:\n{stderr}");
+}
@@ -108,7 +108,7 @@ unsafe fn read(addr: u32) -> u32 {
     unsafe { (addr as *const u32).read_volatile() }
 }
 
-// Keep those values in sync with the memory.x linker script.
+// Keep those values in sync with the memory.x linker script (and HEADER_LEN with the image crate).
 const HEADER_LEN: u32 = 0x00000100;
 const FIRMWARE_A: u32 = 0x00010000;
 const FIRMWARE_B: u32 = 0x00080000;
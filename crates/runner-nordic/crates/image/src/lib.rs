@@ -0,0 +1,289 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signed platform images.
+//!
+//! An image is the content of a side: a header of `HEADER_LEN` bytes followed by the firmware. The
+//! header starts with the following fields (little-endian), the rest being erased:
+//!
+//! | Offset | Length | Field                                          |
+//! | ------ | ------ | ---------------------------------------------- |
+//! | 0x00   | 4      | Version (used by the bootloader to pick sides) |
+//! | 0x04   | 12     | Boot attempts (erased in images)               |
+//! | 0x10   | 4      | Length of the firmware                         |
+//! | 0x14   | 32     | Signature `r` (big-endian)                     |
+//! | 0x34   | 32     | Signature `s` (big-endian)                     |
+//!
+//! The signature is a P-256 ECDSA signature of the SHA-256 digest of the version, the length, and
+//! the firmware.
+
+#![no_std]
+
+use digest::Update;
+use typenum::U32;
+use wasefire_board_api::crypto::ecc::{self, Int};
+use wasefire_board_api::crypto::Hash;
+use wasefire_error::{Code, Error};
+
+/// Length of the header.
+// Keep this value in sync with the header crate.
+pub const HEADER_LEN: usize = 0x100;
+
+const VERSION: usize = 0x00;
+const LENGTH: usize = 0x10;
+const SIGNATURE_R: usize = 0x14;
+const SIGNATURE_S: usize = 0x34;
+
+/// Software implementations to sign images on the host.
+#[cfg(feature = "software")]
+pub type SoftwareP256 = ecc::Software<p256::NistP256, wasefire_board_api::crypto::SoftwareSha256>;
+
+/// Software SHA-256 implementation.
+#[cfg(feature = "software")]
+pub type SoftwareSha256 = wasefire_board_api::crypto::SoftwareSha256;
+
+/// Public key to verify images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    /// Affine coordinate x (big-endian).
+    pub x: [u8; 32],
+
+    /// Affine coordinate y (big-endian).
+    pub y: [u8; 32],
+}
+
+impl PublicKey {
+    /// Parses a public key from the hexadecimal encoding of its coordinates (x then y).
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        let hex = hex.as_bytes();
+        if hex.len() != 128 {
+            return Err(Error::user(Code::InvalidLength));
+        }
+        let mut key = [0; 64];
+        for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+            *byte = digit(digits[0])? << 4 | digit(digits[1])?;
+        }
+        let (x, y) = key.split_at(32);
+        Ok(PublicKey { x: x.try_into().unwrap(), y: y.try_into().unwrap() })
+    }
+
+    /// Returns the public key of a private key.
+    pub fn new<P: ecc::Api<U32>>(private: &[u8; 32]) -> Result<Self, Error> {
+        let mut x = Int::<U32>::default();
+        let mut y = Int::<U32>::default();
+        P::base_point_mul(Int::<U32>::from_slice(private), &mut x, &mut y)?;
+        Ok(PublicKey { x: x.into(), y: y.into() })
+    }
+}
+
+/// Signed fields of the header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Version of the firmware.
+    pub version: u32,
+
+    /// Length of the firmware.
+    pub length: u32,
+}
+
+impl Header {
+    fn decode(header: &[u8; HEADER_LEN]) -> Self {
+        let version = read_u32(header, VERSION);
+        let length = read_u32(header, LENGTH);
+        Header { version, length }
+    }
+}
+
+/// Signs an image in place.
+///
+/// The image must contain the header (with the version already set) followed by the firmware. The
+/// length and signature of the header are overwritten.
+pub fn sign<P: ecc::Api<U32>, H: Hash<OutputSize = U32>>(
+    image: &mut [u8], private: &[u8; 32],
+) -> Result<(), Error> {
+    if image.len() < HEADER_LEN {
+        return Err(Error::user(Code::InvalidLength));
+    }
+    let (header, firmware) = image.split_at_mut(HEADER_LEN);
+    let length = u32::try_from(firmware.len()).map_err(|_| Error::user(Code::InvalidLength))?;
+    header[LENGTH ..][.. 4].copy_from_slice(&length.to_le_bytes());
+    let version = read_u32(header, VERSION);
+    let digest = H::with_error(|| {
+        let mut hash = H::default();
+        hash_prefix(&mut hash, Header { version, length });
+        hash.update(firmware);
+        hash.finalize_fixed_reset()
+    })?;
+    let mut r = Int::<U32>::default();
+    let mut s = Int::<U32>::default();
+    P::ecdsa_sign(Int::<U32>::from_slice(private), &digest, &mut r, &mut s)?;
+    header[SIGNATURE_R ..][.. 32].copy_from_slice(&r);
+    header[SIGNATURE_S ..][.. 32].copy_from_slice(&s);
+    Ok(())
+}
+
+/// Verifies an image while it is streamed.
+pub struct Verifier<H> {
+    /// Header being received.
+    header: [u8; HEADER_LEN],
+
+    /// Number of bytes received (header included).
+    received: usize,
+
+    /// Digest of the signed content (only updated once the header is received).
+    hash: H,
+}
+
+impl<H: Hash<OutputSize = U32>> Default for Verifier<H> {
+    fn default() -> Self {
+        Verifier { header: [0; HEADER_LEN], received: 0, hash: H::default() }
+    }
+}
+
+impl<H: Hash<OutputSize = U32>> Verifier<H> {
+    /// Processes the next chunk of the image.
+    pub fn update(&mut self, mut chunk: &[u8]) -> Result<(), Error> {
+        if self.received < HEADER_LEN {
+            let length = core::cmp::min(chunk.len(), HEADER_LEN - self.received);
+            self.header[self.received ..][.. length].copy_from_slice(&chunk[.. length]);
+            self.received += length;
+            chunk = &chunk[length ..];
+            if self.received == HEADER_LEN {
+                let header = Header::decode(&self.header);
+                H::with_error(|| hash_prefix(&mut self.hash, header))?;
+            }
+        }
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        let header = Header::decode(&self.header);
+        if header.length as usize - (self.received - HEADER_LEN) < chunk.len() {
+            return Err(Error::user(Code::InvalidLength));
+        }
+        self.received += chunk.len();
+        H::with_error(|| self.hash.update(chunk))
+    }
+
+    /// Verifies the image and returns its header.
+    ///
+    /// The image must have been entirely processed.
+    pub fn finalize<P: ecc::Api<U32>>(mut self, key: &PublicKey) -> Result<Header, Error> {
+        if self.received < HEADER_LEN {
+            return Err(Error::user(Code::InvalidLength));
+        }
+        let header = Header::decode(&self.header);
+        if self.received - HEADER_LEN != header.length as usize {
+            return Err(Error::user(Code::InvalidLength));
+        }
+        let digest = H::with_error(|| self.hash.finalize_fixed_reset())?;
+        let x = Int::<U32>::from_slice(&key.x);
+        let y = Int::<U32>::from_slice(&key.y);
+        let r = Int::<U32>::from_slice(&self.header[SIGNATURE_R ..][.. 32]);
+        let s = Int::<U32>::from_slice(&self.header[SIGNATURE_S ..][.. 32]);
+        match P::ecdsa_verify(&digest, x, y, r, s)? {
+            true => Ok(header),
+            false => Err(Error::user(Code::InvalidArgument)),
+        }
+    }
+}
+
+fn hash_prefix<H: Update>(hash: &mut H, header: Header) {
+    hash.update(&header.version.to_le_bytes());
+    hash.update(&header.length.to_le_bytes());
+}
+
+fn read_u32(header: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(header[offset ..][.. 4].try_into().unwrap())
+}
+
+fn digit(x: u8) -> Result<u8, Error> {
+    match x {
+        b'0' ..= b'9' => Ok(x - b'0'),
+        b'a' ..= b'f' => Ok(x - b'a' + 10),
+        b'A' ..= b'F' => Ok(x - b'A' + 10),
+        _ => Err(Error::user(Code::InvalidArgument)),
+    }
+}
+
+#[cfg(all(test, feature = "software"))]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    const PRIVATE: [u8; 32] = [0x42; 32];
+
+    fn image(version: u32, firmware: &[u8]) -> Vec<u8> {
+        let mut image = std::vec![0xff; HEADER_LEN];
+        image[.. 4].copy_from_slice(&version.to_le_bytes());
+        image.extend_from_slice(firmware);
+        sign::<SoftwareP256, SoftwareSha256>(&mut image, &PRIVATE).unwrap();
+        image
+    }
+
+    fn verify(image: &[u8], chunk_size: usize, key: &PublicKey) -> Result<Header, Error> {
+        let mut verifier = Verifier::<SoftwareSha256>::default();
+        for chunk in image.chunks(chunk_size) {
+            verifier.update(chunk)?;
+        }
+        verifier.finalize::<SoftwareP256>(key)
+    }
+
+    #[test]
+    fn sign_verify() {
+        let key = PublicKey::new::<SoftwareP256>(&PRIVATE).unwrap();
+        let image = image(7, b"firmware");
+        for chunk_size in [1, 3, HEADER_LEN, 1000] {
+            assert_eq!(verify(&image, chunk_size, &key), Ok(Header { version: 7, length: 8 }));
+        }
+    }
+
+    #[test]
+    fn tampered() {
+        let key = PublicKey::new::<SoftwareP256>(&PRIVATE).unwrap();
+        let image = image(7, b"firmware");
+        let mut version = image.clone();
+        version[0] = 8;
+        assert_eq!(verify(&version, 16, &key), Err(Error::user(Code::InvalidArgument)));
+        let mut firmware = image.clone();
+        *firmware.last_mut().unwrap() ^= 1;
+        assert_eq!(verify(&firmware, 16, &key), Err(Error::user(Code::InvalidArgument)));
+        let other = PublicKey::new::<SoftwareP256>(&[0x24; 32]).unwrap();
+        assert_eq!(verify(&image, 16, &other), Err(Error::user(Code::InvalidArgument)));
+    }
+
+    #[test]
+    fn invalid_length() {
+        let key = PublicKey::new::<SoftwareP256>(&PRIVATE).unwrap();
+        let image = image(7, b"firmware");
+        let truncated = &image[.. image.len() - 1];
+        assert_eq!(verify(truncated, 16, &key), Err(Error::user(Code::InvalidLength)));
+        let mut extended = image.clone();
+        extended.push(0);
+        assert_eq!(verify(&extended, 16, &key), Err(Error::user(Code::InvalidLength)));
+    }
+
+    #[test]
+    fn from_hex() {
+        let key = PublicKey::new::<SoftwareP256>(&PRIVATE).unwrap();
+        let mut hex = std::string::String::new();
+        for byte in key.x.iter().chain(key.y.iter()) {
+            hex.push_str(&std::format!("{byte:02X}"));
+        }
+        assert_eq!(PublicKey::from_hex(&hex), Ok(key));
+        assert_eq!(PublicKey::from_hex("00"), Err(Error::user(Code::InvalidLength)));
+    }
+}
@@ -16,6 +16,8 @@ use alloc::boxed::Box;
 use alloc::vec::Vec;
 
 use header::{Header, Side};
+use image::{PublicKey, Verifier};
+use wasefire_board_api::crypto::{Sha256, P256};
 use wasefire_board_api::platform::update::Api;
 use wasefire_board_api::Supported;
 use wasefire_error::{Code, Error};
@@ -23,6 +25,10 @@ use wasefire_store::{Storage as _, StorageIndex};
 use wasefire_sync::TakeCell;
 
 use crate::storage::Storage;
+use crate::Board;
+
+/// Public key to verify platform updates (hexadecimal encoding of x then y).
+const PUBLIC_KEY: Option<&str> = option_env!("WASEFIRE_UPDATE_KEY");
 
 pub enum Impl {}
 
@@ -46,6 +52,7 @@ impl Api for Impl {
 
     fn process(mut chunk: &[u8]) -> Result<(), Error> {
         STATE.with(|state| {
+            state.verifier.update(chunk)?;
             while !chunk.is_empty() {
                 state.write(&mut chunk)?;
             }
@@ -56,10 +63,22 @@ impl Api for Impl {
     fn finalize() -> Result<(), Error> {
         STATE.with(|state| {
             state.flush()?;
-            match state.dry_run {
-                true => Ok(()),
-                false => super::reboot(),
+            let key = PublicKey::from_hex(PUBLIC_KEY.ok_or(Error::world(Code::NotImplemented))?)?;
+            let verifier = core::mem::take(&mut state.verifier);
+            let header = verifier.finalize::<P256<Board>>(&key)?;
+            // The bootloader boots the newest side, so the update must be newer.
+            let side = Side::current().ok_or(Error::world(Code::InvalidState))?;
+            if header.version <= Header::new(side).timestamp() {
+                return Err(Error::user(Code::InvalidArgument));
+            }
+            if state.dry_run {
+                return Ok(());
             }
+            // Writing the version marks the side bootable.
+            let index = StorageIndex { page: 0, byte: 0 };
+            let version = header.version.to_le_bytes();
+            state.storage.write_slice(index, &version).map_err(|_| Error::world(0))?;
+            super::reboot()
         })
     }
 }
@@ -76,17 +95,20 @@ struct Update {
     // offset + buffer.len() <= storage.len()
     offset: usize,
     buffer: Vec<u8>,
+    verifier: Verifier<Sha256<Board>>,
 }
 
 impl Update {
     fn new(storage: Storage) -> Self {
-        Update { storage, dry_run: false, offset: 0, buffer: Vec::new() }
+        let verifier = Verifier::default();
+        Update { storage, dry_run: false, offset: 0, buffer: Vec::new(), verifier }
     }
 
     fn reset(&mut self, dry_run: bool) {
         self.dry_run = dry_run;
         self.offset = 0;
         self.buffer.clear();
+        self.verifier = Verifier::default();
     }
 
     fn write(&mut self, chunk: &mut &[u8]) -> Result<(), Error> {
@@ -114,7 +136,14 @@ impl Update {
             if byte == 0 {
                 self.storage.erase_page(page).map_err(|_| Error::world(0))?;
             }
-            self.storage.write_slice(index, value).map_err(|_| Error::world(0))?;
+            if self.offset == 0 {
+                // The version is only written after verification (it marks the side bootable).
+                let mut value = value.to_vec();
+                value[.. 4].fill(0xff);
+                self.storage.write_slice(index, &value).map_err(|_| Error::world(0))?;
+            } else {
+                self.storage.write_slice(index, value).map_err(|_| Error::world(0))?;
+            }
         }
         *chunk = rest;
         self.offset += value.len();
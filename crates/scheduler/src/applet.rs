@@ -12,9 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[cfg(feature = "board-api-platform-protocol")]
+#[cfg(any(
+    feature = "board-api-platform-protocol",
+    all(feature = "wasm", feature = "board-api-storage")
+))]
 use alloc::boxed::Box;
 use alloc::collections::{BTreeSet, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
 
 #[cfg(feature = "internal-hash-context")]
 use wasefire_board_api as board;
@@ -29,7 +34,153 @@ use crate::Trap;
 
 pub mod store;
 
+/// Identifies an applet within the scheduler.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AppletId(usize);
+
+impl AppletId {
+    /// Returns the slot of the applet.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Applets managed by the scheduler.
+pub struct Applets<B: Board> {
+    /// Applet slots indexed by applet id.
+    ///
+    /// A slot is empty when its applet stopped.
+    slots: Vec<Option<Applet<B>>>,
+
+    /// Applet being executed or on behalf of which a host function is being executed.
+    current: AppletId,
+}
+
+// We have to implement manually because derive is not able to find the correct bounds.
+impl<B: Board> Default for Applets<B> {
+    fn default() -> Self {
+        Self { slots: Vec::new(), current: AppletId::default() }
+    }
+}
+
+impl<B: Board> Applets<B> {
+    /// Returns the identifier of the next inserted applet.
+    ///
+    /// This is the first empty slot, such that slots (and thus memories) are reused.
+    #[cfg_attr(feature = "native", allow(dead_code))]
+    pub fn vacant(&self) -> AppletId {
+        AppletId(self.slots.iter().position(|x| x.is_none()).unwrap_or(self.slots.len()))
+    }
+
+    pub fn insert(&mut self, applet: Applet<B>) -> AppletId {
+        let id = self.vacant();
+        match self.slots.get_mut(id.0) {
+            Some(slot) => *slot = Some(applet),
+            None => self.slots.push(Some(applet)),
+        }
+        id
+    }
+
+    pub fn remove(&mut self, id: AppletId) -> Option<Applet<B>> {
+        self.slots.get_mut(id.0)?.take()
+    }
+
+    pub fn get_mut(&mut self, id: AppletId) -> Option<&mut Applet<B>> {
+        self.slots.get_mut(id.0)?.as_mut()
+    }
+
+    /// Returns the identifiers of all applet slots (including stopped applets).
+    #[cfg_attr(feature = "native", allow(dead_code))]
+    pub fn ids(&self) -> impl Iterator<Item = AppletId> {
+        (0 .. self.slots.len()).map(AppletId)
+    }
+
+    /// Returns whether at least one applet is running.
+    #[cfg_attr(feature = "native", allow(dead_code))]
+    pub fn is_alive(&self) -> bool {
+        self.slots.iter().any(|x| x.is_some())
+    }
+
+    /// Returns the running applet installed in a given slot, if any.
+    #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+    #[cfg_attr(not(feature = "board-api-platform-protocol"), allow(dead_code))]
+    pub fn installed(&self, slot: usize) -> Option<AppletId> {
+        let mut slots = self.slots.iter().enumerate();
+        slots.find_map(|(i, x)| (x.as_ref()?.installed == Some(slot)).then_some(AppletId(i)))
+    }
+
+    /// Returns the default applet, if any.
+    ///
+    /// This is the first running applet (the only one when there is a single applet slot).
+    #[cfg(feature = "board-api-platform-protocol")]
+    pub fn default_id(&self) -> Option<AppletId> {
+        self.slots.iter().position(|x| x.is_some()).map(AppletId)
+    }
+
+    /// Returns the running applet with a given name, if any.
+    #[cfg(feature = "board-api-platform-protocol")]
+    pub fn find(&self, name: &str) -> Option<AppletId> {
+        let mut slots = self.slots.iter().enumerate();
+        slots.find_map(|(i, x)| (x.as_ref()?.name == name).then_some(AppletId(i)))
+    }
+
+    pub fn current_id(&self) -> AppletId {
+        self.current
+    }
+
+    #[cfg_attr(feature = "native", allow(dead_code))]
+    pub fn set_current(&mut self, id: AppletId) {
+        self.current = id;
+    }
+
+    /// Returns the current applet.
+    ///
+    /// The current applet must be running.
+    pub fn current(&mut self) -> &mut Applet<B> {
+        self.get_mut(self.current).unwrap()
+    }
+
+    /// Returns the applet with a handler for this key, if any.
+    pub fn owner(&self, key: Key<B>) -> Option<AppletId> {
+        let mut slots = self.slots.iter().enumerate();
+        slots.find_map(|(i, x)| x.as_ref()?.get(key).map(|_| AppletId(i)))
+    }
+
+    /// Enables a handler for the current applet.
+    ///
+    /// Handlers are exclusive: the key must not be handled by another applet. Platform protocol
+    /// requests are an exception because they are routed by applet name.
+    #[allow(dead_code)] // in case there are no events
+    pub fn enable(&mut self, handler: Handler<B>) -> Result<(), Trap> {
+        match self.owner(handler.key) {
+            #[cfg(feature = "board-api-platform-protocol")]
+            _ if handler.key == crate::event::platform::protocol::Key::Request.into() => {
+                self.current().enable(handler)
+            }
+            Some(id) if id != self.current => {
+                log::warn!("Tried to take handler of another applet");
+                Err(Trap)
+            }
+            _ => self.current().enable(handler),
+        }
+    }
+
+    /// Pushes an event to the applet handling it.
+    pub fn push(&mut self, event: Event<B>) {
+        match self.owner(Key::from(&event)) {
+            Some(id) => self.get_mut(id).unwrap().push(event),
+            // This can happen after an applet stopped.
+            None => log::trace!("Discarding {}", log::Debug2Format(&event)),
+        }
+    }
+}
+
 pub struct Applet<B: Board> {
+    /// Name of the applet.
+    ///
+    /// This is used to route platform protocol requests.
+    pub name: String,
+
     pub store: self::store::Store,
 
     /// Pending events.
@@ -43,25 +194,52 @@ pub struct Applet<B: Board> {
     #[cfg(feature = "wasm")]
     done: bool,
 
+    /// Whether we are waiting for an event.
+    #[cfg(feature = "wasm")]
+    waiting: bool,
+
     handlers: BTreeSet<Handler<B>>,
 
     #[cfg(feature = "internal-hash-context")]
     pub hashes: AppletHashes<B>,
+
+    /// Namespace of the applet in the store, if allocated.
+    #[cfg(feature = "board-api-storage")]
+    pub namespace: Option<crate::namespace::Namespace>,
+
+    /// Install slot of the applet, if installed.
+    #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+    pub installed: Option<usize>,
+
+    /// Module of the applet, if owned by the platform.
+    ///
+    /// This field must be dropped after the store, because the store borrows from it.
+    #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+    module: Option<Box<[u8]>>,
 }
 
 // We have to implement manually because derive is not able to find the correct bounds.
 impl<B: Board> Default for Applet<B> {
     fn default() -> Self {
         Self {
+            name: Default::default(),
             store: Default::default(),
             events: Default::default(),
             #[cfg(feature = "board-api-platform-protocol")]
             protocol: Default::default(),
             #[cfg(feature = "wasm")]
             done: Default::default(),
+            #[cfg(feature = "wasm")]
+            waiting: Default::default(),
             handlers: Default::default(),
             #[cfg(feature = "internal-hash-context")]
             hashes: Default::default(),
+            #[cfg(feature = "board-api-storage")]
+            namespace: Default::default(),
+            #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+            installed: Default::default(),
+            #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+            module: Default::default(),
         }
     }
 }
@@ -123,6 +301,19 @@ impl<B: Board> Applet<B> {
         &mut self.store
     }
 
+    /// Takes ownership of the module and returns it for instantiation.
+    ///
+    /// # Safety
+    ///
+    /// The result must only be used to instantiate the store of this applet.
+    #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+    pub unsafe fn own_module(&mut self, module: Box<[u8]>) -> &'static [u8] {
+        let module = self.module.insert(module);
+        // SAFETY: The module is dropped after the store (see the field order) and moving a box
+        // does not move its content.
+        unsafe { core::slice::from_raw_parts(module.as_ptr(), module.len()) }
+    }
+
     #[allow(dead_code)] // in case no API uses memory
     pub fn memory(&mut self) -> Memory {
         self.store.memory()
@@ -140,6 +331,10 @@ impl<B: Board> Applet<B> {
         } else if self.events.len() < MAX_EVENTS {
             log::debug!("Pushing {}", log::Debug2Format(&event));
             self.events.push_back(event);
+            #[cfg(feature = "wasm")]
+            {
+                self.waiting = false;
+            }
         } else {
             log::warn!("Dropping {}", log::Debug2Format(&event));
         }
@@ -153,7 +348,13 @@ impl<B: Board> Applet<B> {
         }
         match self.events.pop_front() {
             Some(event) => EventAction::Handle(event),
-            None => EventAction::Wait,
+            None => {
+                #[cfg(feature = "wasm")]
+                {
+                    self.waiting = true;
+                }
+                EventAction::Wait
+            }
         }
     }
 
@@ -162,6 +363,12 @@ impl<B: Board> Applet<B> {
         self.done = true;
     }
 
+    /// Returns whether the applet can't make progress until an event is pushed.
+    #[cfg(feature = "wasm")]
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
     #[allow(dead_code)] // in case there are no events
     pub fn enable(&mut self, handler: Handler<B>) -> Result<(), Trap> {
         match self.handlers.insert(handler) {
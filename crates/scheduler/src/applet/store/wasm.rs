@@ -19,7 +19,8 @@ use core::marker::PhantomData;
 use core::ops::Range;
 
 use wasefire_interpreter::{
-    Call, Error, InstId, Module, RunResult, Store as InterpreterStore, Val,
+    Call, CoreDump, Error, InstId, Linker, Module, RunAnswer, RunResult, Store as InterpreterStore,
+    StoreLimits, Val,
 };
 use wasefire_logger as log;
 
@@ -30,28 +31,51 @@ use crate::Trap;
 pub struct Store(InterpreterStore<'static>);
 
 impl Store {
+    /// Creates a store using host functions shared with other applets.
+    pub fn new(linker: &'static Linker<'static>) -> Self {
+        Store(InterpreterStore::new(linker))
+    }
+
+    /// Instantiates a module with some resource limits.
     pub fn instantiate(
-        &mut self, module: Module<'static>, memory: &'static mut [u8],
+        &mut self, module: Module<'static>, memory: &'static mut [u8], limits: StoreLimits,
     ) -> Result<InstId, Error> {
+        self.0.set_limits(limits);
         self.0.instantiate(module, memory)
     }
 
-    pub fn link_func(
-        &mut self, module: &'static str, name: &'static str, params: usize, results: usize,
-    ) -> Result<(), Error> {
-        self.0.link_func(module, name, params, results)
+    /// Sets the tracer of the applet.
+    #[cfg(feature = "applet-trace")]
+    pub fn set_tracer(&mut self, tracer: alloc::boxed::Box<dyn wasefire_interpreter::Tracer>) {
+        self.0.set_tracer(Some(tracer));
     }
 
     pub fn link_func_default(&mut self, module: &'static str) -> Result<(), Error> {
         self.0.link_func_default(module)
     }
 
+    /// Invokes a function with a new time slice.
     pub fn invoke<'a>(
         &'a mut self, inst: InstId, name: &str, args: Vec<Val>,
     ) -> Result<RunResult<'a, 'static>, Error> {
+        self.0.set_fuel(crate::applet_fuel());
         self.0.invoke(inst, name, args)
     }
 
+    pub fn is_preempted(&self) -> bool {
+        self.0.is_preempted()
+    }
+
+    /// Resumes a preempted function with a new time slice.
+    pub fn resume(&mut self) -> Result<RunResult<'_, 'static>, Error> {
+        self.0.set_fuel(crate::applet_fuel());
+        self.0.resume()
+    }
+
+    pub fn core_dump(&self) -> Option<&CoreDump<'static>> {
+        self.0.core_dump()
+    }
+
     pub fn last_call(&mut self) -> Option<Call<'_, 'static>> {
         self.0.last_call()
     }
@@ -146,11 +170,18 @@ impl<'a> MemoryApi for Memory<'a> {
         let args = vec![Val::I32(size), Val::I32(align)];
         let inst = store.last_call().unwrap().inst();
         // TODO: We should ideally account this to the applet performance time.
-        let result = match store.invoke(inst, "alloc", args) {
-            Ok(RunResult::Done(x)) if x.len() == 1 => x[0],
-            Ok(RunResult::Done(_)) => return Err(Trap),
-            Ok(RunResult::Host(_)) => log::panic!("alloc called into host"),
-            Err(Error::Trap) => return Err(Trap),
+        // We don't preempt allocation because we are in a host call.
+        let fuel = store.fuel();
+        store.set_fuel(None);
+        let result = store.invoke(inst, "alloc", args).map(|x| x.forget());
+        store.set_fuel(fuel);
+        let result = match result {
+            Ok(RunAnswer::Done(x)) if x.len() == 1 => x[0],
+            Ok(RunAnswer::Done(_)) => return Err(Trap),
+            Ok(RunAnswer::Host) => log::panic!("alloc called into host"),
+            Ok(RunAnswer::Preempted) => unreachable!(),
+            Ok(RunAnswer::Exception(_)) => return Err(Trap),
+            Err(Error::Trap | Error::LimitExceeded(_)) => return Err(Trap),
             Err(x) => log::panic!("alloc failed with {}", log::Debug2Format(&x)),
         };
         match result {
@@ -75,7 +75,7 @@ mod radio;
 mod rng;
 mod scheduling;
 #[cfg(feature = "internal-applet-api-store")]
-mod store;
+pub mod store;
 #[cfg(feature = "applet-api-timer")]
 mod timer;
 #[cfg(feature = "applet-api-uart")]
@@ -47,7 +47,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let result = try {
         let button = Id::new(*button as usize)?;
         call.scheduler()
-            .applet
+            .applets
             .enable(Handler {
                 key: Key { button }.into(),
                 inst,
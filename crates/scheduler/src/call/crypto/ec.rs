@@ -48,7 +48,7 @@ fn is_supported<B: Board>(call: SchedulerCall<B, api::is_supported::Sig>) {
 fn is_valid_scalar<B: Board>(mut call: SchedulerCall<B, api::is_valid_scalar::Sig>) {
     let api::is_valid_scalar::Params { curve, n } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         match convert_curve::<B>(*curve)?? {
             #[cfg(feature = "board-api-crypto-p256")]
@@ -72,7 +72,7 @@ fn is_valid_scalar<B: Board>(mut call: SchedulerCall<B, api::is_valid_scalar::Si
 fn is_valid_point<B: Board>(mut call: SchedulerCall<B, api::is_valid_point::Sig>) {
     let api::is_valid_point::Params { curve, x, y } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         match convert_curve::<B>(*curve)?? {
             #[cfg(feature = "board-api-crypto-p256")]
@@ -98,7 +98,7 @@ fn is_valid_point<B: Board>(mut call: SchedulerCall<B, api::is_valid_point::Sig>
 fn base_point_mul<B: Board>(mut call: SchedulerCall<B, api::base_point_mul::Sig>) {
     let api::base_point_mul::Params { curve, n, x, y } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         match convert_curve::<B>(*curve)?? {
             #[cfg(feature = "board-api-crypto-p256")]
@@ -126,7 +126,7 @@ fn base_point_mul<B: Board>(mut call: SchedulerCall<B, api::base_point_mul::Sig>
 fn point_mul<B: Board>(mut call: SchedulerCall<B, api::point_mul::Sig>) {
     let api::point_mul::Params { curve, n, in_x, in_y, out_x, out_y } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         match convert_curve::<B>(*curve)?? {
             #[cfg(feature = "board-api-crypto-p256")]
@@ -158,7 +158,7 @@ fn point_mul<B: Board>(mut call: SchedulerCall<B, api::point_mul::Sig>) {
 fn ecdsa_sign<B: Board>(mut call: SchedulerCall<B, api::ecdsa_sign::Sig>) {
     let api::ecdsa_sign::Params { curve, key, message, r, s } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         match convert_curve::<B>(*curve)?? {
             #[cfg(feature = "board-api-crypto-p256")]
@@ -188,7 +188,7 @@ fn ecdsa_sign<B: Board>(mut call: SchedulerCall<B, api::ecdsa_sign::Sig>) {
 fn ecdsa_verify<B: Board>(mut call: SchedulerCall<B, api::ecdsa_verify::Sig>) {
     let api::ecdsa_verify::Params { curve, message, x, y, r, s } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         match convert_curve::<B>(*curve)?? {
             #[cfg(feature = "board-api-crypto-p256")]
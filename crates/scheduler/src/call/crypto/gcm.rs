@@ -58,7 +58,7 @@ fn tag_length<B: Board>(call: SchedulerCall<B, api::tag_length::Sig>) {
 fn encrypt<B: Board>(mut call: SchedulerCall<B, api::encrypt::Sig>) {
     let api::encrypt::Params { key, iv, aad, aad_len, length, clear, cipher, tag } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         ensure_support::<B>()?;
         let key = memory.get_array::<32>(*key)?.into();
@@ -77,7 +77,7 @@ fn encrypt<B: Board>(mut call: SchedulerCall<B, api::encrypt::Sig>) {
 fn decrypt<B: Board>(mut call: SchedulerCall<B, api::decrypt::Sig>) {
     let api::decrypt::Params { key, iv, aad, aad_len, tag, length, cipher, clear } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         ensure_support::<B>()?;
         let key = memory.get_array::<32>(*key)?.into();
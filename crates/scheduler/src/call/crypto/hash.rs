@@ -65,7 +65,7 @@ fn is_supported<B: Board>(call: SchedulerCall<B, api::is_supported::Sig>) {
 #[cfg(feature = "applet-api-crypto-hash")]
 fn initialize<B: Board>(mut call: SchedulerCall<B, api::initialize::Sig>) {
     let api::initialize::Params { algorithm } = call.read();
-    let scheduler = call.scheduler();
+    let applet = call.applet();
     let result = try {
         let context = match convert_hash_algorithm::<B>(*algorithm)?? {
             #[cfg(feature = "board-api-crypto-sha256")]
@@ -75,7 +75,7 @@ fn initialize<B: Board>(mut call: SchedulerCall<B, api::initialize::Sig>) {
             #[allow(unreachable_patterns)]
             _ => Err(Trap)?,
         };
-        scheduler.applet.hashes.insert(context)? as u32
+        applet.hashes.insert(context)? as u32
     };
     call.reply(result);
 }
@@ -83,11 +83,11 @@ fn initialize<B: Board>(mut call: SchedulerCall<B, api::initialize::Sig>) {
 #[cfg(feature = "applet-api-crypto-hash")]
 fn update<B: Board>(mut call: SchedulerCall<B, api::update::Sig>) {
     let api::update::Params { id, data, length } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.applet();
+    let memory = applet.store.memory();
     let result = try {
         let data = memory.get(*data, *length)?;
-        match scheduler.applet.hashes.get_mut(*id as usize)? {
+        match applet.hashes.get_mut(*id as usize)? {
             #[cfg(feature = "board-api-crypto-sha256")]
             HashContext::Sha256(context) => context.update(data)?,
             #[cfg(feature = "board-api-crypto-sha384")]
@@ -101,10 +101,10 @@ fn update<B: Board>(mut call: SchedulerCall<B, api::update::Sig>) {
 #[cfg(feature = "applet-api-crypto-hash")]
 fn finalize<B: Board>(mut call: SchedulerCall<B, api::finalize::Sig>) {
     let api::finalize::Params { id, digest } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.applet();
+    let memory = applet.store.memory();
     let result = try {
-        let context = scheduler.applet.hashes.take(*id as usize)?;
+        let context = applet.hashes.take(*id as usize)?;
         match context {
             _ if *digest == 0 => (),
             #[cfg(feature = "board-api-crypto-sha256")]
@@ -132,8 +132,8 @@ fn is_hmac_supported<B: Board>(call: SchedulerCall<B, api::is_hmac_supported::Si
 #[cfg(feature = "applet-api-crypto-hmac")]
 fn hmac_initialize<B: Board>(mut call: SchedulerCall<B, api::hmac_initialize::Sig>) {
     let api::hmac_initialize::Params { algorithm, key, key_len } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let applet = call.applet();
+    let memory = applet.memory();
     let result = try {
         let key = memory.get(*key, *key_len)?;
         let context = match convert_hmac_algorithm::<B>(*algorithm)?? {
@@ -144,7 +144,7 @@ fn hmac_initialize<B: Board>(mut call: SchedulerCall<B, api::hmac_initialize::Si
             #[allow(unreachable_patterns)]
             _ => trap_use!(key),
         };
-        scheduler.applet.hashes.insert(context)? as u32
+        applet.hashes.insert(context)? as u32
     };
     call.reply(result);
 }
@@ -152,11 +152,11 @@ fn hmac_initialize<B: Board>(mut call: SchedulerCall<B, api::hmac_initialize::Si
 #[cfg(feature = "applet-api-crypto-hmac")]
 fn hmac_update<B: Board>(mut call: SchedulerCall<B, api::hmac_update::Sig>) {
     let api::hmac_update::Params { id, data, length } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.applet();
+    let memory = applet.store.memory();
     let result = try {
         let data = memory.get(*data, *length)?;
-        match scheduler.applet.hashes.get_mut(*id as usize)? {
+        match applet.hashes.get_mut(*id as usize)? {
             #[cfg(feature = "board-api-crypto-hmac-sha256")]
             HashContext::HmacSha256(context) => context.update(data)?,
             #[cfg(feature = "board-api-crypto-hmac-sha384")]
@@ -170,10 +170,10 @@ fn hmac_update<B: Board>(mut call: SchedulerCall<B, api::hmac_update::Sig>) {
 #[cfg(feature = "applet-api-crypto-hmac")]
 fn hmac_finalize<B: Board>(mut call: SchedulerCall<B, api::hmac_finalize::Sig>) {
     let api::hmac_finalize::Params { id, hmac } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.applet();
+    let memory = applet.store.memory();
     let result = try {
-        let context = scheduler.applet.hashes.take(*id as usize)?;
+        let context = applet.hashes.take(*id as usize)?;
         match context {
             _ if *hmac == 0 => (),
             #[cfg(feature = "board-api-crypto-hmac-sha256")]
@@ -203,7 +203,7 @@ fn hkdf_expand<B: Board>(mut call: SchedulerCall<B, api::hkdf_expand::Sig>) {
     let api::hkdf_expand::Params { algorithm, prk, prk_len, info, info_len, okm, okm_len } =
         call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let prk = memory.get(*prk, *prk_len)?;
         let info = memory.get(*info, *info_len)?;
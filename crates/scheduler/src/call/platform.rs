@@ -74,7 +74,7 @@ fn alloc_bytes<B: Board>(
     if data.is_empty() {
         return Ok(0);
     }
-    let mut memory = scheduler.applet.memory();
+    let mut memory = scheduler.applets.current().memory();
     memory.alloc_copy(ptr_ptr, None, data)?;
     Ok(data.len() as u32)
 }
@@ -39,10 +39,10 @@ fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
     let api::read::Params { ptr: ptr_ptr, len: len_ptr } = call.read();
     let scheduler = call.scheduler();
     let result = try {
-        match scheduler.applet.get_request()? {
+        match scheduler.applets.current().get_request()? {
             None => false,
             Some(value) => {
-                let mut memory = scheduler.applet.memory();
+                let mut memory = scheduler.applets.current().memory();
                 memory.alloc_copy(*ptr_ptr, Some(*len_ptr), &value)?;
                 true
             }
@@ -56,7 +56,7 @@ fn write<B: Board>(mut call: SchedulerCall<B, api::write::Sig>) {
     let api::write::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
     let result = try {
-        let memory = scheduler.applet.memory();
+        let memory = scheduler.applets.current().memory();
         let input = memory.get(*ptr, *len)?.into();
         crate::protocol::put_response(scheduler, input)?
     };
@@ -70,7 +70,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let scheduler = call.scheduler();
     let result = try {
         // We don't need to enable the event at the board level because it's always enabled.
-        scheduler.applet.enable(Handler {
+        scheduler.applets.enable(Handler {
             key: Key::Request.into(),
             inst,
             func: *handler_func,
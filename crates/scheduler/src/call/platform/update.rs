@@ -48,7 +48,7 @@ fn is_supported<B: Board>(call: SchedulerCall<B, api::is_supported::Sig>) {
 fn metadata<B: Board>(mut call: SchedulerCall<B, api::metadata::Sig>) {
     let api::metadata::Params { ptr: ptr_ptr, len: len_ptr } = call.read();
     let scheduler = call.scheduler();
-    let mut memory = scheduler.applet.memory();
+    let mut memory = scheduler.applets.current().memory();
     let result = try {
         let metadata = board::platform::Update::<B>::metadata()?;
         memory.alloc_copy(*ptr_ptr, Some(*len_ptr), &metadata)?;
@@ -74,7 +74,7 @@ fn initialize<B: Board>(call: SchedulerCall<B, api::initialize::Sig>) {
 fn process_<B: Board>(mut call: SchedulerCall<B, api::process::Sig>) {
     let api::process::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let chunk = memory.get(*ptr, *len)?;
         board::platform::Update::<B>::process(chunk)?
@@ -44,7 +44,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let scheduler = call.scheduler();
     let result = try {
         let event = convert_event(event)?;
-        scheduler.applet.enable(Handler {
+        scheduler.applets.enable(Handler {
             key: Key::from(&event).into(),
             inst,
             func: *handler_func,
@@ -71,7 +71,7 @@ fn unregister<B: Board>(mut call: SchedulerCall<B, api::unregister::Sig>) {
 fn read_advertisement<B: Board>(mut call: SchedulerCall<B, api::read_advertisement::Sig>) {
     let api::read_advertisement::Params { ptr } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let packet = memory.from_bytes_mut::<Advertisement>(*ptr)?;
         board::radio::Ble::<B>::read_advertisement(packet)?
@@ -37,7 +37,7 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
 fn fill_bytes<B: Board>(mut call: SchedulerCall<B, api::fill_bytes::Sig>) {
     let api::fill_bytes::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let output = memory.get_mut(*ptr, *len)?;
         board::Rng::<B>::fill_bytes(output)?
@@ -22,17 +22,79 @@ use wasefire_board_api::Api as Board;
 #[cfg(feature = "board-api-storage")]
 use wasefire_error::{Code, Error};
 #[cfg(feature = "board-api-storage")]
-use wasefire_store::StoreError;
+use wasefire_logger as log;
+#[cfg(feature = "board-api-storage")]
+use wasefire_store::{Storage, Store, StoreError, StoreUpdate};
 
 #[cfg(feature = "board-api-storage")]
 use crate::applet::store::MemoryApi;
+#[cfg(feature = "board-api-storage")]
+use crate::namespace::{self, Namespace};
 use crate::DispatchSchedulerCall;
 #[cfg(feature = "board-api-storage")]
-use crate::SchedulerCall;
+use crate::{Scheduler, SchedulerCall};
 
 #[cfg(feature = "applet-api-store-fragment")]
 mod fragment;
 
+/// First key reserved to the platform.
+///
+/// The keys smaller than this one are partitioned between applets (see [`namespace`]). The
+/// platform uses the other keys to persist installed applets and namespace owners.
+#[cfg(feature = "board-api-storage")]
+pub const PLATFORM_KEY: usize = 3072;
+
+/// Key of the format marker of the platform keys.
+///
+/// Applets could use all keys before some were reserved to the platform. The marker tells whether
+/// the platform keys contain platform entries (marker present) or applet entries (marker absent).
+#[cfg(feature = "board-api-storage")]
+pub const FORMAT_KEY: usize = 4095;
+
+/// Content of the format marker.
+#[cfg(feature = "board-api-storage")]
+const FORMAT: &[u8] = b"wasefire-platform-keys-1";
+
+/// Reserves the platform keys, moving the applet entries written before they were reserved.
+///
+/// This must be called before the platform keys are read. The applet entries written before
+/// namespaces existed are given to the applet of [`Scheduler::run()`] (see [`namespace::LEGACY`]).
+/// Those outside its namespace (e.g. using platform keys) are moved to free keys of its namespace
+/// (the smallest first), such that their data is kept but found under a different key (e.g. with
+/// `store::keys()`). Entries that don't fit are removed. Each entry is moved atomically and before
+/// the owner entry and format marker are written, such that an interrupted migration resumes on
+/// next boot.
+#[cfg(feature = "board-api-storage")]
+pub fn reserve(store: &mut Store<impl Storage>) -> Result<(), Error> {
+    if store.find(FORMAT_KEY).map_err(convert)?.as_deref() == Some(FORMAT) {
+        return Ok(());
+    }
+    let mut used = alloc::vec![false; FORMAT_KEY + 1];
+    for handle in store.iter().map_err(convert)? {
+        used[handle.map_err(convert)?.get_key()] = true;
+    }
+    let (namespace, owner) = namespace::LEGACY;
+    let keys = namespace.keys();
+    let mut free = keys.clone().filter(|&key| !used[key]);
+    for key in (keys.end ..= FORMAT_KEY).filter(|&key| used[key]) {
+        let Some(new) = free.next() else {
+            log::error!("Removing applet entry {} (no free applet key).", key);
+            store.remove(key).map_err(convert)?;
+            continue;
+        };
+        log::warn!("Moving applet entry {} to {}.", key, new);
+        let value = store.find(key).map_err(convert)?.unwrap();
+        let updates = [StoreUpdate::Insert { key: new, value }, StoreUpdate::Remove { key }];
+        store.transaction(&updates).map_err(convert)?;
+    }
+    let mut updates = alloc::vec![StoreUpdate::Insert { key: FORMAT_KEY, value: FORMAT }];
+    if used.contains(&true) {
+        log::warn!("Giving applet entries to {}.", owner);
+        updates.push(StoreUpdate::Insert { key: namespace.owner_key(), value: owner.as_bytes() });
+    }
+    store.transaction(&updates).map_err(convert)
+}
+
 pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
     match call {
         #[cfg(feature = "applet-api-store")]
@@ -45,6 +107,14 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
         Api::Keys(call) => or_fail!("board-api-storage", keys(call)),
         #[cfg(feature = "applet-api-store")]
         Api::Clear(call) => or_fail!("board-api-storage", clear(call)),
+        #[cfg(feature = "applet-api-store")]
+        Api::Transaction(call) => or_fail!("board-api-storage", transaction(call)),
+        #[cfg(feature = "applet-api-store")]
+        Api::Capacity(call) => or_fail!("board-api-storage", capacity(call)),
+        #[cfg(feature = "applet-api-store")]
+        Api::Lifetime(call) => or_fail!("board-api-storage", lifetime(call)),
+        #[cfg(feature = "applet-api-store")]
+        Api::Prepare(call) => or_fail!("board-api-storage", prepare(call)),
         #[cfg(feature = "applet-api-store-fragment")]
         Api::Fragment(call) => fragment::process(call),
     }
@@ -54,10 +124,15 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
 fn insert<B: Board>(mut call: SchedulerCall<B, api::insert::Sig>) {
     let api::insert::Params { key, ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let namespace = namespace(scheduler);
+    let memory = scheduler.applets.current().memory();
     let result = try {
+        let namespace = namespace?;
         let value = memory.get(*ptr, *len)?;
-        scheduler.store.insert(*key as usize, value).map_err(convert)?
+        let key = applet_key(namespace, *key)?;
+        let capacity = scheduler.store.entry_capacity(value.len());
+        namespace.check_quota(&scheduler.store, |x| x == key, capacity)?;
+        scheduler.store.insert(key, value).map_err(convert)?
     };
     call.reply(result);
 }
@@ -65,7 +140,11 @@ fn insert<B: Board>(mut call: SchedulerCall<B, api::insert::Sig>) {
 #[cfg(feature = "board-api-storage")]
 fn remove<B: Board>(mut call: SchedulerCall<B, api::remove::Sig>) {
     let api::remove::Params { key } = call.read();
-    let res = try { call.scheduler().store.remove(*key as usize).map_err(convert)? };
+    let scheduler = call.scheduler();
+    let res = try {
+        let key = applet_key(namespace(scheduler)?, *key)?;
+        scheduler.store.remove(key).map_err(convert)?
+    };
     call.reply(res);
 }
 
@@ -73,9 +152,10 @@ fn remove<B: Board>(mut call: SchedulerCall<B, api::remove::Sig>) {
 fn find<B: Board>(mut call: SchedulerCall<B, api::find::Sig>) {
     let api::find::Params { key, ptr: ptr_ptr, len: len_ptr } = call.read();
     let scheduler = call.scheduler();
-    let mut memory = scheduler.applet.memory();
+    let namespace = namespace(scheduler);
+    let mut memory = scheduler.applets.current().memory();
     let result = try {
-        match scheduler.store.find(*key as usize).map_err(convert)? {
+        match scheduler.store.find(applet_key(namespace?, *key)?).map_err(convert)? {
             None => false,
             Some(value) => {
                 memory.alloc_copy(*ptr_ptr, Some(*len_ptr), &value)?;
@@ -90,11 +170,16 @@ fn find<B: Board>(mut call: SchedulerCall<B, api::find::Sig>) {
 fn keys<B: Board>(mut call: SchedulerCall<B, api::keys::Sig>) {
     let api::keys::Params { ptr: ptr_ptr } = call.read();
     let scheduler = call.scheduler();
-    let mut memory = scheduler.applet.memory();
+    let namespace = namespace(scheduler);
+    let mut memory = scheduler.applets.current().memory();
     let result = try {
+        let namespace = namespace?;
         let mut keys = Vec::new();
         for handle in scheduler.store.iter().map_err(convert)? {
-            keys.push(handle.map_err(convert)?.get_key() as u16);
+            let key = handle.map_err(convert)?.get_key();
+            if let Some(key) = namespace.unmap(key) {
+                keys.push(key as u16);
+            }
         }
         match keys {
             keys if keys.is_empty() => 0,
@@ -114,12 +199,105 @@ fn keys<B: Board>(mut call: SchedulerCall<B, api::keys::Sig>) {
 fn clear<B: Board>(mut call: SchedulerCall<B, api::clear::Sig>) {
     let api::clear::Params {} = call.read();
     let scheduler = call.scheduler();
-    let result = try { scheduler.store.clear(0).map_err(convert)? };
+    // We can't use `clear()` because it would also remove the keys of other applets and the
+    // platform.
+    let result = try {
+        let namespace = namespace(scheduler)?;
+        namespace::clear(&mut scheduler.store, namespace)?
+    };
+    call.reply(result);
+}
+
+#[cfg(feature = "board-api-storage")]
+fn transaction<B: Board>(mut call: SchedulerCall<B, api::transaction::Sig>) {
+    let api::transaction::Params { ptr, len } = call.read();
+    let scheduler = call.scheduler();
+    let namespace = namespace(scheduler);
+    let memory = scheduler.applets.current().memory();
+    let result = try {
+        let namespace = namespace?;
+        let size = core::mem::size_of::<api::Update>();
+        let len = len.checked_mul(size as u32).ok_or(Error::user(Code::InvalidArgument))?;
+        let mut updates = Vec::new();
+        let mut capacity = 0;
+        for update in memory.get(*ptr, len)?.chunks_exact(size) {
+            let api::Update { key, ptr, len } = bytemuck::pod_read_unaligned(update);
+            let key = applet_key(namespace, key)?;
+            updates.push(match ptr {
+                0 => StoreUpdate::Remove { key },
+                _ => {
+                    let value = memory.get(ptr, len)?;
+                    capacity += scheduler.store.entry_capacity(value.len());
+                    StoreUpdate::Insert { key, value }
+                }
+            });
+        }
+        let keys = |x| updates.iter().any(|update| update.key() == x);
+        namespace.check_quota(&scheduler.store, keys, capacity)?;
+        scheduler.store.transaction(&updates).map_err(convert)?
+    };
+    call.reply(result);
+}
+
+#[cfg(feature = "board-api-storage")]
+fn capacity<B: Board>(mut call: SchedulerCall<B, api::capacity::Sig>) {
+    let api::capacity::Params { ptr } = call.read();
+    let scheduler = call.scheduler();
+    let namespace = namespace(scheduler);
+    let memory = scheduler.applets.current().memory();
+    let result = try {
+        let (used, total) = namespace?.capacity(&scheduler.store)?;
+        *memory.from_bytes_mut::<api::Ratio>(*ptr)? = ratio(used, total);
+    };
+    call.reply(result);
+}
+
+#[cfg(feature = "board-api-storage")]
+fn lifetime<B: Board>(mut call: SchedulerCall<B, api::lifetime::Sig>) {
+    let api::lifetime::Params { ptr } = call.read();
+    let scheduler = call.scheduler();
+    let memory = scheduler.applets.current().memory();
+    let result = try {
+        let lifetime = scheduler.store.lifetime().map_err(convert)?;
+        *memory.from_bytes_mut::<api::Ratio>(*ptr)? = ratio(lifetime.used(), lifetime.total());
+    };
     call.reply(result);
 }
 
 #[cfg(feature = "board-api-storage")]
-fn convert(err: StoreError) -> Error {
+fn prepare<B: Board>(mut call: SchedulerCall<B, api::prepare::Sig>) {
+    let api::prepare::Params { len } = call.read();
+    let scheduler = call.scheduler();
+    let result = try {
+        let len = *len as usize;
+        if scheduler.store.max_value_length() < len {
+            Err(Error::user(Code::InvalidArgument))?;
+        }
+        let length = scheduler.store.entry_capacity(len);
+        scheduler.store.prepare(length).map_err(convert)?
+    };
+    call.reply(result);
+}
+
+#[cfg(feature = "board-api-storage")]
+fn ratio(used: usize, total: usize) -> api::Ratio {
+    api::Ratio { used: used as u32, total: total as u32 }
+}
+
+/// Returns the namespace of the current applet.
+#[cfg(feature = "board-api-storage")]
+fn namespace<B: Board>(scheduler: &mut Scheduler<B>) -> Result<Namespace, Error> {
+    scheduler.applets.current().namespace.ok_or(Error::world(Code::NotEnough))
+}
+
+#[cfg(feature = "board-api-storage")]
+fn applet_key(namespace: Namespace, key: u32) -> Result<usize, Error> {
+    let key = key as usize;
+    Ok(namespace.map(key .. key + 1)?.start)
+}
+
+#[cfg(feature = "board-api-storage")]
+pub fn convert(err: StoreError) -> Error {
     match err {
         StoreError::InvalidArgument => Error::user(Code::InvalidArgument),
         StoreError::NoCapacity | StoreError::NoLifetime => Error::world(Code::NotEnough),
@@ -127,3 +305,67 @@ fn convert(err: StoreError) -> Error {
         StoreError::InvalidStorage => Error::world(Code::InvalidState),
     }
 }
+
+#[cfg(all(test, feature = "board-api-storage"))]
+pub(crate) mod tests {
+    use alloc::vec;
+
+    use wasefire_store::{BufferOptions, BufferStorage};
+
+    use super::*;
+
+    /// Returns an empty store with the geometry of the host runner.
+    pub(crate) fn new_store() -> Store<BufferStorage> {
+        let options = BufferOptions {
+            word_size: 4,
+            page_size: 0x1000,
+            max_word_writes: 2,
+            max_page_erases: 10000,
+            strict_mode: true,
+        };
+        let storage = vec![0xff; 16 * options.page_size].into_boxed_slice();
+        Store::new(BufferStorage::new(storage, options)).ok().unwrap()
+    }
+
+    #[test]
+    fn reserve_moves_applet_entries() {
+        let mut store = new_store();
+        store.insert(0, b"applet0").unwrap();
+        store.insert(PLATFORM_KEY, b"applet1").unwrap();
+        store.insert(FORMAT_KEY, b"applet2").unwrap();
+        reserve(&mut store).unwrap();
+        assert_eq!(store.find(0).unwrap().as_deref(), Some(&b"applet0"[..]));
+        assert_eq!(store.find(1).unwrap().as_deref(), Some(&b"applet1"[..]));
+        assert_eq!(store.find(2).unwrap().as_deref(), Some(&b"applet2"[..]));
+        assert_eq!(store.find(PLATFORM_KEY).unwrap(), None);
+        assert_eq!(store.find(FORMAT_KEY).unwrap().as_deref(), Some(FORMAT));
+        let (legacy, owner) = namespace::LEGACY;
+        assert_eq!(namespace::allocate(&mut store, owner), Ok(legacy));
+    }
+
+    #[test]
+    fn reserve_removes_applet_entries_without_free_key() {
+        let mut store = new_store();
+        for key in 0 .. namespace::LEN {
+            store.insert(key, &[]).unwrap();
+        }
+        store.insert(PLATFORM_KEY, b"applet").unwrap();
+        reserve(&mut store).unwrap();
+        assert_eq!(store.find(namespace::LEN - 1).unwrap().as_deref(), Some(&[][..]));
+        assert_eq!(store.find(PLATFORM_KEY).unwrap(), None);
+        assert_eq!(store.find(FORMAT_KEY).unwrap().as_deref(), Some(FORMAT));
+    }
+
+    #[test]
+    fn reserve_keeps_platform_entries() {
+        let mut store = new_store();
+        reserve(&mut store).unwrap();
+        // An empty store has no applet entries to give.
+        assert_eq!(store.iter().unwrap().count(), 1);
+        store.insert(PLATFORM_KEY, b"platform").unwrap();
+        let storage = store.extract_storage();
+        let mut store = Store::new(storage).ok().unwrap();
+        reserve(&mut store).unwrap();
+        assert_eq!(store.find(PLATFORM_KEY).unwrap().as_deref(), Some(&b"platform"[..]));
+    }
+}
@@ -20,12 +20,14 @@ use wasefire_board_api::Api as Board;
 use wasefire_store::fragment;
 
 #[cfg(feature = "board-api-storage")]
-use super::convert;
+use super::{convert, namespace};
 #[cfg(feature = "board-api-storage")]
 use crate::applet::store::MemoryApi;
+#[cfg(feature = "board-api-storage")]
+use crate::namespace::Namespace;
 use crate::DispatchSchedulerCall;
 #[cfg(feature = "board-api-storage")]
-use crate::SchedulerCall;
+use crate::{Failure, SchedulerCall, Trap};
 
 pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
     match call {
@@ -39,10 +41,16 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
 fn insert<B: Board>(mut call: SchedulerCall<B, api::insert::Sig>) {
     let api::insert::Params { keys, ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let namespace = namespace(scheduler);
+    let memory = scheduler.applets.current().memory();
     let result = try {
-        let keys = decode_keys(keys)?;
+        let namespace = namespace?;
+        let keys = decode_keys(namespace, keys)?;
         let value = memory.get(*ptr, *len)?;
+        let store = &scheduler.store;
+        let chunks = value.chunks(store.max_value_length());
+        let capacity = chunks.map(|x| store.entry_capacity(x.len())).sum();
+        namespace.check_quota(store, |x| keys.contains(&x), capacity)?;
         fragment::write(&mut scheduler.store, &keys, value).map_err(convert)?
     };
     call.reply(result);
@@ -51,8 +59,10 @@ fn insert<B: Board>(mut call: SchedulerCall<B, api::insert::Sig>) {
 #[cfg(feature = "board-api-storage")]
 fn remove<B: Board>(mut call: SchedulerCall<B, api::remove::Sig>) {
     let api::remove::Params { keys } = call.read();
+    let scheduler = call.scheduler();
     let result = try {
-        fragment::delete(&mut call.scheduler().store, &decode_keys(keys)?).map_err(convert)?
+        let keys = decode_keys(namespace(scheduler)?, keys)?;
+        fragment::delete(&mut scheduler.store, &keys).map_err(convert)?
     };
     call.reply(result);
 }
@@ -61,9 +71,11 @@ fn remove<B: Board>(mut call: SchedulerCall<B, api::remove::Sig>) {
 fn find<B: Board>(mut call: SchedulerCall<B, api::find::Sig>) {
     let api::find::Params { keys, ptr: ptr_ptr, len: len_ptr } = call.read();
     let scheduler = call.scheduler();
-    let mut memory = scheduler.applet.memory();
+    let namespace = namespace(scheduler);
+    let mut memory = scheduler.applets.current().memory();
     let result = try {
-        match fragment::read(&scheduler.store, &decode_keys(keys)?).map_err(convert)? {
+        let keys = decode_keys(namespace?, keys)?;
+        match fragment::read(&scheduler.store, &keys).map_err(convert)? {
             None => false,
             Some(value) => {
                 memory.alloc_copy(*ptr_ptr, Some(*len_ptr), &value)?;
@@ -75,10 +87,10 @@ fn find<B: Board>(mut call: SchedulerCall<B, api::find::Sig>) {
 }
 
 #[cfg(feature = "board-api-storage")]
-fn decode_keys(keys: u32) -> Result<core::ops::Range<usize>, crate::Trap> {
-    if keys & 0xf000f000 == 0 {
-        Ok((keys & 0xffff) as usize .. ((keys >> 16) & 0xffff) as usize)
-    } else {
-        Err(crate::Trap)
+fn decode_keys(namespace: Namespace, keys: u32) -> Result<core::ops::Range<usize>, Failure> {
+    if keys & 0xf000f000 != 0 {
+        return Err(Trap.into());
     }
+    let keys = (keys & 0xffff) as usize .. ((keys >> 16) & 0xffff) as usize;
+    Ok(namespace.map(keys)?)
 }
@@ -47,10 +47,12 @@ fn allocate<B: Board>(mut call: SchedulerCall<B, api::allocate::Sig>) {
     let api::allocate::Params { handler_func, handler_data } = call.read();
     let inst = call.inst();
     let result = try {
-        let timers = &mut call.scheduler().timers;
+        let scheduler = call.scheduler();
+        let applet = scheduler.applets.current_id();
+        let timers = &mut scheduler.timers;
         let timer = timers.iter().position(|x| x.is_none()).ok_or(Trap)?;
-        timers[timer] = Some(Timer {});
-        call.scheduler().applet.enable(Handler {
+        timers[timer] = Some(Timer { applet });
+        call.scheduler().applets.enable(Handler {
             key: Key { timer: Id::new(timer).unwrap() }.into(),
             inst,
             func: *handler_func,
@@ -98,14 +100,14 @@ fn free<B: Board>(mut call: SchedulerCall<B, api::free::Sig>) {
     call.reply(result);
 }
 
-// TODO: Should also check that the timer belongs to the calling applet.
+/// Returns the timer if it belongs to the calling applet.
 #[cfg(feature = "board-api-timer")]
 fn get_timer<B: Board>(
     scheduler: &Scheduler<B>, timer: usize,
 ) -> Result<Id<board::Timer<B>>, Trap> {
     let id = Id::new(timer).map_err(|_| Trap)?;
-    if scheduler.timers[timer].is_none() {
-        return Err(Trap);
+    match &scheduler.timers[timer] {
+        Some(Timer { applet }) if *applet == scheduler.applets.current_id() => Ok(id),
+        _ => Err(Trap),
     }
-    Ok(id)
 }
@@ -83,7 +83,7 @@ fn stop<B: Board>(call: SchedulerCall<B, api::stop::Sig>) {
 fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
     let api::read::Params { uart, ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let uart = Id::new(*uart as usize).map_err(|_| Trap)?;
         let output = memory.get_mut(*ptr, *len)?;
@@ -96,7 +96,7 @@ fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
 fn write<B: Board>(mut call: SchedulerCall<B, api::write::Sig>) {
     let api::write::Params { uart, ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let uart = Id::new(*uart as usize).map_err(|_| Trap)?;
         let input = memory.get(*ptr, *len)?;
@@ -113,7 +113,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let result = try {
         let uart = Id::new(*uart as usize).map_err(|_| Trap)?;
         let event = convert_event(uart, *event)?;
-        scheduler.applet.enable(Handler {
+        scheduler.applets.enable(Handler {
             key: Key::from(&event).into(),
             inst,
             func: *handler_func,
@@ -43,7 +43,7 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
 fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
     let api::read::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let output = memory.get_mut(*ptr, *len)?;
         board::usb::Serial::<B>::read(output)? as u32
@@ -55,7 +55,7 @@ fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
 fn write<B: Board>(mut call: SchedulerCall<B, api::write::Sig>) {
     let api::write::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applets.current().memory();
     let result = try {
         let input = memory.get(*ptr, *len)?;
         board::usb::Serial::<B>::write(input)? as u32
@@ -70,7 +70,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let scheduler = call.scheduler();
     let result = try {
         let event = convert_event(*event)?;
-        scheduler.applet.enable(Handler {
+        scheduler.applets.enable(Handler {
             key: Key::from(&event).into(),
             inst,
             func: *handler_func,
@@ -0,0 +1,74 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Applet capabilities.
+//!
+//! An applet may declare the API modules it needs in a custom section named
+//! `wasefire-capabilities`. The section content is a comma-separated list of paths designating API
+//! modules (e.g. `store` or `crypto::ec`) or functions (e.g. `crypto::ec::is_supported`). Only the
+//! host functions granted by those paths are linked, and applets importing other host functions are
+//! rejected. The `debug` and `scheduling` modules are always granted.
+//!
+//! Applets without capability section are granted all host functions.
+
+use alloc::vec::Vec;
+
+use wasefire_applet_api::{Api, Id};
+use wasefire_interpreter::{ImportDesc, Module};
+
+/// Name of the capability section.
+// Keep in sync with the capabilities macro of the prelude.
+const SECTION: &str = "wasefire-capabilities";
+
+/// Paths granted to all applets.
+const ALWAYS: &[&str] = &["debug", "scheduling"];
+
+/// Host functions an applet is allowed to import.
+pub struct Grant<'m>(Option<Vec<&'m str>>);
+
+impl<'m> Grant<'m> {
+    /// Reads the capabilities of a module.
+    ///
+    /// Returns `None` if the capability section is not valid UTF-8.
+    pub fn new(module: &Module<'m>) -> Option<Self> {
+        let Some(section) = module.custom_section(SECTION) else { return Some(Grant(None)) };
+        let section = core::str::from_utf8(section).ok()?;
+        let paths = section.split(',').map(|x| x.trim()).filter(|x| !x.is_empty());
+        Some(Grant(Some(ALWAYS.iter().copied().chain(paths).collect())))
+    }
+
+    /// Returns whether a host function is granted.
+    pub fn contains(&self, api: &Api<Id>) -> bool {
+        match &self.0 {
+            None => true,
+            Some(paths) => paths.iter().any(|x| api.matches(x)),
+        }
+    }
+
+    /// Returns the first host function imported by a module but not granted, if any.
+    ///
+    /// The host functions must be sorted by name.
+    pub fn forbidden(&self, module: &Module<'m>, host_funcs: &[Api<Id>]) -> Option<&'m str> {
+        module.imports().find_map(|import| {
+            if import.module != "env" || !matches!(import.desc, ImportDesc::Func(_)) {
+                return None;
+            }
+            let index = host_funcs.binary_search_by_key(&import.name, |x| x.descriptor().name);
+            match index {
+                Ok(index) if !self.contains(&host_funcs[index]) => Some(import.name),
+                _ => None,
+            }
+        })
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistence of installed applets.
+//!
+//! Installed applets are stored in the platform store using keys reserved to the platform. Each
+//! install slot has a metadata entry, which points to the bank (a range of entries) of its module.
+//! There is one more bank than slots, such that a new module is written in a free bank and the
+//! metadata entry switches to it last. A slot is installed if and only if its metadata entry is
+//! present, and an interrupted install keeps the previously installed applet.
+
+// Without the platform protocol, installed applets can only be loaded.
+#![cfg_attr(not(feature = "board-api-platform-protocol"), allow(dead_code))]
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use wasefire_error::{Code, Error};
+use wasefire_store::{fragment, Storage, Store};
+
+use crate::call::store::{convert, FORMAT_KEY, PLATFORM_KEY};
+
+/// First key of the metadata entries (one per slot).
+const METADATA_KEY: usize = PLATFORM_KEY;
+
+/// First key of the module entries (`MODULE_LEN` per bank).
+const MODULE_KEY: usize = PLATFORM_KEY + 64;
+
+/// Number of keys for the module of a bank.
+const MODULE_LEN: usize = 95;
+
+// The applet count is a single digit.
+const _: () = assert!(MODULE_KEY + 10 * MODULE_LEN <= FORMAT_KEY);
+
+#[derive(Debug)]
+pub struct Metadata {
+    pub name: String,
+    pub size: u32,
+}
+
+impl Metadata {
+    fn encode(&self, bank: usize) -> Vec<u8> {
+        let mut data = self.size.to_le_bytes().to_vec();
+        data.push(bank as u8);
+        data.extend_from_slice(self.name.as_bytes());
+        data
+    }
+
+    /// Decodes the metadata and the bank of its module.
+    fn decode(data: &[u8]) -> Result<(Self, usize), Error> {
+        if data.len() < 5 {
+            return Err(Error::world(Code::InvalidLength));
+        }
+        let (size, data) = data.split_at(4);
+        let size = u32::from_le_bytes(size.try_into().unwrap());
+        let bank = data[0] as usize;
+        if !banks().contains(&bank) {
+            return Err(Error::world(Code::InvalidArgument));
+        }
+        let name = core::str::from_utf8(&data[1 ..]);
+        let name = name.map_err(|_| Error::world(Code::InvalidArgument))?;
+        Ok((Metadata { name: name.into(), size }, bank))
+    }
+}
+
+/// Returns the maximum size of an installed module.
+pub fn max_size(store: &Store<impl Storage>) -> usize {
+    MODULE_LEN * store.max_value_length()
+}
+
+/// Returns the installed applets with their slot.
+pub fn list(store: &Store<impl Storage>) -> Result<Vec<(usize, Metadata)>, Error> {
+    Ok(installed(store)?.into_iter().map(|(slot, metadata, _)| (slot, metadata)).collect())
+}
+
+/// Returns the slot of an installed applet given its name.
+pub fn find(store: &Store<impl Storage>, name: &str) -> Result<Option<usize>, Error> {
+    Ok(list(store)?.into_iter().find(|(_, x)| x.name == name).map(|(slot, _)| slot))
+}
+
+/// Returns the slot where an applet would be installed.
+///
+/// This is the slot of the installed applet with the same name if any, and the first free slot
+/// otherwise.
+pub fn allocate(store: &Store<impl Storage>, name: &str) -> Result<usize, Error> {
+    let installed = list(store)?;
+    if let Some((slot, _)) = installed.iter().find(|(_, x)| x.name == name) {
+        return Ok(*slot);
+    }
+    let mut free = slots().filter(|slot| installed.iter().all(|(x, _)| x != slot));
+    free.next().ok_or(Error::world(Code::NotEnough))
+}
+
+/// Returns the metadata and module of an installed applet.
+pub fn read(store: &Store<impl Storage>, slot: usize) -> Result<(Metadata, Vec<u8>), Error> {
+    let metadata = store.find(METADATA_KEY + slot).map_err(convert)?;
+    let (metadata, bank) = Metadata::decode(&metadata.ok_or(Error::world(Code::NotFound))?)?;
+    let module = fragment::read(store, &module_keys(bank)).map_err(convert)?;
+    Ok((metadata, module.ok_or(Error::world(Code::NotFound))?))
+}
+
+/// Installs an applet, replacing the applet installed in that slot if any.
+///
+/// The installed applet is only replaced once the new module is fully written.
+pub fn write(
+    store: &mut Store<impl Storage>, slot: usize, metadata: &Metadata, module: &[u8],
+) -> Result<(), Error> {
+    if max_size(store) < module.len() {
+        return Err(Error::user(Code::InvalidLength));
+    }
+    let installed = installed(store)?;
+    let old = installed.iter().find(|(x, _, _)| *x == slot).map(|(_, _, bank)| *bank);
+    // There is one more bank than slots, so there is always a free bank.
+    let bank = banks().find(|bank| installed.iter().all(|(_, _, x)| x != bank)).unwrap();
+    // The free bank may contain the module of an interrupted install.
+    clear(store, bank)?;
+    // The module is too large for a single transaction, so we write it chunk by chunk.
+    for (key, chunk) in module_keys(bank).zip(module.chunks(store.max_value_length())) {
+        store.insert(key, chunk).map_err(convert)?;
+    }
+    store.insert(METADATA_KEY + slot, &metadata.encode(bank)).map_err(convert)?;
+    match old {
+        Some(old) => clear(store, old),
+        None => Ok(()),
+    }
+}
+
+/// Uninstalls the applet of a slot, if any.
+pub fn delete(store: &mut Store<impl Storage>, slot: usize) -> Result<(), Error> {
+    let metadata = store.find(METADATA_KEY + slot).map_err(convert)?;
+    store.remove(METADATA_KEY + slot).map_err(convert)?;
+    // The module of an invalid metadata entry is cleared when its bank is reused.
+    match metadata.map(|x| Metadata::decode(&x)) {
+        Some(Ok((_, bank))) => clear(store, bank),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the installed applets with their slot and the bank of their module.
+fn installed(store: &Store<impl Storage>) -> Result<Vec<(usize, Metadata, usize)>, Error> {
+    let mut result = Vec::new();
+    for slot in slots() {
+        if let Some(data) = store.find(METADATA_KEY + slot).map_err(convert)? {
+            let (metadata, bank) = Metadata::decode(&data)?;
+            result.push((slot, metadata, bank));
+        }
+    }
+    Ok(result)
+}
+
+/// Removes the module of a bank.
+fn clear(store: &mut Store<impl Storage>, bank: usize) -> Result<(), Error> {
+    for key in module_keys(bank) {
+        store.remove(key).map_err(convert)?;
+    }
+    Ok(())
+}
+
+fn slots() -> Range<usize> {
+    0 .. crate::applet_count()
+}
+
+fn banks() -> Range<usize> {
+    0 .. crate::applet_count() + 1
+}
+
+fn module_keys(bank: usize) -> Range<usize> {
+    let start = MODULE_KEY + bank * MODULE_LEN;
+    start .. start + MODULE_LEN
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::call::store::tests::new_store;
+
+    fn metadata(name: &str, module: &[u8]) -> Metadata {
+        Metadata { name: name.into(), size: module.len() as u32 }
+    }
+
+    #[test]
+    fn write_replaces_installed_applet() {
+        let mut store = new_store();
+        let old = vec![1; 1500];
+        let new = vec![2; 2500];
+        write(&mut store, 0, &metadata("foo", &old), &old).unwrap();
+        write(&mut store, 0, &metadata("foo", &new), &new).unwrap();
+        let (metadata, module) = read(&store, 0).unwrap();
+        assert_eq!((metadata.name.as_str(), metadata.size), ("foo", 2500));
+        assert_eq!(module, new);
+        // The old module is removed.
+        let used = store.iter().unwrap().count();
+        assert_eq!(used, 1 + new.len().div_ceil(store.max_value_length()));
+        delete(&mut store, 0).unwrap();
+        assert_eq!(store.iter().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn interrupted_write_keeps_installed_applet() {
+        let old = vec![1; 1500];
+        let new = vec![2; 1500];
+        for delay in 0 .. {
+            let mut store = new_store();
+            write(&mut store, 0, &metadata("foo", &old), &old).unwrap();
+            store.storage_mut().arm_interruption(delay);
+            if write(&mut store, 0, &metadata("foo", &new), &new).is_ok() {
+                store.storage_mut().disarm_interruption();
+                break;
+            }
+            let mut storage = store.extract_storage();
+            storage.reset_interruption();
+            let store = Store::new(storage).ok().unwrap();
+            let (_, module) = read(&store, 0).unwrap();
+            assert!(module == old || module == new, "delay {delay}");
+        }
+    }
+}
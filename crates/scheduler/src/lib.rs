@@ -40,20 +40,43 @@ use wasefire_interpreter::{self as interpreter, Call, Module, RunAnswer, Val};
 use wasefire_logger as log;
 #[cfg(feature = "board-api-storage")]
 use wasefire_store as store;
+// Those dev-dependencies are only used to test applet signatures.
+#[cfg(all(test, not(feature = "applet-signature")))]
+use {p256 as _, wasefire_cli_tools as _};
 
 use crate::applet::store::{Memory, Store, StoreApi};
-use crate::applet::{Applet, EventAction};
+use crate::applet::{Applet, Applets, EventAction};
 use crate::event::InstId;
 
 mod applet;
 mod call;
+#[cfg(feature = "wasm")]
+mod capability;
 mod event;
+#[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+mod install;
+#[cfg(feature = "wasm")]
+mod limits;
+#[cfg(feature = "board-api-storage")]
+mod namespace;
 #[cfg(feature = "native")]
 mod native;
 #[cfg(feature = "internal-debug")]
 mod perf;
 #[cfg(feature = "board-api-platform-protocol")]
 mod protocol;
+#[cfg(feature = "applet-signature")]
+mod signature;
+
+/// Storage of the platform store.
+#[cfg(all(feature = "board-api-storage", not(feature = "store-health")))]
+type Storage<B> = <B as Board>::Storage;
+#[cfg(feature = "store-health")]
+type Storage<B> = store::MeteredStorage<<B as Board>::Storage>;
+
+/// Creates the tracer of a WASM applet given its name.
+#[cfg(feature = "applet-trace")]
+pub type NewTracer = fn(&str) -> alloc::boxed::Box<dyn interpreter::Tracer>;
 
 #[cfg(all(feature = "native", not(target_pointer_width = "32")))]
 compile_error!("Only 32-bits architectures support native applets.");
@@ -90,21 +113,40 @@ impl<B: Board> Events<B> {
 
 pub struct Scheduler<B: Board> {
     #[cfg(feature = "board-api-storage")]
-    store: store::Store<B::Storage>,
+    store: store::Store<Storage<B>>,
     host_funcs: Vec<Api<Id>>,
-    applet: Applet<B>,
+    /// Host functions linked once and shared by the stores of all applets.
+    ///
+    /// The index of a host call is the index of the host function in `host_funcs`.
+    #[cfg(feature = "wasm")]
+    linker: &'static interpreter::Linker<'static>,
+    /// Creates the tracer of a WASM applet given its name.
+    #[cfg(feature = "applet-trace")]
+    new_tracer: Option<NewTracer>,
+    applets: Applets<B>,
     #[cfg(feature = "board-api-timer")]
     timers: Vec<Option<Timer>>,
     #[cfg(feature = "internal-debug")]
     perf: perf::Perf<B>,
     #[cfg(feature = "board-api-platform-protocol")]
     protocol: protocol::State,
+    #[cfg(all(
+        feature = "board-api-platform-protocol",
+        feature = "wasm",
+        feature = "board-api-storage"
+    ))]
+    install: protocol::Install,
+    #[cfg(all(feature = "board-api-platform-protocol", feature = "board-api-platform-update"))]
+    update: protocol::Update,
+    #[cfg(all(feature = "board-api-platform-protocol", feature = "wasm"))]
+    rejections: protocol::Rejections,
 }
 
 #[cfg(feature = "board-api-timer")]
 #[derive(Clone)]
 struct Timer {
-    // TODO: Add AppletId.
+    /// Applet owning the timer.
+    applet: applet::AppletId,
 }
 
 impl<B: Board> core::fmt::Debug for Scheduler<B> {
@@ -191,7 +233,10 @@ impl<'a, B: Board, T: Signature> SchedulerCall<'a, B, T> {
         let result = Error::encode(match result {
             Ok(x) => Ok(*x),
             Err(Failure::Error(e)) => Err(e),
-            Err(Failure::Trap(Trap)) => applet_trapped::<B>(Some(T::NAME)),
+            Err(Failure::Trap(Trap)) => {
+                let mut this = self;
+                return this.scheduler().applet_trapped(Some(T::NAME));
+            }
         });
         #[cfg(feature = "wasm")]
         {
@@ -211,7 +256,7 @@ impl<'a, B: Board, T: Signature> SchedulerCall<'a, B, T> {
     }
 
     fn applet(&mut self) -> &mut Applet<B> {
-        &mut self.erased.scheduler.applet
+        self.erased.scheduler.applets.current()
     }
 
     fn store(&mut self) -> &mut Store {
@@ -227,18 +272,63 @@ impl<'a, B: Board, T: Signature> SchedulerCall<'a, B, T> {
 impl<B: Board> Scheduler<B> {
     #[cfg(feature = "wasm")]
     pub fn run(wasm: &'static [u8]) -> ! {
+        Self::run_applets(&[("applet", wasm)])
+    }
+
+    /// Runs the applets side by side.
+    ///
+    /// Each applet is given with its name, which identifies it in the platform protocol. The
+    /// applets installed through the platform protocol are also loaded. The number of applets
+    /// is limited by `WASEFIRE_APPLET_COUNT` at compile time.
+    #[cfg(feature = "wasm")]
+    pub fn run_applets(wasms: &[(&'static str, &'static [u8])]) -> ! {
+        Self::run_loaded(Self::new(), wasms)
+    }
+
+    /// Runs the applets side by side with a tracer for each WASM applet.
+    ///
+    /// This is like [`Self::run_applets()`] except that the tracer of each applet is created from
+    /// its name when the applet is loaded.
+    #[cfg(feature = "applet-trace")]
+    pub fn run_applets_traced(wasms: &[(&'static str, &'static [u8])], new_tracer: NewTracer) -> ! {
         let mut scheduler = Self::new();
-        log::debug!("Loading applet.");
-        scheduler.load(wasm);
+        scheduler.new_tracer = Some(new_tracer);
+        Self::run_loaded(scheduler, wasms)
+    }
+
+    #[cfg(feature = "wasm")]
+    fn run_loaded(mut scheduler: Self, wasms: &[(&'static str, &'static [u8])]) -> ! {
+        for &(name, wasm) in wasms {
+            log::debug!("Loading applet {}.", name);
+            let mut applet = Applet::default();
+            applet.name = name.into();
+            scheduler.load(applet, wasm);
+        }
+        #[cfg(feature = "board-api-storage")]
+        match install::list(&scheduler.store) {
+            Ok(installed) => {
+                for (slot, _) in installed {
+                    scheduler.load_installed(slot);
+                }
+            }
+            Err(error) => log::error!("Failed to list installed applets: {}", error),
+        }
         loop {
             scheduler.flush_events();
-            scheduler.process_applet();
+            scheduler.schedule();
         }
     }
 
     #[cfg(feature = "native")]
     pub fn run() -> ! {
-        native::set_scheduler(Self::new());
+        #[cfg_attr(not(feature = "board-api-storage"), allow(unused_mut))]
+        let mut scheduler = Self::new();
+        #[cfg(feature = "board-api-storage")]
+        {
+            let namespace = scheduler.namespace("applet");
+            scheduler.applets.current().namespace = namespace;
+        }
+        native::set_scheduler(scheduler);
         extern "C" {
             fn applet_init();
             fn applet_main();
@@ -288,64 +378,212 @@ impl<B: Board> Scheduler<B> {
         host_funcs.sort_by_key(|x| x.descriptor().name);
         assert!(host_funcs.windows(2).all(|x| x[0].descriptor().name != x[1].descriptor().name));
         #[cfg(feature = "wasm")]
-        let applet = {
-            let mut applet = Applet::default();
-            let store = applet.store_mut();
-            for f in &host_funcs {
-                let d = f.descriptor();
-                store.link_func("env", d.name, d.params, 1).unwrap();
+        let linker = {
+            let mut linker = interpreter::Linker::default();
+            for x in &host_funcs {
+                let d = x.descriptor();
+                linker.link_func("env", d.name, d.params, 1).unwrap();
             }
-            store.link_func_default("env").unwrap();
-            applet
+            alloc::boxed::Box::leak(alloc::boxed::Box::new(linker))
         };
         #[cfg(feature = "board-api-platform-protocol")]
         protocol::enable::<B>();
+        #[cfg_attr(feature = "wasm", allow(unused_mut))]
+        let mut applets = Applets::default();
         #[cfg(feature = "native")]
-        let applet = Applet::default();
+        {
+            let mut applet = Applet::default();
+            applet.name = "applet".into();
+            applets.insert(applet);
+        }
+        #[cfg(feature = "board-api-storage")]
+        let mut store = store::Store::new(storage::<B>()).ok().unwrap();
+        #[cfg(feature = "board-api-storage")]
+        if let Err(error) = call::store::reserve(&mut store) {
+            log::error!("Failed to reserve the platform keys: {}", error);
+        }
         Self {
             #[cfg(feature = "board-api-storage")]
-            store: store::Store::new(board::Storage::<B>::take().unwrap()).ok().unwrap(),
+            store,
             host_funcs,
-            applet,
+            #[cfg(feature = "wasm")]
+            linker,
+            #[cfg(feature = "applet-trace")]
+            new_tracer: None,
+            applets,
             #[cfg(feature = "board-api-timer")]
             timers: alloc::vec![None; board::Timer::<B>::SUPPORT],
             #[cfg(feature = "internal-debug")]
             perf: perf::Perf::default(),
             #[cfg(feature = "board-api-platform-protocol")]
             protocol: protocol::State::default(),
+            #[cfg(all(
+                feature = "board-api-platform-protocol",
+                feature = "wasm",
+                feature = "board-api-storage"
+            ))]
+            install: protocol::Install::default(),
+            #[cfg(all(
+                feature = "board-api-platform-protocol",
+                feature = "board-api-platform-update"
+            ))]
+            update: protocol::Update::default(),
+            #[cfg(all(feature = "board-api-platform-protocol", feature = "wasm"))]
+            rejections: protocol::Rejections::default(),
         }
     }
 
+    #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+    fn load_installed(&mut self, slot: usize) {
+        log::debug!("Loading installed applet {}.", slot);
+        let (metadata, module) = match install::read(&self.store, slot) {
+            Ok(x) => x,
+            Err(error) => return log::error!("Failed to read installed applet: {}", error),
+        };
+        let mut applet = Applet::default();
+        applet.name = metadata.name;
+        applet.installed = Some(slot);
+        // SAFETY: The module is only used to instantiate the applet.
+        let wasm = unsafe { applet.own_module(module.into_boxed_slice()) };
+        self.load(applet, wasm);
+    }
+
     #[cfg(feature = "wasm")]
-    fn load(&mut self, wasm: &'static [u8]) {
+    fn load(&mut self, mut applet: Applet<B>, wasm: &'static [u8]) {
+        const APPLET_COUNT: usize = applet_count();
         const MEMORY_SIZE: usize = memory_size();
         #[repr(align(16))]
         struct Memory([u8; MEMORY_SIZE]);
-        static mut MEMORY: Memory = Memory([0; MEMORY_SIZE]);
-        #[cfg(not(feature = "unsafe-skip-validation"))]
-        let module = Module::new(wasm).unwrap();
-        // SAFETY: The module is valid by the feature invariant.
-        #[cfg(feature = "unsafe-skip-validation")]
-        let module = unsafe { Module::new_unchecked(wasm) };
-        let store = self.applet.store_mut();
-        // SAFETY: This function is called once in `run()`.
-        let inst = store.instantiate(module, unsafe { &mut MEMORY.0 }).unwrap();
+        const EMPTY: Memory = Memory([0; MEMORY_SIZE]);
+        static mut MEMORY: [Memory; APPLET_COUNT] = [EMPTY; APPLET_COUNT];
+        let id = self.applets.vacant().index();
+        if APPLET_COUNT <= id {
+            return log::error!("Not enough memory to load more than {} applets.", APPLET_COUNT);
+        }
+        #[cfg(feature = "applet-signature")]
+        if let Err(reason) = signature::verify::<B>(wasm) {
+            #[cfg(feature = "board-api-platform-protocol")]
+            self.rejections.set(&applet.name, Some(reason));
+            let name = applet.name.as_str();
+            return log::error!("Rejecting applet {}: {}", name, log::Debug2Format(&reason));
+        }
+        // SAFETY: Prepared modules are trusted if signed by a trusted key (see above) or by the
+        // feature invariant.
+        #[cfg(any(feature = "applet-signature", feature = "unsafe-skip-validation"))]
+        let prepared = unsafe { Module::new_prepared(wasm) }.ok();
+        #[cfg(not(any(feature = "applet-signature", feature = "unsafe-skip-validation")))]
+        let prepared = None;
+        // Other modules are validated, because their side table is computed during validation.
+        let module = match prepared {
+            Some(x) => x,
+            None => match Module::new(wasm) {
+                Ok(x) => x,
+                Err(e) => {
+                    #[cfg(feature = "board-api-platform-protocol")]
+                    self.rejections
+                        .set(&applet.name, Some(wasefire_protocol::applet::Rejection::Invalid));
+                    return log::error!("Failed to validate applet: {}", log::Debug2Format(&e));
+                }
+            },
+        };
+        #[cfg(feature = "board-api-platform-protocol")]
+        self.rejections.set(&applet.name, None);
+        let Some(grant) = capability::Grant::new(&module) else {
+            #[cfg(feature = "board-api-platform-protocol")]
+            self.rejections.set(&applet.name, Some(wasefire_protocol::applet::Rejection::Invalid));
+            return log::error!("Failed to parse applet capabilities.");
+        };
+        if let Some(name) = grant.forbidden(&module, &self.host_funcs) {
+            #[cfg(feature = "board-api-platform-protocol")]
+            self.rejections.set(&applet.name, Some(wasefire_protocol::applet::Rejection::Invalid));
+            return log::error!("Applet imports {} outside its capabilities.", name);
+        }
+        // Host functions outside the capabilities are linked but cannot be imported (see above).
+        applet.store = Store::new(self.linker);
+        #[cfg(feature = "applet-trace")]
+        if let Some(new_tracer) = self.new_tracer {
+            applet.store.set_tracer(new_tracer(&applet.name));
+        }
+        let limits = limits::applet_limits(&applet.name);
+        let store = &mut applet.store;
+        store.link_func_default("env").unwrap();
+        // SAFETY: The memory of a slot is only used by the applet of that slot, and slots are only
+        // reused after their applet is stopped.
+        let inst = match store.instantiate(module, unsafe { &mut MEMORY[id].0 }, limits) {
+            Ok(x) => x,
+            Err(e) => {
+                #[cfg(feature = "board-api-platform-protocol")]
+                self.rejections
+                    .set(&applet.name, Some(wasefire_protocol::applet::Rejection::Invalid));
+                return log::error!("Failed to instantiate applet: {}", log::Debug2Format(&e));
+            }
+        };
+        #[cfg(feature = "board-api-storage")]
+        {
+            applet.namespace = self.namespace(&applet.name);
+        }
+        let id = self.applets.insert(applet);
+        self.applets.set_current(id);
         #[cfg(feature = "internal-debug")]
         self.perf.record(perf::Slot::Platform);
         self.call(inst, "init", &[]);
-        while let Some(call) = self.applet.store_mut().last_call() {
+        loop {
+            let Some(applet) = self.applets.get_mut(id) else {
+                return; // the applet trapped
+            };
+            if applet.store.is_preempted() {
+                let answer = applet.store.resume().map(|x| x.forget());
+                self.process_answer(answer);
+                continue;
+            }
+            let Some(call) = applet.store.last_call() else { break };
             match self.host_funcs[call.index()].descriptor().name {
                 "dp" => (),
                 x => log::panic!("init called {} into host", log::Debug2Format(&x)),
             }
             self.process_applet();
         }
-        assert!(matches!(self.applet.pop(), EventAction::Reply));
+        assert!(matches!(self.applets.current().pop(), EventAction::Reply));
         #[cfg(feature = "internal-debug")]
         self.perf.record(perf::Slot::Applets);
         self.call(inst, "main", &[]);
     }
 
+    /// Makes progress on each applet that is not waiting for an event.
+    ///
+    /// If all applets are waiting, waits for the next event.
+    #[cfg(feature = "wasm")]
+    fn schedule(&mut self) {
+        #[cfg(all(feature = "board-api-platform-protocol", feature = "board-api-storage"))]
+        for pending in core::mem::take(&mut self.install.pending) {
+            match pending {
+                protocol::Pending::Start(slot) => {
+                    self.stop_installed(slot);
+                    self.load_installed(slot);
+                }
+                protocol::Pending::Stop(slot) => self.stop_installed(slot),
+            }
+        }
+        let mut idle = true;
+        for id in self.applets.ids() {
+            match self.applets.get_mut(id) {
+                Some(applet) if !applet.is_waiting() => (),
+                _ => continue,
+            }
+            idle = false;
+            self.applets.set_current(id);
+            self.process_applet();
+        }
+        if idle {
+            #[cfg(feature = "internal-debug")]
+            self.perf.record(perf::Slot::Platform);
+            let event = B::wait_event();
+            #[cfg(feature = "internal-debug")]
+            self.perf.record(perf::Slot::Waiting);
+            self.triage_event(event);
+        }
+    }
+
     fn flush_events(&mut self) {
         while let Some(event) = B::try_event() {
             self.triage_event(event);
@@ -358,13 +596,23 @@ impl<B: Board> Scheduler<B> {
             protocol::process_event(self, event);
             return;
         }
-        self.applet.push(event);
+        self.applets.push(event);
     }
 
     /// Returns whether execution should resume.
+    ///
+    /// With wasm applets, this function doesn't block: it returns false when there are no events
+    /// such that other applets can execute.
     fn process_event(&mut self) -> bool {
+        #[cfg(feature = "wasm")]
+        let event = match self.applets.current().pop() {
+            EventAction::Handle(event) => event,
+            EventAction::Wait => return false,
+            EventAction::Reply => return true,
+        };
+        #[cfg(feature = "native")]
         let event = loop {
-            match self.applet.pop() {
+            match self.applets.current().pop() {
                 EventAction::Handle(event) => break event,
                 EventAction::Wait => {
                     #[cfg(feature = "internal-debug")]
@@ -374,8 +622,6 @@ impl<B: Board> Scheduler<B> {
                     self.perf.record(perf::Slot::Waiting);
                     self.triage_event(event);
                 }
-                #[cfg(feature = "wasm")]
-                EventAction::Reply => return true,
             }
         };
         event::process(self, event);
@@ -384,7 +630,16 @@ impl<B: Board> Scheduler<B> {
 
     #[cfg(feature = "wasm")]
     fn process_applet(&mut self) {
-        let call = match self.applet.store_mut().last_call() {
+        if self.applets.current().store.is_preempted() {
+            #[cfg(feature = "internal-debug")]
+            self.perf.record(perf::Slot::Platform);
+            let answer = self.applets.current().store.resume().map(|x| x.forget());
+            #[cfg(feature = "internal-debug")]
+            self.perf.record(perf::Slot::Applets);
+            return self.process_answer(answer);
+        }
+        let applet = self.applets.current();
+        let call = match applet.store.last_call() {
             Some(x) => x,
             None => {
                 self.process_event();
@@ -411,7 +666,7 @@ impl<B: Board> Scheduler<B> {
 
     #[allow(dead_code)] // in case there are no events
     fn disable_event(&mut self, key: Key<B>) -> Result<(), Trap> {
-        self.applet.disable(key)?;
+        self.applets.current().disable(key)?;
         self.flush_events();
         Ok(())
     }
@@ -422,7 +677,8 @@ impl<B: Board> Scheduler<B> {
         let args = args.iter().map(|&x| Val::I32(x)).collect();
         #[cfg(feature = "internal-debug")]
         self.perf.record(perf::Slot::Platform);
-        let answer = self.applet.store_mut().invoke(inst, name, args).map(|x| x.forget());
+        let answer =
+            self.applets.current().store_mut().invoke(inst, name, args).map(|x| x.forget());
         #[cfg(feature = "internal-debug")]
         self.perf.record(perf::Slot::Applets);
         self.process_answer(answer);
@@ -434,23 +690,114 @@ impl<B: Board> Scheduler<B> {
             Ok(RunAnswer::Done(x)) => {
                 log::debug!("Thread is done.");
                 debug_assert!(x.is_empty());
-                self.applet.done();
+                self.applets.current().done();
             }
             Ok(RunAnswer::Host) => (),
-            Err(interpreter::Error::Trap) => applet_trapped::<B>(None),
+            Ok(RunAnswer::Preempted) => log::trace!("Thread is preempted."),
+            Ok(RunAnswer::Exception(e)) => {
+                let index = self.applets.current_id().index();
+                log::error!("Applet {} threw an uncaught exception (tag {}).", index, e.tag);
+                self.applet_failed();
+            }
+            Err(interpreter::Error::Trap) => self.applet_trapped(None),
+            Err(interpreter::Error::LimitExceeded(limit)) => {
+                let index = self.applets.current_id().index();
+                log::error!("Applet {} exceeded its {} limit.", index, log::Debug2Format(&limit));
+                self.applet_failed();
+            }
             Err(e) => log::panic!("{}", log::Debug2Format(&e)),
         }
     }
-}
 
-fn applet_trapped<B: Board>(reason: Option<&'static str>) -> ! {
-    // Until we support multiple applets, we just exit the platform when the applet traps.
-    match reason {
-        None => log::error!("Applet trapped in wasm (think segfault)."),
-        Some("sa") => log::error!("Applet aborted (probably a panic)."),
-        Some(name) => log::error!("Applet trapped calling host {:?}.", name),
+    fn applet_trapped(&mut self, reason: Option<&'static str>) {
+        let index = self.applets.current_id().index();
+        match reason {
+            None => {
+                log::error!("Applet {} trapped in wasm (think segfault).", index);
+                #[cfg(feature = "wasm")]
+                self.log_backtrace();
+            }
+            Some("sa") => log::error!("Applet {} aborted (probably a panic).", index),
+            Some(name) => log::error!("Applet {} trapped calling host {:?}.", index, name),
+        }
+        self.applet_failed();
+    }
+
+    /// Stops the current applet after a failure.
+    ///
+    /// The platform keeps running when no applet is left, such that the platform protocol can still
+    /// be used (e.g. to install a fixed applet).
+    fn applet_failed(&mut self) {
+        #[cfg(feature = "wasm")]
+        let id = self.applets.current_id();
+        // Native applets are linked with the platform, so we exit the platform.
+        #[cfg(feature = "native")]
+        <board::Debug<B> as board::debug::Api>::exit(false);
+        #[cfg(feature = "wasm")]
+        self.stop_applet(id);
+        #[cfg(feature = "wasm")]
+        if !self.applets.is_alive() {
+            log::warn!("All applets stopped.");
+        }
+    }
+
+    /// Logs the reason and backtrace of the last trap of the current applet.
+    #[cfg(feature = "wasm")]
+    fn log_backtrace(&mut self) {
+        let Some(dump) = self.applets.current().store.core_dump() else { return };
+        log::error!("Trap reason: {}", log::Debug2Format(&dump.reason));
+        for (i, frame) in dump.frames.iter().enumerate() {
+            let name = frame.name.unwrap_or("<unknown>");
+            log::error!("  #{} {} (function {} at {:#x})", i, name, frame.func, frame.offset);
+        }
+    }
+
+    /// Returns the namespace of an applet in the store, if it can be allocated.
+    #[cfg(feature = "board-api-storage")]
+    fn namespace(&mut self, name: &str) -> Option<namespace::Namespace> {
+        match namespace::allocate(&mut self.store, name) {
+            Ok(x) => Some(x),
+            Err(error) => {
+                log::warn!("Failed to allocate a store namespace for {}: {}", name, error);
+                None
+            }
+        }
+    }
+
+    /// Stops the applet installed in a slot, if running.
+    #[cfg(all(
+        feature = "board-api-platform-protocol",
+        feature = "wasm",
+        feature = "board-api-storage"
+    ))]
+    fn stop_installed(&mut self, slot: usize) {
+        if let Some(id) = self.applets.installed(slot) {
+            log::debug!("Stopping installed applet {}.", slot);
+            self.stop_applet(id);
+        }
+    }
+
+    /// Stops an applet and releases its resources.
+    ///
+    /// Other applets are not impacted.
+    #[cfg(feature = "wasm")]
+    fn stop_applet(&mut self, id: applet::AppletId) {
+        // This drops the interpreter instance, handlers, pending events, and hash contexts.
+        drop(self.applets.remove(id));
+        #[cfg(feature = "board-api-timer")]
+        for (timer, slot) in self.timers.iter_mut().enumerate() {
+            if slot.as_ref().is_some_and(|x| x.applet == id) {
+                use wasefire_board_api::timer::Api as _;
+                *slot = None;
+                let _ = board::Timer::<B>::disarm(wasefire_board_api::Id::new(timer).unwrap());
+            }
+        }
+        // The slot may be reused by another applet, which must not inherit the tunnel.
+        #[cfg(feature = "board-api-platform-protocol")]
+        if matches!(self.protocol, protocol::State::Tunnel { applet_id, .. } if applet_id == id) {
+            self.protocol = protocol::State::Normal;
+        }
     }
-    <board::Debug<B> as board::debug::Api>::exit(false);
 }
 
 struct Trap;
@@ -472,9 +819,69 @@ impl From<Error> for Failure {
     }
 }
 
+/// Takes the storage of the platform store.
+///
+/// With the `store-health` feature, the storage is metered to report its wear. Its last pages are
+/// then used to persist the wear statistics and are not part of the store.
+#[cfg(feature = "board-api-storage")]
+fn storage<B: Board>() -> Storage<B> {
+    let storage = board::Storage::<B>::take().unwrap();
+    #[cfg(feature = "store-health")]
+    let storage = store::MeteredStorage::new(storage).unwrap();
+    storage
+}
+
 #[cfg(feature = "wasm")]
 const fn memory_size() -> usize {
-    let page = match option_env!("WASEFIRE_MEMORY_PAGE_COUNT") {
+    single_digit(option_env!("WASEFIRE_MEMORY_PAGE_COUNT"), 1) * 0x10000
+}
+
+#[cfg(feature = "wasm")]
+const fn applet_count() -> usize {
+    single_digit(option_env!("WASEFIRE_APPLET_COUNT"), 1)
+}
+
+/// Returns the number of instructions an applet may execute before being preempted.
+///
+/// The time slice is reset each time the scheduler calls into the applet. It is configured with
+/// `WASEFIRE_APPLET_FUEL` at compile time (0 disables preemption).
+#[cfg(feature = "wasm")]
+const fn applet_fuel() -> Option<usize> {
+    let fuel = match option_env!("WASEFIRE_APPLET_FUEL") {
+        Some(x) => decimal(x),
+        None => 1_000_000,
+    };
+    match fuel {
+        0 => None,
+        x => Some(x),
+    }
+}
+
+#[cfg(feature = "wasm")]
+const fn decimal_or(value: Option<&str>, default: usize) -> usize {
+    match value {
+        Some(x) => decimal(x),
+        None => default,
+    }
+}
+
+#[cfg(feature = "wasm")]
+const fn decimal(value: &str) -> usize {
+    let value = value.as_bytes();
+    assert!(!value.is_empty(), "not a decimal");
+    let mut result = 0;
+    let mut i = 0;
+    while i < value.len() {
+        assert!(value[i].is_ascii_digit(), "not a decimal");
+        result = result * 10 + (value[i] - b'0') as usize;
+        i += 1;
+    }
+    result
+}
+
+#[cfg(feature = "wasm")]
+const fn single_digit(value: Option<&str>, default: usize) -> usize {
+    match value {
         Some(x) => {
             let x = x.as_bytes();
             assert!(x.len() == 1, "not a single digit");
@@ -482,7 +889,6 @@ const fn memory_size() -> usize {
             assert!(x.is_ascii_digit(), "not a single digit");
             (x - b'0') as usize
         }
-        None => 1,
-    };
-    page * 0x10000
+        None => default,
+    }
 }
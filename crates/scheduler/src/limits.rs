@@ -0,0 +1,97 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resource limits of WASM applets.
+//!
+//! The value stack is limited to `WASEFIRE_APPLET_STACK_SIZE` bytes (default 65536), the call stack
+//! to `WASEFIRE_APPLET_FRAME_COUNT` frames (default 1000), and tables to
+//! `WASEFIRE_APPLET_TABLE_SIZE` elements (default 4096) at compile time. Memories are limited to
+//! the applet memory (see `WASEFIRE_MEMORY_PAGE_COUNT`).
+//!
+//! Those defaults may be overridden per applet name with `WASEFIRE_APPLET_LIMITS` at compile time.
+//! It is a comma-separated list of applet names, each followed by colon-separated limits among
+//! `stack`, `frames`, and `table`. For example, `foo:stack=4096:frames=100,bar:table=16` limits the
+//! value stack and call stack of `foo` and the tables of `bar`.
+
+use wasefire_interpreter::StoreLimits;
+use wasefire_logger as log;
+
+use crate::{decimal_or, memory_size};
+
+/// Per-applet resource limits.
+const LIMITS: &str = match option_env!("WASEFIRE_APPLET_LIMITS") {
+    Some(x) => x,
+    None => "",
+};
+
+/// Returns the resource limits of an applet given its name.
+pub fn applet_limits(name: &str) -> StoreLimits {
+    limits(LIMITS, name)
+}
+
+fn limits(table: &str, name: &str) -> StoreLimits {
+    const STACK_SIZE: usize = decimal_or(option_env!("WASEFIRE_APPLET_STACK_SIZE"), 0x10000);
+    const FRAME_COUNT: usize = decimal_or(option_env!("WASEFIRE_APPLET_FRAME_COUNT"), 1000);
+    const TABLE_SIZE: usize = decimal_or(option_env!("WASEFIRE_APPLET_TABLE_SIZE"), 4096);
+    let mut limits = StoreLimits {
+        max_stack_bytes: STACK_SIZE,
+        max_frames: FRAME_COUNT,
+        max_table_elems: TABLE_SIZE as u32,
+        max_mem_pages: (memory_size() / 0x10000) as u32,
+        ..Default::default()
+    };
+    for entry in table.split(',') {
+        let mut fields = entry.split(':');
+        if fields.next() != Some(name) {
+            continue;
+        }
+        for field in fields {
+            let limit = field.split_once('=').and_then(|(k, v)| Some((k, v.parse().ok()?)));
+            match limit {
+                Some(("stack", x)) => limits.max_stack_bytes = x,
+                Some(("frames", x)) => limits.max_frames = x,
+                Some(("table", x)) if x <= u32::MAX as usize => limits.max_table_elems = x as u32,
+                _ => log::error!("Invalid limit {} for applet {}.", field, name),
+            }
+        }
+    }
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_default() {
+        let expected = limits("", "foo");
+        assert_eq!(limits("bar:stack=10", "foo"), expected);
+        assert_eq!(limits("foobar:stack=10,fo:frames=10", "foo"), expected);
+        // Invalid limits are ignored.
+        assert_eq!(
+            limits("foo:stack,foo:size=1,foo:frames=-1,foo:table=99999999999", "foo"),
+            expected
+        );
+    }
+
+    #[test]
+    fn limits_override() {
+        let default = limits("", "foo");
+        let table = "bar:table=16,foo:stack=4096:frames=100";
+        let foo = StoreLimits { max_stack_bytes: 4096, max_frames: 100, ..default };
+        assert_eq!(limits(table, "foo"), foo);
+        assert_eq!(limits(table, "bar"), StoreLimits { max_table_elems: 16, ..default });
+        assert_eq!(limits(table, "baz"), default);
+    }
+}
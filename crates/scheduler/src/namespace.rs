@@ -0,0 +1,325 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Partition of the applet key space between applets.
+//!
+//! The keys smaller than `PLATFORM_KEY` are split in one namespace of `LEN` keys per applet slot.
+//! An applet owns the namespace of its name and only sees the keys of this namespace, which it
+//! addresses starting from zero. Ownership is persisted in owner entries using keys reserved to the
+//! platform, such that an upgraded applet (same name) keeps its data. Entries written before
+//! namespaces existed are moved to the first namespace and given to its applet on boot (see
+//! [`LEGACY`] and [`reserve`](crate::call::store::reserve)).
+//!
+//! A namespace is owned if and only if its owner entry is present. Its entries are removed before
+//! its owner entry, and a free namespace is cleared before its owner entry is inserted, such that
+//! an applet never sees the entries of another. Namespaces are only released when their applet is
+//! uninstalled, in particular an applet missing on boot keeps its data.
+
+// Namespaces are only allocated for WASM applets and released by the platform protocol.
+#![cfg_attr(not(all(feature = "wasm", feature = "board-api-platform-protocol")), allow(dead_code))]
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use wasefire_error::{Code, Error};
+use wasefire_store::{Storage, Store};
+
+use crate::call::store::{convert, PLATFORM_KEY};
+
+/// Number of namespaces.
+#[cfg(feature = "wasm")]
+const COUNT: usize = crate::applet_count();
+#[cfg(not(feature = "wasm"))]
+const COUNT: usize = 1;
+
+/// Number of keys of a namespace.
+pub const LEN: usize = PLATFORM_KEY / COUNT;
+
+/// First key of the owner entries (one per namespace).
+///
+/// The owner entry of a namespace contains the name of its applet. The keys before are used by
+/// the metadata entries of installed applets.
+const OWNER_KEY: usize = PLATFORM_KEY + 32;
+
+/// Namespace and owner of the entries written before namespaces existed.
+///
+/// Those entries were written by the only applet of the platform, which is named like the applet of
+/// [`Scheduler::run()`](crate::Scheduler).
+pub const LEGACY: (Namespace, &str) = (Namespace(0), "applet");
+
+// The applet count is a single digit.
+const _: () = assert!(COUNT <= 9);
+
+/// Namespace of an applet in the store.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Namespace(usize);
+
+impl Namespace {
+    /// Returns the store keys of the namespace.
+    pub fn keys(self) -> Range<usize> {
+        self.0 * LEN .. (self.0 + 1) * LEN
+    }
+
+    /// Returns the key of the owner entry.
+    pub fn owner_key(self) -> usize {
+        OWNER_KEY + self.0
+    }
+
+    /// Maps applet keys to store keys.
+    ///
+    /// Returns an error if the applet keys are outside the namespace.
+    pub fn map(self, keys: Range<usize>) -> Result<Range<usize>, Error> {
+        if LEN < keys.end {
+            return Err(Error::user(Code::InvalidArgument));
+        }
+        let start = self.keys().start;
+        Ok(start + keys.start .. start + keys.end)
+    }
+
+    /// Maps a store key to an applet key, if in the namespace.
+    pub fn unmap(self, key: usize) -> Option<usize> {
+        let keys = self.keys();
+        keys.contains(&key).then(|| key - keys.start)
+    }
+
+    /// Returns the capacity used by the namespace and its quota, in words.
+    ///
+    /// The quota is an even share of the store capacity.
+    pub fn capacity(self, store: &Store<impl Storage>) -> Result<(usize, usize), Error> {
+        Ok((self.used(store, |_| false)?, quota(store)?))
+    }
+
+    /// Checks that replacing the entries of some store keys fits in the quota of the namespace.
+    ///
+    /// The capacity of the new entries is given in words.
+    pub fn check_quota(
+        self, store: &Store<impl Storage>, keys: impl Fn(usize) -> bool, capacity: usize,
+    ) -> Result<(), Error> {
+        match self.used(store, keys)? + capacity <= quota(store)? {
+            true => Ok(()),
+            false => Err(Error::world(Code::NotEnough)),
+        }
+    }
+
+    /// Returns the capacity used by the entries of the namespace, except some store keys.
+    fn used(
+        self, store: &Store<impl Storage>, except: impl Fn(usize) -> bool,
+    ) -> Result<usize, Error> {
+        let mut used = 0;
+        for handle in store.iter().map_err(convert)? {
+            let handle = handle.map_err(convert)?;
+            let key = handle.get_key();
+            if self.keys().contains(&key) && !except(key) {
+                used += store.entry_capacity(handle.get_length(store).map_err(convert)?);
+            }
+        }
+        Ok(used)
+    }
+}
+
+/// Returns the namespace of an applet, allocating a free one if needed.
+pub fn allocate(store: &mut Store<impl Storage>, name: &str) -> Result<Namespace, Error> {
+    let owners = owners(store)?;
+    if let Some(x) = owners.iter().position(|x| x.as_deref() == Some(name.as_bytes())) {
+        return Ok(Namespace(x));
+    }
+    let x = owners.iter().position(|x| x.is_none()).ok_or(Error::world(Code::NotEnough))?;
+    let namespace = Namespace(x);
+    // Free namespaces should be empty, but we don't want to leak entries if they aren't.
+    clear(store, namespace)?;
+    store.insert(namespace.owner_key(), name.as_bytes()).map_err(convert)?;
+    Ok(namespace)
+}
+
+/// Removes the entries of an applet and frees its namespace, if any.
+pub fn release(store: &mut Store<impl Storage>, name: &str) -> Result<(), Error> {
+    let owners = owners(store)?;
+    match owners.iter().position(|x| x.as_deref() == Some(name.as_bytes())) {
+        Some(x) => free(store, Namespace(x)),
+        None => Ok(()),
+    }
+}
+
+/// Removes the entries of a namespace.
+pub fn clear(store: &mut Store<impl Storage>, namespace: Namespace) -> Result<(), Error> {
+    // We remove the entries one by one because transactions have a limited number of updates.
+    let mut keys = Vec::new();
+    for handle in store.iter().map_err(convert)? {
+        let key = handle.map_err(convert)?.get_key();
+        if namespace.keys().contains(&key) {
+            keys.push(key);
+        }
+    }
+    for key in keys {
+        store.remove(key).map_err(convert)?;
+    }
+    Ok(())
+}
+
+fn quota(store: &Store<impl Storage>) -> Result<usize, Error> {
+    Ok(store.capacity().map_err(convert)?.total() / COUNT)
+}
+
+fn free(store: &mut Store<impl Storage>, namespace: Namespace) -> Result<(), Error> {
+    clear(store, namespace)?;
+    store.remove(namespace.owner_key()).map_err(convert)
+}
+
+/// Returns the owner of each namespace.
+fn owners(store: &Store<impl Storage>) -> Result<Vec<Option<Vec<u8>>>, Error> {
+    let mut owners = alloc::vec![None; COUNT];
+    for handle in store.iter().map_err(convert)? {
+        let handle = handle.map_err(convert)?;
+        if let Some(x) = handle.get_key().checked_sub(OWNER_KEY).filter(|&x| x < COUNT) {
+            owners[x] = Some(handle.get_value(store).map_err(convert)?);
+        }
+    }
+    Ok(owners)
+}
+
+#[cfg(all(test, feature = "board-api-storage"))]
+mod tests {
+    use super::*;
+    use crate::call::store::tests::new_store;
+    use crate::call::store::{reserve, FORMAT_KEY};
+
+    #[test]
+    fn map_rejects_keys_outside_namespace() {
+        let namespace = Namespace(COUNT - 1);
+        let start = namespace.keys().start;
+        assert_eq!(namespace.map(0 .. LEN), Ok(start .. start + LEN));
+        assert_eq!(namespace.map(LEN - 1 .. LEN), Ok(start + LEN - 1 .. start + LEN));
+        assert_eq!(namespace.map(0 .. LEN + 1), Err(Error::user(Code::InvalidArgument)));
+        assert_eq!(namespace.map(LEN .. LEN + 1), Err(Error::user(Code::InvalidArgument)));
+        assert_eq!(namespace.unmap(start + LEN - 1), Some(LEN - 1));
+        assert_eq!(namespace.unmap(start + LEN), None);
+    }
+
+    #[test]
+    fn allocate_disjoint_namespaces() {
+        let mut store = new_store();
+        let foo = allocate(&mut store, "foo").unwrap();
+        assert_eq!(allocate(&mut store, "foo"), Ok(foo));
+        if COUNT == 1 {
+            assert_eq!(allocate(&mut store, "bar"), Err(Error::world(Code::NotEnough)));
+            return;
+        }
+        let bar = allocate(&mut store, "bar").unwrap();
+        assert_ne!(foo, bar);
+        let (foo, bar) = (foo.keys(), bar.keys());
+        assert!(foo.end <= bar.start || bar.end <= foo.start);
+        assert!(foo.end <= PLATFORM_KEY && bar.end <= PLATFORM_KEY);
+    }
+
+    #[test]
+    fn release_removes_own_entries() {
+        let mut store = new_store();
+        let foo = allocate(&mut store, "foo").unwrap();
+        let bar = (1 < COUNT).then(|| allocate(&mut store, "bar").unwrap());
+        for namespace in [Some(foo), bar].into_iter().flatten() {
+            for key in namespace.map(0 .. 3).unwrap() {
+                store.insert(key, b"data").unwrap();
+            }
+        }
+        store.insert(PLATFORM_KEY, b"platform").unwrap();
+        release(&mut store, "foo").unwrap();
+        for key in foo.map(0 .. 3).unwrap() {
+            assert_eq!(store.find(key).unwrap(), None);
+        }
+        if let Some(bar) = bar {
+            for key in bar.map(0 .. 3).unwrap() {
+                assert_eq!(store.find(key).unwrap().as_deref(), Some(&b"data"[..]));
+            }
+            assert_eq!(allocate(&mut store, "bar"), Ok(bar));
+        }
+        assert_eq!(store.find(PLATFORM_KEY).unwrap().as_deref(), Some(&b"platform"[..]));
+        assert_eq!(owners(&store).unwrap().iter().flatten().count(), bar.iter().count());
+        // Releasing an applet without namespace does nothing.
+        release(&mut store, "foo").unwrap();
+    }
+
+    #[test]
+    fn owners_persist_across_reboot() {
+        let mut store = new_store();
+        let names = ["foo", "bar"];
+        let namespaces: Vec<_> =
+            names[.. COUNT.min(2)].iter().map(|name| allocate(&mut store, name).unwrap()).collect();
+        let mut store = Store::new(store.extract_storage()).ok().unwrap();
+        // Allocate in reverse order to check that ownership doesn't depend on it.
+        for (name, namespace) in names.iter().zip(namespaces).rev() {
+            assert_eq!(allocate(&mut store, name), Ok(namespace));
+        }
+    }
+
+    #[test]
+    fn allocate_clears_free_namespace() {
+        let mut store = new_store();
+        let namespace = Namespace(COUNT - 1);
+        store.insert(namespace.keys().start, b"stale").unwrap();
+        for i in 0 .. COUNT {
+            assert_eq!(allocate(&mut store, &alloc::format!("applet{i}")), Ok(Namespace(i)));
+        }
+        assert_eq!(namespace.capacity(&store).unwrap().0, 0);
+    }
+
+    #[test]
+    fn legacy_entries_stay_with_their_applet() {
+        let mut store = new_store();
+        let mut keys =
+            alloc::vec![0, LEN - 1, LEN, PLATFORM_KEY - 1, PLATFORM_KEY, OWNER_KEY, FORMAT_KEY];
+        // The namespace and platform keys are the same with a single namespace.
+        keys.sort();
+        keys.dedup();
+        for &key in &keys {
+            store.insert(key, b"legacy").unwrap();
+        }
+        reserve(&mut store).unwrap();
+        let (legacy, owner) = LEGACY;
+        assert_eq!(allocate(&mut store, owner), Ok(legacy));
+        assert_eq!(legacy.capacity(&store).unwrap().0, keys.len() * store.entry_capacity(6));
+        let foo = allocate(&mut store, "foo");
+        if COUNT == 1 {
+            assert_eq!(foo, Err(Error::world(Code::NotEnough)));
+            return;
+        }
+        let foo = foo.unwrap();
+        assert_eq!(foo.capacity(&store).unwrap().0, 0);
+    }
+
+    #[test]
+    fn check_quota_counts_namespace_entries() {
+        let mut store = new_store();
+        let foo = allocate(&mut store, "foo").unwrap();
+        let quota = quota(&store).unwrap();
+        assert_eq!(foo.capacity(&store), Ok((0, quota)));
+        assert_eq!(foo.check_quota(&store, |_| false, quota), Ok(()));
+        assert_eq!(
+            foo.check_quota(&store, |_| false, quota + 1),
+            Err(Error::world(Code::NotEnough))
+        );
+        let key = foo.map(0 .. 1).unwrap().start;
+        store.insert(key, b"data").unwrap();
+        // Platform entries don't count in the quota.
+        store.insert(PLATFORM_KEY, b"platform").unwrap();
+        let used = store.entry_capacity(4);
+        assert_eq!(foo.capacity(&store), Ok((used, quota)));
+        assert_eq!(foo.check_quota(&store, |_| false, quota - used), Ok(()));
+        assert_eq!(
+            foo.check_quota(&store, |_| false, quota - used + 1),
+            Err(Error::world(Code::NotEnough))
+        );
+        // Replaced entries don't count either.
+        assert_eq!(foo.check_quota(&store, |x| x == key, quota), Ok(()));
+    }
+}
@@ -13,13 +13,20 @@
 // limitations under the License.
 
 use alloc::boxed::Box;
+#[cfg(feature = "wasm")]
+use alloc::string::String;
+#[cfg(feature = "wasm")]
+use alloc::vec::Vec;
 
 use wasefire_board_api::platform::protocol::Api as _;
 use wasefire_board_api::{self as board, Api as Board};
 use wasefire_error::{Code, Error};
 use wasefire_logger as log;
+#[cfg(feature = "wasm")]
+use wasefire_protocol::applet::Rejection;
 use wasefire_protocol::{self as service, Api, ApiResult, Request, Service, VERSION};
 
+use crate::applet::{Applet, AppletId};
 use crate::Scheduler;
 
 #[derive(Debug, Default)]
@@ -27,11 +34,83 @@ pub enum State {
     #[default]
     Normal,
     Tunnel {
-        applet_id: service::applet::AppletId,
+        applet_id: AppletId,
         delimiter: Box<[u8]>,
     },
 }
 
+/// Applet installation state.
+#[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+#[derive(Default)]
+pub struct Install {
+    /// Installation in progress, if any.
+    session: Option<Session>,
+
+    /// Installation changes to apply to the running applets.
+    ///
+    /// Those changes are applied by the scheduler outside of applet execution.
+    pub pending: Vec<Pending>,
+}
+
+#[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+struct Session {
+    name: String,
+    size: usize,
+    module: Vec<u8>,
+}
+
+#[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+pub enum Pending {
+    /// Starts the applet installed in this slot (stopping the previous one if running).
+    Start(usize),
+
+    /// Stops the applet installed in this slot if running.
+    Stop(usize),
+}
+
+/// Platform update state.
+#[cfg(feature = "board-api-platform-update")]
+#[derive(Default)]
+pub struct Update {
+    /// Update in progress, if any.
+    session: Option<UpdateSession>,
+}
+
+#[cfg(feature = "board-api-platform-update")]
+#[derive(Default)]
+struct UpdateSession {
+    /// Offset of the last processed chunk.
+    previous: usize,
+
+    /// Number of processed bytes.
+    offset: usize,
+}
+
+/// Reasons why applets were last rejected, by applet name.
+#[cfg(feature = "wasm")]
+#[derive(Default)]
+pub struct Rejections(Vec<(String, Rejection)>);
+
+#[cfg(feature = "wasm")]
+impl Rejections {
+    /// Maximum number of remembered rejections.
+    const MAX: usize = 8;
+
+    /// Records (or clears) why an applet was rejected.
+    pub fn set(&mut self, name: &str, reason: Option<Rejection>) {
+        self.0.retain(|(x, _)| x != name);
+        let Some(reason) = reason else { return };
+        if self.0.len() == Self::MAX {
+            self.0.remove(0);
+        }
+        self.0.push((name.into(), reason));
+    }
+
+    fn get(&self, name: &str) -> Option<Rejection> {
+        self.0.iter().find(|(x, _)| x == name).map(|(_, x)| *x)
+    }
+}
+
 pub fn enable<B: Board>() {
     if let Err(error) = board::platform::Protocol::<B>::enable() {
         log::warn!("Failed to enable platform protocol: {}", error);
@@ -55,8 +134,9 @@ pub fn process_event<B: Board>(scheduler: &mut Scheduler<B>, event: board::Event
                 scheduler.protocol = State::Normal;
                 return reply::<B, service::AppletTunnel>(());
             }
-            let service::applet::AppletId = applet_id;
-            match scheduler.applet.put_request(event, &request) {
+            let applet_id = *applet_id;
+            let applet = scheduler.applets.get_mut(applet_id).ok_or(Error::world(Code::NotFound));
+            match applet.and_then(|x| x.put_request(event, &request)) {
                 Ok(()) => (),
                 Err(error) => {
                     log::warn!("Failed to put platform protocol request: {}", error);
@@ -76,21 +156,20 @@ pub fn process_event<B: Board>(scheduler: &mut Scheduler<B>, event: board::Event
     match request {
         Api::ApiVersion(()) => reply::<B, service::ApiVersion>(VERSION),
         Api::AppletRequest(service::applet::Request { applet_id, request }) => {
-            let service::applet::AppletId = applet_id;
-            match scheduler.applet.put_request(event, request) {
-                Ok(()) => reply::<B, service::AppletRequest>(()),
-                Err(e) => reply_error::<B>(e),
-            }
+            let id = find(scheduler, applet_id);
+            put_request::<B, service::AppletRequest>(scheduler, id, event, request)
+        }
+        Api::LegacyAppletRequest(service::applet::legacy::Request { applet_id: _, request }) => {
+            let id = default(scheduler);
+            put_request::<B, service::LegacyAppletRequest>(scheduler, id, event, request)
         }
         Api::AppletResponse(applet_id) => {
-            let service::applet::AppletId = applet_id;
-            match scheduler.applet.get_response() {
-                Ok(response) => {
-                    let response = response.as_deref();
-                    reply::<B, service::AppletResponse>(service::applet::Response { response })
-                }
-                Err(e) => reply_error::<B>(e),
-            }
+            let id = find(scheduler, applet_id);
+            get_response::<B, service::AppletResponse>(scheduler, id)
+        }
+        Api::LegacyAppletResponse(service::applet::legacy::AppletId) => {
+            let id = default(scheduler);
+            get_response::<B, service::LegacyAppletResponse>(scheduler, id)
         }
         #[cfg(feature = "board-api-platform")]
         Api::PlatformReboot(()) => {
@@ -101,14 +180,12 @@ pub fn process_event<B: Board>(scheduler: &mut Scheduler<B>, event: board::Event
             }
         }
         Api::AppletTunnel(service::applet::Tunnel { applet_id, delimiter }) => {
-            match scheduler.protocol {
-                State::Normal => {
-                    let delimiter = delimiter.to_vec().into_boxed_slice();
-                    scheduler.protocol = State::Tunnel { applet_id, delimiter };
-                    reply::<B, service::AppletTunnel>(())
-                }
-                State::Tunnel { .. } => unreachable!(),
-            }
+            let id = find(scheduler, applet_id);
+            tunnel::<B, service::AppletTunnel>(scheduler, id, delimiter)
+        }
+        Api::LegacyAppletTunnel(service::applet::legacy::Tunnel { applet_id: _, delimiter }) => {
+            let id = default(scheduler);
+            tunnel::<B, service::LegacyAppletTunnel>(scheduler, id, delimiter)
         }
         #[cfg(feature = "board-api-platform")]
         Api::PlatformInfo(()) => {
@@ -125,6 +202,53 @@ pub fn process_event<B: Board>(scheduler: &mut Scheduler<B>, event: board::Event
                 Err(error) => reply_error::<B>(error),
             }
         }
+        #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+        Api::AppletInstall(request) => match install(scheduler, request) {
+            Ok(()) => reply::<B, service::AppletInstall>(()),
+            Err(error) => reply_error::<B>(error),
+        },
+        #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+        Api::AppletList(()) => match crate::install::list(&scheduler.store) {
+            Ok(installed) => {
+                let list = installed
+                    .iter()
+                    .map(|(slot, metadata)| service::applet::Metadata {
+                        name: &metadata.name,
+                        size: metadata.size,
+                        running: scheduler.applets.installed(*slot).is_some(),
+                    })
+                    .collect();
+                reply::<B, service::AppletList>(list)
+            }
+            Err(error) => reply_error::<B>(error),
+        },
+        #[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+        Api::AppletUninstall(name) => match uninstall(scheduler, name) {
+            Ok(()) => reply::<B, service::AppletUninstall>(()),
+            Err(error) => reply_error::<B>(error),
+        },
+        #[cfg(feature = "board-api-platform-update")]
+        Api::PlatformUpdateMetadata(()) => {
+            use wasefire_board_api::platform::update::Api as _;
+            match board::platform::Update::<B>::metadata() {
+                Ok(metadata) => reply::<B, service::PlatformUpdateMetadata>(&metadata),
+                Err(error) => reply_error::<B>(error),
+            }
+        }
+        #[cfg(feature = "board-api-platform-update")]
+        Api::PlatformUpdate(request) => match update(scheduler, request) {
+            Ok(()) => reply::<B, service::PlatformUpdate>(()),
+            Err(error) => reply_error::<B>(error),
+        },
+        #[cfg(feature = "wasm")]
+        Api::AppletRejection(name) => {
+            reply::<B, service::AppletRejection>(scheduler.rejections.get(name))
+        }
+        #[cfg(feature = "store-health")]
+        Api::PlatformStoreHealth(()) => match store_health(scheduler) {
+            Ok(health) => reply::<B, service::PlatformStoreHealth>(health),
+            Err(error) => reply_error::<B>(error),
+        },
         #[cfg(not(feature = "_test"))]
         _ => reply_error::<B>(Error::internal(Code::NotImplemented)),
     }
@@ -133,28 +257,208 @@ pub fn process_event<B: Board>(scheduler: &mut Scheduler<B>, event: board::Event
 pub fn put_response<B: Board>(
     scheduler: &mut Scheduler<B>, response: Box<[u8]>,
 ) -> Result<(), Error> {
-    match scheduler.applet.put_response(response) {
+    let current = scheduler.applets.current_id();
+    let applet = scheduler.applets.current();
+    match applet.put_response(response) {
         Ok(()) => (),
         Err(error) if error == Error::world(Code::InvalidState) => {
             // The response was discarded because there is a new request. Send the event.
-            scheduler.applet.push(board::platform::protocol::Event.into());
+            applet.push(board::platform::protocol::Event.into());
             return Ok(());
         }
         Err(error) => return Err(error),
     }
     match &scheduler.protocol {
         State::Normal => Ok(()),
-        State::Tunnel { applet_id, .. } => {
-            let service::applet::AppletId = applet_id;
-            match scheduler.applet.get_response()? {
-                Some(response) => board::platform::Protocol::<B>::write(&response),
-                None => {
-                    log::error!("Failed to read response back.");
-                    Err(Error::internal(Code::InvalidState))
-                }
+        // Only the tunnel applet writes its responses directly.
+        State::Tunnel { applet_id, .. } if *applet_id != current => Ok(()),
+        State::Tunnel { .. } => match applet.get_response()? {
+            Some(response) => board::platform::Protocol::<B>::write(&response),
+            None => {
+                log::error!("Failed to read response back.");
+                Err(Error::internal(Code::InvalidState))
+            }
+        },
+    }
+}
+
+#[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+fn install<B: Board>(
+    scheduler: &mut Scheduler<B>, request: service::applet::Install,
+) -> Result<(), Error> {
+    use crate::install::{self, Metadata};
+    let state = &mut scheduler.install;
+    match request {
+        service::applet::Install::Start { name, size } => {
+            let size = size as usize;
+            if name.is_empty() || install::max_size(&scheduler.store) < size {
+                return Err(Error::user(Code::InvalidArgument));
             }
+            // The module is buffered because it must be validated before being installed. We don't
+            // abort when the heap is too small, since the size is chosen by the host.
+            state.session = None;
+            let mut module = Vec::new();
+            if module.try_reserve_exact(size).is_err() {
+                return Err(Error::world(Code::NotEnough));
+            }
+            state.session = Some(Session { name: name.into(), size, module });
+        }
+        service::applet::Install::Write { chunk } => {
+            let session = state.session.as_mut().ok_or(Error::user(Code::InvalidState))?;
+            if session.size - session.module.len() < chunk.len() {
+                return Err(Error::user(Code::InvalidLength));
+            }
+            session.module.extend_from_slice(chunk);
+        }
+        service::applet::Install::Finish => {
+            let Session { name, size, module } =
+                state.session.take().ok_or(Error::user(Code::InvalidState))?;
+            if module.len() != size {
+                return Err(Error::user(Code::InvalidLength));
+            }
+            #[cfg(feature = "applet-signature")]
+            if let Err(reason) = crate::signature::verify::<B>(&module) {
+                log::warn!("Rejecting applet {}: {}", name.as_str(), log::Debug2Format(&reason));
+                scheduler.rejections.set(&name, Some(reason));
+                return Err(Error::user(Code::NoPermission));
+            }
+            if let Err(error) = wasefire_interpreter::Module::new(&module) {
+                log::warn!("Rejecting invalid applet: {}", log::Debug2Format(&error));
+                scheduler.rejections.set(&name, Some(Rejection::Invalid));
+                return Err(Error::user(Code::InvalidArgument));
+            }
+            scheduler.rejections.set(&name, None);
+            let slot = install::allocate(&scheduler.store, &name)?;
+            let metadata = Metadata { name, size: size as u32 };
+            install::write(&mut scheduler.store, slot, &metadata, &module)?;
+            state.pending.push(Pending::Start(slot));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "wasm", feature = "board-api-storage"))]
+fn uninstall<B: Board>(scheduler: &mut Scheduler<B>, name: &str) -> Result<(), Error> {
+    let slot = crate::install::find(&scheduler.store, name)?;
+    let slot = slot.ok_or(Error::user(Code::NotFound))?;
+    crate::install::delete(&mut scheduler.store, slot)?;
+    // The applet may still run until stopped, so it must not access its namespace anymore.
+    if let Some(id) = scheduler.applets.installed(slot) {
+        scheduler.applets.get_mut(id).unwrap().namespace = None;
+    }
+    crate::namespace::release(&mut scheduler.store, name)?;
+    scheduler.install.pending.push(Pending::Stop(slot));
+    Ok(())
+}
+
+#[cfg(feature = "board-api-platform-update")]
+fn update<B: Board>(
+    scheduler: &mut Scheduler<B>, request: service::platform::Update,
+) -> Result<(), Error> {
+    use wasefire_board_api::platform::update::Api as _;
+    let state = &mut scheduler.update;
+    match request {
+        service::platform::Update::Initialize { dry_run } => {
+            state.session = None;
+            board::platform::Update::<B>::initialize(dry_run)?;
+            state.session = Some(UpdateSession::default());
+        }
+        service::platform::Update::Process { offset, chunk } => {
+            let session = state.session.as_mut().ok_or(Error::user(Code::InvalidState))?;
+            let offset = offset as usize;
+            if offset == session.previous && offset + chunk.len() == session.offset {
+                // The host did not receive the response and resent the chunk.
+                return Ok(());
+            }
+            if offset != session.offset {
+                return Err(Error::user(Code::InvalidArgument));
+            }
+            if let Err(error) = board::platform::Update::<B>::process(chunk) {
+                state.session = None;
+                return Err(error);
+            }
+            session.previous = offset;
+            session.offset += chunk.len();
+        }
+        service::platform::Update::Finalize => {
+            state.session.take().ok_or(Error::user(Code::InvalidState))?;
+            board::platform::Update::<B>::finalize()?;
         }
     }
+    Ok(())
+}
+
+#[cfg(feature = "store-health")]
+fn store_health<B: Board>(
+    scheduler: &Scheduler<B>,
+) -> Result<service::platform::StoreHealth, Error> {
+    use crate::call::store::convert;
+    let health = scheduler.store.health().map_err(convert)?;
+    let ratio = |x: wasefire_store::StoreRatio| service::platform::Ratio {
+        used: x.used() as u32,
+        total: x.total() as u32,
+    };
+    Ok(service::platform::StoreHealth {
+        capacity: ratio(health.capacity),
+        lifetime: ratio(health.lifetime),
+        remaining_erases: health.remaining_erases as u32,
+        wear: health.wear.iter().map(|&(bucket, count)| (bucket as u32, count as u32)).collect(),
+    })
+}
+
+/// Returns the running applet with a given name.
+fn find<B: Board>(
+    scheduler: &Scheduler<B>, applet_id: service::applet::AppletId,
+) -> Result<AppletId, Error> {
+    scheduler.applets.find(applet_id.name).ok_or(Error::user(Code::NotFound))
+}
+
+/// Returns the default applet (for the legacy services).
+fn default<B: Board>(scheduler: &Scheduler<B>) -> Result<AppletId, Error> {
+    scheduler.applets.default_id().ok_or(Error::user(Code::NotFound))
+}
+
+fn put_request<B: Board, T: for<'a> Service<Response<'a> = ()>>(
+    scheduler: &mut Scheduler<B>, id: Result<AppletId, Error>, event: board::Event<B>,
+    request: &[u8],
+) {
+    match id.and_then(|id| applet(scheduler, id).put_request(event, request)) {
+        Ok(()) => reply::<B, T>(()),
+        Err(e) => reply_error::<B>(e),
+    }
+}
+
+fn get_response<B: Board, T: for<'a> Service<Response<'a> = service::applet::Response<'a>>>(
+    scheduler: &mut Scheduler<B>, id: Result<AppletId, Error>,
+) {
+    match id.and_then(|id| applet(scheduler, id).get_response()) {
+        Ok(response) => {
+            let response = response.as_deref();
+            reply::<B, T>(service::applet::Response { response })
+        }
+        Err(e) => reply_error::<B>(e),
+    }
+}
+
+fn tunnel<B: Board, T: for<'a> Service<Response<'a> = ()>>(
+    scheduler: &mut Scheduler<B>, id: Result<AppletId, Error>, delimiter: &[u8],
+) {
+    match scheduler.protocol {
+        State::Normal => {
+            let applet_id = match id {
+                Ok(x) => x,
+                Err(error) => return reply_error::<B>(error),
+            };
+            let delimiter = delimiter.to_vec().into_boxed_slice();
+            scheduler.protocol = State::Tunnel { applet_id, delimiter };
+            reply::<B, T>(())
+        }
+        State::Tunnel { .. } => unreachable!(),
+    }
+}
+
+fn applet<B: Board>(scheduler: &mut Scheduler<B>, id: AppletId) -> &mut Applet<B> {
+    scheduler.applets.get_mut(id).unwrap()
 }
 
 fn reply<B: Board, T: Service>(response: T::Response<'_>) {
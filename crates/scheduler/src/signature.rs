@@ -0,0 +1,226 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Applet signatures.
+//!
+//! An applet is signed by appending a custom section named `wasefire-signature` to its module. The
+//! section content is a P-256 ECDSA signature (`r` then `s`, big-endian) of the SHA-256 digest of
+//! the module before the section. Applets are only loaded if signed by a key of the trust list.
+//!
+//! The trust list is provisioned at compile time with `WASEFIRE_APPLET_KEYS`, a comma-separated
+//! list of public keys (hexadecimal encoding of the affine coordinates x then y).
+
+use digest::OutputSizeUser;
+use generic_array::GenericArray;
+use typenum::U32;
+use wasefire_board_api::crypto::{ecc, Hash};
+use wasefire_board_api::{self as board, Api as Board};
+use wasefire_interpreter::custom_sections;
+use wasefire_logger as log;
+use wasefire_protocol::applet::Rejection;
+
+/// Name of the signature section.
+// Keep in sync with the applet-sign command of the CLI.
+const SECTION: &str = "wasefire-signature";
+
+/// Public keys trusted to sign applets.
+const TRUSTED: &str = match option_env!("WASEFIRE_APPLET_KEYS") {
+    Some(x) => x,
+    None => "",
+};
+
+/// Verifies the signature of an applet.
+pub fn verify<B: Board>(wasm: &[u8]) -> Result<(), Rejection> {
+    verify_with::<board::crypto::Sha256<B>, board::crypto::P256<B>>(wasm, TRUSTED)
+}
+
+/// Verifies the signature of an applet against a trust list.
+fn verify_with<H, E>(wasm: &[u8], trusted: &str) -> Result<(), Rejection>
+where
+    H: Hash + OutputSizeUser<OutputSize = U32>,
+    E: ecc::Api<U32>,
+{
+    let (content, signature) = split(wasm)?;
+    let digest = H::with_error(|| {
+        let mut hash = H::default();
+        hash.update(content);
+        hash.finalize_fixed_reset()
+    });
+    let digest = digest.map_err(|_| Rejection::Untrusted)?;
+    let (r, s) = signature.split_at(32);
+    let (r, s) = (GenericArray::from_slice(r), GenericArray::from_slice(s));
+    for key in trusted.split(',').filter(|x| !x.is_empty()) {
+        let Some(key) = decode_key(key) else {
+            log::error!("Invalid key in the applet trust list.");
+            continue;
+        };
+        let (x, y) = key.split_at(32);
+        let (x, y) = (GenericArray::from_slice(x), GenericArray::from_slice(y));
+        if E::ecdsa_verify(&digest, x, y, r, s) == Ok(true) {
+            return Ok(());
+        }
+    }
+    Err(Rejection::Untrusted)
+}
+
+/// Splits a module between its signed content and its signature.
+///
+/// The signature section must be the last section.
+fn split(wasm: &[u8]) -> Result<(&[u8], &[u8]), Rejection> {
+    let sections = custom_sections(wasm).map_err(|_| Rejection::Invalid)?;
+    let section = sections.into_iter().find(|x| x.name == SECTION).ok_or(Rejection::Unsigned)?;
+    if section.range.end != wasm.len() || section.content.len() != 64 {
+        return Err(Rejection::Malformed);
+    }
+    Ok((&wasm[.. section.range.start], section.content))
+}
+
+fn decode_key(hex: &str) -> Option<[u8; 64]> {
+    let hex = hex.as_bytes();
+    // We check the digits because `from_str_radix()` accepts a sign.
+    if hex.len() != 128 || !hex.iter().all(|x| x.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut key = [0; 64];
+    for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+        let digits = core::str::from_utf8(digits).ok()?;
+        *byte = u8::from_str_radix(digits, 16).ok()?;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use p256::ecdsa::SigningKey;
+    use wasefire_board_api::crypto::SoftwareSha256;
+    use wasefire_interpreter::push_custom_section;
+
+    use super::*;
+
+    type P256 = ecc::Software<p256::NistP256, SoftwareSha256>;
+
+    /// Module with an empty function: `(module (func))`.
+    const MODULE: &[u8] =
+        b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\x03\x02\x01\0\x0a\x04\x01\x02\0\x0b";
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    /// Returns the trust list entry of a signing key.
+    fn trusted(key: &SigningKey) -> String {
+        let point = key.verifying_key().to_encoded_point(false);
+        format!("{:x}{:x}", point.x().unwrap(), point.y().unwrap())
+    }
+
+    fn sign(key: &SigningKey) -> Vec<u8> {
+        wasefire_cli_tools::action::sign_applet(MODULE.to_vec(), key).unwrap()
+    }
+
+    fn verify(wasm: &[u8], trusted: &str) -> Result<(), Rejection> {
+        verify_with::<SoftwareSha256, P256>(wasm, trusted)
+    }
+
+    #[test]
+    fn split_signed_module() {
+        let mut wasm = MODULE.to_vec();
+        push_custom_section(&mut wasm, SECTION, &[7; 64]).unwrap();
+        assert_eq!(split(&wasm), Ok((MODULE, &[7; 64][..])));
+    }
+
+    #[test]
+    fn split_rejects_malformed_modules() {
+        assert_eq!(split(&MODULE[.. 7]), Err(Rejection::Invalid));
+        assert_eq!(split(&MODULE[.. MODULE.len() - 1]), Err(Rejection::Invalid));
+        assert_eq!(split(b"\0asm\x02\0\0\0"), Err(Rejection::Invalid));
+        let mut wasm = MODULE.to_vec();
+        push_custom_section(&mut wasm, SECTION, &[7; 64]).unwrap();
+        // Truncated section.
+        assert_eq!(split(&wasm[.. wasm.len() - 1]), Err(Rejection::Invalid));
+        // Truncated section size.
+        assert_eq!(split(&[MODULE, b"\0\x80"].concat()), Err(Rejection::Invalid));
+        // Overlong section size.
+        let overlong = b"\0\x80\x80\x80\x80\x80\x00";
+        assert_eq!(split(&[MODULE, overlong].concat()), Err(Rejection::Invalid));
+        // Section size overflowing 32 bits.
+        assert_eq!(split(&[MODULE, b"\0\xff\xff\xff\xff\x7f"].concat()), Err(Rejection::Invalid));
+    }
+
+    #[test]
+    fn split_rejects_misplaced_signatures() {
+        assert_eq!(split(MODULE), Err(Rejection::Unsigned));
+        let mut wasm = MODULE.to_vec();
+        push_custom_section(&mut wasm, "other", &[7; 64]).unwrap();
+        assert_eq!(split(&wasm), Err(Rejection::Unsigned));
+        push_custom_section(&mut wasm, SECTION, &[7; 63]).unwrap();
+        assert_eq!(split(&wasm), Err(Rejection::Malformed));
+        let mut wasm = MODULE.to_vec();
+        push_custom_section(&mut wasm, SECTION, &[7; 64]).unwrap();
+        push_custom_section(&mut wasm, "other", &[]).unwrap();
+        assert_eq!(split(&wasm), Err(Rejection::Malformed));
+        // Duplicate signature sections.
+        let mut wasm = MODULE.to_vec();
+        push_custom_section(&mut wasm, SECTION, &[7; 64]).unwrap();
+        push_custom_section(&mut wasm, SECTION, &[7; 64]).unwrap();
+        assert_eq!(split(&wasm), Err(Rejection::Malformed));
+    }
+
+    #[test]
+    fn decode_key_hex() {
+        let key = [[0x12; 32], [0xab; 32]].concat();
+        assert_eq!(
+            decode_key(&format!("{}{}", "12".repeat(32), "AB".repeat(32))).unwrap()[..],
+            key
+        );
+        assert_eq!(decode_key(&"12".repeat(63)), None);
+        assert_eq!(decode_key(&"12".repeat(65)), None);
+        assert_eq!(decode_key(&format!("{}1g", "12".repeat(63))), None);
+        assert_eq!(decode_key(&format!("{}+1", "12".repeat(63))), None);
+        assert_eq!(decode_key(&format!("{}é", "12".repeat(63))), None);
+    }
+
+    #[test]
+    fn verify_signed_module() {
+        let key = signing_key(1);
+        let wasm = sign(&key);
+        assert_eq!(verify(&wasm, &trusted(&key)), Ok(()));
+        let other = trusted(&signing_key(2));
+        assert_eq!(verify(&wasm, &format!("invalid,{other},{}", trusted(&key))), Ok(()));
+        assert_eq!(verify(&wasm, &other), Err(Rejection::Untrusted));
+        assert_eq!(verify(&wasm, ""), Err(Rejection::Untrusted));
+    }
+
+    #[test]
+    fn verify_rejects_flipped_bytes() {
+        let key = signing_key(1);
+        let trusted = trusted(&key);
+        let wasm = sign(&key);
+        let (content, _) = split(&wasm).unwrap();
+        assert_eq!(content, MODULE);
+        // Flip a byte of the code.
+        let mut flipped = wasm.clone();
+        flipped[content.len() - 1] ^= 1;
+        assert_eq!(verify(&flipped, &trusted), Err(Rejection::Untrusted));
+        // Flip a byte of the signature.
+        for i in [content.len() + 21, wasm.len() - 33, wasm.len() - 1] {
+            let mut flipped = wasm.clone();
+            flipped[i] ^= 1;
+            assert_eq!(verify(&flipped, &trusted), Err(Rejection::Untrusted), "byte {i}");
+        }
+    }
+}
@@ -25,8 +25,13 @@ check_board_api board-api- --features=wasm,std,
 
 test_helper
 
-cargo test --lib --features=_test,full-api,wasm,std
+cargo test --lib --features=_test,full-api,wasm,std,store-health
+WASEFIRE_APPLET_COUNT=2 cargo test --lib --features=_test,full-api,wasm,std,store-health
+cargo test --lib --features=wasm,std,applet-signature
 cargo check --lib --features=full-api,wasm,std,log
+cargo check --lib --features=full-api,wasm,std,applet-signature
+cargo check --lib --features=full-api,wasm,std,applet-trace
+cargo check --lib --features=full-api,wasm,std,store-health
 cargo check --lib --target=i686-unknown-linux-gnu --features=full-api,native,std
 cargo check --lib --target=i686-unknown-linux-gnu --features=full-api,native,std,log
 cargo check --lib --target=thumbv7em-none-eabi --features=full-api,wasm
@@ -76,6 +76,9 @@ pub enum StatKey {
     /// The number of times a prepare operation was applied.
     PrepareCount,
 
+    /// The number of times a record operation was started.
+    RecordCount,
+
     /// The number of times an insert update was applied.
     InsertCount,
 
@@ -18,8 +18,8 @@ use std::convert::TryInto;
 use rand_core::{RngCore, SeedableRng};
 use rand_pcg::Pcg32;
 use wasefire_store::{
-    BufferOptions, BufferStorage, Store, StoreDriver, StoreDriverOff, StoreDriverOn,
-    StoreInterruption, StoreInvariant, StoreOperation, StoreUpdate,
+    record, BufferOptions, BufferStorage, Store, StoreDriver, StoreDriverOff, StoreDriverOn,
+    StoreInterruption, StoreInvariant, StoreOperation, StoreResult, StoreUpdate,
 };
 
 use crate::stats::{StatKey, Stats};
@@ -28,6 +28,11 @@ use crate::Entropy;
 // NOTE: We should be able to improve coverage by only checking the last operation. Because
 // operations before the last could be checked with a shorter entropy.
 
+/// Keys of the records.
+///
+/// They overlap with the keys of the other operations to also test records with invalid entries.
+const RECORD_KEYS: core::ops::Range<usize> = 0 .. 64;
+
 // NOTE: Maybe we should split the fuzz target in smaller parts (like one per init). We should also
 // name the fuzz targets with action names.
 
@@ -108,6 +113,11 @@ struct Fuzzer<'a> {
     /// The fuzzing mode.
     init: Init,
 
+    /// The remaining operations of a record operation (in reverse order).
+    ///
+    /// They are cleared when the store is interrupted, like a record operation would be.
+    pending: Vec<StoreOperation>,
+
     /// Whether debugging is enabled.
     debug: bool,
 
@@ -127,8 +137,15 @@ impl<'a> Fuzzer<'a> {
         let mut entropy = Entropy::new(data);
         let seed = entropy.read_slice(16);
         let values = Pcg32::from_seed(seed[..].try_into().unwrap());
-        let mut fuzzer =
-            Fuzzer { entropy, values, init: Init::Clean, debug, stats, counters: HashMap::new() };
+        let mut fuzzer = Fuzzer {
+            entropy,
+            values,
+            init: Init::Clean,
+            pending: Vec::new(),
+            debug,
+            stats,
+            counters: HashMap::new(),
+        };
         fuzzer.init_counters();
         fuzzer.record(StatKey::Entropy, data.len());
         fuzzer
@@ -189,6 +206,7 @@ impl<'a> Fuzzer<'a> {
             println!("Power on the store.");
         }
         self.increment(StatKey::PowerOnCount);
+        self.pending.clear();
         let interruption = self.interruption(driver.count_operations());
         match driver.partial_power_on(interruption) {
             Err((storage, _)) if self.init.is_dirty() => {
@@ -203,7 +221,10 @@ impl<'a> Fuzzer<'a> {
     /// Generates and applies an operation with possible interruption.
     #[allow(clippy::result_large_err)]
     fn apply(&mut self, driver: StoreDriverOn) -> Result<StoreDriver, Store<BufferStorage>> {
-        let operation = self.operation(&driver);
+        let operation = match self.pending.pop() {
+            Some(x) => x,
+            None => self.operation(&driver),
+        };
         if self.debug {
             println!("{operation:?}");
         }
@@ -257,6 +278,7 @@ impl<'a> Fuzzer<'a> {
             self.counters.insert(TransactionCount, 0);
             self.counters.insert(ClearCount, 0);
             self.counters.insert(PrepareCount, 0);
+            self.counters.insert(RecordCount, 0);
             self.counters.insert(InsertCount, 0);
             self.counters.insert(RemoveCount, 0);
             self.counters.insert(InterruptionCount, 0);
@@ -275,7 +297,7 @@ impl<'a> Fuzzer<'a> {
     /// Generates a possibly invalid operation.
     fn operation(&mut self, driver: &StoreDriverOn) -> StoreOperation {
         let format = driver.model().format();
-        match self.entropy.read_range(0, 2) {
+        match self.entropy.read_range(0, 3) {
             0 => {
                 // We also generate an invalid count (one past the maximum value) to test the error
                 // scenario. Since the test for the error scenario is monotonic, this is a good
@@ -300,6 +322,35 @@ impl<'a> Fuzzer<'a> {
                 self.increment(StatKey::PrepareCount);
                 StoreOperation::Prepare { length }
             }
+            3 => {
+                self.increment(StatKey::RecordCount);
+                let mut operations = self.record_operations(driver).unwrap_or_default();
+                operations.reverse();
+                // We apply an empty transaction if the record operation is empty or fails.
+                let updates = Vec::new();
+                let operation = operations.pop().unwrap_or(StoreOperation::Transaction { updates });
+                self.pending = operations;
+                operation
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Generates the operations of a possibly invalid record operation.
+    fn record_operations(&mut self, driver: &StoreDriverOn) -> StoreResult<Vec<StoreOperation>> {
+        let store = driver.store();
+        // We use few names to update existing records.
+        let name = [self.entropy.read_range(0, 3) as u8];
+        match self.entropy.read_range(0, 2) {
+            0 => {
+                // Use 1024 bytes per fragment as the canonical invalid length.
+                let length = self.entropy.read_range(0, 1024 * (record::MAX_FRAGMENTS + 1));
+                let mut value = vec![0; length];
+                self.values.fill_bytes(&mut value);
+                record::write_operations(store, &RECORD_KEYS, &name, &value)
+            }
+            1 => record::delete_operations(store, &RECORD_KEYS, &name),
+            2 => record::recover_operations(store, &RECORD_KEYS),
             _ => unreachable!(),
         }
     }
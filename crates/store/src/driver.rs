@@ -17,11 +17,12 @@
 //! [`StoreDriver`] wraps a [`Store`] and compares its behavior with its associated [`StoreModel`].
 
 use crate::format::{Format, Position};
+use crate::fragment::Keys;
 #[cfg(feature = "std")]
 use crate::StoreUpdate;
 use crate::{
-    BufferCorruptFunction, BufferOptions, BufferStorage, Nat, Store, StoreError, StoreHandle,
-    StoreModel, StoreOperation, StoreResult,
+    record, BufferCorruptFunction, BufferOptions, BufferStorage, Nat, Store, StoreError,
+    StoreHandle, StoreModel, StoreOperation, StoreResult,
 };
 
 /// Tracks the store behavior against its model and its storage.
@@ -349,6 +350,13 @@ impl StoreDriverOn {
 
     /// Applies a store operation to the store and model without interruption.
     pub fn apply(&mut self, operation: StoreOperation) -> Result<(), StoreInvariant> {
+        self.apply_result(operation).map(|_| ())
+    }
+
+    /// Applies an operation to the store and model without interruption and returns its result.
+    fn apply_result(
+        &mut self, operation: StoreOperation,
+    ) -> Result<StoreResult<()>, StoreInvariant> {
         let (deleted, store_result) = self.store.apply(&operation);
         let model_result = self.model.apply(operation);
         if store_result != model_result {
@@ -358,7 +366,7 @@ impl StoreDriverOn {
             });
         }
         self.check_deleted(&deleted)?;
-        Ok(())
+        Ok(store_result)
     }
 
     /// Applies a store operation to the store and model with a possible interruption.
@@ -442,6 +450,38 @@ impl StoreDriverOn {
         self.apply(StoreOperation::Clear { min_key })
     }
 
+    /// Writes a record to the store and model without interruption.
+    pub fn record_write(
+        &mut self, keys: &impl Keys, name: &[u8], value: &[u8],
+    ) -> Result<(), StoreInvariant> {
+        let operations = record::write_operations(&self.store, keys, name, value);
+        self.apply_all(operations.map_err(StoreInvariant::StoreError)?)
+    }
+
+    /// Deletes a record from the store and model without interruption.
+    pub fn record_delete(&mut self, keys: &impl Keys, name: &[u8]) -> Result<(), StoreInvariant> {
+        let operations = record::delete_operations(&self.store, keys, name);
+        self.apply_all(operations.map_err(StoreInvariant::StoreError)?)
+    }
+
+    /// Removes the unreferenced record fragments from the store and model without interruption.
+    pub fn record_recover(&mut self, keys: &impl Keys) -> Result<(), StoreInvariant> {
+        let operations = record::recover_operations(&self.store, keys);
+        self.apply_all(operations.map_err(StoreInvariant::StoreError)?)
+    }
+
+    /// Applies operations to the store and model without interruption.
+    ///
+    /// Like the record functions, this stops at the first failing operation.
+    fn apply_all(&mut self, operations: Vec<StoreOperation>) -> Result<(), StoreInvariant> {
+        for operation in operations {
+            if self.apply_result(operation)?.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     /// Checks that the store and model are in sync.
     pub fn check(&self) -> Result<(), StoreInvariant> {
         self.recover_check(&[])
@@ -0,0 +1,424 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for encrypted entries.
+//!
+//! This module permits to store values encrypted and authenticated with an AEAD cipher (e.g.
+//! AES-GCM or AES-CCM) under a device key. The key of an entry is bound as associated data, such
+//! that values can't be moved between keys.
+//!
+//! The last key ([`MAX_KEY_INDEX`]) holds a metadata entry with:
+//! - The version of the format. A store without metadata entry is a plaintext store (version 0).
+//! - The freshness counter of the last sealed value. Each update increments the counter, which is
+//!   used as nonce and stored in clear with the sealed value.
+//! - The keys already migrated from plaintext (all keys once the store is opened).
+//! - The exclusive-or of the tags of the live entries.
+//!
+//! The metadata entry is sealed and updated in the same transaction as the entry. When opening the
+//! store, the tags and counters of the entries are checked against the metadata entry. This
+//! detects the rollback (or removal) of individual entries. Rolling back the whole store, including
+//! the metadata entry, is not detected and reuses nonces. This needs a monotonic counter outside
+//! the store.
+//!
+//! The value of an entry is sealed as follows:
+//!
+//! | Counter (8 bytes, little-endian) | Ciphertext (same length as value) | Tag |
+//! | -------------------------------- | --------------------------------- | --- |
+
+use alloc::vec::Vec;
+
+use aead::generic_array::typenum::Unsigned;
+use aead::{AeadCore, AeadInPlace, Nonce, Tag};
+
+use crate::format::MAX_KEY_INDEX;
+use crate::{Storage, Store, StoreError, StoreResult, StoreUpdate};
+
+/// Version of the format.
+const VERSION: u8 = 1;
+
+/// Key of the metadata entry.
+const META: usize = MAX_KEY_INDEX as usize;
+
+/// Length in bytes of the freshness counter.
+const COUNTER_LEN: usize = 8;
+
+/// Nonce domain of the entries.
+const ENTRY: u8 = 0;
+
+/// Nonce domain of the metadata entry.
+const METADATA: u8 = 1;
+
+/// Store with encrypted entries.
+///
+/// Keys must be smaller than [`MAX_KEY_INDEX`], which is reserved for the metadata entry. The
+/// underlying store should only be modified through this wrapper.
+pub struct EncryptedStore<S: Storage, C: AeadInPlace> {
+    store: Store<S>,
+    cipher: C,
+
+    /// Freshness counter of the last sealed value.
+    counter: u64,
+
+    /// Keys smaller than this value are sealed.
+    migrated: usize,
+
+    /// Exclusive-or of the tags of the live entries.
+    digest: Tag<C>,
+}
+
+impl<S: Storage, C: AeadInPlace> EncryptedStore<S, C> {
+    /// Opens an encrypted store, migrating plaintext entries if needed.
+    ///
+    /// The nonce of the cipher must be at least 9 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::InvalidStorage`] if an entry was tampered with or rolled back, or if
+    /// the cipher key is not the one of the store. Returns [`StoreError::InvalidArgument`] if the
+    /// cipher is not supported. If migration is interrupted, it resumes the next time the store is
+    /// opened.
+    pub fn new(store: Store<S>, cipher: C) -> Result<Self, (StoreError, Store<S>)> {
+        let metadata_len = 1 + COUNTER_LEN + 2 + 2 * C::TagSize::USIZE;
+        if C::NonceSize::USIZE < 1 + COUNTER_LEN || store.max_value_length() < metadata_len {
+            return Err((StoreError::InvalidArgument, store));
+        }
+        let digest = Tag::<C>::default();
+        let mut result = EncryptedStore { store, cipher, counter: 0, migrated: 0, digest };
+        match result.init() {
+            Ok(()) => Ok(result),
+            Err(error) => Err((error, result.store)),
+        }
+    }
+
+    /// Returns the underlying store.
+    ///
+    /// This is useful to query its capacity and lifetime.
+    pub fn store(&self) -> &Store<S> {
+        &self.store
+    }
+
+    /// Extracts the underlying store.
+    pub fn extract_store(self) -> Store<S> {
+        self.store
+    }
+
+    /// Returns the value of an entry, if present.
+    ///
+    /// Returns [`StoreError::InvalidStorage`] if the entry was tampered with.
+    pub fn find(&self, key: usize) -> StoreResult<Option<Vec<u8>>> {
+        check_key(key)?;
+        let mut value = match self.store.find(key)? {
+            None => return Ok(None),
+            Some(x) => x,
+        };
+        let (counter, data, tag) = split::<C>(&mut value)?;
+        if self.counter < counter {
+            return Err(StoreError::InvalidStorage);
+        }
+        let tag = tag.clone();
+        self.open(ENTRY, key, counter, data, &tag)?;
+        value.truncate(value.len() - C::TagSize::USIZE);
+        value.drain(.. COUNTER_LEN);
+        Ok(Some(value))
+    }
+
+    /// Inserts an entry.
+    ///
+    /// If an entry for the same key is already present, it is replaced.
+    pub fn insert(&mut self, key: usize, value: &[u8]) -> StoreResult<()> {
+        check_key(key)?;
+        if self.max_value_length() < value.len() {
+            return Err(StoreError::InvalidArgument);
+        }
+        self.insert_(key, value, META)
+    }
+
+    /// Removes an entry.
+    ///
+    /// This is not an error if there is no entry for this key.
+    pub fn remove(&mut self, key: usize) -> StoreResult<()> {
+        check_key(key)?;
+        if self.store.find_handle(key)?.is_none() {
+            return Ok(());
+        }
+        let digest = self.old_digest(key)?;
+        self.next_counter()?;
+        self.commit(Some(StoreUpdate::Remove { key }), digest, META)
+    }
+
+    /// Returns the maximum length in bytes of a value.
+    pub fn max_value_length(&self) -> usize {
+        self.store.max_value_length() - COUNTER_LEN - C::TagSize::USIZE
+    }
+
+    fn init(&mut self) -> StoreResult<()> {
+        match self.store.find(META)? {
+            Some(metadata) => self.read_metadata(metadata)?,
+            None => {
+                self.next_counter()?;
+                self.commit(None, Tag::<C>::default(), 0)?;
+            }
+        }
+        let mut digest = Tag::<C>::default();
+        let mut plaintext = Vec::new();
+        for handle in self.store.iter()? {
+            let handle = handle?;
+            let key = handle.get_key();
+            if key == META {
+                continue;
+            }
+            if self.migrated <= key {
+                plaintext.push(key);
+                continue;
+            }
+            let mut value = handle.get_value(&self.store)?;
+            let (counter, _, tag) = split::<C>(&mut value)?;
+            if self.counter < counter {
+                return Err(StoreError::InvalidStorage);
+            }
+            xor(&mut digest, tag);
+        }
+        if digest != self.digest {
+            return Err(StoreError::InvalidStorage);
+        }
+        plaintext.sort_unstable();
+        for key in plaintext {
+            let value = self.store.find(key)?.ok_or(StoreError::InvalidStorage)?;
+            self.insert_(key, &value, key + 1)?;
+        }
+        if self.migrated < META {
+            self.next_counter()?;
+            self.commit(None, self.digest.clone(), META)?;
+        }
+        Ok(())
+    }
+
+    fn read_metadata(&mut self, mut metadata: Vec<u8>) -> StoreResult<()> {
+        if metadata.first() != Some(&VERSION) {
+            return Err(StoreError::InvalidStorage);
+        }
+        let (counter, data, tag) = split::<C>(&mut metadata[1 ..])?;
+        if data.len() != 2 + C::TagSize::USIZE {
+            return Err(StoreError::InvalidStorage);
+        }
+        let tag = tag.clone();
+        self.open(METADATA, META, counter, data, &tag)?;
+        self.counter = counter;
+        self.migrated = u16::from_le_bytes([data[0], data[1]]) as usize;
+        self.digest = Tag::<C>::clone_from_slice(&data[2 ..]);
+        Ok(())
+    }
+
+    /// Seals and inserts an entry, then marks the keys smaller than `migrated` as sealed.
+    fn insert_(&mut self, key: usize, value: &[u8], migrated: usize) -> StoreResult<()> {
+        let mut digest = self.old_digest(key)?;
+        let counter = self.next_counter()?;
+        let mut sealed = Vec::with_capacity(COUNTER_LEN + value.len() + C::TagSize::USIZE);
+        sealed.extend_from_slice(&counter.to_le_bytes());
+        sealed.extend_from_slice(value);
+        let tag = self.seal(ENTRY, key, counter, &mut sealed[COUNTER_LEN ..])?;
+        xor(&mut digest, &tag);
+        sealed.extend_from_slice(&tag);
+        self.commit(Some(StoreUpdate::Insert { key, value: sealed }), digest, migrated)
+    }
+
+    /// Returns the digest without the current entry of a key.
+    fn old_digest(&self, key: usize) -> StoreResult<Tag<C>> {
+        let mut digest = self.digest.clone();
+        if key < self.migrated {
+            if let Some(mut value) = self.store.find(key)? {
+                xor(&mut digest, split::<C>(&mut value)?.2);
+            }
+        }
+        Ok(digest)
+    }
+
+    /// Increments the freshness counter.
+    ///
+    /// The counter is incremented even if the update fails, such that nonces are not reused.
+    fn next_counter(&mut self) -> StoreResult<u64> {
+        self.counter = self.counter.checked_add(1).ok_or(StoreError::NoLifetime)?;
+        Ok(self.counter)
+    }
+
+    /// Applies an update together with the metadata entry for the current counter.
+    fn commit(
+        &mut self, update: Option<StoreUpdate<Vec<u8>>>, digest: Tag<C>, migrated: usize,
+    ) -> StoreResult<()> {
+        let mut metadata = Vec::with_capacity(1 + COUNTER_LEN + 2 + 2 * C::TagSize::USIZE);
+        metadata.push(VERSION);
+        metadata.extend_from_slice(&self.counter.to_le_bytes());
+        metadata.extend_from_slice(&(migrated as u16).to_le_bytes());
+        metadata.extend_from_slice(&digest);
+        let tag = self.seal(METADATA, META, self.counter, &mut metadata[1 + COUNTER_LEN ..])?;
+        metadata.extend_from_slice(&tag);
+        let mut updates: Vec<_> = update.into_iter().collect();
+        updates.push(StoreUpdate::Insert { key: META, value: metadata });
+        self.store.transaction(&updates)?;
+        self.migrated = migrated;
+        self.digest = digest;
+        Ok(())
+    }
+
+    fn seal(&self, domain: u8, key: usize, counter: u64, data: &mut [u8]) -> StoreResult<Tag<C>> {
+        let nonce = nonce::<C>(domain, counter);
+        let result = self.cipher.encrypt_in_place_detached(&nonce, &associated(key), data);
+        result.map_err(|_| StoreError::InvalidArgument)
+    }
+
+    fn open(
+        &self, domain: u8, key: usize, counter: u64, data: &mut [u8], tag: &Tag<C>,
+    ) -> StoreResult<()> {
+        let nonce = nonce::<C>(domain, counter);
+        let result = self.cipher.decrypt_in_place_detached(&nonce, &associated(key), data, tag);
+        result.map_err(|_| StoreError::InvalidStorage)
+    }
+}
+
+fn check_key(key: usize) -> StoreResult<()> {
+    match key < META {
+        true => Ok(()),
+        false => Err(StoreError::InvalidArgument),
+    }
+}
+
+fn nonce<C: AeadCore>(domain: u8, counter: u64) -> Nonce<C> {
+    let mut nonce = Nonce::<C>::default();
+    nonce[0] = domain;
+    nonce[1 ..= COUNTER_LEN].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+fn associated(key: usize) -> [u8; 3] {
+    [VERSION, key as u8, (key >> 8) as u8]
+}
+
+/// Splits a sealed value into its counter, ciphertext, and tag.
+fn split<C: AeadCore>(value: &mut [u8]) -> StoreResult<(u64, &mut [u8], &Tag<C>)> {
+    if value.len() < COUNTER_LEN + C::TagSize::USIZE {
+        return Err(StoreError::InvalidStorage);
+    }
+    let (counter, value) = value.split_at_mut(COUNTER_LEN);
+    let (data, tag) = value.split_at_mut(value.len() - C::TagSize::USIZE);
+    let counter = u64::from_le_bytes(counter.try_into().unwrap());
+    Ok((counter, data, Tag::<C>::from_slice(tag)))
+}
+
+fn xor(digest: &mut [u8], tag: &[u8]) {
+    digest.iter_mut().zip(tag).for_each(|(x, y)| *x ^= y);
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use aes_gcm::{Aes256Gcm, KeyInit};
+
+    use super::*;
+    use crate::test::ENCRYPTED;
+    use crate::BufferStorage;
+
+    fn cipher(key: u8) -> Aes256Gcm {
+        Aes256Gcm::new(&[key; 32].into())
+    }
+
+    fn open(store: Store<BufferStorage>) -> EncryptedStore<BufferStorage, Aes256Gcm> {
+        EncryptedStore::new(store, cipher(0)).ok().unwrap()
+    }
+
+    fn open_err(store: Store<BufferStorage>) -> StoreError {
+        EncryptedStore::new(store, cipher(0)).err().unwrap().0
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut store = open(ENCRYPTED.new_store());
+        store.insert(0, b"secret").unwrap();
+        store.insert(1, b"other").unwrap();
+        store.insert(0, b"updated").unwrap();
+        store.remove(1).unwrap();
+        store.remove(2).unwrap();
+        assert_eq!(store.find(0).unwrap().unwrap(), b"updated");
+        assert_eq!(store.find(1).unwrap(), None);
+        assert_eq!(store.insert(META, b""), Err(StoreError::InvalidArgument));
+        let value = vec![0; store.max_value_length() + 1];
+        assert_eq!(store.insert(2, &value), Err(StoreError::InvalidArgument));
+        let raw = store.extract_store();
+        assert!(!raw.find(0).unwrap().unwrap().windows(7).any(|x| x == b"updated"));
+        let store = open(raw);
+        assert_eq!(store.find(0).unwrap().unwrap(), b"updated");
+        let raw = store.extract_store();
+        let error = EncryptedStore::new(raw, cipher(1)).err().unwrap().0;
+        assert_eq!(error, StoreError::InvalidStorage);
+    }
+
+    #[test]
+    fn migration() {
+        let mut raw = ENCRYPTED.new_store();
+        raw.insert(7, b"seven").unwrap();
+        raw.insert(3, b"three").unwrap();
+        let store = open(raw);
+        assert_eq!(store.find(3).unwrap().unwrap(), b"three");
+        assert_eq!(store.find(7).unwrap().unwrap(), b"seven");
+        let raw = store.extract_store();
+        assert_ne!(raw.find(3).unwrap().unwrap(), b"three");
+        let store = open(raw);
+        assert_eq!(store.find(7).unwrap().unwrap(), b"seven");
+    }
+
+    #[test]
+    fn rollback() {
+        let mut store = open(ENCRYPTED.new_store());
+        store.insert(0, b"old").unwrap();
+        let old = store.store().find(0).unwrap().unwrap();
+        store.insert(0, b"new").unwrap();
+        let mut raw = store.extract_store();
+        raw.insert(0, &old).unwrap();
+        assert_eq!(open_err(raw), StoreError::InvalidStorage);
+    }
+
+    #[test]
+    fn removal() {
+        let mut store = open(ENCRYPTED.new_store());
+        store.insert(0, b"value").unwrap();
+        let mut raw = store.extract_store();
+        raw.remove(0).unwrap();
+        assert_eq!(open_err(raw), StoreError::InvalidStorage);
+    }
+
+    #[test]
+    fn tamper() {
+        let mut store = open(ENCRYPTED.new_store());
+        store.insert(0, b"value").unwrap();
+        let mut raw = store.extract_store();
+        let mut value = raw.find(0).unwrap().unwrap();
+        value[COUNTER_LEN] ^= 1;
+        raw.insert(0, &value).unwrap();
+        let store = open(raw);
+        assert_eq!(store.find(0), Err(StoreError::InvalidStorage));
+    }
+
+    #[test]
+    fn swap_keys() {
+        let mut store = open(ENCRYPTED.new_store());
+        store.insert(0, b"zero").unwrap();
+        store.insert(1, b"one!").unwrap();
+        let mut raw = store.extract_store();
+        let zero = raw.find(0).unwrap().unwrap();
+        let one = raw.find(1).unwrap().unwrap();
+        raw.insert(0, &one).unwrap();
+        raw.insert(1, &zero).unwrap();
+        let store = open(raw);
+        assert_eq!(store.find(0), Err(StoreError::InvalidStorage));
+        assert_eq!(store.find(1), Err(StoreError::InvalidStorage));
+    }
+}
@@ -353,6 +353,7 @@
 //! is checked not to crash.
 
 #![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(test, allow(unused_crate_dependencies))]
 
 #[macro_use]
 extern crate alloc;
@@ -361,12 +362,16 @@ extern crate alloc;
 mod buffer;
 #[cfg(feature = "std")]
 mod driver;
+#[cfg(feature = "encrypted")]
+mod encrypted;
 #[cfg(feature = "std")]
 mod file;
 pub mod format;
 pub mod fragment;
+mod metered;
 #[cfg(feature = "std")]
 mod model;
+pub mod record;
 mod storage;
 mod store;
 #[cfg(test)]
@@ -378,8 +383,11 @@ pub use self::buffer::{BufferCorruptFunction, BufferOptions, BufferStorage};
 pub use self::driver::{
     StoreDriver, StoreDriverOff, StoreDriverOn, StoreInterruption, StoreInvariant,
 };
+#[cfg(feature = "encrypted")]
+pub use self::encrypted::EncryptedStore;
 #[cfg(feature = "std")]
 pub use self::file::{FileOptions, FileStorage};
+pub use self::metered::{MeteredStorage, StoreHealth};
 #[cfg(feature = "std")]
 pub use self::model::{StoreModel, StoreOperation};
 pub use self::storage::{Storage, StorageError, StorageIndex, StorageResult};
@@ -0,0 +1,626 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wear statistics of a flash storage.
+//!
+//! [`MeteredStorage`] wraps a flash [`Storage`] and counts how many times its pages are erased and
+//! its words are written. Those counts are persisted in a journal using the last
+//! [`JOURNAL_PAGES`](MeteredStorage::JOURNAL_PAGES) pages of the wrapped storage, which are not
+//! visible through the wrapper.
+//!
+//! The journal uses one page at a time. This page starts with a snapshot of the counts, followed by
+//! records of their changes. When the page is full, a new snapshot is written to the other page.
+//! To keep the journal small, the write count of a word is stored as its difference with the
+//! implicit count, which is one if the word is not erased and zero otherwise. Only the writes
+//! changing this difference are recorded, which for the store is mostly when deleting entries.
+//! Note that the journal pages may wear faster than the other pages.
+//!
+//! The words of a journal page are:
+//! - The sequence number of the snapshot, written last. The valid page with the highest sequence
+//!   number is used.
+//! - The erase count of each page.
+//! - A bitmap with one bit per word, cleared if the word was written more than implicitly.
+//! - The records up to the first erased word. The snapshot starts with records for the words
+//!   written at least twice more than implicitly (if they fit).
+
+use alloc::borrow::Cow;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::format::{is_erased, Format, Word, WordState};
+use crate::{usize_to_nat, Storage, StorageError, StorageIndex, StorageResult, StoreRatio};
+
+/// Size of a word in bytes.
+///
+/// Only storages with this word size can be metered.
+const WORD_SIZE: usize = 4;
+
+/// Maximum number of words (excluding the journal pages) of a metered storage.
+///
+/// Records encode the index of a word or page in 2 bytes.
+const MAX_WORDS: usize = 0x10000;
+
+/// Index of the sequence number in a journal page.
+const SEQUENCE_WORD: usize = 0;
+
+/// Index of the first erase count in a journal page.
+const ERASES_WORD: usize = 1;
+
+/// Flash storage counting page erases and word writes.
+pub struct MeteredStorage<S: Storage> {
+    /// The wrapped storage.
+    storage: S,
+
+    /// Number of times a page was erased.
+    page_erases: Vec<usize>,
+
+    /// Number of times a word was written since the last time its page was erased.
+    ///
+    /// The counts saturate, which is not an issue since words can't be written that many times.
+    word_writes: Vec<u8>,
+
+    /// The journal page in use (either 0 or 1).
+    journal: usize,
+
+    /// The sequence number of the journal page in use.
+    sequence: u32,
+
+    /// The number of words used in the journal page in use.
+    journal_len: usize,
+}
+
+/// Health report of a store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoreHealth {
+    /// The current and total capacity in words.
+    pub capacity: StoreRatio,
+
+    /// The current and total lifetime in words.
+    pub lifetime: StoreRatio,
+
+    /// The projected number of remaining page erases.
+    ///
+    /// See [`MeteredStorage::remaining_lifetime()`].
+    pub remaining_erases: usize,
+
+    /// The wear histogram.
+    ///
+    /// See [`MeteredStorage::wear_histogram()`].
+    pub wear: Vec<(usize, usize)>,
+}
+
+/// Change of the counts recorded in the journal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Record {
+    /// A page was erased.
+    Erase { page: usize },
+
+    /// A word is now written `delta` times more than implicitly.
+    Write { word: usize, delta: u8 },
+}
+
+impl<S: Storage> MeteredStorage<S> {
+    /// Number of pages of the wrapped storage used by the journal.
+    pub const JOURNAL_PAGES: usize = 2;
+
+    /// Wraps a storage, restoring the counts from its journal.
+    ///
+    /// If there is no journal, it is created with the counts restored from the storage content.
+    /// The erase count of a page is then the erase cycle of its init word. Pages without init word
+    /// (erased but not yet used by the store) are assumed to be erased as many times as the most
+    /// erased page, which may be one less than the actual count. The write count of a word is its
+    /// implicit count, which may also be less than the actual count.
+    ///
+    /// Returns an error if words are not 4 bytes, if there are more than 2^16 words (excluding the
+    /// journal pages), or if a snapshot doesn't fit in a page.
+    pub fn new(storage: S) -> StorageResult<Self> {
+        if storage.word_size() != WORD_SIZE {
+            return Err(StorageError::NotAligned);
+        }
+        let num_pages = match storage.num_pages().checked_sub(Self::JOURNAL_PAGES) {
+            Some(x) => x,
+            None => return Err(StorageError::OutOfBounds),
+        };
+        let num_words = num_pages * storage.page_size() / WORD_SIZE;
+        if MAX_WORDS < num_words {
+            return Err(StorageError::OutOfBounds);
+        }
+        let mut result = MeteredStorage {
+            storage,
+            page_erases: vec![0; num_pages],
+            word_writes: vec![0; num_words],
+            journal: 0,
+            sequence: 0,
+            journal_len: 0,
+        };
+        if result.words_per_page() <= result.snapshot_len() {
+            return Err(StorageError::OutOfBounds);
+        }
+        match result.latest_journal()? {
+            Some((journal, sequence)) => result.replay(journal, sequence)?,
+            None => {
+                result.restore()?;
+                result.snapshot(0, 0)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Accesses the wrapped storage.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Extracts the wrapped storage.
+    pub fn extract_storage(self) -> S {
+        self.storage
+    }
+
+    /// Returns the number of times a page was erased.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page is out of bounds.
+    pub fn page_erases(&self, page: usize) -> usize {
+        self.page_erases[page]
+    }
+
+    /// Returns the number of times a word was written since its page was last erased.
+    ///
+    /// The index must designate the first byte of the word.
+    pub fn word_writes(&self, index: StorageIndex) -> StorageResult<usize> {
+        let range = index.range(self.word_size(), self)?;
+        Ok(self.word_writes[range.start / self.word_size()] as usize)
+    }
+
+    /// Returns the projected number of remaining page erases.
+    ///
+    /// The projection assumes that wear is leveled, which is the case for the store since it erases
+    /// pages in a circular order.
+    pub fn remaining_lifetime(&self) -> usize {
+        let max_page_erases = self.max_page_erases();
+        self.page_erases.iter().map(|&x| max_page_erases.saturating_sub(x)).sum()
+    }
+
+    /// Returns the wear histogram.
+    ///
+    /// Maps each bucket of erase counts to its number of pages. The buckets are the same as the
+    /// fuzzing histograms: numbers sharing the same highest bit, identified by their lower-bound
+    /// (the first buckets are 0, 1, 2, 4, and 8). Only non-empty buckets are returned, in
+    /// increasing order.
+    pub fn wear_histogram(&self) -> Vec<(usize, usize)> {
+        let mut histogram: Vec<(usize, usize)> = Vec::new();
+        for &erases in &self.page_erases {
+            let bucket = get_bucket(erases);
+            match histogram.binary_search_by_key(&bucket, |&(x, _)| x) {
+                Ok(i) => histogram[i].1 += 1,
+                Err(i) => histogram.insert(i, (bucket, 1)),
+            }
+        }
+        histogram
+    }
+
+    /// Returns the number of words per page.
+    fn words_per_page(&self) -> usize {
+        self.page_size() / WORD_SIZE
+    }
+
+    /// Returns the number of words of a snapshot, excluding its records.
+    fn snapshot_len(&self) -> usize {
+        ERASES_WORD + self.page_erases.len() + self.word_writes.len().div_ceil(32)
+    }
+
+    /// Returns the storage index of a word in a journal page.
+    fn journal_index(&self, journal: usize, word: usize) -> StorageIndex {
+        StorageIndex { page: self.page_erases.len() + journal, byte: word * WORD_SIZE }
+    }
+
+    /// Reads a word of a journal page.
+    fn read_journal(&self, journal: usize, word: usize) -> StorageResult<u32> {
+        let slice = self.storage.read_slice(self.journal_index(journal, word), WORD_SIZE)?;
+        Ok(u32::from_le_bytes(slice[..].try_into().unwrap()))
+    }
+
+    /// Returns the journal page with the highest sequence number and this number, if any.
+    fn latest_journal(&self) -> StorageResult<Option<(usize, u32)>> {
+        let mut latest: Option<(usize, u32)> = None;
+        for journal in 0 .. Self::JOURNAL_PAGES {
+            let sequence = self.read_journal(journal, SEQUENCE_WORD)?;
+            if sequence != u32::MAX && latest.map_or(true, |(_, x)| x < sequence) {
+                latest = Some((journal, sequence));
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Restores the counts from a journal page.
+    fn replay(&mut self, journal: usize, sequence: u32) -> StorageResult<()> {
+        let num_pages = self.page_erases.len();
+        let words = self.words_per_page();
+        for page in 0 .. num_pages {
+            self.page_erases[page] = self.read_journal(journal, ERASES_WORD + page)? as usize;
+        }
+        let mut deltas = vec![0; self.word_writes.len()];
+        for (i, chunk) in deltas.chunks_mut(32).enumerate() {
+            let bitmap = self.read_journal(journal, ERASES_WORD + num_pages + i)?;
+            for (j, delta) in chunk.iter_mut().enumerate() {
+                *delta = (bitmap >> j & 1 == 0) as u8;
+            }
+        }
+        let mut journal_len = self.snapshot_len();
+        while journal_len < words {
+            let record = self.read_journal(journal, journal_len)?;
+            if record == u32::MAX {
+                break;
+            }
+            match Record::decode(record) {
+                Some(Record::Erase { page }) if page < num_pages => {
+                    self.page_erases[page] += 1;
+                    deltas[page * words .. (page + 1) * words].fill(0);
+                }
+                Some(Record::Write { word, delta }) if word < deltas.len() => deltas[word] = delta,
+                // The record was interrupted. We can't write after it, so the next record will
+                // write a new snapshot instead.
+                _ => {
+                    journal_len = words;
+                    break;
+                }
+            }
+            journal_len += 1;
+        }
+        for page in 0 .. num_pages {
+            let content = read_page(&self.storage, page)?;
+            for (i, word) in content.chunks_exact(WORD_SIZE).enumerate() {
+                let index = page * words + i;
+                self.word_writes[index] = implicit_writes(word).saturating_add(deltas[index]);
+            }
+        }
+        self.journal = journal;
+        self.sequence = sequence;
+        self.journal_len = journal_len;
+        Ok(())
+    }
+
+    /// Restores the counts from the storage content.
+    fn restore(&mut self) -> StorageResult<()> {
+        let words = self.words_per_page();
+        let format = Format::new(self);
+        let mut cycles = Vec::with_capacity(self.page_erases.len());
+        for page in 0 .. self.page_erases.len() {
+            let content = read_page(&self.storage, page)?;
+            for (i, word) in content.chunks_exact(WORD_SIZE).enumerate() {
+                self.word_writes[page * words + i] = implicit_writes(word);
+            }
+            let cycle = format.as_ref().and_then(|format| {
+                let index = format.index_init(usize_to_nat(page));
+                let word = Word::from_slice(&content[index.byte ..][.. WORD_SIZE]);
+                match format.parse_init(word) {
+                    Ok(WordState::Valid(init)) => Some(init.cycle as usize),
+                    _ => None,
+                }
+            });
+            cycles.push(cycle);
+        }
+        let max_cycle = cycles.iter().flatten().max().cloned().unwrap_or(0);
+        for (erases, cycle) in self.page_erases.iter_mut().zip(cycles) {
+            *erases = cycle.unwrap_or(max_cycle);
+        }
+        Ok(())
+    }
+
+    /// Writes a snapshot of the counts to a journal page, which becomes the one in use.
+    fn snapshot(&mut self, journal: usize, sequence: u32) -> StorageResult<()> {
+        let num_pages = self.page_erases.len();
+        let words = self.words_per_page();
+        let mut snapshot = vec![u32::MAX; words];
+        for (page, &erases) in self.page_erases.iter().enumerate() {
+            snapshot[ERASES_WORD + page] = erases as u32;
+        }
+        let mut len = self.snapshot_len();
+        for page in 0 .. num_pages {
+            let content = read_page(&self.storage, page)?;
+            for (i, word) in content.chunks_exact(WORD_SIZE).enumerate() {
+                let index = page * words + i;
+                let delta = self.word_writes[index].saturating_sub(implicit_writes(word));
+                if 0 < delta {
+                    snapshot[ERASES_WORD + num_pages + index / 32] &= !(1 << (index % 32));
+                }
+                if 1 < delta && len < words {
+                    snapshot[len] = Record::Write { word: index, delta }.encode();
+                    len += 1;
+                }
+            }
+        }
+        let snapshot: Vec<u8> = snapshot[.. len].iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.storage.erase_page(self.journal_index(journal, 0).page)?;
+        let index = self.journal_index(journal, SEQUENCE_WORD + 1);
+        self.storage.write_slice(index, &snapshot[(SEQUENCE_WORD + 1) * WORD_SIZE ..])?;
+        let index = self.journal_index(journal, SEQUENCE_WORD);
+        self.storage.write_slice(index, &sequence.to_le_bytes())?;
+        self.journal = journal;
+        self.sequence = sequence;
+        self.journal_len = len;
+        Ok(())
+    }
+
+    /// Appends a record to the journal.
+    ///
+    /// The counts must already be updated, because a new snapshot is written instead if the
+    /// journal page is full.
+    fn record(&mut self, record: Record) -> StorageResult<()> {
+        if self.journal_len == self.words_per_page() {
+            return self.snapshot(1 - self.journal, self.sequence.wrapping_add(1));
+        }
+        let index = self.journal_index(self.journal, self.journal_len);
+        self.storage.write_slice(index, &record.encode().to_le_bytes())?;
+        self.journal_len += 1;
+        Ok(())
+    }
+}
+
+impl<S: Storage> Storage for MeteredStorage<S> {
+    fn word_size(&self) -> usize {
+        self.storage.word_size()
+    }
+
+    fn page_size(&self) -> usize {
+        self.storage.page_size()
+    }
+
+    fn num_pages(&self) -> usize {
+        self.page_erases.len()
+    }
+
+    fn max_word_writes(&self) -> usize {
+        self.storage.max_word_writes()
+    }
+
+    fn max_page_erases(&self) -> usize {
+        self.storage.max_page_erases()
+    }
+
+    fn read_slice(&self, index: StorageIndex, length: usize) -> StorageResult<Cow<[u8]>> {
+        index.range(length, self)?;
+        self.storage.read_slice(index, length)
+    }
+
+    fn write_slice(&mut self, index: StorageIndex, value: &[u8]) -> StorageResult<()> {
+        let range = index.range(value.len(), self)?;
+        let before = self.storage.read_slice(index, value.len())?.into_owned();
+        self.storage.write_slice(index, value)?;
+        let after = self.storage.read_slice(index, value.len())?.into_owned();
+        let mut records = Vec::new();
+        let words = before.chunks_exact(WORD_SIZE).zip(after.chunks_exact(WORD_SIZE));
+        for ((before, after), word) in words.zip(range.start / WORD_SIZE ..) {
+            let count = &mut self.word_writes[word];
+            let delta = count.saturating_sub(implicit_writes(before));
+            *count = count.saturating_add(1);
+            let new_delta = count.saturating_sub(implicit_writes(after));
+            if new_delta != delta {
+                records.push(Record::Write { word, delta: new_delta });
+            }
+        }
+        for record in records {
+            self.record(record)?;
+        }
+        Ok(())
+    }
+
+    fn erase_page(&mut self, page: usize) -> StorageResult<()> {
+        if self.num_pages() <= page {
+            return Err(StorageError::OutOfBounds);
+        }
+        self.storage.erase_page(page)?;
+        self.page_erases[page] += 1;
+        let words = self.words_per_page();
+        self.word_writes[page * words .. (page + 1) * words].fill(0);
+        self.record(Record::Erase { page })
+    }
+}
+
+impl Record {
+    /// Encodes a record in a word, which is never erased.
+    ///
+    /// The highest byte is the tag (0 for writes and 1 for erases), the next byte is the delta, and
+    /// the lowest 2 bytes are the word or page.
+    fn encode(self) -> u32 {
+        match self {
+            Record::Erase { page } => 1 << 24 | page as u32,
+            Record::Write { word, delta } => (delta as u32) << 16 | word as u32,
+        }
+    }
+
+    /// Decodes a record from a word.
+    fn decode(word: u32) -> Option<Self> {
+        let index = (word & 0xffff) as usize;
+        match word >> 16 {
+            x if x >> 8 == 0 => Some(Record::Write { word: index, delta: x as u8 }),
+            0x100 => Some(Record::Erase { page: index }),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a page of a storage.
+fn read_page(storage: &impl Storage, page: usize) -> StorageResult<Cow<[u8]>> {
+    storage.read_slice(StorageIndex { page, byte: 0 }, storage.page_size())
+}
+
+/// Returns the implicit write count of a word.
+fn implicit_writes(word: &[u8]) -> u8 {
+    !is_erased(word) as u8
+}
+
+/// Returns the bucket of an item.
+///
+/// The bucket of `item` is the highest power of two, lower or equal to `item`. If `item` is zero,
+/// then its bucket is also zero.
+fn get_bucket(item: usize) -> usize {
+    match item {
+        0 => 0,
+        _ => 1 << item.ilog2(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::METERED;
+    use crate::{BufferOptions, BufferStorage, Store};
+
+    /// Number of pages of the store (see [`METERED`]).
+    const NUM_PAGES: usize = 5;
+
+    const JOURNAL_PAGES: usize = MeteredStorage::<BufferStorage>::JOURNAL_PAGES;
+
+    fn new_storage() -> MeteredStorage<BufferStorage> {
+        MeteredStorage::new(METERED.new_journaled_storage()).unwrap()
+    }
+
+    #[test]
+    fn get_bucket_ok() {
+        assert_eq!(get_bucket(0), 0);
+        assert_eq!(get_bucket(1), 1);
+        assert_eq!(get_bucket(2), 2);
+        assert_eq!(get_bucket(3), 2);
+        assert_eq!(get_bucket(4), 4);
+        assert_eq!(get_bucket(7), 4);
+        assert_eq!(get_bucket(8), 8);
+        assert_eq!(get_bucket(15), 8);
+    }
+
+    #[test]
+    fn record_encoding() {
+        for record in [
+            Record::Erase { page: 0 },
+            Record::Erase { page: 63 },
+            Record::Write { word: 0, delta: 1 },
+            Record::Write { word: 0xffff, delta: 0xff },
+        ] {
+            assert_ne!(record.encode(), u32::MAX);
+            assert_eq!(Record::decode(record.encode()), Some(record));
+        }
+        assert_eq!(Record::decode(0x0101_0000), None);
+        assert_eq!(Record::decode(0x0200_0000), None);
+    }
+
+    #[test]
+    fn new_rejects_too_many_words() {
+        let options = BufferOptions {
+            word_size: 4,
+            page_size: 0x10000,
+            max_word_writes: 2,
+            max_page_erases: 10,
+            strict_mode: true,
+        };
+        let new = |num_pages: usize| {
+            let storage = vec![0xff; (num_pages + JOURNAL_PAGES) * options.page_size];
+            MeteredStorage::new(BufferStorage::new(storage.into_boxed_slice(), options.clone()))
+        };
+        // Records can encode 2^16 words.
+        assert!(new(4).is_ok());
+        assert_eq!(new(5).err(), Some(StorageError::OutOfBounds));
+    }
+
+    #[test]
+    fn count_operations() {
+        let mut storage = new_storage();
+        assert_eq!(storage.num_pages(), NUM_PAGES);
+        let index = StorageIndex { page: 1, byte: 8 };
+        assert_eq!(storage.word_writes(index), Ok(0));
+        storage.write_slice(index, &[0xfe, 0xff, 0xff, 0xff, 0xfc, 0xff, 0xff, 0xff]).unwrap();
+        storage.write_slice(index, &[0xfc, 0xff, 0xff, 0xff]).unwrap();
+        assert_eq!(storage.word_writes(index), Ok(2));
+        assert_eq!(storage.word_writes(StorageIndex { page: 1, byte: 12 }), Ok(1));
+        assert_eq!(storage.word_writes(StorageIndex { page: 1, byte: 16 }), Ok(0));
+        storage.erase_page(1).unwrap();
+        assert_eq!(storage.page_erases(0), 0);
+        assert_eq!(storage.page_erases(1), 1);
+        assert_eq!(storage.word_writes(index), Ok(0));
+        // The journal pages are not visible.
+        let journal = StorageIndex { page: NUM_PAGES, byte: 0 };
+        assert_eq!(storage.read_slice(journal, 4).err(), Some(StorageError::OutOfBounds));
+        assert_eq!(storage.erase_page(NUM_PAGES), Err(StorageError::OutOfBounds));
+    }
+
+    #[test]
+    fn persist_counts() {
+        let mut storage = new_storage();
+        let index = StorageIndex { page: 2, byte: 8 };
+        // The first word still looks erased after its first write.
+        storage.write_slice(index, &[0xff; 8]).unwrap();
+        storage.write_slice(index, &[0xfe, 0xff, 0xff, 0xff]).unwrap();
+        storage.write_slice(StorageIndex { page: 2, byte: 12 }, &[0x00; 4]).unwrap();
+        storage.erase_page(3).unwrap();
+        let restored = MeteredStorage::new(storage.extract_storage()).unwrap();
+        assert_eq!(restored.word_writes(index), Ok(2));
+        assert_eq!(restored.word_writes(StorageIndex { page: 2, byte: 12 }), Ok(2));
+        assert_eq!(restored.word_writes(StorageIndex { page: 2, byte: 16 }), Ok(0));
+        assert_eq!(restored.page_erases(2), 0);
+        assert_eq!(restored.page_erases(3), 1);
+    }
+
+    #[test]
+    fn restore_counts() {
+        let mut store = Store::new(new_storage()).ok().unwrap();
+        let value = vec![0x5a; store.max_value_length()];
+        // Write until all pages are erased at least twice. This also fills the journal.
+        while store.health().unwrap().wear[0].0 < 2 {
+            store.insert(0, &value).unwrap();
+        }
+        let actual = store.extract_storage();
+        assert!(0 < actual.sequence);
+        assert!(actual.word_writes.contains(&2));
+        let restored = MeteredStorage::new(actual.storage().clone()).unwrap();
+        for page in 0 .. actual.num_pages() {
+            assert_eq!(restored.page_erases(page), actual.page_erases(page));
+            for byte in (0 .. actual.page_size()).step_by(actual.word_size()) {
+                let index = StorageIndex { page, byte };
+                assert_eq!(restored.word_writes(index), actual.word_writes(index));
+            }
+        }
+        assert_eq!(restored.wear_histogram(), actual.wear_histogram());
+    }
+
+    #[test]
+    fn restore_counts_without_journal() {
+        let store = METERED.new_store();
+        let mut storage = METERED.new_journaled_storage();
+        for page in 0 .. NUM_PAGES {
+            let index = StorageIndex { page, byte: 0 };
+            let content = store.storage().read_slice(index, 64).unwrap();
+            storage.write_slice(index, &content).unwrap();
+        }
+        let mut store = Store::new(MeteredStorage::new(storage).unwrap()).ok().unwrap();
+        store.insert(0, &[0x5a; 8]).unwrap();
+        let storage = store.extract_storage();
+        for page in 0 .. storage.num_pages() {
+            assert_eq!(storage.page_erases(page), 0);
+        }
+        // The init word, the header, and the value.
+        for byte in [0, 8, 12, 16] {
+            assert_eq!(storage.word_writes(StorageIndex { page: 0, byte }), Ok(1));
+        }
+    }
+
+    #[test]
+    fn health_report() {
+        let store = Store::new(new_storage()).ok().unwrap();
+        let health = store.health().unwrap();
+        assert_eq!(health.capacity.used(), 0);
+        assert_eq!(health.lifetime.used(), 0);
+        assert_eq!(health.remaining_erases, 5 * 100);
+        assert_eq!(health.wear, [(0, 5)]);
+    }
+}
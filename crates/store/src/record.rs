@@ -0,0 +1,554 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for named records.
+//!
+//! This module permits to map names (byte strings of at most 255 bytes) to values of any length
+//! (as long as they fit in [`MAX_FRAGMENTS`] fragments whose positions fit in their head) within a
+//! sequence of keys. Keys are allocated by this module. Each record has a head entry holding its
+//! name, the positions of its fragments in the sequence of keys, and the beginning of its value.
+//! The rest of the value is stored in fragments of maximal length. The head entries form the index
+//! of the records.
+//!
+//! The entries have the following format:
+//! - Head: [`HEAD`] (1 byte), name length (1 byte), name, number of fragments (2 bytes), position
+//!   of each fragment (2 bytes each), and beginning of the value.
+//! - Fragment: [`FRAGMENT`] (1 byte) and part of the value.
+//!
+//! Integers are little-endian.
+//!
+//! Writing a record writes its new fragments and its head in the same transaction, then removes its
+//! old fragments. Deleting a record removes its head, then its fragments. Those updates are grouped
+//! in as few transactions as possible. In particular, records are always written atomically, and
+//! they are deleted atomically if their fragments fit in a transaction. If an operation is
+//! interrupted, the record is either updated or left unchanged (depending on whether its head was
+//! written or removed), but some fragments may not be referenced anymore. Those fragments should be
+//! removed with [`recover`] after boot, since they use capacity and keys.
+
+use alloc::vec::Vec;
+
+use crate::format::MAX_UPDATES;
+use crate::fragment::Keys;
+#[cfg(feature = "std")]
+use crate::StoreOperation;
+use crate::{Storage, Store, StoreError, StoreResult, StoreUpdate};
+
+/// First byte of a head entry.
+pub const HEAD: u8 = 0;
+
+/// First byte of a fragment entry.
+pub const FRAGMENT: u8 = 1;
+
+/// Maximum number of fragments of a record.
+///
+/// The fragments and the head of a record must fit in a transaction to be written atomically.
+pub const MAX_FRAGMENTS: usize = MAX_UPDATES as usize - 1;
+
+/// Length in bytes of the head of a record without fragments, name, and value.
+const HEAD_LEN: usize = 4;
+
+/// Reads the value of a record.
+pub fn read(
+    store: &Store<impl Storage>, keys: &impl Keys, name: &[u8],
+) -> StoreResult<Option<Vec<u8>>> {
+    let index = Index::new(store, keys)?;
+    let head = match index.find(name) {
+        None => return Ok(None),
+        Some(x) => x,
+    };
+    let mut result = head.data.clone();
+    for &pos in &head.fragments {
+        let value = store.find(keys.key(pos))?.ok_or(StoreError::InvalidStorage)?;
+        match value.split_first() {
+            Some((&FRAGMENT, data)) => result.extend_from_slice(data),
+            _ => return Err(StoreError::InvalidStorage),
+        }
+    }
+    Ok(Some(result))
+}
+
+/// Writes the value of a record.
+///
+/// If the record already exists, its value is overwritten. Returns
+/// [`StoreError::InvalidArgument`] if the value doesn't fit in [`MAX_FRAGMENTS`] fragments.
+pub fn write(
+    store: &mut Store<impl Storage>, keys: &impl Keys, name: &[u8], value: &[u8],
+) -> StoreResult<()> {
+    apply(store, write_transactions(store, keys, name, value)?)
+}
+
+/// Deletes a record.
+///
+/// This is not an error if the record does not exist.
+pub fn delete(store: &mut Store<impl Storage>, keys: &impl Keys, name: &[u8]) -> StoreResult<()> {
+    apply(store, delete_transactions(store, keys, name)?)
+}
+
+/// Returns the sorted names of the records starting with a prefix.
+pub fn names(
+    store: &Store<impl Storage>, keys: &impl Keys, prefix: &[u8],
+) -> StoreResult<Vec<Vec<u8>>> {
+    let index = Index::new(store, keys)?;
+    let mut names: Vec<_> =
+        index.heads.into_iter().map(|x| x.name).filter(|x| x.starts_with(prefix)).collect();
+    names.sort_unstable();
+    Ok(names)
+}
+
+/// Removes the fragments not referenced by any record.
+///
+/// Such fragments may remain after an interrupted operation.
+pub fn recover(store: &mut Store<impl Storage>, keys: &impl Keys) -> StoreResult<()> {
+    apply(store, recover_transactions(store, keys)?)
+}
+
+/// Returns the store operations to write the value of a record.
+///
+/// Applying them in order is equivalent to [`write`]. This is used to simulate records.
+#[cfg(feature = "std")]
+pub fn write_operations(
+    store: &Store<impl Storage>, keys: &impl Keys, name: &[u8], value: &[u8],
+) -> StoreResult<Vec<StoreOperation>> {
+    Ok(operations(write_transactions(store, keys, name, value)?))
+}
+
+/// Returns the store operations to delete a record.
+///
+/// Applying them in order is equivalent to [`delete`]. This is used to simulate records.
+#[cfg(feature = "std")]
+pub fn delete_operations(
+    store: &Store<impl Storage>, keys: &impl Keys, name: &[u8],
+) -> StoreResult<Vec<StoreOperation>> {
+    Ok(operations(delete_transactions(store, keys, name)?))
+}
+
+/// Returns the store operations to remove the fragments not referenced by any record.
+///
+/// Applying them in order is equivalent to [`recover`]. This is used to simulate records.
+#[cfg(feature = "std")]
+pub fn recover_operations(
+    store: &Store<impl Storage>, keys: &impl Keys,
+) -> StoreResult<Vec<StoreOperation>> {
+    Ok(operations(recover_transactions(store, keys)?))
+}
+
+/// Sequence of transactions.
+type Transactions = Vec<Vec<StoreUpdate<Vec<u8>>>>;
+
+fn write_transactions(
+    store: &Store<impl Storage>, keys: &impl Keys, name: &[u8], value: &[u8],
+) -> StoreResult<Transactions> {
+    let max_value_length = store.max_value_length();
+    let name_len = u8::try_from(name.len()).map_err(|_| StoreError::InvalidArgument)?;
+    // Find the minimum number of fragments to fit the value.
+    let fragment_len = max_value_length - 1;
+    let mut count = 0;
+    let inline_len = loop {
+        let head_len = HEAD_LEN + name.len() + 2 * count;
+        if max_value_length < head_len {
+            return Err(StoreError::InvalidArgument);
+        }
+        let inline_len = max_value_length - head_len;
+        if value.len() <= inline_len + count * fragment_len {
+            break core::cmp::min(value.len(), inline_len);
+        }
+        if count == MAX_FRAGMENTS {
+            return Err(StoreError::InvalidArgument);
+        }
+        count += 1;
+    };
+    let index = Index::new(store, keys)?;
+    let old = index.find(name);
+    let mut free = (0 .. keys.len()).filter(|&pos| !index.used[pos]);
+    let mut updates = Vec::new();
+    let mut fragments = Vec::with_capacity(count);
+    for data in value[inline_len ..].chunks(fragment_len) {
+        let pos = free.next().ok_or(StoreError::NoCapacity)?;
+        fragments.push(encode_pos(pos)?);
+        let mut value = Vec::with_capacity(1 + data.len());
+        value.push(FRAGMENT);
+        value.extend_from_slice(data);
+        updates.push(StoreUpdate::Insert { key: keys.key(pos), value });
+    }
+    let pos = match old {
+        Some(head) => head.pos,
+        None => free.next().ok_or(StoreError::NoCapacity)?,
+    };
+    let mut head = Vec::with_capacity(HEAD_LEN + name.len() + 2 * count + inline_len);
+    head.push(HEAD);
+    head.push(name_len);
+    head.extend_from_slice(name);
+    head.extend_from_slice(&encode_pos(count)?);
+    fragments.iter().for_each(|x| head.extend_from_slice(x));
+    head.extend_from_slice(&value[.. inline_len]);
+    updates.push(StoreUpdate::Insert { key: keys.key(pos), value: head });
+    if let Some(head) = old {
+        let removes = head.fragments.iter().map(|&pos| StoreUpdate::Remove { key: keys.key(pos) });
+        updates.extend(removes);
+    }
+    Ok(transactions(updates))
+}
+
+fn delete_transactions(
+    store: &Store<impl Storage>, keys: &impl Keys, name: &[u8],
+) -> StoreResult<Transactions> {
+    let index = Index::new(store, keys)?;
+    let head = match index.find(name) {
+        None => return Ok(Vec::new()),
+        Some(x) => x,
+    };
+    let positions = core::iter::once(head.pos).chain(head.fragments.iter().cloned());
+    Ok(transactions(positions.map(|pos| StoreUpdate::Remove { key: keys.key(pos) }).collect()))
+}
+
+fn recover_transactions(
+    store: &Store<impl Storage>, keys: &impl Keys,
+) -> StoreResult<Transactions> {
+    let index = Index::new(store, keys)?;
+    let mut used = index.used;
+    for head in &index.heads {
+        used[head.pos] = false;
+        head.fragments.iter().for_each(|&pos| used[pos] = false);
+    }
+    let positions = (0 .. keys.len()).filter(|&pos| used[pos]);
+    Ok(transactions(positions.map(|pos| StoreUpdate::Remove { key: keys.key(pos) }).collect()))
+}
+
+/// Groups a sequence of updates in transactions preserving their order.
+fn transactions(updates: Vec<StoreUpdate<Vec<u8>>>) -> Transactions {
+    let mut result = Vec::new();
+    let mut updates = updates.into_iter().peekable();
+    while updates.peek().is_some() {
+        result.push(updates.by_ref().take(MAX_UPDATES as usize).collect());
+    }
+    result
+}
+
+#[cfg(feature = "std")]
+fn operations(transactions: Transactions) -> Vec<StoreOperation> {
+    transactions.into_iter().map(|updates| StoreOperation::Transaction { updates }).collect()
+}
+
+fn apply(store: &mut Store<impl Storage>, transactions: Transactions) -> StoreResult<()> {
+    for updates in transactions {
+        store.transaction(&updates)?;
+    }
+    Ok(())
+}
+
+fn encode_pos(pos: usize) -> StoreResult<[u8; 2]> {
+    let pos = u16::try_from(pos).map_err(|_| StoreError::InvalidArgument)?;
+    Ok(pos.to_le_bytes())
+}
+
+/// Index of the records in a sequence of keys.
+struct Index {
+    /// The heads of the records.
+    heads: Vec<Head>,
+
+    /// Whether each position of the sequence of keys has an entry.
+    used: Vec<bool>,
+}
+
+/// Head of a record.
+struct Head {
+    /// The position of the head in the sequence of keys.
+    pos: usize,
+
+    /// The name of the record.
+    name: Vec<u8>,
+
+    /// The positions of the fragments in the sequence of keys.
+    fragments: Vec<usize>,
+
+    /// The beginning of the value.
+    data: Vec<u8>,
+}
+
+impl Index {
+    fn new(store: &Store<impl Storage>, keys: &impl Keys) -> StoreResult<Self> {
+        let mut heads = Vec::new();
+        let mut used = vec![false; keys.len()];
+        for handle in store.iter()? {
+            let handle = handle?;
+            let pos = match keys.pos(handle.get_key()) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            if pos >= keys.len() {
+                return Err(StoreError::InvalidArgument);
+            }
+            used[pos] = true;
+            let value = handle.get_value(store)?;
+            if value.first() == Some(&HEAD) {
+                heads.push(Head::decode(pos, &value).ok_or(StoreError::InvalidStorage)?);
+            }
+        }
+        let index = Index { heads, used };
+        for head in &index.heads {
+            if index.heads.iter().filter(|x| x.name == head.name).count() != 1 {
+                return Err(StoreError::InvalidStorage);
+            }
+            if head.fragments.iter().any(|&pos| keys.len() <= pos || !index.used[pos]) {
+                return Err(StoreError::InvalidStorage);
+            }
+        }
+        Ok(index)
+    }
+
+    fn find(&self, name: &[u8]) -> Option<&Head> {
+        self.heads.iter().find(|x| x.name == name)
+    }
+}
+
+impl Head {
+    fn decode(pos: usize, value: &[u8]) -> Option<Self> {
+        let (&name_len, value) = value.get(1 ..)?.split_first()?;
+        let (name, value) = split_at(value, name_len as usize)?;
+        let (count, value) = split_at(value, 2)?;
+        let count = u16::from_le_bytes([count[0], count[1]]) as usize;
+        let (fragments, data) = split_at(value, 2 * count)?;
+        let fragments = fragments.chunks(2).map(|x| u16::from_le_bytes([x[0], x[1]]) as usize);
+        Some(Head { pos, name: name.to_vec(), fragments: fragments.collect(), data: data.to_vec() })
+    }
+}
+
+fn split_at(value: &[u8], mid: usize) -> Option<(&[u8], &[u8])> {
+    (mid <= value.len()).then(|| value.split_at(mid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{Config, LARGE_RECORDS, RECORDS};
+    use crate::{BufferStorage, StoreDriver, StoreDriverOn, StoreInterruption};
+
+    const KEYS: core::ops::Range<usize> = 10 .. 30;
+
+    fn new_driver(config: &Config) -> StoreDriverOn {
+        config.new_driver().power_on().unwrap()
+    }
+
+    /// Applies operations until interrupted after a given number of storage operations.
+    ///
+    /// Returns whether the operations were interrupted.
+    fn partial_apply(
+        driver: &mut StoreDriverOn, operations: Vec<StoreOperation>, delay: usize,
+    ) -> bool {
+        let mut delay = delay;
+        for operation in operations {
+            let count = driver.count_operations(&operation).unwrap();
+            if delay < count {
+                let interruption = StoreInterruption::pure(delay);
+                let off = match driver.clone().partial_apply(operation, interruption) {
+                    Ok((None, StoreDriver::Off(off))) => off,
+                    _ => panic!(),
+                };
+                *driver = off.power_on().unwrap();
+                return true;
+            }
+            delay -= count;
+            driver.apply(operation).unwrap();
+        }
+        false
+    }
+
+    /// Returns whether all entries of the keys are referenced by a record.
+    fn is_clean(store: &Store<BufferStorage>, keys: &impl Keys) -> bool {
+        let index = Index::new(store, keys).unwrap();
+        let count = index.heads.iter().map(|x| 1 + x.fragments.len()).sum::<usize>();
+        count == index.used.iter().filter(|&&x| x).count()
+    }
+
+    /// Checks that interrupting an operation leaves the record old or new.
+    ///
+    /// The store is initialized with some records before the operation.
+    fn check_interrupted(
+        mut driver: StoreDriverOn, keys: &impl Keys, init: &[(&[u8], &[u8])],
+        operations: impl Fn(&Store<BufferStorage>) -> Vec<StoreOperation>, name: &[u8],
+        old: Option<&[u8]>, new: Option<&[u8]>,
+    ) {
+        for &(name, value) in init {
+            driver.record_write(keys, name, value).unwrap();
+        }
+        for delay in 0 .. {
+            let mut driver = driver.clone();
+            let operations = operations(driver.store());
+            let interrupted = partial_apply(&mut driver, operations, delay);
+            let value = read(driver.store(), keys, name).unwrap();
+            assert!(value.as_deref() == old || value.as_deref() == new);
+            driver.record_recover(keys).unwrap();
+            driver.check().unwrap();
+            assert!(is_clean(driver.store(), keys));
+            assert_eq!(read(driver.store(), keys, name).unwrap(), value);
+            if !interrupted {
+                assert_eq!(value.as_deref(), new);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn read_missing() {
+        let store = RECORDS.new_store();
+        assert_eq!(read(&store, &KEYS, b"name"), Ok(None));
+    }
+
+    #[test]
+    fn write_small() {
+        let mut store = RECORDS.new_store();
+        assert_eq!(write(&mut store, &KEYS, b"name", b"hello"), Ok(()));
+        assert_eq!(read(&store, &KEYS, b"name"), Ok(Some(b"hello".to_vec())));
+        assert_eq!(read(&store, &KEYS, b"other"), Ok(None));
+        assert_eq!(store.find(KEYS.start).unwrap().unwrap(), b"\x00\x04name\x00\x00hello");
+    }
+
+    #[test]
+    fn write_large() {
+        let mut store = RECORDS.new_store();
+        let value: Vec<_> = (0 .. 200).collect();
+        assert_eq!(write(&mut store, &KEYS, b"name", &value), Ok(()));
+        assert_eq!(read(&store, &KEYS, b"name"), Ok(Some(value)));
+        assert!(is_clean(&store, &KEYS));
+    }
+
+    #[test]
+    fn overwrite() {
+        let mut store = RECORDS.new_store();
+        let large: Vec<_> = (0 .. 200).collect();
+        assert_eq!(write(&mut store, &KEYS, b"a", b"small"), Ok(()));
+        assert_eq!(write(&mut store, &KEYS, b"b", b"other"), Ok(()));
+        assert_eq!(write(&mut store, &KEYS, b"a", &large), Ok(()));
+        assert_eq!(read(&store, &KEYS, b"a"), Ok(Some(large)));
+        assert_eq!(write(&mut store, &KEYS, b"a", b""), Ok(()));
+        assert_eq!(read(&store, &KEYS, b"a"), Ok(Some(Vec::new())));
+        assert_eq!(read(&store, &KEYS, b"b"), Ok(Some(b"other".to_vec())));
+        assert!(is_clean(&store, &KEYS));
+    }
+
+    #[test]
+    fn delete_record() {
+        let mut store = RECORDS.new_store();
+        let large: Vec<_> = (0 .. 200).collect();
+        assert_eq!(write(&mut store, &KEYS, b"a", &large), Ok(()));
+        assert_eq!(write(&mut store, &KEYS, b"b", b"other"), Ok(()));
+        assert_eq!(delete(&mut store, &KEYS, b"a"), Ok(()));
+        assert_eq!(delete(&mut store, &KEYS, b"c"), Ok(()));
+        assert_eq!(read(&store, &KEYS, b"a"), Ok(None));
+        assert_eq!(read(&store, &KEYS, b"b"), Ok(Some(b"other".to_vec())));
+        assert_eq!(store.iter().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn names_prefix() {
+        let mut store = RECORDS.new_store();
+        for name in [b"ab".as_slice(), b"b", b"aa", b"a"] {
+            assert_eq!(write(&mut store, &KEYS, name, name), Ok(()));
+        }
+        let expected = vec![b"a".to_vec(), b"aa".to_vec(), b"ab".to_vec()];
+        assert_eq!(names(&store, &KEYS, b"a"), Ok(expected));
+        assert_eq!(names(&store, &KEYS, b"").unwrap().len(), 4);
+        assert_eq!(names(&store, &KEYS, b"c"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let mut store = RECORDS.new_store();
+        let name = vec![0; 256];
+        assert_eq!(write(&mut store, &KEYS, &name, b""), Err(StoreError::InvalidArgument));
+        let name = vec![0; store.max_value_length() - HEAD_LEN + 1];
+        assert_eq!(write(&mut store, &KEYS, &name, b""), Err(StoreError::InvalidArgument));
+        let value = vec![0; (MAX_FRAGMENTS + 1) * store.max_value_length()];
+        assert_eq!(
+            write(&mut store, &(0 .. 100), b"name", &value),
+            Err(StoreError::InvalidArgument)
+        );
+        let value = vec![0; 2 * store.max_value_length()];
+        assert_eq!(write(&mut store, &(0 .. 2), b"name", &value), Err(StoreError::NoCapacity));
+    }
+
+    #[test]
+    fn write_interrupted() {
+        let old: Vec<_> = (0 .. 150).collect();
+        let new: Vec<_> = (50 .. 250).collect();
+        let init = [(b"name".as_slice(), old.as_slice()), (b"other", &[7; 60])];
+        for name in [b"name".as_slice(), b"new"] {
+            let old = (name == b"name").then_some(old.as_slice());
+            let operations = |store: &_| write_operations(store, &KEYS, name, &new).unwrap();
+            check_interrupted(
+                new_driver(&RECORDS),
+                &KEYS,
+                &init,
+                operations,
+                name,
+                old,
+                Some(&new),
+            );
+        }
+    }
+
+    #[test]
+    fn write_max_fragments() {
+        let mut store = LARGE_RECORDS.new_store();
+        let keys = 0 .. 100;
+        let fragment_len = store.max_value_length() - 1;
+        let inline_len = store.max_value_length() - HEAD_LEN - 4 - 2 * MAX_FRAGMENTS;
+        let value = vec![0x5a; inline_len + MAX_FRAGMENTS * fragment_len];
+        assert_eq!(write(&mut store, &keys, b"name", &value), Ok(()));
+        assert_eq!(read(&store, &keys, b"name"), Ok(Some(value.clone())));
+        let mut value = value;
+        value.push(0x5a);
+        assert_eq!(write(&mut store, &keys, b"name", &value), Err(StoreError::InvalidArgument));
+    }
+
+    #[test]
+    fn write_many_fragments_interrupted() {
+        let old = vec![0xa5; 20 * 115];
+        let new = vec![0x5a; 25 * 115];
+        let init = [(b"name".as_slice(), old.as_slice())];
+        let keys = 0 .. 100;
+        let operations = |store: &_| {
+            let operations = write_operations(store, &keys, b"name", &new).unwrap();
+            // The old fragments are removed in a separate transaction.
+            assert_eq!(operations.len(), 2);
+            operations
+        };
+        let new = Some(new.as_slice());
+        check_interrupted(
+            new_driver(&LARGE_RECORDS),
+            &keys,
+            &init,
+            operations,
+            b"name",
+            Some(&old),
+            new,
+        );
+    }
+
+    #[test]
+    fn delete_interrupted() {
+        let old: Vec<_> = (0 .. 150).collect();
+        let init = [(b"name".as_slice(), old.as_slice()), (b"other", &[7; 60])];
+        let operations = |store: &_| delete_operations(store, &KEYS, b"name").unwrap();
+        check_interrupted(
+            new_driver(&RECORDS),
+            &KEYS,
+            &init,
+            operations,
+            b"name",
+            Some(&old),
+            None,
+        );
+    }
+}
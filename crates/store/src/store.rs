@@ -27,7 +27,7 @@ use crate::format::{
     is_erased, CompactInfo, Format, Header, InitInfo, InternalEntry, Padding, ParsedWord, Position,
     Word, WordState,
 };
-use crate::{usize_to_nat, Nat, Storage, StorageError, StorageIndex};
+use crate::{usize_to_nat, MeteredStorage, Nat, Storage, StorageError, StorageIndex, StoreHealth};
 #[cfg(feature = "std")]
 use crate::{BufferStorage, StoreOperation};
 
@@ -444,6 +444,13 @@ impl<S: Storage> Store<S> {
         self.format.max_value_len() as usize
     }
 
+    /// Returns the capacity in words of an entry given the length in bytes of its value.
+    ///
+    /// This is how much an entry contributes to the used [capacity](Self::capacity).
+    pub fn entry_capacity(&self, length: usize) -> usize {
+        (1 + self.format.bytes_to_words(usize_to_nat(length))) as usize
+    }
+
     /// Returns the length of the value of an entry given its handle.
     fn get_length(&self, handle: &StoreHandle) -> StoreResult<usize> {
         self.check_handle(handle)?;
@@ -1122,6 +1129,18 @@ impl<S: Storage> Store<S> {
     }
 }
 
+impl<S: Storage> Store<MeteredStorage<S>> {
+    /// Returns the health report of the store.
+    pub fn health(&self) -> StoreResult<StoreHealth> {
+        Ok(StoreHealth {
+            capacity: self.capacity()?,
+            lifetime: self.lifetime()?,
+            remaining_erases: self.storage.remaining_lifetime(),
+            wear: self.storage.wear_histogram(),
+        })
+    }
+}
+
 // Those functions are not meant for production.
 #[cfg(feature = "std")]
 impl Store<BufferStorage> {
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{BufferOptions, BufferStorage, Store, StoreDriverOff};
+use crate::{BufferOptions, BufferStorage, MeteredStorage, Store, StoreDriverOff};
 
 #[derive(Clone)]
 pub(crate) struct Config {
@@ -24,25 +24,59 @@ pub(crate) struct Config {
 }
 
 impl Config {
-    pub(crate) fn new_driver(&self) -> StoreDriverOff {
-        let options = BufferOptions {
+    fn options(&self) -> BufferOptions {
+        BufferOptions {
             word_size: self.word_size,
             page_size: self.page_size,
             max_word_writes: self.max_word_writes,
             max_page_erases: self.max_page_erases,
             strict_mode: true,
-        };
-        StoreDriverOff::new(options, self.num_pages)
+        }
+    }
+
+    pub(crate) fn new_driver(&self) -> StoreDriverOff {
+        StoreDriverOff::new(self.options(), self.num_pages)
     }
 
     pub(crate) fn new_store(&self) -> Store<BufferStorage> {
         self.new_driver().power_on().unwrap().extract_store()
     }
+
+    /// Returns an erased storage with the journal pages of a metered storage.
+    pub(crate) fn new_journaled_storage(&self) -> BufferStorage {
+        let num_pages = self.num_pages + MeteredStorage::<BufferStorage>::JOURNAL_PAGES;
+        let storage = vec![0xff; num_pages * self.page_size].into_boxed_slice();
+        BufferStorage::new(storage, self.options())
+    }
 }
 
 pub(crate) const MINIMAL: Config =
     Config { word_size: 4, page_size: 64, num_pages: 5, max_word_writes: 2, max_page_erases: 9 };
 
+/// Minimal configuration with pages large enough for the metadata of encrypted stores.
+#[cfg(feature = "encrypted")]
+pub(crate) const ENCRYPTED: Config =
+    Config { word_size: 4, page_size: 256, num_pages: 5, max_word_writes: 2, max_page_erases: 10 };
+
+/// Minimal configuration for metered storages.
+///
+/// The journal pages are erased more often than the store pages, so the erase limit is larger.
+pub(crate) const METERED: Config =
+    Config { word_size: 4, page_size: 64, num_pages: 5, max_word_writes: 2, max_page_erases: 100 };
+
+/// Configuration with enough capacity for records with a few fragments.
+pub(crate) const RECORDS: Config =
+    Config { word_size: 4, page_size: 64, num_pages: 20, max_word_writes: 2, max_page_erases: 100 };
+
+/// Configuration with enough capacity for records with many fragments.
+pub(crate) const LARGE_RECORDS: Config = Config {
+    word_size: 4,
+    page_size: 128,
+    num_pages: 63,
+    max_word_writes: 2,
+    max_page_erases: 100,
+};
+
 const NORDIC: Config = Config {
     word_size: 4,
     page_size: 0x1000,
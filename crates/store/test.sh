@@ -20,5 +20,7 @@ set -e
 test_helper
 
 cargo test --lib --features=std
+cargo test --lib --features=std,encrypted
 cargo test --test=store --features=std
 cargo check --lib --target=thumbv7em-none-eabi
+cargo check --lib --target=thumbv7em-none-eabi --features=encrypted
@@ -16,9 +16,10 @@
 
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
+use std::fmt::Write as _;
 use std::process::Command;
 
-use anyhow::{bail, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use clap::Parser;
 use lazy_static::lazy_static;
 use probe_rs::config::TargetSelector;
@@ -172,6 +173,20 @@ struct RunnerOptions {
     #[clap(long)]
     bundle: bool,
 
+    /// Path to the private key (32 bytes) signing platform updates.
+    ///
+    /// For Nordic, the platform only accepts updates signed by this key, and the bundled files are
+    /// signed with it.
+    #[clap(long)]
+    update_key: Option<String>,
+
+    /// Public key trusted to sign applets (hexadecimal encoding of x then y).
+    ///
+    /// When provided (possibly multiple times), the platform only loads applets signed by one of
+    /// those keys.
+    #[clap(long)]
+    applet_key: Vec<String>,
+
     /// Resets the persistent storage before running.
     #[clap(long)]
     reset_storage: bool,
@@ -215,6 +230,13 @@ struct RunnerOptions {
     /// missing is 1 page.
     #[clap(long)]
     memory_page_count: Option<usize>,
+
+    /// Allocates memory for <APPLET_COUNT> WASM applets.
+    ///
+    /// Supported values are numbers between 1 and 9 inclusive, i.e. single digit. The default when
+    /// missing is 1 applet.
+    #[clap(long)]
+    applet_count: Option<usize>,
 }
 
 impl Flags {
@@ -392,6 +414,16 @@ impl RunnerOptions {
             };
             ensure!(version < u32::MAX, "--version must be smaller than u32::MAX");
             rustflags.push(format!("-C link-arg=--defsym=RUNNER_VERSION={version}"));
+            if let Some(key) = &self.update_key {
+                let key = read_update_key(key)?;
+                let key = image::PublicKey::new::<image::SoftwareP256>(&key)
+                    .map_err(|e| anyhow!("invalid --update-key: {e}"))?;
+                let mut hex = String::new();
+                for x in key.x.iter().chain(&key.y) {
+                    write!(hex, "{x:02x}")?;
+                }
+                cargo.env("WASEFIRE_UPDATE_KEY", hex);
+            }
             rustflags.push("-C link-arg=-Tlink.x".to_string());
             if main.release {
                 cargo.arg("-Zbuild-std=core,alloc");
@@ -437,6 +469,11 @@ impl RunnerOptions {
         } else {
             features.push("wasm".to_string());
         }
+        if !self.applet_key.is_empty() {
+            ensure!(!main.native, "--applet-key is only supported for WASM applets");
+            features.push("wasefire-scheduler/applet-signature".to_string());
+            cargo.env("WASEFIRE_APPLET_KEYS", self.applet_key.join(","));
+        }
         if !features.is_empty() {
             cargo.arg(format!("--features={}", features.join(",")));
         }
@@ -444,6 +481,10 @@ impl RunnerOptions {
             ensure!((0 ..= 9).contains(&n), "--memory-page-count supports single digit only");
             cargo.env("WASEFIRE_MEMORY_PAGE_COUNT", format!("{n}"));
         }
+        if let Some(n) = self.applet_count {
+            ensure!((1 ..= 9).contains(&n), "--applet-count supports non-zero single digit only");
+            cargo.env("WASEFIRE_APPLET_COUNT", format!("{n}"));
+        }
         if !rustflags.is_empty() {
             cargo.env("RUSTFLAGS", rustflags.join(" "));
         }
@@ -457,6 +498,10 @@ impl RunnerOptions {
                 fs::remove_file(path)?;
             }
             cargo.arg("--");
+            // The applet is not built in the runner, so it needs to be given (if any).
+            if !main.native && fs::metadata("target/wasefire/applet.wasm")?.len() > 0 {
+                cargo.arg("--applet=../../target/wasefire/applet.wasm");
+            }
             if let Some(host) = &self.web_host {
                 cargo.arg(format!("--web-host={host}"));
             }
@@ -526,8 +571,16 @@ impl RunnerOptions {
         if self.bundle {
             let mut objcopy = wrap_command()?;
             objcopy.args(["rust-objcopy", "-O", "binary", &elf]);
-            objcopy.arg(format!("target/wasefire/platform{side}.bin"));
+            let bundle = format!("target/wasefire/platform{side}.bin");
+            objcopy.arg(&bundle);
             cmd::execute(&mut objcopy)?;
+            if let Some(key) = self.update_key.as_ref().filter(|_| self.name == "nordic") {
+                let key = read_update_key(key)?;
+                let mut platform = fs::read(&bundle)?;
+                image::sign::<image::SoftwareP256, image::SoftwareSha256>(&mut platform, &key)
+                    .map_err(|e| anyhow!("failed to sign {bundle}: {e}"))?;
+                fs::write(&bundle, platform)?;
+            }
             if step < max_step {
                 return self.execute(main, step + 1, run);
             }
@@ -610,6 +663,11 @@ impl RunnerOptions {
     }
 }
 
+fn read_update_key(path: &str) -> Result<[u8; 32]> {
+    let key = fs::read(path)?;
+    key.try_into().map_err(|_| anyhow!("{path} must contain exactly 32 bytes"))
+}
+
 fn ensure_command(cmd: &[&str]) -> Result<()> {
     let mut wrapper = Command::new("./scripts/wrapper.sh");
     wrapper.args(cmd);
@@ -909,7 +909,8 @@
   export declare function store_insert(
     // Key of the entry.
     //
-    // This must be smaller than 4096.
+    // This must be smaller than 3072 divided by the number of applets the platform
+    // can run.
     key: usize,
 
     // Value of the entry.
@@ -964,6 +965,72 @@
   export declare function store_clear(
   ): i32
 
+  // Update of a transaction.
+  class store_Update {
+    // Key of the entry.
+    //
+    // This has the same constraints as for `insert()`.
+    key: u32;
+
+    // Value of the entry, or null to remove the entry.
+    ptr: u32;
+
+    // Length of the value (ignored when removing the entry).
+    len: u32;
+  }
+
+  // Applies a sequence of updates atomically.
+  //
+  // Either all updates are applied or none. The keys of the updates must be distinct
+  // and there must be at most 31 updates.
+  @external("env", "st")
+  export declare function store_transaction(
+    // Array of [`super::Update`] structs.
+    ptr: usize,
+
+    // Number of updates.
+    len: usize,
+  ): i32
+
+  // Progression ratio in words for store metrics.
+  class store_Ratio {
+    // How much of the metric is used.
+    used: u32;
+
+    // How much of the metric can be used at most.
+    total: u32;
+  }
+
+  // Returns the capacity used by the applet.
+  //
+  // The total is the quota of the applet, an even share of the store capacity between
+  // the applets the platform can run.
+  @external("env", "sy")
+  export declare function store_capacity(
+    // Pointer to the output [`super::Ratio`] struct.
+    ptr: usize,
+  ): i32
+
+  // Returns the lifetime used by the store.
+  //
+  // The lifetime is shared by all applets and the platform. It is used when writing
+  // entries and when compacting the store.
+  @external("env", "sl")
+  export declare function store_lifetime(
+    // Pointer to the output [`super::Ratio`] struct.
+    ptr: usize,
+  ): i32
+
+  // Compacts the store once if needed to insert a value without compaction.
+  //
+  // This permits to compact the store at a convenient time, since compaction may take
+  // a while. This is a no-op if the value can already be inserted without compaction.
+  @external("env", "sp")
+  export declare function store_prepare(
+    // Length of the value.
+    len: usize,
+  ): i32
+
   // START OF MODULE store_fragment
   // Support for fragmented entries.
     // Inserts an entry in the store.
@@ -26,7 +26,17 @@ use wasefire_wire::Yoke;
 mod tests;
 
 #[derive(Parser)]
-enum Flags {
+struct Flags {
+    /// Name of the applet.
+    #[clap(long, default_value = "applet")]
+    applet: String,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
     /// Starts a request/response call with an applet.
     Call,
 
@@ -47,20 +57,21 @@ fn main() -> Result<()> {
     let candidate = rpc::choose_device(&context).context("choosing device")?;
     let connection =
         candidate.connect(Duration::from_secs(1)).context("connecting to the device")?;
-    match flags {
-        Flags::Call => {
+    let applet_id = AppletId { name: &flags.applet };
+    match flags.command {
+        Command::Call => {
             let mut request = Vec::new();
             std::io::stdin().read_to_end(&mut request)?;
-            let request = applet::Request { applet_id: AppletId, request: &request };
+            let request = applet::Request { applet_id, request: &request };
             connection.call::<service::AppletRequest>(request)?.get();
-            let response = connection.call::<service::AppletResponse>(AppletId)?;
+            let response = connection.call::<service::AppletResponse>(applet_id)?;
             let response = response.get().response.context("no applet response")?;
             print!("{}", std::str::from_utf8(response).unwrap());
             Ok(())
         }
-        Flags::Tunnel { delimiter } => {
+        Command::Tunnel { delimiter } => {
             let delimiter = delimiter.as_bytes();
-            let tunnel = applet::Tunnel { applet_id: applet::AppletId, delimiter };
+            let tunnel = applet::Tunnel { applet_id, delimiter };
             send(&connection, &Api::<Request>::AppletTunnel(tunnel))?;
             read_tunnel(&connection)?;
             for line in std::io::stdin().lines() {
@@ -75,7 +86,7 @@ fn main() -> Result<()> {
             connection.send_raw(delimiter)?;
             read_tunnel(&connection)
         }
-        Flags::Test => tests::main(connection),
+        Command::Test => tests::main(connection, applet_id),
     }
 }
 
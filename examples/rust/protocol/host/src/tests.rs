@@ -16,14 +16,15 @@ use std::time::Duration;
 
 use anyhow::Result;
 use rusb::{Error, GlobalContext};
-use wasefire_protocol::{applet, Api, Request};
+use wasefire_protocol::applet::{self, AppletId};
+use wasefire_protocol::{Api, Request};
 use wasefire_protocol_usb::Connection;
 
-pub fn main(mut connection: Connection<GlobalContext>) -> Result<()> {
+pub fn main(mut connection: Connection<GlobalContext>, applet_id: AppletId) -> Result<()> {
     connection.set_timeout(Duration::from_millis(200));
     let connection = &connection;
     println!("Enter tunnel.");
-    let tunnel = applet::Tunnel { applet_id: applet::AppletId, delimiter: b"EOF" };
+    let tunnel = applet::Tunnel { applet_id, delimiter: b"EOF" };
     crate::send(connection, &Api::<Request>::AppletTunnel(tunnel))?;
     crate::read_tunnel(connection)?;
 